@@ -0,0 +1,205 @@
+use base64::{engine::general_purpose::STANDARD, Engine};
+use std::path::PathBuf;
+
+use crate::catalog::Catalog;
+use crate::journal;
+use crate::model_image;
+use crate::ollama::{AiError, Ollama};
+use crate::settings::{Settings, SettingsStore};
+use crate::tag_output;
+
+/// Longest description kept, in characters. Models occasionally ramble on.
+const MAX_DESCRIPTION_CHARS: usize = 1000;
+
+/// Labels some models put in front of their answer despite the prompt.
+const DESCRIPTION_LABELS: &[&str] = &["description:", "caption:", "answer:"];
+
+/// Asks the vision model for tags for the image at `image_path` and stores
+/// them if the image is part of the library. Images tagged in the same
+/// `batch` are undone together.
+pub async fn tag_image(
+    catalog: &Catalog,
+    ollama: &Ollama,
+    settings: &Settings,
+    image_path: &str,
+    batch: Option<i64>,
+) -> Result<Vec<String>, AiError> {
+    let image_base64 = encoded_image(settings, image_path).await?;
+
+    let answer = ollama
+        .generate_json(
+            settings,
+            &settings.tag_prompt,
+            &[image_base64],
+            &tag_output::schema(),
+        )
+        .await?;
+    let parsed = tag_output::parse(&answer);
+    let tags = settings.tag_rules.apply(&parsed.tags);
+
+    // Keep the tags if the image is part of the library
+    let Some(image_id) = catalog.image_id_for_path(image_path)? else {
+        return Ok(tags);
+    };
+
+    let current = catalog.images_by_ids(std::slice::from_ref(&image_id))?;
+    let Some(current) = current.first() else {
+        return Ok(tags);
+    };
+
+    let summary = |images: usize| match images {
+        1 => format!("Tagged {}", current.name),
+        n => format!("Tagged {} images", n),
+    };
+    let ids = std::slice::from_ref(&image_id);
+    journal::edit_in_batch(catalog, batch, summary, ids, || {
+        // A description that came along for free fills an empty one, never replaces it
+        if let Some(description) = parsed.description.as_deref().map(clean_description) {
+            if current.description.is_empty() && !description.is_empty() {
+                catalog.set_description(&image_id, &description)?;
+            }
+        }
+
+        // Retagging replaces the model's tags but keeps the user's and the file's
+        catalog.set_model_tags(&image_id, &tags)
+    })
+    .map_err(AiError::from)
+}
+
+/// Asks the vision model for a one-paragraph caption for the image at
+/// `image_path` and stores it if the image is part of the library.
+pub async fn describe_image(
+    catalog: &Catalog,
+    ollama: &Ollama,
+    settings: &Settings,
+    image_path: &str,
+) -> Result<String, AiError> {
+    let image_base64 = encoded_image(settings, image_path).await?;
+
+    let answer = ollama
+        .generate(settings, &settings.description_prompt, &[image_base64])
+        .await?;
+
+    let description = clean_description(&answer);
+    if description.is_empty() {
+        return Err(AiError::InvalidResponse(
+            "the model returned an empty description".to_string(),
+        ));
+    }
+
+    if let Some(image_id) = catalog.image_id_for_path(image_path)? {
+        let summary = format!("Described {}", journal::name_of(catalog, &image_id)?);
+        journal::edit(catalog, &summary, std::slice::from_ref(&image_id), || {
+            catalog.set_description(&image_id, &description)
+        })?;
+    }
+    Ok(description)
+}
+
+/// The image as base64 JPEG, ready for the model.
+async fn encoded_image(settings: &Settings, image_path: &str) -> Result<String, AiError> {
+    // Decode, shrink and re-encode off the async runtime; large files take a while
+    let path = PathBuf::from(image_path);
+    let max_edge = settings.max_image_edge;
+    let image_bytes =
+        tauri::async_runtime::spawn_blocking(move || model_image::prepare(&path, max_edge))
+            .await
+            .map_err(|e| AiError::Other(format!("Failed to prepare image: {}", e)))??;
+
+    Ok(STANDARD.encode(&image_bytes))
+}
+
+/// Turns the model's answer into a single tidy paragraph: label and
+/// surrounding quotes removed, whitespace collapsed, control characters
+/// dropped and overly long answers cut at a sentence or word boundary.
+fn clean_description(answer: &str) -> String {
+    let mut text: String = answer
+        .split_whitespace()
+        .collect::<Vec<_>>()
+        .join(" ")
+        .chars()
+        .filter(|c| !c.is_control())
+        .collect();
+
+    let labelled = DESCRIPTION_LABELS.iter().find(|label| {
+        text.get(..label.len())
+            .is_some_and(|head| head.eq_ignore_ascii_case(label))
+    });
+    if let Some(label) = labelled {
+        text = text[label.len()..].trim_start().to_string();
+    }
+
+    let text = text.trim_matches(|c| matches!(c, '"' | '\'' | '“' | '”' | '`' | ' '));
+    truncate(text, MAX_DESCRIPTION_CHARS)
+}
+
+fn truncate(text: &str, max_chars: usize) -> String {
+    let Some((cut, _)) = text.char_indices().nth(max_chars) else {
+        return text.to_string();
+    };
+    let head = &text[..cut];
+
+    if let Some(end) = head.rfind(['.', '!', '?']) {
+        return head[..=end].to_string();
+    }
+    match head.rfind(' ') {
+        Some(space) => format!("{}…", head[..space].trim_end()),
+        None => format!("{}…", head),
+    }
+}
+
+#[tauri::command]
+pub async fn generate_tags(
+    catalog: tauri::State<'_, Catalog>,
+    ollama: tauri::State<'_, Ollama>,
+    settings: tauri::State<'_, SettingsStore>,
+    image_path: String,
+) -> Result<Vec<String>, AiError> {
+    let settings = settings.get();
+    tag_image(&catalog, &ollama, &settings, &image_path, None).await
+}
+
+#[tauri::command]
+pub async fn generate_description(
+    catalog: tauri::State<'_, Catalog>,
+    ollama: tauri::State<'_, Ollama>,
+    settings: tauri::State<'_, SettingsStore>,
+    image_path: String,
+) -> Result<String, AiError> {
+    let settings = settings.get();
+    describe_image(&catalog, &ollama, &settings, &image_path).await
+}
+
+#[tauri::command]
+pub async fn check_ollama(
+    ollama: tauri::State<'_, Ollama>,
+    settings: tauri::State<'_, SettingsStore>,
+) -> Result<bool, AiError> {
+    Ok(ollama.is_available(&settings.get()).await)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn descriptions_become_one_clean_paragraph() {
+        assert_eq!(
+            clean_description("  Description: \"A dog runs\n\non the beach.\r\n\"  "),
+            "A dog runs on the beach."
+        );
+        assert_eq!(clean_description("A red\tbarn.\u{0007}"), "A red barn.");
+        assert_eq!(clean_description("\n \n"), "");
+    }
+
+    #[test]
+    fn long_descriptions_are_cut_at_a_boundary() {
+        let sentence = "A cat sleeps on a sofa. ";
+        let long = sentence.repeat(100);
+        let cleaned = clean_description(&long);
+        assert!(cleaned.chars().count() <= MAX_DESCRIPTION_CHARS);
+        assert!(cleaned.ends_with("sofa."));
+
+        assert_eq!(truncate("one two three", 9), "one two…");
+    }
+}
@@ -0,0 +1,1552 @@
+use rusqlite::{params, Connection, OptionalExtension};
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use std::path::Path;
+use std::sync::{Mutex, MutexGuard};
+
+use crate::identity::{self, FileStamp};
+use crate::journal;
+use crate::metadata::FileDetails;
+use crate::natural;
+use crate::scan::{self, ImageInfo, IssueKind, ScanIssue, ScanOptions, ScannedFile};
+
+/// Schema migrations, applied in order. The database's `user_version` records
+/// how many have run, so only append to this list — never edit an entry.
+const MIGRATIONS: &[&str] = &[
+    // 1: images, tags and the roots they were scanned from
+    "CREATE TABLE roots (
+        id INTEGER PRIMARY KEY,
+        path TEXT NOT NULL UNIQUE,
+        last_scanned INTEGER
+    );
+    CREATE TABLE images (
+        id TEXT PRIMARY KEY,
+        root_id INTEGER REFERENCES roots(id) ON DELETE SET NULL,
+        path TEXT NOT NULL UNIQUE,
+        relative_path TEXT NOT NULL,
+        name TEXT NOT NULL,
+        description TEXT NOT NULL DEFAULT ''
+    );
+    CREATE INDEX images_root ON images(root_id);
+    CREATE TABLE tags (
+        id INTEGER PRIMARY KEY,
+        name TEXT NOT NULL UNIQUE
+    );
+    CREATE TABLE image_tags (
+        image_id TEXT NOT NULL REFERENCES images(id) ON DELETE CASCADE ON UPDATE CASCADE,
+        tag_id INTEGER NOT NULL REFERENCES tags(id) ON DELETE CASCADE,
+        PRIMARY KEY (image_id, tag_id)
+    );
+    CREATE INDEX image_tags_tag ON image_tags(tag_id);",
+    // 2: content hashes for stable ids and rename detection
+    "ALTER TABLE images ADD COLUMN content_hash TEXT;
+    ALTER TABLE images ADD COLUMN size INTEGER;
+    ALTER TABLE images ADD COLUMN modified INTEGER;
+    CREATE INDEX images_hash ON images(content_hash);",
+    // 3: scan options per root, reused by the watcher and rescans
+    "ALTER TABLE roots ADD COLUMN options TEXT;",
+    // 4: pending batch tagging jobs, kept across restarts
+    "CREATE TABLE tagging_queue (
+        image_id TEXT PRIMARY KEY REFERENCES images(id) ON DELETE CASCADE ON UPDATE CASCADE,
+        force INTEGER NOT NULL DEFAULT 0,
+        queued_at INTEGER NOT NULL
+    );",
+    // 5: dimensions and capture time, for search; filled in as metadata is read
+    "ALTER TABLE images ADD COLUMN width INTEGER;
+    ALTER TABLE images ADD COLUMN height INTEGER;
+    ALTER TABLE images ADD COLUMN taken INTEGER;",
+    // 6: full-text index over names, tags and descriptions, kept in sync by triggers
+    "CREATE VIRTUAL TABLE image_text USING fts5(
+        image_id UNINDEXED, name, tags, description,
+        tokenize = 'porter unicode61 remove_diacritics 2'
+    );
+    CREATE VIRTUAL TABLE image_terms USING fts5vocab(image_text, 'row');
+    CREATE VIEW image_tag_text AS
+        SELECT it.image_id, group_concat(t.name, ', ') AS tags
+        FROM image_tags it JOIN tags t ON t.id = it.tag_id
+        GROUP BY it.image_id;
+    INSERT INTO image_text (image_id, name, tags, description)
+        SELECT i.id, i.name, COALESCE((SELECT tags FROM image_tag_text WHERE image_id = i.id), ''), i.description
+        FROM images i;
+    CREATE TRIGGER images_text_insert AFTER INSERT ON images BEGIN
+        INSERT INTO image_text (image_id, name, tags, description)
+            VALUES (new.id, new.name, '', new.description);
+    END;
+    CREATE TRIGGER images_text_update AFTER UPDATE OF id, name, description ON images BEGIN
+        DELETE FROM image_text WHERE image_id = old.id;
+        INSERT INTO image_text (image_id, name, tags, description)
+            VALUES (new.id, new.name, COALESCE((SELECT tags FROM image_tag_text WHERE image_id = new.id), ''), new.description);
+    END;
+    CREATE TRIGGER images_text_delete AFTER DELETE ON images BEGIN
+        DELETE FROM image_text WHERE image_id = old.id;
+    END;
+    CREATE TRIGGER image_tags_text_insert AFTER INSERT ON image_tags BEGIN
+        UPDATE image_text SET tags = COALESCE((SELECT tags FROM image_tag_text WHERE image_id = new.image_id), '')
+            WHERE image_id = new.image_id;
+    END;
+    CREATE TRIGGER image_tags_text_delete AFTER DELETE ON image_tags BEGIN
+        UPDATE image_text SET tags = COALESCE((SELECT tags FROM image_tag_text WHERE image_id = old.image_id), '')
+            WHERE image_id = old.image_id;
+    END;
+    CREATE TRIGGER image_tags_text_update AFTER UPDATE ON image_tags BEGIN
+        UPDATE image_text SET tags = COALESCE((SELECT tags FROM image_tag_text WHERE image_id = image_text.image_id), '')
+            WHERE image_id IN (old.image_id, new.image_id);
+    END;
+    CREATE TRIGGER tags_text_rename AFTER UPDATE OF name ON tags BEGIN
+        UPDATE image_text SET tags = COALESCE((SELECT tags FROM image_tag_text WHERE image_id = image_text.image_id), '')
+            WHERE image_id IN (SELECT image_id FROM image_tags WHERE tag_id = new.id);
+    END;",
+    // 7: text embeddings for semantic search, with the model and text they came from
+    "CREATE TABLE image_embeddings (
+        image_id TEXT PRIMARY KEY REFERENCES images(id) ON DELETE CASCADE ON UPDATE CASCADE,
+        model TEXT NOT NULL,
+        source TEXT NOT NULL,
+        vector BLOB NOT NULL
+    );",
+    // 8: embedded EXIF/XMP/IPTC metadata as JSON (NULL until read), and where a tag came from
+    "ALTER TABLE images ADD COLUMN metadata TEXT;
+    ALTER TABLE image_tags ADD COLUMN source TEXT;",
+    // 9: creation time and format; clearing metadata makes the next scan
+    // re-read every file, which also fills in the dimensions
+    "ALTER TABLE images ADD COLUMN created INTEGER;
+    ALTER TABLE images ADD COLUMN mime TEXT;
+    UPDATE images SET metadata = NULL;",
+    // 10: problems found in files by scans, including files left out as not images
+    "CREATE TABLE scan_issues (
+        path TEXT NOT NULL,
+        kind TEXT NOT NULL,
+        root_id INTEGER NOT NULL REFERENCES roots(id) ON DELETE CASCADE,
+        relative_path TEXT NOT NULL,
+        message TEXT NOT NULL,
+        PRIMARY KEY (path, kind)
+    );
+    CREATE INDEX scan_issues_root ON scan_issues(root_id);",
+    // 11: the RAW half of RAW+JPEG pairs, which share the JPEG's record
+    "ALTER TABLE images ADD COLUMN raw_path TEXT;",
+    // 12: perceptual hashes; clearing metadata makes the next scan read
+    // every file again, which computes them
+    "ALTER TABLE images ADD COLUMN dhash INTEGER;
+    ALTER TABLE images ADD COLUMN phash INTEGER;
+    UPDATE images SET metadata = NULL;",
+    // 13: colour histograms, computed on the rescan the reset triggers
+    "ALTER TABLE images ADD COLUMN histogram BLOB;
+    UPDATE images SET metadata = NULL;",
+    // 14: dominant colours as JSON, likewise
+    "ALTER TABLE images ADD COLUMN palette TEXT;
+    UPDATE images SET metadata = NULL;",
+    // 15: what file operations did, step by step, so they can be undone
+    "CREATE TABLE journal (
+        id INTEGER PRIMARY KEY,
+        at INTEGER NOT NULL,
+        summary TEXT NOT NULL,
+        steps TEXT NOT NULL
+    );",
+    // 16: undone operations stay until something new is done, for redo
+    "ALTER TABLE journal ADD COLUMN undone INTEGER NOT NULL DEFAULT 0;",
+    // 17: images queued together for tagging are journaled as one operation
+    "ALTER TABLE tagging_queue ADD COLUMN batch INTEGER;
+     ALTER TABLE journal ADD COLUMN batch INTEGER;",
+];
+
+/// Columns read by `row_to_image`, for queries that alias `images` as `i`.
+pub(crate) const IMAGE_COLUMNS: &str = "i.id, i.path, i.relative_path, i.name, i.description,
+    i.width, i.height, i.size, i.created, i.modified, i.mime, i.metadata, i.raw_path,
+    i.palette";
+/// How many columns `IMAGE_COLUMNS` selects, for queries that add their own after it.
+pub(crate) const IMAGE_COLUMN_COUNT: usize = 14;
+
+/// `image_tags.source` of keywords imported from the file itself.
+/// Tags the user set have no source.
+pub(crate) const FILE_TAG_SOURCE: &str = "file";
+/// `image_tags.source` of tags suggested by the vision model, which a
+/// retag replaces.
+const MODEL_TAG_SOURCE: &str = "model";
+
+/// Images whose tags are looked up per query, within SQLite's smallest
+/// limit on parameters.
+const TAG_LOOKUP_CHUNK: usize = 500;
+
+pub struct Catalog {
+    conn: Mutex<Connection>,
+}
+
+/// What the catalog already knows about a file on disk.
+pub struct KnownFile {
+    pub content_hash: String,
+    pub stamp: FileStamp,
+    /// Whether its dimensions, format and embedded metadata have been read.
+    pub has_metadata: bool,
+}
+
+/// An image's description and the tags given to it rather than read from
+/// its file, which only live in the catalog.
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct Annotations {
+    pub description: String,
+    pub tags: Vec<String>,
+    /// Tags the vision model suggested, kept apart from the user's.
+    #[serde(default)]
+    pub model_tags: Vec<String>,
+}
+
+/// An image's file, for derived data cached outside the catalog.
+pub struct SourceFile {
+    pub path: String,
+    /// `None` for records from before content hashing.
+    pub content_hash: Option<String>,
+    pub modified: Option<i64>,
+}
+
+/// Filters for `query_images`. Every field is optional; set fields are ANDed.
+#[derive(Deserialize, Default)]
+#[serde(rename_all = "camelCase", default)]
+pub struct ImageQuery {
+    /// Only images scanned from this root folder.
+    pub root: Option<String>,
+    /// Images must carry every one of these tags.
+    pub tags: Vec<String>,
+    /// Case-insensitive substring match on name, description or any tag.
+    pub text: Option<String>,
+    pub limit: Option<u32>,
+    pub offset: Option<u32>,
+}
+
+/// What `upsert_file` did with a scanned file.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub enum Upserted {
+    New,
+    Unchanged,
+    Modified,
+    /// Reconciled to an existing record previously stored at this path.
+    Moved(String),
+}
+
+/// Result of `apply_changes`: what happened to each file, and the ids of
+/// the records that were deleted.
+pub struct AppliedChanges {
+    pub upserted: Vec<(String, Upserted)>,
+    pub deleted: Vec<String>,
+}
+
+pub(crate) fn db_err(e: rusqlite::Error) -> String {
+    format!("Database error: {}", e)
+}
+
+pub(crate) fn now_secs() -> i64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|d| d.as_secs() as i64)
+        .unwrap_or(0)
+}
+
+impl Catalog {
+    pub fn open(path: &Path) -> Result<Self, String> {
+        let conn = Connection::open(path).map_err(db_err)?;
+        Self::init(conn)
+    }
+
+    #[cfg(test)]
+    pub(crate) fn open_in_memory() -> Result<Self, String> {
+        Self::init(Connection::open_in_memory().map_err(db_err)?)
+    }
+
+    fn init(mut conn: Connection) -> Result<Self, String> {
+        conn.execute_batch("PRAGMA foreign_keys = ON; PRAGMA journal_mode = WAL;")
+            .map_err(db_err)?;
+        conn.create_collation(natural::COLLATION, natural::compare)
+            .map_err(db_err)?;
+        migrate(&mut conn)?;
+        Ok(Catalog {
+            conn: Mutex::new(conn),
+        })
+    }
+
+    pub(crate) fn conn(&self) -> MutexGuard<'_, Connection> {
+        // A panic while holding the lock can't leave SQLite half-written, so keep going
+        self.conn.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Hash and stamp recorded for every stored path, so a rescan can skip
+    /// rehashing files whose size and mtime are unchanged.
+    pub fn known_files(&self) -> Result<HashMap<String, KnownFile>, String> {
+        let conn = self.conn();
+        let mut stmt = conn
+            .prepare(
+                "SELECT path, content_hash, size, modified, metadata IS NOT NULL FROM images
+                 WHERE content_hash IS NOT NULL",
+            )
+            .map_err(db_err)?;
+        let rows = stmt
+            .query_map([], |r| {
+                let known = KnownFile {
+                    content_hash: r.get(1)?,
+                    stamp: FileStamp {
+                        size: r.get::<_, i64>(2)? as u64,
+                        modified: r.get(3)?,
+                    },
+                    has_metadata: r.get(4)?,
+                };
+                Ok((r.get(0)?, known))
+            })
+            .map_err(db_err)?;
+        rows.collect::<Result<_, _>>().map_err(db_err)
+    }
+
+    /// Records the start of a scan of `root`, creating the root if needed
+    /// and storing the options it is scanned with.
+    pub fn begin_scan(&self, root: &Path, options: &ScanOptions) -> Result<(), String> {
+        let options_json = serde_json::to_string(options).map_err(|e| e.to_string())?;
+        self.conn()
+            .execute(
+                "INSERT INTO roots (path, last_scanned, options) VALUES (?1, ?2, ?3)
+                 ON CONFLICT(path) DO UPDATE SET
+                     last_scanned = excluded.last_scanned, options = excluded.options",
+                params![root.to_string_lossy(), now_secs(), options_json],
+            )
+            .map_err(db_err)?;
+        Ok(())
+    }
+
+    /// Merges a batch of scanned files under `root` with what is already
+    /// stored and returns their records. Known paths keep their id, tags and
+    /// description; a new path whose content matches a record whose file has
+    /// disappeared is treated as a rename or move of that record; anything
+    /// else gets a new id from its content hash.
+    pub fn merge_batch(
+        &self,
+        root: &Path,
+        files: &[ScannedFile],
+    ) -> Result<Vec<ImageInfo>, String> {
+        let applied = self.apply_changes(root, files, &[])?;
+        let ids: Vec<String> = applied.upserted.into_iter().map(|(id, _)| id).collect();
+        self.images_by_ids(&ids)
+    }
+
+    /// Drops records under `root` that a completed scan didn't see and whose
+    /// file is really gone. Files skipped by depth or glob filters still
+    /// exist, so they are kept. Returns the deleted ids.
+    pub fn prune_missing(
+        &self,
+        root: &Path,
+        seen: &HashSet<String>,
+    ) -> Result<Vec<String>, String> {
+        let mut conn = self.conn();
+        let tx = conn.transaction().map_err(db_err)?;
+
+        let Some(root_id) = root_id(&tx, root)? else {
+            return Ok(Vec::new());
+        };
+
+        let stale: Vec<String> = {
+            let mut stmt = tx
+                .prepare("SELECT id, path FROM images WHERE root_id = ?1")
+                .map_err(db_err)?;
+            let rows = stmt
+                .query_map([root_id], |r| Ok((r.get(0)?, r.get(1)?)))
+                .map_err(db_err)?;
+            let rows: Vec<(String, String)> = rows.collect::<Result<_, _>>().map_err(db_err)?;
+            rows.into_iter()
+                .filter(|(_, path)| !seen.contains(path) && !Path::new(path).exists())
+                .map(|(id, _)| id)
+                .collect()
+        };
+        for id in &stale {
+            tx.execute("DELETE FROM images WHERE id = ?1", [id])
+                .map_err(db_err)?;
+        }
+
+        tx.commit().map_err(db_err)?;
+        Ok(stale)
+    }
+
+    /// Applies a batch of watcher changes under an already scanned root:
+    /// `files` are created or modified images, `removed` are paths (files or
+    /// whole folders) that no longer exist.
+    pub fn apply_changes(
+        &self,
+        root: &Path,
+        files: &[ScannedFile],
+        removed: &[String],
+    ) -> Result<AppliedChanges, String> {
+        let mut conn = self.conn();
+        let tx = conn.transaction().map_err(db_err)?;
+
+        let root_id = root_id(&tx, root)?
+            .ok_or_else(|| format!("{} has not been scanned", root.display()))?;
+
+        // Upserts go first so a rename reported as remove + create reconciles
+        // to the old record before the removal is applied
+        let seen: HashSet<&str> = files.iter().map(|f| f.path.as_str()).collect();
+        let mut upserted = Vec::with_capacity(files.len());
+        let mut deleted = Vec::new();
+        for file in files {
+            let (id, change) = upsert_file(&tx, root_id, file, &seen)?;
+            if let Some(raw_path) = &file.raw_path {
+                deleted.extend(absorb_raw(&tx, &id, raw_path)?);
+            }
+            upserted.push((id, change));
+        }
+
+        {
+            let mut stmt = tx
+                .prepare(
+                    "SELECT id FROM images WHERE path = ?1
+                        OR substr(path, 1, length(?2)) = ?2",
+                )
+                .map_err(db_err)?;
+            for path in removed {
+                let folder_prefix = format!("{}{}", path, std::path::MAIN_SEPARATOR);
+                let rows = stmt
+                    .query_map([path, &folder_prefix], |r| r.get::<_, String>(0))
+                    .map_err(db_err)?;
+                for id in rows {
+                    deleted.push(id.map_err(db_err)?);
+                }
+                tx.execute(
+                    "DELETE FROM scan_issues WHERE path = ?1
+                        OR substr(path, 1, length(?2)) = ?2",
+                    [path, &folder_prefix],
+                )
+                .map_err(db_err)?;
+            }
+        }
+        for id in &deleted {
+            tx.execute("DELETE FROM images WHERE id = ?1", [id])
+                .map_err(db_err)?;
+        }
+
+        tx.commit().map_err(db_err)?;
+        Ok(AppliedChanges { upserted, deleted })
+    }
+
+    /// Records files under `root` that are named like images but aren't,
+    /// replacing whatever was recorded for them before.
+    pub fn record_rejected(&self, root: &Path, issues: &[ScanIssue]) -> Result<(), String> {
+        if issues.is_empty() {
+            return Ok(());
+        }
+        let mut conn = self.conn();
+        let tx = conn.transaction().map_err(db_err)?;
+        let root_id = root_id(&tx, root)?
+            .ok_or_else(|| format!("{} has not been scanned", root.display()))?;
+        for issue in issues {
+            replace_issues(&tx, root_id, &issue.path, std::slice::from_ref(issue))?;
+        }
+        tx.commit().map_err(db_err)
+    }
+
+    /// Drops issues under `root` for files a completed scan no longer found,
+    /// either as images (`seen`) or as rejected files.
+    pub fn prune_issues(
+        &self,
+        root: &Path,
+        seen: &HashSet<String>,
+        rejected: &HashSet<String>,
+    ) -> Result<(), String> {
+        let mut conn = self.conn();
+        let tx = conn.transaction().map_err(db_err)?;
+        let Some(root_id) = root_id(&tx, root)? else {
+            return Ok(());
+        };
+
+        let stale: Vec<String> = {
+            let mut stmt = tx
+                .prepare("SELECT DISTINCT path FROM scan_issues WHERE root_id = ?1")
+                .map_err(db_err)?;
+            let rows = stmt
+                .query_map([root_id], |r| r.get::<_, String>(0))
+                .map_err(db_err)?;
+            let rows: Vec<String> = rows.collect::<Result<_, _>>().map_err(db_err)?;
+            rows.into_iter()
+                .filter(|path| !seen.contains(path) && !rejected.contains(path))
+                .collect()
+        };
+        for path in &stale {
+            tx.execute("DELETE FROM scan_issues WHERE path = ?1", [path])
+                .map_err(db_err)?;
+        }
+        tx.commit().map_err(db_err)
+    }
+
+    /// Recorded issues, optionally only those under `root`, in path order.
+    pub fn scan_issues(&self, root: Option<&str>) -> Result<Vec<ScanIssue>, String> {
+        let conn = self.conn();
+        let mut stmt = conn
+            .prepare(
+                "SELECT s.path, s.relative_path, s.kind, s.message FROM scan_issues s
+                 JOIN roots r ON r.id = s.root_id
+                 WHERE ?1 IS NULL OR r.path = ?1
+                 ORDER BY r.path, s.relative_path COLLATE natural_order, s.kind",
+            )
+            .map_err(db_err)?;
+        let rows = stmt
+            .query_map([root], |r| {
+                Ok((
+                    r.get::<_, String>(0)?,
+                    r.get::<_, String>(1)?,
+                    r.get::<_, String>(2)?,
+                    r.get::<_, String>(3)?,
+                ))
+            })
+            .map_err(db_err)?;
+
+        let mut issues = Vec::new();
+        for row in rows {
+            let (path, relative_path, kind, message) = row.map_err(db_err)?;
+            // Kinds written by a newer version are skipped rather than failing the list
+            if let Some(kind) = IssueKind::parse(&kind) {
+                issues.push(ScanIssue {
+                    path,
+                    relative_path,
+                    kind,
+                    message,
+                });
+            }
+        }
+        Ok(issues)
+    }
+
+    /// Scan options stored for `root` by its last scan.
+    pub fn root_options(&self, root: &Path) -> Result<Option<ScanOptions>, String> {
+        let options: Option<Option<String>> = self
+            .conn()
+            .query_row(
+                "SELECT options FROM roots WHERE path = ?1",
+                [root.to_string_lossy()],
+                |r| r.get(0),
+            )
+            .optional()
+            .map_err(db_err)?;
+
+        Ok(options.map(|json| {
+            json.and_then(|json| serde_json::from_str(&json).ok())
+                .unwrap_or_default()
+        }))
+    }
+
+    /// Every stored image, ordered by root and relative path.
+    pub fn load_library(&self) -> Result<Vec<ImageInfo>, String> {
+        self.select(&ImageQuery::default(), true)
+    }
+
+    pub fn query(&self, query: &ImageQuery) -> Result<Vec<ImageInfo>, String> {
+        self.select(query, false)
+    }
+
+    /// Images matching `query`. With `whole_library`, their tags are read
+    /// in one pass over the table rather than looked up image by image.
+    fn select(&self, query: &ImageQuery, whole_library: bool) -> Result<Vec<ImageInfo>, String> {
+        let conn = self.conn();
+
+        let mut sql = format!(
+            "SELECT {} FROM images i LEFT JOIN roots r ON r.id = i.root_id WHERE 1 = 1",
+            IMAGE_COLUMNS
+        );
+        let mut args: Vec<Box<dyn rusqlite::ToSql>> = Vec::new();
+
+        if let Some(root) = &query.root {
+            args.push(Box::new(root.clone()));
+            sql.push_str(&format!(" AND r.path = ?{}", args.len()));
+        }
+        for tag in &query.tags {
+            args.push(Box::new(tag.trim().to_lowercase()));
+            sql.push_str(&format!(
+                " AND EXISTS (SELECT 1 FROM image_tags it JOIN tags t ON t.id = it.tag_id
+                   WHERE it.image_id = i.id AND t.name = ?{})",
+                args.len()
+            ));
+        }
+        if let Some(text) = query.text.as_deref().filter(|t| !t.trim().is_empty()) {
+            args.push(Box::new(format!("%{}%", text.trim().to_lowercase())));
+            sql.push_str(&format!(
+                " AND (lower(i.name) LIKE ?{n} OR lower(i.description) LIKE ?{n}
+                   OR EXISTS (SELECT 1 FROM image_tags it JOIN tags t ON t.id = it.tag_id
+                              WHERE it.image_id = i.id AND t.name LIKE ?{n}))",
+                n = args.len()
+            ));
+        }
+
+        sql.push_str(" ORDER BY r.path, i.relative_path COLLATE natural_order");
+        sql.push_str(&format!(
+            " LIMIT {} OFFSET {}",
+            query.limit.map(i64::from).unwrap_or(-1),
+            query.offset.unwrap_or(0)
+        ));
+
+        let mut stmt = conn.prepare(&sql).map_err(db_err)?;
+        let rows = stmt
+            .query_map(rusqlite::params_from_iter(args.iter()), row_to_image)
+            .map_err(db_err)?;
+        let mut images: Vec<ImageInfo> = rows.collect::<Result<_, _>>().map_err(db_err)?;
+        drop(stmt);
+
+        if whole_library {
+            attach_all_tags(&conn, &mut images)?;
+        } else {
+            attach_tags(&conn, &mut images)?;
+        }
+        Ok(images)
+    }
+
+    pub fn images_by_ids(&self, ids: &[String]) -> Result<Vec<ImageInfo>, String> {
+        let conn = self.conn();
+        let mut stmt = conn
+            .prepare(&format!(
+                "SELECT {} FROM images i WHERE i.id = ?1",
+                IMAGE_COLUMNS
+            ))
+            .map_err(db_err)?;
+
+        let mut images = Vec::with_capacity(ids.len());
+        for id in ids {
+            if let Some(image) = stmt
+                .query_row([id], row_to_image)
+                .optional()
+                .map_err(db_err)?
+            {
+                images.push(image);
+            }
+        }
+        drop(stmt);
+
+        attach_tags(&conn, &mut images)?;
+        Ok(images)
+    }
+
+    pub fn image_id_for_path(&self, path: &str) -> Result<Option<String>, String> {
+        self.conn()
+            .query_row("SELECT id FROM images WHERE path = ?1", [path], |r| {
+                r.get(0)
+            })
+            .optional()
+            .map_err(db_err)
+    }
+
+    /// The image `path` belongs to, as its file or its RAW partner.
+    pub fn image_owning(&self, path: &str) -> Result<Option<String>, String> {
+        self.conn()
+            .query_row(
+                "SELECT id FROM images WHERE path = ?1 OR raw_path = ?1",
+                [path],
+                |r| r.get(0),
+            )
+            .optional()
+            .map_err(db_err)
+    }
+
+    /// Where an image's file is and what identifies its current contents.
+    pub fn source_file(&self, image_id: &str) -> Result<Option<SourceFile>, String> {
+        self.conn()
+            .query_row(
+                "SELECT path, content_hash, modified FROM images WHERE id = ?1",
+                [image_id],
+                |r| {
+                    Ok(SourceFile {
+                        path: r.get(0)?,
+                        content_hash: r.get(1)?,
+                        modified: r.get(2)?,
+                    })
+                },
+            )
+            .optional()
+            .map_err(db_err)
+    }
+
+    /// Replaces the tags of an image. Tags are trimmed, lowercased and deduplicated.
+    pub fn set_tags(&self, image_id: &str, tags: &[String]) -> Result<Vec<String>, String> {
+        let mut conn = self.conn();
+        let tx = conn.transaction().map_err(db_err)?;
+
+        ensure_image(&tx, image_id)?;
+        // Keywords from the file and the model's tags stay theirs if they're kept
+        let sources: HashMap<String, String> = {
+            let mut stmt = tx
+                .prepare(
+                    "SELECT t.name, it.source FROM image_tags it JOIN tags t ON t.id = it.tag_id
+                     WHERE it.image_id = ?1 AND it.source IS NOT NULL",
+                )
+                .map_err(db_err)?;
+            let rows = stmt
+                .query_map([image_id], |r| Ok((r.get(0)?, r.get(1)?)))
+                .map_err(db_err)?;
+            rows.collect::<Result<_, _>>().map_err(db_err)?
+        };
+        let stored = replace_tags(&tx, image_id, tags, |tag| {
+            sources.get(tag).map(String::as_str)
+        })?;
+
+        tx.commit().map_err(db_err)?;
+        Ok(stored)
+    }
+
+    /// Puts back tags as `set_tags` would, with `file_tags` among them
+    /// marked as the file's own keywords again and `model_tags` as the
+    /// model's.
+    pub fn restore_tags(
+        &self,
+        image_id: &str,
+        tags: &[String],
+        file_tags: &[String],
+        model_tags: &[String],
+    ) -> Result<(), String> {
+        let mut conn = self.conn();
+        let tx = conn.transaction().map_err(db_err)?;
+        ensure_image(&tx, image_id)?;
+        replace_tags(&tx, image_id, tags, |tag| {
+            tag_source(tag, file_tags, model_tags)
+        })?;
+        tx.commit().map_err(db_err)
+    }
+
+    /// Replaces the tags the model suggested for an image with `tags`,
+    /// leaving the user's and the file's alone. A tag the image already has
+    /// stays whoever's it was. Returns all of the image's tags.
+    pub fn set_model_tags(&self, image_id: &str, tags: &[String]) -> Result<Vec<String>, String> {
+        let mut conn = self.conn();
+        let tx = conn.transaction().map_err(db_err)?;
+        ensure_image(&tx, image_id)?;
+
+        tx.execute(
+            "DELETE FROM image_tags WHERE image_id = ?1 AND source = ?2",
+            params![image_id, MODEL_TAG_SOURCE],
+        )
+        .map_err(db_err)?;
+        for tag in tags {
+            let tag = tag.trim().to_lowercase();
+            if tag.is_empty() {
+                continue;
+            }
+            tx.execute("INSERT OR IGNORE INTO tags (name) VALUES (?1)", [&tag])
+                .map_err(db_err)?;
+            tx.execute(
+                "INSERT OR IGNORE INTO image_tags (image_id, tag_id, source)
+                 SELECT ?1, id, ?3 FROM tags WHERE name = ?2",
+                params![image_id, tag, MODEL_TAG_SOURCE],
+            )
+            .map_err(db_err)?;
+        }
+
+        let stored = {
+            let mut stmt = tx
+                .prepare(
+                    "SELECT t.name FROM image_tags it JOIN tags t ON t.id = it.tag_id
+                     WHERE it.image_id = ?1 ORDER BY it.rowid",
+                )
+                .map_err(db_err)?;
+            let rows = stmt.query_map([image_id], |r| r.get(0)).map_err(db_err)?;
+            rows.collect::<Result<_, _>>().map_err(db_err)?
+        };
+        tx.commit().map_err(db_err)?;
+        Ok(stored)
+    }
+
+    pub fn set_description(&self, image_id: &str, description: &str) -> Result<(), String> {
+        let conn = self.conn();
+        ensure_image(&conn, image_id)?;
+        conn.execute(
+            "UPDATE images SET description = ?2 WHERE id = ?1",
+            params![image_id, description],
+        )
+        .map_err(db_err)?;
+        Ok(())
+    }
+
+    /// What people and models have said about an image, as opposed to what
+    /// a scan can read back from the file.
+    pub fn annotations(&self, image_id: &str) -> Result<Annotations, String> {
+        let conn = self.conn();
+        let description: String = conn
+            .query_row(
+                "SELECT description FROM images WHERE id = ?1",
+                [image_id],
+                |r| r.get(0),
+            )
+            .optional()
+            .map_err(db_err)?
+            .ok_or_else(|| format!("Unknown image: {}", image_id))?;
+        let mut stmt = conn
+            .prepare(
+                "SELECT t.name, it.source IS ?2 FROM image_tags it JOIN tags t ON t.id = it.tag_id
+                 WHERE it.image_id = ?1 AND it.source IS NOT ?3 ORDER BY it.rowid",
+            )
+            .map_err(db_err)?;
+        let rows: Vec<(String, bool)> = stmt
+            .query_map(params![image_id, MODEL_TAG_SOURCE, FILE_TAG_SOURCE], |r| {
+                Ok((r.get(0)?, r.get(1)?))
+            })
+            .map_err(db_err)?
+            .collect::<Result<_, _>>()
+            .map_err(db_err)?;
+        let (model_tags, tags): (Vec<_>, Vec<_>) =
+            rows.into_iter().partition(|(_, from_model)| *from_model);
+        Ok(Annotations {
+            description,
+            tags: tags.into_iter().map(|(tag, _)| tag).collect(),
+            model_tags: model_tags.into_iter().map(|(tag, _)| tag).collect(),
+        })
+    }
+
+    /// Adds `annotations` to an image: the tags join its own, and the
+    /// description replaces an empty one.
+    pub fn annotate(&self, image_id: &str, annotations: &Annotations) -> Result<(), String> {
+        let mut conn = self.conn();
+        let tx = conn.transaction().map_err(db_err)?;
+        ensure_image(&tx, image_id)?;
+        let tags = annotations.tags.iter().map(|tag| (tag, None));
+        let model_tags = annotations
+            .model_tags
+            .iter()
+            .map(|tag| (tag, Some(MODEL_TAG_SOURCE)));
+        for (tag, source) in tags.chain(model_tags) {
+            tx.execute("INSERT OR IGNORE INTO tags (name) VALUES (?1)", [tag])
+                .map_err(db_err)?;
+            tx.execute(
+                "INSERT OR IGNORE INTO image_tags (image_id, tag_id, source)
+                 SELECT ?1, id, ?3 FROM tags WHERE name = ?2",
+                params![image_id, tag, source],
+            )
+            .map_err(db_err)?;
+        }
+        tx.execute(
+            "UPDATE images SET description = ?2 WHERE id = ?1 AND description = ''",
+            params![image_id, annotations.description],
+        )
+        .map_err(db_err)?;
+        tx.commit().map_err(db_err)
+    }
+
+    /// Points an image's record at where its files were moved, inside the
+    /// scanned folder `root`. Id, tags and everything read from the file
+    /// stay as they are.
+    pub fn relocate(
+        &self,
+        image_id: &str,
+        root: &Path,
+        path: &Path,
+        raw_path: Option<&Path>,
+    ) -> Result<(), String> {
+        let mut conn = self.conn();
+        let tx = conn.transaction().map_err(db_err)?;
+        let root_id = root_id(&tx, root)?
+            .ok_or_else(|| format!("{} has not been scanned", root.display()))?;
+        let old_path: String = tx
+            .query_row("SELECT path FROM images WHERE id = ?1", [image_id], |r| {
+                r.get(0)
+            })
+            .optional()
+            .map_err(db_err)?
+            .ok_or_else(|| format!("Unknown image: {}", image_id))?;
+
+        let new_path = path.to_string_lossy().to_string();
+        let relative = scan::relative_path(root, path);
+        tx.execute(
+            "UPDATE images SET root_id = ?2, path = ?3, relative_path = ?4, name = ?5,
+                 raw_path = ?6
+             WHERE id = ?1",
+            params![
+                image_id,
+                root_id,
+                new_path,
+                relative,
+                path.file_name()
+                    .map(|name| name.to_string_lossy().to_string())
+                    .unwrap_or_default(),
+                raw_path.map(|raw| raw.to_string_lossy().to_string())
+            ],
+        )
+        .map_err(db_err)?;
+        tx.execute(
+            "UPDATE scan_issues SET path = ?2, relative_path = ?3, root_id = ?4 WHERE path = ?1",
+            params![old_path, new_path, relative, root_id],
+        )
+        .map_err(db_err)?;
+        tx.commit().map_err(db_err)
+    }
+
+    /// Drops an image's record, for when its file went to the trash.
+    pub fn remove_image(&self, image_id: &str) -> Result<(), String> {
+        let conn = self.conn();
+        conn.execute(
+            "DELETE FROM scan_issues WHERE path = (SELECT path FROM images WHERE id = ?1)",
+            [image_id],
+        )
+        .map_err(db_err)?;
+        conn.execute("DELETE FROM images WHERE id = ?1", [image_id])
+            .map_err(db_err)?;
+        Ok(())
+    }
+
+    pub fn roots(&self) -> Result<Vec<String>, String> {
+        let conn = self.conn();
+        let mut stmt = conn
+            .prepare("SELECT path FROM roots ORDER BY path")
+            .map_err(db_err)?;
+        let rows = stmt.query_map([], |r| r.get(0)).map_err(db_err)?;
+        rows.collect::<Result<_, _>>().map_err(db_err)
+    }
+}
+
+fn migrate(conn: &mut Connection) -> Result<(), String> {
+    let version: usize = conn
+        .query_row("PRAGMA user_version", [], |r| r.get(0))
+        .map_err(db_err)?;
+
+    if version > MIGRATIONS.len() {
+        return Err(format!(
+            "Catalog schema version {} is newer than this app supports ({})",
+            version,
+            MIGRATIONS.len()
+        ));
+    }
+
+    for (index, migration) in MIGRATIONS.iter().enumerate().skip(version) {
+        let tx = conn.transaction().map_err(db_err)?;
+        tx.execute_batch(migration).map_err(db_err)?;
+        tx.pragma_update(None, "user_version", index + 1)
+            .map_err(db_err)?;
+        tx.commit().map_err(db_err)?;
+        log::info!("Applied catalog migration {}", index + 1);
+    }
+
+    Ok(())
+}
+
+fn upsert_file(
+    conn: &Connection,
+    root_id: i64,
+    file: &ScannedFile,
+    seen: &HashSet<&str>,
+) -> Result<(String, Upserted), String> {
+    let by_path: Option<(String, Option<String>, Option<String>)> = conn
+        .query_row(
+            "SELECT id, content_hash, raw_path FROM images WHERE path = ?1",
+            [&file.path],
+            |r| Ok((r.get(0)?, r.get(1)?, r.get(2)?)),
+        )
+        .optional()
+        .map_err(db_err)?;
+
+    if let Some((mut id, previous_hash, previous_raw_path)) = by_path {
+        // Records from before content hashing get re-keyed the first time they're hashed
+        if previous_hash.is_none() {
+            let hashed_id = identity::image_id(&file.content_hash);
+            if !id_taken(conn, &hashed_id)? {
+                conn.execute(
+                    "UPDATE images SET id = ?2 WHERE id = ?1",
+                    params![id, hashed_id],
+                )
+                .map_err(db_err)?;
+                id = hashed_id;
+            }
+        }
+        let change = if previous_hash.as_deref() == Some(file.content_hash.as_str())
+            && previous_raw_path == file.raw_path
+        {
+            Upserted::Unchanged
+        } else {
+            Upserted::Modified
+        };
+        update_file(conn, &id, root_id, file)?;
+        return Ok((id, change));
+    }
+
+    // Same content at a path that no longer exists: the file was renamed or moved
+    let candidates: Vec<(String, String)> = {
+        let mut stmt = conn
+            .prepare("SELECT id, path FROM images WHERE content_hash = ?1")
+            .map_err(db_err)?;
+        let rows = stmt
+            .query_map([&file.content_hash], |r| Ok((r.get(0)?, r.get(1)?)))
+            .map_err(db_err)?;
+        rows.collect::<Result<_, _>>().map_err(db_err)?
+    };
+    let moved = candidates
+        .into_iter()
+        .find(|(_, old_path)| !seen.contains(old_path.as_str()) && !Path::new(old_path).exists());
+    if let Some((id, old_path)) = moved {
+        log::info!("Reconciled {} -> {}", old_path, file.path);
+        update_file(conn, &id, root_id, file)?;
+        return Ok((id, Upserted::Moved(old_path)));
+    }
+
+    let mut id = identity::image_id(&file.content_hash);
+    if id_taken(conn, &id)? {
+        id = identity::copy_image_id(&file.content_hash, &file.path);
+    }
+    conn.execute(
+        "INSERT INTO images
+             (id, root_id, path, relative_path, name, content_hash, size, modified, created, mime,
+              raw_path)
+         VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11)",
+        params![
+            id,
+            root_id,
+            file.path,
+            file.relative_path,
+            file.name,
+            file.content_hash,
+            file.stamp.size as i64,
+            file.stamp.modified,
+            file.created,
+            file.format.mime(),
+            file.raw_path
+        ],
+    )
+    .map_err(db_err)?;
+    store_contents(conn, &id, root_id, file)?;
+    Ok((id, Upserted::New))
+}
+
+fn update_file(
+    conn: &Connection,
+    id: &str,
+    root_id: i64,
+    file: &ScannedFile,
+) -> Result<(), String> {
+    conn.execute(
+        "UPDATE images SET root_id = ?2, path = ?3, relative_path = ?4, name = ?5,
+             content_hash = ?6, size = ?7, modified = ?8, created = ?9, mime = ?10,
+             raw_path = ?11
+         WHERE id = ?1",
+        params![
+            id,
+            root_id,
+            file.path,
+            file.relative_path,
+            file.name,
+            file.content_hash,
+            file.stamp.size as i64,
+            file.stamp.modified,
+            file.created,
+            file.format.mime(),
+            file.raw_path
+        ],
+    )
+    .map_err(db_err)?;
+    store_contents(conn, id, root_id, file)
+}
+
+/// Stores what was read from inside the file, if it was read this time.
+fn store_contents(
+    conn: &Connection,
+    id: &str,
+    root_id: i64,
+    file: &ScannedFile,
+) -> Result<(), String> {
+    let Some(details) = &file.details else {
+        return Ok(());
+    };
+    store_details(conn, id, details)?;
+
+    let appearance = file.appearance.as_ref();
+    let palette = appearance
+        .map(|a| serde_json::to_string(&a.palette))
+        .transpose()
+        .map_err(|e| e.to_string())?;
+    // Hashes are stored as the signed integers SQLite has, bit for bit
+    conn.execute(
+        "UPDATE images SET dhash = ?2, phash = ?3, histogram = ?4, palette = ?5 WHERE id = ?1",
+        params![
+            id,
+            appearance.map(|a| a.fingerprint.dhash as i64),
+            appearance.map(|a| a.fingerprint.phash as i64),
+            appearance.map(|a| a.histogram.to_blob()),
+            palette
+        ],
+    )
+    .map_err(db_err)?;
+    replace_issues(conn, root_id, &file.path, &file.issues)
+}
+
+/// `image_tags.source` for `tag` when it is among the file's keywords or
+/// the model's tags.
+fn tag_source(tag: &str, file_tags: &[String], model_tags: &[String]) -> Option<&'static str> {
+    if file_tags.iter().any(|t| t == tag) {
+        Some(FILE_TAG_SOURCE)
+    } else if model_tags.iter().any(|t| t == tag) {
+        Some(MODEL_TAG_SOURCE)
+    } else {
+        None
+    }
+}
+
+/// Replaces the tags of an image with `tags`, trimmed, lowercased and
+/// deduplicated, each stored with the source `source_of` gives it.
+/// Returns the tags stored.
+fn replace_tags<'a>(
+    conn: &Connection,
+    image_id: &str,
+    tags: &[String],
+    source_of: impl Fn(&str) -> Option<&'a str>,
+) -> Result<Vec<String>, String> {
+    conn.execute("DELETE FROM image_tags WHERE image_id = ?1", [image_id])
+        .map_err(db_err)?;
+
+    let mut stored = Vec::new();
+    for tag in tags {
+        let tag = tag.trim().to_lowercase();
+        if tag.is_empty() || stored.contains(&tag) {
+            continue;
+        }
+        conn.execute("INSERT OR IGNORE INTO tags (name) VALUES (?1)", [&tag])
+            .map_err(db_err)?;
+        let source = source_of(&tag);
+        conn.execute(
+            "INSERT OR IGNORE INTO image_tags (image_id, tag_id, source)
+             SELECT ?1, id, ?3 FROM tags WHERE name = ?2",
+            params![image_id, tag, source],
+        )
+        .map_err(db_err)?;
+        stored.push(tag);
+    }
+    Ok(stored)
+}
+
+/// Folds the record a RAW file had of its own, from before its JPEG turned
+/// up, into the pair's record `id`: user and model tags and a description
+/// carry over, the RAW's own keywords don't. Returns the dropped id.
+fn absorb_raw(conn: &Connection, id: &str, raw_path: &str) -> Result<Option<String>, String> {
+    let raw: Option<(String, String)> = conn
+        .query_row(
+            "SELECT id, description FROM images WHERE path = ?1",
+            [raw_path],
+            |r| Ok((r.get(0)?, r.get(1)?)),
+        )
+        .optional()
+        .map_err(db_err)?;
+    let Some((raw_id, description)) = raw.filter(|(raw_id, _)| raw_id != id) else {
+        return Ok(None);
+    };
+
+    conn.execute(
+        "INSERT OR IGNORE INTO image_tags (image_id, tag_id, source)
+         SELECT ?1, tag_id, source FROM image_tags WHERE image_id = ?2 AND source IS NOT ?3",
+        params![id, raw_id, FILE_TAG_SOURCE],
+    )
+    .map_err(db_err)?;
+    conn.execute(
+        "UPDATE images SET description = ?2 WHERE id = ?1 AND description = ''",
+        params![id, description],
+    )
+    .map_err(db_err)?;
+    conn.execute("DELETE FROM images WHERE id = ?1", [&raw_id])
+        .map_err(db_err)?;
+    conn.execute("DELETE FROM scan_issues WHERE path = ?1", [raw_path])
+        .map_err(db_err)?;
+    Ok(Some(raw_id))
+}
+
+/// Stores freshly read details and re-imports the file's keywords as tags.
+/// A keyword the image already has as a user or model tag stays theirs.
+fn store_details(conn: &Connection, id: &str, details: &FileDetails) -> Result<(), String> {
+    let metadata = &details.metadata;
+    let json = serde_json::to_string(metadata).map_err(|e| e.to_string())?;
+    conn.execute(
+        "UPDATE images SET metadata = ?2, taken = ?3, width = ?4, height = ?5 WHERE id = ?1",
+        params![id, json, metadata.taken, details.width, details.height],
+    )
+    .map_err(db_err)?;
+
+    conn.execute(
+        "DELETE FROM image_tags WHERE image_id = ?1 AND source = ?2",
+        params![id, FILE_TAG_SOURCE],
+    )
+    .map_err(db_err)?;
+    for keyword in &metadata.keywords {
+        let tag = keyword.trim().to_lowercase();
+        if tag.is_empty() {
+            continue;
+        }
+        conn.execute("INSERT OR IGNORE INTO tags (name) VALUES (?1)", [&tag])
+            .map_err(db_err)?;
+        conn.execute(
+            "INSERT OR IGNORE INTO image_tags (image_id, tag_id, source)
+             SELECT ?1, id, ?3 FROM tags WHERE name = ?2",
+            params![id, tag, FILE_TAG_SOURCE],
+        )
+        .map_err(db_err)?;
+    }
+    Ok(())
+}
+
+/// Replaces what is recorded as wrong with the file at `path`.
+fn replace_issues(
+    conn: &Connection,
+    root_id: i64,
+    path: &str,
+    issues: &[ScanIssue],
+) -> Result<(), String> {
+    conn.execute("DELETE FROM scan_issues WHERE path = ?1", [path])
+        .map_err(db_err)?;
+    for issue in issues {
+        conn.execute(
+            "INSERT OR REPLACE INTO scan_issues (path, kind, root_id, relative_path, message)
+             VALUES (?1, ?2, ?3, ?4, ?5)",
+            params![
+                path,
+                issue.kind.as_str(),
+                root_id,
+                issue.relative_path,
+                issue.message
+            ],
+        )
+        .map_err(db_err)?;
+    }
+    Ok(())
+}
+
+fn root_id(conn: &Connection, root: &Path) -> Result<Option<i64>, String> {
+    conn.query_row(
+        "SELECT id FROM roots WHERE path = ?1",
+        [root.to_string_lossy()],
+        |r| r.get(0),
+    )
+    .optional()
+    .map_err(db_err)
+}
+
+fn id_taken(conn: &Connection, id: &str) -> Result<bool, String> {
+    conn.query_row(
+        "SELECT EXISTS (SELECT 1 FROM images WHERE id = ?1)",
+        [id],
+        |r| r.get(0),
+    )
+    .map_err(db_err)
+}
+
+fn ensure_image(conn: &Connection, image_id: &str) -> Result<(), String> {
+    let exists: bool = conn
+        .query_row(
+            "SELECT EXISTS (SELECT 1 FROM images WHERE id = ?1)",
+            [image_id],
+            |r| r.get(0),
+        )
+        .map_err(db_err)?;
+    if exists {
+        Ok(())
+    } else {
+        Err(format!("Unknown image: {}", image_id))
+    }
+}
+
+pub(crate) fn row_to_image(row: &rusqlite::Row) -> rusqlite::Result<ImageInfo> {
+    Ok(ImageInfo {
+        id: row.get(0)?,
+        path: row.get(1)?,
+        relative_path: row.get(2)?,
+        name: row.get(3)?,
+        tags: Vec::new(),
+        file_tags: Vec::new(),
+        model_tags: Vec::new(),
+        description: row.get(4)?,
+        width: row.get(5)?,
+        height: row.get(6)?,
+        size: row.get::<_, Option<i64>>(7)?.map(|size| size as u64),
+        created: row.get(8)?,
+        modified: row.get(9)?,
+        mime: row.get(10)?,
+        metadata: row
+            .get::<_, Option<String>>(11)?
+            .and_then(|json| serde_json::from_str(&json).ok()),
+        raw_path: row.get(12)?,
+        palette: row
+            .get::<_, Option<String>>(13)?
+            .and_then(|json| serde_json::from_str(&json).ok()),
+    })
+}
+
+/// Fills in the tags of `images`, reading only theirs.
+pub(crate) fn attach_tags(conn: &Connection, images: &mut [ImageInfo]) -> Result<(), String> {
+    for chunk in images.chunks_mut(TAG_LOOKUP_CHUNK) {
+        let placeholders = vec!["?"; chunk.len()];
+        let params = chunk.iter().map(|image| &image.id);
+        let by_image = read_tags(
+            conn,
+            &format!("WHERE it.image_id IN ({})", placeholders.join(", ")),
+            rusqlite::params_from_iter(params),
+        )?;
+        fill_tags(chunk, by_image);
+    }
+    Ok(())
+}
+
+/// Fills in the tags of `images` from the whole table, which is cheaper
+/// than looking them up when they are most of the library.
+fn attach_all_tags(conn: &Connection, images: &mut [ImageInfo]) -> Result<(), String> {
+    if images.is_empty() {
+        return Ok(());
+    }
+    let by_image = read_tags(conn, "", [])?;
+    fill_tags(images, by_image);
+    Ok(())
+}
+
+/// Tags by image in the order they were added, each with its source.
+type TagsByImage = HashMap<String, Vec<(String, Option<String>)>>;
+
+fn read_tags(
+    conn: &Connection,
+    filter: &str,
+    params: impl rusqlite::Params,
+) -> Result<TagsByImage, String> {
+    let mut stmt = conn
+        .prepare(&format!(
+            "SELECT it.image_id, t.name, it.source FROM image_tags it
+             JOIN tags t ON t.id = it.tag_id
+             {} ORDER BY it.rowid",
+            filter
+        ))
+        .map_err(db_err)?;
+    let rows = stmt
+        .query_map(params, |r| {
+            Ok((
+                r.get::<_, String>(0)?,
+                r.get::<_, String>(1)?,
+                r.get::<_, Option<String>>(2)?,
+            ))
+        })
+        .map_err(db_err)?;
+
+    let mut by_image = TagsByImage::new();
+    for row in rows {
+        let (image_id, tag, source) = row.map_err(db_err)?;
+        by_image.entry(image_id).or_default().push((tag, source));
+    }
+    Ok(by_image)
+}
+
+fn fill_tags(images: &mut [ImageInfo], mut by_image: TagsByImage) {
+    for image in images.iter_mut() {
+        if let Some(tags) = by_image.remove(&image.id) {
+            let from = |source: &str| -> Vec<String> {
+                tags.iter()
+                    .filter(|(_, s)| s.as_deref() == Some(source))
+                    .map(|(tag, _)| tag.clone())
+                    .collect()
+            };
+            image.file_tags = from(FILE_TAG_SOURCE);
+            image.model_tags = from(MODEL_TAG_SOURCE);
+            image.tags = tags.into_iter().map(|(tag, _)| tag).collect();
+        }
+    }
+}
+
+#[tauri::command]
+pub fn load_library(catalog: tauri::State<'_, Catalog>) -> Result<Vec<ImageInfo>, String> {
+    catalog.load_library()
+}
+
+#[tauri::command]
+pub fn query_images(
+    catalog: tauri::State<'_, Catalog>,
+    query: ImageQuery,
+) -> Result<Vec<ImageInfo>, String> {
+    catalog.query(&query)
+}
+
+#[tauri::command]
+pub fn set_image_tags(
+    catalog: tauri::State<'_, Catalog>,
+    image_id: String,
+    tags: Vec<String>,
+) -> Result<Vec<String>, String> {
+    let summary = format!("Edited tags of {}", journal::name_of(&catalog, &image_id)?);
+    journal::edit(&catalog, &summary, std::slice::from_ref(&image_id), || {
+        catalog.set_tags(&image_id, &tags)
+    })
+}
+
+#[tauri::command]
+pub fn set_image_description(
+    catalog: tauri::State<'_, Catalog>,
+    image_id: String,
+    description: String,
+) -> Result<(), String> {
+    let summary = format!(
+        "Edited description of {}",
+        journal::name_of(&catalog, &image_id)?
+    );
+    journal::edit(&catalog, &summary, std::slice::from_ref(&image_id), || {
+        catalog.set_description(&image_id, description.trim())
+    })
+}
+
+#[tauri::command]
+pub fn list_roots(catalog: tauri::State<'_, Catalog>) -> Result<Vec<String>, String> {
+    catalog.roots()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::formats::Format;
+
+    const ROOT: &str = "/photos";
+
+    fn file(relative_path: &str, content_hash: &str) -> ScannedFile {
+        let name = relative_path.rsplit('/').next().unwrap();
+        ScannedFile {
+            path: format!("{}/{}", ROOT, relative_path),
+            relative_path: relative_path.to_string(),
+            name: name.to_string(),
+            content_hash: content_hash.to_string(),
+            stamp: FileStamp {
+                size: 1,
+                modified: 0,
+            },
+            created: None,
+            format: Format::Jpeg,
+            appearance: None,
+            issues: Vec::new(),
+            raw_path: None,
+            details: None,
+        }
+    }
+
+    fn catalog() -> Catalog {
+        let catalog = Catalog::open_in_memory().unwrap();
+        catalog
+            .begin_scan(Path::new(ROOT), &ScanOptions::default())
+            .unwrap();
+        catalog
+    }
+
+    /// Applies one change and returns what became of each file.
+    fn apply(
+        catalog: &Catalog,
+        files: &[ScannedFile],
+        removed: &[&str],
+    ) -> Vec<(String, Upserted)> {
+        let removed: Vec<String> = removed.iter().map(|p| format!("{}/{}", ROOT, p)).collect();
+        catalog
+            .apply_changes(Path::new(ROOT), files, &removed)
+            .unwrap()
+            .upserted
+    }
+
+    fn paths(catalog: &Catalog) -> Vec<(String, String)> {
+        let mut images: Vec<(String, String)> = catalog
+            .load_library()
+            .unwrap()
+            .into_iter()
+            .map(|image| (image.id, image.relative_path))
+            .collect();
+        images.sort();
+        images
+    }
+
+    fn strings(tags: &[&str]) -> Vec<String> {
+        tags.iter().map(|t| t.to_string()).collect()
+    }
+
+    const HASH: &str = "00112233445566778899aabbccddeeff";
+    const EDITED: &str = "ffeeddccbbaa99887766554433221100";
+
+    #[test]
+    fn renames_and_moves_keep_the_record() {
+        let catalog = catalog();
+        let [(id, change)] = &apply(&catalog, &[file("a/one.jpg", HASH)], &[])[..] else {
+            panic!("one file was applied");
+        };
+        assert_eq!(*change, Upserted::New);
+        assert_eq!(*id, identity::image_id(HASH));
+        catalog.set_tags(id, &["boat".to_string()]).unwrap();
+
+        // Renamed within its folder
+        assert_eq!(
+            apply(&catalog, &[file("a/two.jpg", HASH)], &["a/one.jpg"]),
+            [(id.clone(), Upserted::Moved("/photos/a/one.jpg".to_string()))]
+        );
+        // Moved to another folder
+        assert_eq!(
+            apply(&catalog, &[file("b/two.jpg", HASH)], &["a/two.jpg"]),
+            [(id.clone(), Upserted::Moved("/photos/a/two.jpg".to_string()))]
+        );
+        assert_eq!(paths(&catalog), [(id.clone(), "b/two.jpg".to_string())]);
+        assert_eq!(catalog.load_library().unwrap()[0].tags, ["boat"]);
+    }
+
+    #[test]
+    fn edited_content_is_a_new_image_unless_it_stays_put() {
+        let catalog = catalog();
+        let id = apply(&catalog, &[file("a.jpg", HASH)], &[]).remove(0).0;
+
+        // Edited where it is: the record and its tags carry on
+        assert_eq!(
+            apply(&catalog, &[file("a.jpg", EDITED)], &[]),
+            [(id.clone(), Upserted::Modified)]
+        );
+        assert_eq!(
+            apply(&catalog, &[file("a.jpg", EDITED)], &[]),
+            [(id.clone(), Upserted::Unchanged)]
+        );
+
+        // Edited and moved: nothing ties it to the old record
+        let third = "0123456789abcdef0123456789abcdef";
+        assert_eq!(
+            apply(&catalog, &[file("b.jpg", third)], &["a.jpg"]),
+            [(identity::image_id(third), Upserted::New)]
+        );
+        assert_eq!(
+            paths(&catalog),
+            [(identity::image_id(third), "b.jpg".to_string())]
+        );
+    }
+
+    #[test]
+    fn copies_get_ids_of_their_own() {
+        let catalog = catalog();
+        let files = [file("a.jpg", HASH), file("copy/a.jpg", HASH)];
+        let applied = apply(&catalog, &files, &[]);
+        let original = identity::image_id(HASH);
+        let copy = identity::copy_image_id(HASH, "/photos/copy/a.jpg");
+        assert_eq!(
+            applied,
+            [
+                (original.clone(), Upserted::New),
+                (copy.clone(), Upserted::New)
+            ]
+        );
+
+        // Both are stable across rescans, whichever comes first
+        let files = [file("copy/a.jpg", HASH), file("a.jpg", HASH)];
+        assert_eq!(
+            apply(&catalog, &files, &[]),
+            [(copy, Upserted::Unchanged), (original, Upserted::Unchanged)]
+        );
+    }
+
+    #[test]
+    fn a_retag_keeps_the_users_and_the_files_tags() {
+        let catalog = catalog();
+        let id = apply(&catalog, &[file("a.jpg", HASH)], &[]).remove(0).0;
+        catalog
+            .restore_tags(&id, &strings(&["beach", "mum"]), &strings(&["beach"]), &[])
+            .unwrap();
+
+        let tags = catalog
+            .set_model_tags(&id, &strings(&["sea", "Sand", "beach"]))
+            .unwrap();
+        assert_eq!(tags, ["beach", "mum", "sea", "sand"]);
+        let tags = catalog.set_model_tags(&id, &strings(&["dog"])).unwrap();
+        assert_eq!(tags, ["beach", "mum", "dog"]);
+
+        let image = catalog
+            .images_by_ids(std::slice::from_ref(&id))
+            .unwrap()
+            .remove(0);
+        assert_eq!(image.file_tags, ["beach"]);
+        assert_eq!(image.model_tags, ["dog"]);
+        // Editing by hand keeps the model's tag marked as such
+        catalog
+            .set_tags(&id, &strings(&["dog", "mum", "park"]))
+            .unwrap();
+        let image = catalog
+            .images_by_ids(std::slice::from_ref(&id))
+            .unwrap()
+            .remove(0);
+        assert_eq!(image.model_tags, ["dog"]);
+        assert_eq!(catalog.set_model_tags(&id, &[]).unwrap(), ["mum", "park"]);
+    }
+}
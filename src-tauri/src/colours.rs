@@ -0,0 +1,232 @@
+use image::DynamicImage;
+use serde::{Deserialize, Serialize};
+
+/// Most colours kept per image.
+const PALETTE_SIZE: usize = 5;
+/// CIE76 distance a palette colour may be from a searched colour and still
+/// match, when the search doesn't say. Around 2 is just noticeable; 25
+/// takes in the shades people would give the same name.
+pub const DEFAULT_DISTANCE: f64 = 25.0;
+/// Share of the picture a colour needs before searches find it, so specks
+/// don't count.
+pub const MIN_SHARE: f64 = 0.05;
+
+/// Names searches accept, each standing for a typical shade.
+const NAMED: &[(&str, [u8; 3])] = &[
+    ("red", [215, 35, 35]),
+    ("orange", [245, 130, 30]),
+    ("yellow", [245, 215, 40]),
+    ("green", [60, 160, 60]),
+    ("teal", [0, 128, 128]),
+    ("cyan", [0, 200, 220]),
+    ("blue", [40, 90, 200]),
+    ("navy", [20, 30, 90]),
+    ("purple", [120, 50, 160]),
+    ("magenta", [210, 40, 160]),
+    ("pink", [240, 130, 170]),
+    ("brown", [120, 75, 40]),
+    ("beige", [225, 205, 165]),
+    ("black", [15, 15, 15]),
+    ("grey", [128, 128, 128]),
+    ("gray", [128, 128, 128]),
+    ("white", [245, 245, 245]),
+];
+
+/// One of an image's dominant colours.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct PaletteColour {
+    /// `#rrggbb`.
+    pub hex: String,
+    /// CIE L*a*b* under D65, which searches measure distance in.
+    pub lab: [f32; 3],
+    /// Fraction of the picture, from 0 to 1.
+    pub share: f32,
+}
+
+/// The dominant colours of `image`, most common first, by median cut on a
+/// downsampled copy. Each split goes at a value boundary rather than the
+/// exact middle, so flat areas of one colour stay together instead of being
+/// averaged with their neighbours.
+pub fn palette(image: &DynamicImage) -> Vec<PaletteColour> {
+    let small = image.thumbnail(64, 64).to_rgb8();
+    let total = small.pixels().len();
+    if total == 0 {
+        return Vec::new();
+    }
+
+    let mut boxes: Vec<Vec<[u8; 3]>> = vec![small.pixels().map(|p| p.0).collect()];
+    while boxes.len() < PALETTE_SIZE {
+        // Split where it matters most: wide ranges covering many pixels
+        let Some((index, channel)) = boxes
+            .iter()
+            .enumerate()
+            .map(|(index, pixels)| {
+                let (channel, range) = widest_channel(pixels);
+                (index, channel, usize::from(range) * pixels.len())
+            })
+            .filter(|&(_, _, priority)| priority > 0)
+            .max_by_key(|&(index, _, priority)| (priority, std::cmp::Reverse(index)))
+            .map(|(index, channel, _)| (index, channel))
+        else {
+            break;
+        };
+
+        let mut pixels = boxes.remove(index);
+        pixels.sort_unstable_by_key(|p| p[channel]);
+        let median = pixels[pixels.len() / 2][channel];
+        let mut split = pixels.partition_point(|p| p[channel] < median);
+        if split == 0 {
+            split = pixels.partition_point(|p| p[channel] <= median);
+        }
+        let upper = pixels.split_off(split);
+        boxes.push(pixels);
+        boxes.push(upper);
+    }
+
+    let mut colours: Vec<PaletteColour> = boxes
+        .iter()
+        .map(|pixels| {
+            let mut sums = [0u64; 3];
+            for pixel in pixels {
+                for (sum, &c) in sums.iter_mut().zip(pixel) {
+                    *sum += u64::from(c);
+                }
+            }
+            let rgb = sums.map(|sum| (sum as f64 / pixels.len() as f64).round() as u8);
+            PaletteColour {
+                hex: hex(rgb),
+                lab: to_lab(rgb).map(|v| v as f32),
+                share: pixels.len() as f32 / total as f32,
+            }
+        })
+        .collect();
+    colours.sort_by(|a, b| b.share.total_cmp(&a.share).then_with(|| a.hex.cmp(&b.hex)));
+    colours
+}
+
+/// The channel with the widest spread of values, and that spread.
+fn widest_channel(pixels: &[[u8; 3]]) -> (usize, u8) {
+    (0..3)
+        .map(|channel| {
+            let values = pixels.iter().map(|p| p[channel]);
+            let range = values.clone().max().unwrap_or(0) - values.min().unwrap_or(0);
+            (channel, range)
+        })
+        .max_by_key(|&(channel, range)| (range, std::cmp::Reverse(channel)))
+        .unwrap_or((0, 0))
+}
+
+pub fn hex(rgb: [u8; 3]) -> String {
+    format!("#{:02x}{:02x}{:02x}", rgb[0], rgb[1], rgb[2])
+}
+
+/// A colour name from the list above, or a hex code with or without `#`
+/// in six or three digits.
+pub fn parse(text: &str) -> Result<[u8; 3], String> {
+    let text = text.trim().to_lowercase();
+    if let Some((_, rgb)) = NAMED.iter().find(|(name, _)| *name == text) {
+        return Ok(*rgb);
+    }
+
+    let digits = text.strip_prefix('#').unwrap_or(&text);
+    let expanded: String = match digits.len() {
+        3 => digits.chars().flat_map(|c| [c, c]).collect(),
+        6 => digits.to_string(),
+        _ => String::new(),
+    };
+    if expanded.is_empty() || !expanded.chars().all(|c| c.is_ascii_hexdigit()) {
+        return Err(format!(
+            "Unknown colour '{}'. Use a name such as orange or a hex code such as #ff8800",
+            text
+        ));
+    }
+    let [_, r, g, b] = u32::from_str_radix(&expanded, 16)
+        .unwrap_or_default()
+        .to_be_bytes();
+    Ok([r, g, b])
+}
+
+/// sRGB to CIE L*a*b* under the D65 white point.
+pub fn to_lab(rgb: [u8; 3]) -> [f64; 3] {
+    let [r, g, b] = rgb.map(|c| {
+        let c = f64::from(c) / 255.0;
+        if c <= 0.040_45 {
+            c / 12.92
+        } else {
+            ((c + 0.055) / 1.055).powf(2.4)
+        }
+    });
+    let x = (0.412_456_4 * r + 0.357_576_1 * g + 0.180_437_5 * b) / 0.950_47;
+    let y = 0.212_672_9 * r + 0.715_152_2 * g + 0.072_175 * b;
+    let z = (0.019_333_9 * r + 0.119_192 * g + 0.950_304_1 * b) / 1.088_83;
+
+    let f = |t: f64| {
+        if t > 216.0 / 24_389.0 {
+            t.cbrt()
+        } else {
+            (24_389.0 / 27.0 * t + 16.0) / 116.0
+        }
+    };
+    let (fx, fy, fz) = (f(x), f(y), f(z));
+    [116.0 * fy - 16.0, 500.0 * (fx - fy), 200.0 * (fy - fz)]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::{Rgb, RgbImage};
+
+    fn distance(a: [f64; 3], b: [f64; 3]) -> f64 {
+        a.iter()
+            .zip(&b)
+            .map(|(x, y)| (x - y).powi(2))
+            .sum::<f64>()
+            .sqrt()
+    }
+
+    #[test]
+    fn flat_areas_become_palette_colours_by_share() {
+        // Three quarters orange, a quarter navy, and a few specks of white
+        let image = DynamicImage::ImageRgb8(RgbImage::from_fn(200, 200, |x, y| {
+            if x % 50 == 0 && y % 50 == 0 {
+                Rgb([255, 255, 255])
+            } else if y < 150 {
+                Rgb([240, 130, 20])
+            } else {
+                Rgb([20, 30, 90])
+            }
+        }));
+
+        let palette = palette(&image);
+        assert!(palette.len() <= PALETTE_SIZE);
+        assert_eq!(palette[0].hex, "#f08214");
+        assert_eq!(palette[1].hex, "#141e5a");
+        assert!((palette[0].share - 0.75).abs() < 0.02);
+        assert!((palette[1].share - 0.25).abs() < 0.02);
+        let total: f32 = palette.iter().map(|c| c.share).sum();
+        assert!((total - 1.0).abs() < 1e-4);
+
+        // Same answer every time
+        assert_eq!(super::palette(&image), palette);
+    }
+
+    #[test]
+    fn names_and_hex_codes_parse_to_lab() {
+        assert_eq!(parse("Orange"), Ok([245, 130, 30]));
+        assert_eq!(parse("#FF8800"), Ok([255, 136, 0]));
+        assert_eq!(parse("f80"), Ok([255, 136, 0]));
+        assert!(parse("#ff88").is_err());
+        assert!(parse("#gg8800").is_err());
+        assert!(parse("chartreuse-ish").is_err());
+
+        let white = to_lab([255, 255, 255]);
+        assert!(distance(white, [100.0, 0.0, 0.0]) < 0.01);
+        assert!(distance(to_lab([0, 0, 0]), [0.0, 0.0, 0.0]) < 0.01);
+        // Reference value for sRGB red
+        assert!(distance(to_lab([255, 0, 0]), [53.24, 80.09, 67.20]) < 0.1);
+        assert!(
+            distance(to_lab([255, 136, 0]), to_lab(parse("orange").unwrap())) < DEFAULT_DISTANCE
+        );
+    }
+}
@@ -0,0 +1,416 @@
+use serde::Serialize;
+use std::cmp::Reverse;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+use crate::catalog::{db_err, Catalog};
+use crate::fingerprint::Fingerprint;
+use crate::natural;
+use crate::scan::{self, ImageInfo};
+use crate::settings::SettingsStore;
+
+#[derive(Serialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub enum DuplicateKind {
+    /// Every file in the group has the same bytes.
+    Exact,
+    /// The same picture at another size, quality or format.
+    Similar,
+}
+
+#[derive(Serialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct DuplicateGroup {
+    pub kind: DuplicateKind,
+    /// Id of the copy worth keeping: the largest, then the oldest.
+    pub keeper: String,
+    /// The keeper first, then the others in natural path order.
+    pub images: Vec<ImageInfo>,
+}
+
+/// What grouping needs to know about an image.
+#[derive(Clone, Debug)]
+struct Candidate {
+    id: String,
+    path: String,
+    content_hash: Option<String>,
+    fingerprint: Option<Fingerprint>,
+    /// Pixel count, if the size is known.
+    area: Option<u64>,
+    /// Capture time, or failing that the file's creation or modification time.
+    date: Option<i64>,
+}
+
+/// A group of candidates, as indices, with its keeper first.
+#[derive(Debug, PartialEq)]
+struct Group {
+    kind: DuplicateKind,
+    members: Vec<usize>,
+}
+
+/// Groups byte-identical candidates and those whose fingerprints are at
+/// most `max_distance` apart. Near duplicates chain: if A is close to B and
+/// B to C, all three are grouped even when A and C are further apart.
+fn group(candidates: &[Candidate], max_distance: u32) -> Vec<Group> {
+    let mut sets = DisjointSets::new(candidates.len());
+
+    let mut by_hash: HashMap<&str, usize> = HashMap::new();
+    for (index, candidate) in candidates.iter().enumerate() {
+        if let Some(hash) = &candidate.content_hash {
+            let first = *by_hash.entry(hash).or_insert(index);
+            sets.union(first, index);
+        }
+    }
+
+    let mut tree = BkTree::default();
+    for (index, candidate) in candidates.iter().enumerate() {
+        let Some(fingerprint) = candidate.fingerprint else {
+            continue;
+        };
+        for other in tree.within(&fingerprint, max_distance) {
+            sets.union(other, index);
+        }
+        tree.insert(fingerprint, index);
+    }
+
+    let mut members: HashMap<usize, Vec<usize>> = HashMap::new();
+    for index in 0..candidates.len() {
+        members.entry(sets.find(index)).or_default().push(index);
+    }
+
+    let mut groups: Vec<Group> = members
+        .into_values()
+        .filter(|members| members.len() > 1)
+        .map(|mut members| {
+            members.sort_by(|&a, &b| keeper_order(&candidates[a], &candidates[b]));
+            let first = &candidates[members[0]].content_hash;
+            let exact = first.is_some()
+                && members
+                    .iter()
+                    .all(|&m| &candidates[m].content_hash == first);
+            Group {
+                kind: if exact {
+                    DuplicateKind::Exact
+                } else {
+                    DuplicateKind::Similar
+                },
+                members,
+            }
+        })
+        .collect();
+    groups.sort_by(|a, b| {
+        natural::compare(
+            &candidates[a.members[0]].path,
+            &candidates[b.members[0]].path,
+        )
+    });
+    groups
+}
+
+/// The keeper first: the most pixels, then the oldest, then by path. Unknown
+/// sizes and dates count as smallest and newest.
+fn keeper_order(a: &Candidate, b: &Candidate) -> std::cmp::Ordering {
+    let date = |c: &Candidate| c.date.map_or((1, 0), |date| (0, date));
+    Reverse(a.area)
+        .cmp(&Reverse(b.area))
+        .then_with(|| date(a).cmp(&date(b)))
+        .then_with(|| natural::compare(&a.path, &b.path))
+}
+
+/// Union-find over indices, with path halving.
+struct DisjointSets(Vec<usize>);
+
+impl DisjointSets {
+    fn new(len: usize) -> Self {
+        DisjointSets((0..len).collect())
+    }
+
+    fn find(&mut self, mut index: usize) -> usize {
+        while self.0[index] != index {
+            self.0[index] = self.0[self.0[index]];
+            index = self.0[index];
+        }
+        index
+    }
+
+    fn union(&mut self, a: usize, b: usize) {
+        let (a, b) = (self.find(a), self.find(b));
+        if a != b {
+            self.0[b] = a;
+        }
+    }
+}
+
+/// A BK-tree: each child is keyed by its distance to the parent, so by the
+/// triangle inequality a search only descends into children whose key is
+/// within `max` of the query's distance to the parent.
+#[derive(Default)]
+struct BkTree {
+    nodes: Vec<BkNode>,
+}
+
+struct BkNode {
+    fingerprint: Fingerprint,
+    item: usize,
+    children: Vec<(u32, usize)>,
+}
+
+impl BkTree {
+    fn insert(&mut self, fingerprint: Fingerprint, item: usize) {
+        let new = self.nodes.len();
+        self.nodes.push(BkNode {
+            fingerprint,
+            item,
+            children: Vec::new(),
+        });
+        if new == 0 {
+            return;
+        }
+
+        let mut at = 0;
+        loop {
+            let distance = self.nodes[at].fingerprint.distance(&fingerprint);
+            match self.nodes[at].children.iter().find(|(d, _)| *d == distance) {
+                Some(&(_, child)) => at = child,
+                None => {
+                    self.nodes[at].children.push((distance, new));
+                    return;
+                }
+            }
+        }
+    }
+
+    fn within(&self, fingerprint: &Fingerprint, max: u32) -> Vec<usize> {
+        let mut found = Vec::new();
+        let mut pending = if self.nodes.is_empty() {
+            Vec::new()
+        } else {
+            vec![0]
+        };
+        while let Some(at) = pending.pop() {
+            let node = &self.nodes[at];
+            let distance = node.fingerprint.distance(fingerprint);
+            if distance <= max {
+                found.push(node.item);
+            }
+            pending.extend(
+                node.children
+                    .iter()
+                    .filter(|(d, _)| d.abs_diff(distance) <= max)
+                    .map(|&(_, child)| child),
+            );
+        }
+        found
+    }
+}
+
+/// Every image at or below `folder`, with what grouping needs.
+fn candidates(catalog: &Catalog, folder: &Path) -> Result<Vec<Candidate>, String> {
+    let folder = folder.to_string_lossy();
+    let prefix = format!(
+        "{}{}",
+        folder.trim_end_matches(std::path::MAIN_SEPARATOR),
+        std::path::MAIN_SEPARATOR
+    );
+
+    let conn = catalog.conn();
+    let mut stmt = conn
+        .prepare(
+            "SELECT id, path, content_hash, dhash, phash, width * height,
+                 coalesce(taken, created, modified)
+             FROM images WHERE substr(path, 1, length(?1)) = ?1",
+        )
+        .map_err(db_err)?;
+    let rows = stmt
+        .query_map([prefix], |r| {
+            let dhash: Option<i64> = r.get(3)?;
+            let phash: Option<i64> = r.get(4)?;
+            Ok(Candidate {
+                id: r.get(0)?,
+                path: r.get(1)?,
+                content_hash: r.get(2)?,
+                fingerprint: dhash.zip(phash).map(|(dhash, phash)| Fingerprint {
+                    dhash: dhash as u64,
+                    phash: phash as u64,
+                }),
+                area: r.get::<_, Option<i64>>(5)?.map(|area| area as u64),
+                date: r.get(6)?,
+            })
+        })
+        .map_err(db_err)?;
+    rows.collect::<Result<_, _>>().map_err(db_err)
+}
+
+/// Duplicate groups among the images at or below `folder`.
+fn find(
+    catalog: &Catalog,
+    folder: &Path,
+    max_distance: u32,
+) -> Result<Vec<DuplicateGroup>, String> {
+    let candidates = candidates(catalog, folder)?;
+    group(&candidates, max_distance)
+        .into_iter()
+        .map(|group| {
+            let ids: Vec<String> = group
+                .members
+                .iter()
+                .map(|&m| candidates[m].id.clone())
+                .collect();
+            Ok(DuplicateGroup {
+                kind: group.kind,
+                keeper: ids[0].clone(),
+                images: catalog.images_by_ids(&ids)?,
+            })
+        })
+        .collect()
+}
+
+/// Finds duplicates among the images scanned under `root`, which may be a
+/// scanned folder or any folder inside one. `max_distance` defaults to the
+/// setting.
+#[tauri::command]
+pub fn find_duplicates(
+    catalog: tauri::State<'_, Catalog>,
+    settings: tauri::State<'_, SettingsStore>,
+    root: String,
+    max_distance: Option<u32>,
+) -> Result<Vec<DuplicateGroup>, String> {
+    let path = PathBuf::from(&root);
+    scan::check_folder(&path)?;
+
+    let max_distance = max_distance.unwrap_or_else(|| settings.get().duplicate_distance);
+    find(&catalog, &path, max_distance)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::temp_dir;
+
+    fn candidate(path: &str, hash: &str, fingerprint: u64, area: u64, date: i64) -> Candidate {
+        Candidate {
+            id: path.to_string(),
+            path: path.to_string(),
+            content_hash: Some(hash.to_string()),
+            fingerprint: Some(Fingerprint {
+                dhash: fingerprint,
+                phash: fingerprint,
+            }),
+            area: Some(area),
+            date: Some(date),
+        }
+    }
+
+    #[test]
+    fn exact_and_near_duplicates_are_grouped_with_a_keeper() {
+        let candidates = [
+            candidate("a/beach.jpg", "h1", 0b1111_0000, 4000 * 3000, 300),
+            candidate("b/beach-small.jpg", "h2", 0b1111_0001, 800 * 600, 100),
+            candidate("c/beach-copy.jpg", "h1", 0b1111_0000, 4000 * 3000, 200),
+            candidate("a/forest.jpg", "h3", u64::MAX, 4000 * 3000, 100),
+            candidate("a/forest-edit.jpg", "h4", u64::MAX << 8, 4000 * 3000, 100),
+            candidate("d/same.png", "h5", 0xAA << 32, 100, 5),
+            candidate("e/same.png", "h5", 0xAA << 32, 100, 5),
+        ];
+
+        let groups: Vec<(DuplicateKind, Vec<&str>)> = group(&candidates, 2)
+            .into_iter()
+            .map(|g| {
+                let paths = g
+                    .members
+                    .iter()
+                    .map(|&m| candidates[m].path.as_str())
+                    .collect();
+                (g.kind, paths)
+            })
+            .collect();
+        assert_eq!(
+            groups,
+            [
+                // Same size: the older copy wins
+                (
+                    DuplicateKind::Similar,
+                    vec!["c/beach-copy.jpg", "a/beach.jpg", "b/beach-small.jpg"]
+                ),
+                (DuplicateKind::Exact, vec!["d/same.png", "e/same.png"]),
+            ]
+        );
+    }
+
+    #[test]
+    fn bk_tree_finds_everything_within_range() {
+        let mut tree = BkTree::default();
+        let values: Vec<u64> = (0..200u64)
+            .map(|i| i.wrapping_mul(0x9E37_79B9_7F4A_7C15))
+            .collect();
+        for (item, &value) in values.iter().enumerate() {
+            tree.insert(
+                Fingerprint {
+                    dhash: value,
+                    phash: value,
+                },
+                item,
+            );
+        }
+
+        let query = Fingerprint {
+            dhash: values[7] ^ 0b101,
+            phash: values[7] ^ 0b101,
+        };
+        for max in [0, 2, 20, 30, 64] {
+            let mut found = tree.within(&query, max);
+            found.sort();
+            let expected: Vec<usize> = values
+                .iter()
+                .enumerate()
+                .filter(|(_, &v)| (v ^ query.dhash).count_ones() <= max)
+                .map(|(i, _)| i)
+                .collect();
+            assert_eq!(found, expected, "max {}", max);
+        }
+    }
+
+    #[test]
+    fn resized_exports_are_found_after_a_scan() {
+        use crate::scan::{scan_root, ScanOptions};
+        use image::{imageops::FilterType, DynamicImage, Rgb, RgbImage};
+
+        let root = temp_dir("duplicates");
+        std::fs::create_dir(root.join("exports")).unwrap();
+
+        // Flat gradients hash unstably; real photos have edges like these
+        let photo = DynamicImage::ImageRgb8(RgbImage::from_fn(640, 480, |x, y| {
+            if (x / 80 + y / 120) % 2 == 0 {
+                Rgb([200, (y / 4) as u8, 40])
+            } else {
+                Rgb([30, 90, (x / 4) as u8])
+            }
+        }));
+        let other = photo.fliph();
+        photo.save(root.join("photo.png")).unwrap();
+        photo
+            .resize(320, 240, FilterType::Lanczos3)
+            .save(root.join("exports/photo-small.jpg"))
+            .unwrap();
+        other.save(root.join("other.png")).unwrap();
+
+        let catalog = Catalog::open_in_memory().unwrap();
+        let options = ScanOptions {
+            recursive: true,
+            ..ScanOptions::default()
+        };
+        scan_root(&catalog, &root, &options).unwrap();
+        let groups = find(&catalog, &root, 6).unwrap();
+
+        assert_eq!(groups.len(), 1);
+        assert_eq!(groups[0].kind, DuplicateKind::Similar);
+        let names: Vec<&str> = groups[0].images.iter().map(|i| i.name.as_str()).collect();
+        assert_eq!(names, ["photo.png", "photo-small.jpg"]);
+        assert_eq!(groups[0].keeper, groups[0].images[0].id);
+
+        // Only what is inside the folder asked about
+        assert!(find(&catalog, &root.join("exports"), 6).unwrap().is_empty());
+
+        std::fs::remove_dir_all(&root).unwrap();
+    }
+}
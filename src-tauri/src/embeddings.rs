@@ -0,0 +1,607 @@
+use rusqlite::params;
+use serde::Serialize;
+use std::collections::{HashMap, HashSet};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::RwLock;
+use tauri::{AppHandle, Emitter, Manager};
+
+use crate::catalog::{db_err, Catalog};
+use crate::ollama::{AiError, Ollama};
+use crate::scan::ImageInfo;
+use crate::settings::{Settings, SettingsStore};
+
+/// Texts sent to the embedding model per request.
+const BATCH_SIZE: usize = 16;
+const DEFAULT_RESULTS: usize = 20;
+const MAX_RESULTS: usize = 200;
+
+/// Semantic search over descriptions and tags. Vectors live in the
+/// catalog's `image_embeddings` table together with the model and the text
+/// they were made from, so a changed description or a different embedding
+/// model shows up as pending work. Generation runs as one background job.
+#[derive(Default)]
+pub struct Embeddings {
+    running: AtomicBool,
+    cancel: AtomicBool,
+    /// Vectors for the current model, loaded on the first search.
+    index: RwLock<Option<VectorIndex>>,
+}
+
+/// Every stored vector for one model, scaled to unit length so a dot product
+/// is the cosine similarity. Searched brute force, which stays well under a
+/// few milliseconds for libraries of tens of thousands of images.
+struct VectorIndex {
+    model: String,
+    entries: Vec<(String, Vec<f32>)>,
+}
+
+/// An image that needs a (new) vector, and the text to embed.
+#[derive(Debug, PartialEq)]
+struct PendingText {
+    image_id: String,
+    text: String,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SemanticMatch {
+    image: ImageInfo,
+    /// Cosine similarity, higher is closer.
+    score: f32,
+}
+
+#[derive(Serialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct EmbeddingStatus {
+    running: bool,
+    model: String,
+    /// Images with an up-to-date vector for `model`.
+    embedded: usize,
+    /// Images whose vector is missing, outdated or from another model.
+    pending: usize,
+}
+
+#[derive(Serialize, Clone)]
+#[serde(rename_all = "camelCase")]
+struct ProgressEvent {
+    done: usize,
+    total: usize,
+}
+
+#[derive(Serialize, Clone, Copy)]
+#[serde(rename_all = "lowercase")]
+enum JobStatus {
+    Completed,
+    Cancelled,
+    Failed,
+}
+
+#[derive(Serialize, Clone)]
+#[serde(rename_all = "camelCase")]
+struct FinishedEvent {
+    status: JobStatus,
+    embedded: usize,
+    error: Option<AiError>,
+}
+
+impl Embeddings {
+    /// Starts embedding every image that needs it, or all of them with
+    /// `force`. Only one job runs at a time.
+    fn start(&self, app: &AppHandle, force: bool) -> Result<(), String> {
+        if self.running.swap(true, Ordering::SeqCst) {
+            return Err("Embeddings are already being generated".to_string());
+        }
+        self.cancel.store(false, Ordering::Relaxed);
+
+        let app = app.clone();
+        tauri::async_runtime::spawn(async move {
+            let mut embedded = 0;
+            let result = run(&app, force, &mut embedded).await;
+            app.state::<Embeddings>()
+                .running
+                .store(false, Ordering::SeqCst);
+
+            let (status, error) = match result {
+                Ok(true) => (JobStatus::Completed, None),
+                Ok(false) => (JobStatus::Cancelled, None),
+                Err(e) => {
+                    log::warn!("Embedding stopped: {}", e);
+                    (JobStatus::Failed, Some(e))
+                }
+            };
+            let _ = app.emit(
+                "embedding://finished",
+                FinishedEvent {
+                    status,
+                    embedded,
+                    error,
+                },
+            );
+        });
+        Ok(())
+    }
+
+    fn invalidate(&self) {
+        *self.index.write().unwrap_or_else(|e| e.into_inner()) = None;
+    }
+
+    /// The `k` stored images closest to the unit-length `query`.
+    fn nearest(
+        &self,
+        catalog: &Catalog,
+        model: &str,
+        query: &[f32],
+        k: usize,
+    ) -> Result<Vec<(String, f32)>, String> {
+        {
+            let index = self.index.read().unwrap_or_else(|e| e.into_inner());
+            if let Some(index) = index.as_ref().filter(|i| i.model == model) {
+                return Ok(index.nearest(query, k));
+            }
+        }
+
+        let loaded = load_index(catalog, model)?;
+        let results = loaded.nearest(query, k);
+        *self.index.write().unwrap_or_else(|e| e.into_inner()) = Some(loaded);
+        Ok(results)
+    }
+}
+
+impl VectorIndex {
+    fn nearest(&self, query: &[f32], k: usize) -> Vec<(String, f32)> {
+        let mut scored: Vec<(&str, f32)> = self
+            .entries
+            .iter()
+            // Vectors from a model with another dimension can't be compared
+            .filter(|(_, vector)| vector.len() == query.len())
+            .map(|(id, vector)| (id.as_str(), dot(vector, query)))
+            .collect();
+
+        let by_score = |a: &(&str, f32), b: &(&str, f32)| b.1.total_cmp(&a.1);
+        if k < scored.len() {
+            scored.select_nth_unstable_by(k, by_score);
+            scored.truncate(k);
+        }
+        scored.sort_by(by_score);
+
+        scored
+            .into_iter()
+            .map(|(id, score)| (id.to_string(), score))
+            .collect()
+    }
+}
+
+/// Returns `Ok(true)` when every pending image was embedded and `Ok(false)`
+/// when cancelled. `embedded` counts the images stored either way.
+async fn run(app: &AppHandle, force: bool, embedded: &mut usize) -> Result<bool, AiError> {
+    let catalog = app.state::<Catalog>();
+    let ollama = app.state::<Ollama>();
+    let embeddings = app.state::<Embeddings>();
+    let settings = app.state::<SettingsStore>().get();
+
+    drop_textless(&catalog)?;
+    let pending = pending(&catalog, &settings.embedding_model, force)?;
+    let total = pending.len();
+    let _ = app.emit("embedding://progress", ProgressEvent { done: 0, total });
+
+    for batch in pending.chunks(BATCH_SIZE) {
+        if embeddings.cancel.load(Ordering::Relaxed) {
+            return Ok(false);
+        }
+
+        let result = embed_batch(&catalog, &ollama, &settings, batch).await;
+        embeddings.invalidate();
+        result?;
+
+        *embedded += batch.len();
+        let _ = app.emit(
+            "embedding://progress",
+            ProgressEvent {
+                done: *embedded,
+                total,
+            },
+        );
+    }
+    Ok(true)
+}
+
+async fn embed_batch(
+    catalog: &Catalog,
+    ollama: &Ollama,
+    settings: &Settings,
+    batch: &[PendingText],
+) -> Result<(), AiError> {
+    let texts: Vec<String> = batch.iter().map(|p| p.text.clone()).collect();
+    let vectors = ollama.embed(settings, &texts).await?;
+    Ok(store(catalog, &settings.embedding_model, batch, &vectors)?)
+}
+
+/// What gets embedded for an image: its tags and description. File names are
+/// left out; `IMG_4032` says nothing about what is in the picture.
+fn embedding_text(image: &ImageInfo) -> String {
+    let mut parts = Vec::new();
+    if !image.tags.is_empty() {
+        parts.push(format!("Tags: {}.", image.tags.join(", ")));
+    }
+    let description = image.description.trim();
+    if !description.is_empty() {
+        parts.push(description.to_string());
+    }
+    parts.join(" ")
+}
+
+/// Images whose stored vector is missing, was made from different text or
+/// by a different model. Images with no tags and no description are skipped.
+fn pending(catalog: &Catalog, model: &str, force: bool) -> Result<Vec<PendingText>, String> {
+    let stored: HashMap<String, (String, String)> = {
+        let conn = catalog.conn();
+        let mut stmt = conn
+            .prepare("SELECT image_id, model, source FROM image_embeddings")
+            .map_err(db_err)?;
+        let rows = stmt
+            .query_map([], |r| Ok((r.get(0)?, (r.get(1)?, r.get(2)?))))
+            .map_err(db_err)?;
+        rows.collect::<Result<_, _>>().map_err(db_err)?
+    };
+
+    let mut pending = Vec::new();
+    for image in catalog.load_library()? {
+        let text = embedding_text(&image);
+        if text.is_empty() {
+            continue;
+        }
+        let current = stored
+            .get(&image.id)
+            .is_some_and(|(m, source)| m == model && *source == text);
+        if force || !current {
+            pending.push(PendingText {
+                image_id: image.id,
+                text,
+            });
+        }
+    }
+    Ok(pending)
+}
+
+/// Removes vectors of images that no longer have anything to embed, so
+/// cleared descriptions and tags stop matching.
+fn drop_textless(catalog: &Catalog) -> Result<(), String> {
+    catalog
+        .conn()
+        .execute(
+            "DELETE FROM image_embeddings WHERE image_id IN (
+                SELECT id FROM images i WHERE trim(i.description) = ''
+                AND NOT EXISTS (SELECT 1 FROM image_tags it WHERE it.image_id = i.id))",
+            [],
+        )
+        .map_err(db_err)?;
+    Ok(())
+}
+
+fn store(
+    catalog: &Catalog,
+    model: &str,
+    batch: &[PendingText],
+    vectors: &[Vec<f32>],
+) -> Result<(), String> {
+    let mut conn = catalog.conn();
+    let tx = conn.transaction().map_err(db_err)?;
+    for (pending, vector) in batch.iter().zip(vectors) {
+        // A zero vector matches nothing; leave the image pending instead
+        let Some(vector) = normalized(vector) else {
+            continue;
+        };
+        tx.execute(
+            "INSERT INTO image_embeddings (image_id, model, source, vector)
+             SELECT id, ?2, ?3, ?4 FROM images WHERE id = ?1
+             ON CONFLICT(image_id) DO UPDATE SET
+                 model = excluded.model, source = excluded.source, vector = excluded.vector",
+            params![pending.image_id, model, pending.text, to_blob(&vector)],
+        )
+        .map_err(db_err)?;
+    }
+    tx.commit().map_err(db_err)
+}
+
+fn load_index(catalog: &Catalog, model: &str) -> Result<VectorIndex, String> {
+    let conn = catalog.conn();
+    let mut stmt = conn
+        .prepare("SELECT image_id, vector FROM image_embeddings WHERE model = ?1")
+        .map_err(db_err)?;
+    let rows = stmt
+        .query_map([model], |r| {
+            Ok((r.get::<_, String>(0)?, from_blob(&r.get::<_, Vec<u8>>(1)?)))
+        })
+        .map_err(db_err)?;
+
+    Ok(VectorIndex {
+        model: model.to_string(),
+        entries: rows.collect::<Result<_, _>>().map_err(db_err)?,
+    })
+}
+
+fn status(
+    catalog: &Catalog,
+    embeddings: &Embeddings,
+    model: &str,
+) -> Result<EmbeddingStatus, String> {
+    let pending = pending(catalog, model, false)?;
+    let stale: HashSet<&str> = pending.iter().map(|p| p.image_id.as_str()).collect();
+
+    let conn = catalog.conn();
+    let mut stmt = conn
+        .prepare("SELECT image_id FROM image_embeddings WHERE model = ?1")
+        .map_err(db_err)?;
+    let rows = stmt
+        .query_map([model], |r| r.get::<_, String>(0))
+        .map_err(db_err)?;
+    let mut embedded = 0;
+    for id in rows {
+        if !stale.contains(id.map_err(db_err)?.as_str()) {
+            embedded += 1;
+        }
+    }
+
+    Ok(EmbeddingStatus {
+        running: embeddings.running.load(Ordering::SeqCst),
+        model: model.to_string(),
+        embedded,
+        pending: pending.len(),
+    })
+}
+
+/// Embeds `query` and returns the closest images, best first.
+async fn search(
+    catalog: &Catalog,
+    ollama: &Ollama,
+    embeddings: &Embeddings,
+    settings: &Settings,
+    query: &str,
+    k: usize,
+) -> Result<Vec<SemanticMatch>, AiError> {
+    let query = query.trim();
+    if query.is_empty() {
+        return Ok(Vec::new());
+    }
+
+    let vectors = ollama.embed(settings, &[query.to_string()]).await?;
+    let vector = vectors
+        .first()
+        .and_then(|v| normalized(v))
+        .ok_or_else(|| AiError::InvalidResponse("the query embedding is empty".to_string()))?;
+
+    let nearest = embeddings.nearest(
+        catalog,
+        &settings.embedding_model,
+        &vector,
+        k.clamp(1, MAX_RESULTS),
+    )?;
+    let ids: Vec<String> = nearest.iter().map(|(id, _)| id.clone()).collect();
+    let scores: HashMap<String, f32> = nearest.into_iter().collect();
+
+    // images_by_ids keeps the order of `ids` and skips deleted images
+    Ok(catalog
+        .images_by_ids(&ids)?
+        .into_iter()
+        .map(|image| SemanticMatch {
+            score: scores[&image.id],
+            image,
+        })
+        .collect())
+}
+
+fn normalized(vector: &[f32]) -> Option<Vec<f32>> {
+    let norm = dot(vector, vector).sqrt();
+    (norm > 0.0 && norm.is_finite()).then(|| vector.iter().map(|x| x / norm).collect())
+}
+
+fn dot(a: &[f32], b: &[f32]) -> f32 {
+    a.iter().zip(b).map(|(x, y)| x * y).sum()
+}
+
+fn to_blob(vector: &[f32]) -> Vec<u8> {
+    vector.iter().flat_map(|x| x.to_le_bytes()).collect()
+}
+
+fn from_blob(blob: &[u8]) -> Vec<f32> {
+    blob.chunks_exact(4)
+        .map(|b| f32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+        .collect()
+}
+
+#[tauri::command]
+pub async fn semantic_search(
+    catalog: tauri::State<'_, Catalog>,
+    ollama: tauri::State<'_, Ollama>,
+    embeddings: tauri::State<'_, Embeddings>,
+    settings: tauri::State<'_, SettingsStore>,
+    query: String,
+    k: Option<usize>,
+) -> Result<Vec<SemanticMatch>, AiError> {
+    let settings = settings.get();
+    search(
+        &catalog,
+        &ollama,
+        &embeddings,
+        &settings,
+        &query,
+        k.unwrap_or(DEFAULT_RESULTS),
+    )
+    .await
+}
+
+/// Starts generating embeddings in the background. Progress arrives as
+/// `embedding://progress` events and the outcome as `embedding://finished`.
+/// Run it again after changing the embedding model; `force` re-embeds
+/// images whose vectors look current.
+#[tauri::command]
+pub fn build_embeddings(
+    app: AppHandle,
+    embeddings: tauri::State<'_, Embeddings>,
+    force: Option<bool>,
+) -> Result<(), String> {
+    embeddings.start(&app, force.unwrap_or(false))
+}
+
+/// Stops the running job after the current batch.
+#[tauri::command]
+pub fn cancel_embeddings(embeddings: tauri::State<'_, Embeddings>) {
+    embeddings.cancel.store(true, Ordering::Relaxed);
+}
+
+#[tauri::command]
+pub fn embedding_status(
+    catalog: tauri::State<'_, Catalog>,
+    embeddings: tauri::State<'_, Embeddings>,
+    settings: tauri::State<'_, SettingsStore>,
+) -> Result<EmbeddingStatus, String> {
+    status(&catalog, &embeddings, &settings.get().embedding_model)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{add_image, ImageRow};
+    use std::time::Duration;
+
+    fn catalog() -> Catalog {
+        let catalog = Catalog::open_in_memory().unwrap();
+        for (id, description) in [
+            ("beach", "Children playing in the waves"),
+            ("city", "A busy street at night"),
+            ("blank", ""),
+        ] {
+            add_image(
+                &catalog,
+                ImageRow {
+                    id,
+                    relative_path: id,
+                    description,
+                    ..ImageRow::default()
+                },
+            );
+        }
+        catalog
+            .set_tags("beach", &["beach".to_string(), "kids".to_string()])
+            .unwrap();
+        catalog
+    }
+
+    fn pending_ids(catalog: &Catalog, model: &str) -> Vec<String> {
+        pending(catalog, model, false)
+            .unwrap()
+            .into_iter()
+            .map(|p| p.image_id)
+            .collect()
+    }
+
+    #[test]
+    fn text_combines_tags_and_description() {
+        let catalog = catalog();
+        let pending = pending(&catalog, "m", false).unwrap();
+        assert_eq!(
+            pending[0],
+            PendingText {
+                image_id: "beach".to_string(),
+                text: "Tags: beach, kids. Children playing in the waves".to_string(),
+            }
+        );
+        assert_eq!(pending[1].text, "A busy street at night");
+    }
+
+    #[test]
+    fn changed_text_and_other_models_are_pending() {
+        let catalog = catalog();
+        let all = pending(&catalog, "m", false).unwrap();
+        store(&catalog, "m", &all, &[vec![1.0, 0.0], vec![0.0, 1.0]]).unwrap();
+        assert!(pending_ids(&catalog, "m").is_empty());
+        assert_eq!(pending_ids(&catalog, "other"), ["beach", "city"]);
+
+        catalog.set_description("city", "A quiet street").unwrap();
+        assert_eq!(pending_ids(&catalog, "m"), ["city"]);
+        let status = status(&catalog, &Embeddings::default(), "m").unwrap();
+        assert_eq!((status.embedded, status.pending), (1, 1));
+        assert_eq!(pending(&catalog, "m", true).unwrap().len(), 2);
+
+        catalog.set_tags("beach", &[]).unwrap();
+        catalog.set_description("beach", "").unwrap();
+        drop_textless(&catalog).unwrap();
+        let embeddings = Embeddings::default();
+        let nearest = embeddings.nearest(&catalog, "m", &[1.0, 0.0], 5).unwrap();
+        assert_eq!(nearest.len(), 1);
+        assert_eq!(nearest[0].0, "city");
+    }
+
+    #[test]
+    fn nearest_ranks_by_cosine_similarity() {
+        let index = VectorIndex {
+            model: "m".to_string(),
+            entries: vec![
+                ("a".to_string(), normalized(&[1.0, 0.0]).unwrap()),
+                ("b".to_string(), normalized(&[1.0, 1.0]).unwrap()),
+                ("c".to_string(), normalized(&[0.0, 1.0]).unwrap()),
+                ("other-model".to_string(), vec![1.0, 0.0, 0.0]),
+            ],
+        };
+        let query = normalized(&[0.2, 1.0]).unwrap();
+
+        let ids: Vec<String> = index.nearest(&query, 2).into_iter().map(|r| r.0).collect();
+        assert_eq!(ids, ["c", "b"]);
+        assert_eq!(index.nearest(&query, 10).len(), 3);
+        assert!(normalized(&[0.0, 0.0]).is_none());
+        assert_eq!(from_blob(&to_blob(&[1.5, -2.0])), [1.5, -2.0]);
+    }
+
+    #[tokio::test]
+    async fn search_embeds_the_query_and_returns_images() {
+        let catalog = catalog();
+        let mut server = mockito::Server::new_async().await;
+        let settings = Settings {
+            backend_url: server.url(),
+            embedding_model: "m".to_string(),
+            ..Settings::default()
+        };
+        let ollama = Ollama::with_backoff(Duration::from_millis(1));
+        let embeddings = Embeddings::default();
+
+        let documents = server
+            .mock("POST", "/api/embed")
+            .match_body(mockito::Matcher::PartialJsonString(
+                r#"{"input": ["Tags: beach, kids. Children playing in the waves", "A busy street at night"]}"#
+                    .to_string(),
+            ))
+            .with_body(r#"{"embeddings": [[0.9, 0.1], [0.1, 0.9]]}"#)
+            .create_async()
+            .await;
+        let batch = pending(&catalog, "m", false).unwrap();
+        embed_batch(&catalog, &ollama, &settings, &batch)
+            .await
+            .unwrap();
+        documents.assert_async().await;
+
+        let query = server
+            .mock("POST", "/api/embed")
+            .match_body(mockito::Matcher::PartialJsonString(
+                r#"{"input": ["kids playing near water"]}"#.to_string(),
+            ))
+            .with_body(r#"{"embeddings": [[1.0, 0.0]]}"#)
+            .create_async()
+            .await;
+        let results = search(
+            &catalog,
+            &ollama,
+            &embeddings,
+            &settings,
+            "kids playing near water",
+            1,
+        )
+        .await
+        .unwrap();
+        query.assert_async().await;
+
+        assert_eq!(results.len(), 1);
+        assert_eq!(results[0].image.id, "beach");
+        assert_eq!(results[0].image.tags, ["beach", "kids"]);
+        assert!(results[0].score > 0.9);
+    }
+}
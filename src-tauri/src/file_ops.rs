@@ -0,0 +1,1192 @@
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::catalog::{Annotations, Catalog};
+use crate::journal::{self, Relocation, Step, TrashedFile};
+use crate::scan::{self, Filters, ImageInfo, Inspection, Siblings};
+use crate::trash::Trash;
+
+/// What to do when a file is already where an image is going.
+#[derive(Deserialize, Default, Clone, Copy, Debug, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub enum Collision {
+    /// Leave the image where it is.
+    Skip,
+    /// Send whatever is in the way to the trash.
+    Overwrite,
+    /// Add " (2)", " (3)" and so on to the name until it is free.
+    #[default]
+    Suffix,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Rename {
+    image_id: String,
+    /// New file name. Without an extension the image keeps its own.
+    name: String,
+}
+
+#[derive(Serialize, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct FileOpReport {
+    /// Records of the images in their new place, or of the new copies.
+    images: Vec<ImageInfo>,
+    /// Ids of images whose files went to the trash, including any overwritten.
+    removed: Vec<String>,
+    /// Images left alone because their new name was taken.
+    skipped: Vec<FileOpProblem>,
+    failed: Vec<FileOpProblem>,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct FileOpProblem {
+    image_id: String,
+    message: String,
+}
+
+/// A scanned folder that files are going into, with its filters so they
+/// don't end up somewhere the next scan would leave out.
+struct Destination {
+    root: PathBuf,
+    filters: Filters,
+}
+
+impl Destination {
+    fn containing(catalog: &Catalog, folder: &Path) -> Result<Self, String> {
+        let root = root_containing(catalog, folder)?;
+        let options = catalog.root_options(&root)?.unwrap_or_default();
+        Ok(Destination {
+            filters: Filters::new(&options)?,
+            root,
+        })
+    }
+
+    fn check(&self, path: &Path) -> Result<(), String> {
+        if self.filters.accepts_file(&self.root, path) {
+            Ok(())
+        } else {
+            Err(format!(
+                "Scans of {} leave out {}",
+                self.root.display(),
+                scan::relative_path(&self.root, path)
+            ))
+        }
+    }
+}
+
+/// One operation over a batch of images. Each image succeeds or fails on
+/// its own; the steps taken are journaled together at the end.
+struct Batch<'a> {
+    catalog: &'a Catalog,
+    trash: &'a Trash,
+    collision: Collision,
+    steps: Vec<Step>,
+    /// Images moved or copied, for the report.
+    placed: Vec<String>,
+    report: FileOpReport,
+}
+
+impl<'a> Batch<'a> {
+    fn new(catalog: &'a Catalog, trash: &'a Trash, collision: Collision) -> Self {
+        Batch {
+            catalog,
+            trash,
+            collision,
+            steps: Vec::new(),
+            placed: Vec::new(),
+            report: FileOpReport::default(),
+        }
+    }
+
+    fn image(&self, image_id: &str) -> Result<ImageInfo, String> {
+        self.catalog
+            .images_by_ids(&[image_id.to_string()])?
+            .pop()
+            .ok_or_else(|| format!("Unknown image: {}", image_id))
+    }
+
+    /// Runs `apply` for each image, noting failures against it.
+    fn each(
+        &mut self,
+        image_ids: impl IntoIterator<Item = String>,
+        mut apply: impl FnMut(&mut Self, &str) -> Result<(), String>,
+    ) {
+        for image_id in image_ids {
+            if let Err(message) = apply(self, &image_id) {
+                self.report.failed.push(FileOpProblem { image_id, message });
+            }
+        }
+    }
+
+    /// Journals what was done as one operation and reports it.
+    fn finish(mut self, summary: &str) -> Result<FileOpReport, String> {
+        journal::record(self.catalog, summary, &self.steps)?;
+        self.report.images = self.catalog.images_by_ids(&self.placed)?;
+        Ok(self.report)
+    }
+
+    /// Where `files` go when the first goes to `target` in `destination`,
+    /// after dealing with anything in the way. `None` when the image is to
+    /// be skipped.
+    fn place(
+        &mut self,
+        image_id: &str,
+        files: &[PathBuf],
+        target: &Path,
+        destination: &Destination,
+    ) -> Result<Option<Vec<PathBuf>>, String> {
+        let in_way = |targets: &[PathBuf]| -> Vec<PathBuf> {
+            targets
+                .iter()
+                .zip(files)
+                .filter(|(to, from)| to != from && fs::symlink_metadata(to).is_ok())
+                .map(|(to, _)| to.clone())
+                .collect()
+        };
+
+        let mut targets = targets(files, target);
+        let blocking = in_way(&targets);
+        if !blocking.is_empty() {
+            match self.collision {
+                Collision::Skip => {
+                    self.report.skipped.push(FileOpProblem {
+                        image_id: image_id.to_string(),
+                        message: format!("{} already exists", blocking[0].display()),
+                    });
+                    return Ok(None);
+                }
+                Collision::Overwrite => {}
+                Collision::Suffix => {
+                    let mut n = 2;
+                    loop {
+                        targets = self::targets(files, &with_suffix(target, n));
+                        if in_way(&targets).is_empty() {
+                            break;
+                        }
+                        n += 1;
+                    }
+                }
+            }
+        }
+
+        // Nothing is trashed until every target is known to be usable
+        distinct(&targets)?;
+        for (to, from) in targets.iter().zip(files) {
+            if to != from {
+                destination.check(to)?;
+            }
+        }
+        if self.collision == Collision::Overwrite {
+            for path in in_way(&targets) {
+                self.trash_in_way(&path)?;
+            }
+        }
+        Ok(Some(targets))
+    }
+
+    /// Trashes a file about to be overwritten: the whole image, RAW
+    /// partner included, if it is one of the library's.
+    fn trash_in_way(&mut self, path: &Path) -> Result<(), String> {
+        match self.catalog.image_owning(&path.to_string_lossy())? {
+            Some(image_id) => self.trash_image(&image_id),
+            None => {
+                let trashed = self.trash.put(path)?;
+                self.steps.push(Step::Trash {
+                    image_id: None,
+                    annotations: Default::default(),
+                    files: vec![TrashedFile {
+                        path: path.to_path_buf(),
+                        trashed,
+                    }],
+                });
+                Ok(())
+            }
+        }
+    }
+
+    fn trash_image(&mut self, image_id: &str) -> Result<(), String> {
+        let image = self.image(image_id)?;
+        let annotations = self.catalog.annotations(image_id)?;
+
+        let mut files = Vec::new();
+        let mut result = Ok(());
+        for path in files_of(&image) {
+            if fs::symlink_metadata(&path).is_err() {
+                continue;
+            }
+            match self.trash.put(&path) {
+                Ok(trashed) => files.push(TrashedFile { path, trashed }),
+                Err(e) => {
+                    result = Err(e);
+                    break;
+                }
+            }
+        }
+
+        // Once the image's own file is gone, so is its record
+        let primary_gone = fs::symlink_metadata(&image.path).is_err();
+        if primary_gone {
+            self.catalog.remove_image(image_id)?;
+            self.report.removed.push(image_id.to_string());
+        }
+        if !files.is_empty() {
+            self.steps.push(Step::Trash {
+                image_id: primary_gone.then(|| image_id.to_string()),
+                annotations,
+                files,
+            });
+        }
+        result
+    }
+
+    fn move_image(
+        &mut self,
+        image_id: &str,
+        destination: &Destination,
+        target: &Path,
+    ) -> Result<(), String> {
+        let image = self.image(image_id)?;
+        let files = files_of(&image);
+        let Some(targets) = self.place(image_id, &files, target, destination)? else {
+            return Ok(());
+        };
+        if targets == files {
+            return Ok(());
+        }
+
+        let moved = move_all(&files, &targets)?;
+        self.catalog.relocate(
+            image_id,
+            &destination.root,
+            &targets[0],
+            targets.get(1).map(PathBuf::as_path),
+        )?;
+        self.steps.push(Step::Move {
+            image_id: image_id.to_string(),
+            files: moved,
+        });
+        self.placed.push(image_id.to_string());
+        Ok(())
+    }
+
+    fn rename_image(&mut self, image_id: &str, name: &str) -> Result<(), String> {
+        let image = self.image(image_id)?;
+        let name = checked_name(name)?;
+        let current = Path::new(&image.path);
+        let name = match (Path::new(name).extension(), current.extension()) {
+            (None, Some(extension)) => format!("{}.{}", name, extension.to_string_lossy()),
+            _ => name.to_string(),
+        };
+        let folder = current.parent().unwrap_or(Path::new(""));
+        let destination = Destination::containing(self.catalog, folder)?;
+        self.move_image(image_id, &destination, &folder.join(name))
+    }
+
+    fn copy_image(
+        &mut self,
+        image_id: &str,
+        destination: &Destination,
+        target: &Path,
+    ) -> Result<(), String> {
+        let image = self.image(image_id)?;
+        let files = files_of(&image);
+        let Some(targets) = self.place(image_id, &files, target, destination)? else {
+            return Ok(());
+        };
+
+        let mut copied: Vec<PathBuf> = Vec::new();
+        for (from, to) in files.iter().zip(&targets) {
+            if let Err(e) = fs::copy(from, to) {
+                for done in &copied {
+                    let _ = fs::remove_file(done);
+                }
+                return Err(format!("Failed to copy {}: {}", from.display(), e));
+            }
+            copied.push(to.clone());
+        }
+
+        let annotations = self.catalog.annotations(image_id)?;
+        let copy_id = self.add_to_library(&destination.root, &targets[0], &annotations);
+        let copy_id = match copy_id {
+            Ok(copy_id) => copy_id,
+            Err(e) => {
+                for done in &copied {
+                    let _ = fs::remove_file(done);
+                }
+                return Err(e);
+            }
+        };
+
+        self.steps.push(Step::Copy {
+            image_id: copy_id.clone(),
+            files: copied,
+        });
+        self.placed.push(copy_id);
+        Ok(())
+    }
+
+    /// Gives a file new to the catalog a record of its own, as a scan
+    /// would, with `annotations` added. Returns its id.
+    fn add_to_library(
+        &self,
+        root: &Path,
+        path: &Path,
+        annotations: &Annotations,
+    ) -> Result<String, String> {
+        let options = self.catalog.root_options(root)?.unwrap_or_default();
+        let mut siblings = Siblings::new(root, Filters::new(&options)?);
+        let read = scan::inspect_file(root, path, &HashMap::new(), &mut siblings)
+            .map_err(|e| format!("Failed to read {}: {}", path.display(), e))?;
+        let Inspection::Image(file) = read else {
+            return Err(format!("{} isn't readable as an image", path.display()));
+        };
+        let applied = self.catalog.apply_changes(root, &[*file], &[])?;
+        let Some((image_id, _)) = applied.upserted.into_iter().next() else {
+            return Err(format!("{} wasn't added to the library", path.display()));
+        };
+        self.catalog.annotate(&image_id, annotations)?;
+        Ok(image_id)
+    }
+
+    /// Moves an image's files back to where they were.
+    fn move_back(&mut self, image_id: &str, files: &[Relocation]) -> Result<(), String> {
+        self.image(image_id)?;
+        let (current, previous): (Vec<PathBuf>, Vec<PathBuf>) = files
+            .iter()
+            .map(|file| (file.to.clone(), file.from.clone()))
+            .unzip();
+        for (now, before) in current.iter().zip(&previous) {
+            if fs::symlink_metadata(now).is_err() {
+                return Err(format!("{} is no longer there", now.display()));
+            }
+            if fs::symlink_metadata(before).is_ok() {
+                return Err(format!("{} is taken by another file now", before.display()));
+            }
+        }
+        let Some(primary) = previous.first() else {
+            return Ok(());
+        };
+        let root = root_containing(self.catalog, primary.parent().unwrap_or(Path::new("")))?;
+
+        let moved = move_all(&current, &previous)?;
+        self.catalog.relocate(
+            image_id,
+            &root,
+            primary,
+            previous.get(1).map(PathBuf::as_path),
+        )?;
+        self.steps.push(Step::Move {
+            image_id: image_id.to_string(),
+            files: moved,
+        });
+        Ok(())
+    }
+
+    /// Sends files that were added back to the trash: the image they make
+    /// up if it's still in the library, otherwise the files themselves.
+    fn take_back(&mut self, image_id: Option<&str>, files: &[PathBuf]) -> Result<(), String> {
+        if let Some(image_id) = image_id {
+            if self.image(image_id).is_ok() {
+                return self.trash_image(image_id);
+            }
+        }
+
+        let mut trashed = Vec::new();
+        let mut result = Ok(());
+        for path in files {
+            if fs::symlink_metadata(path).is_err() {
+                continue;
+            }
+            match self.trash.put(path) {
+                Ok(file) => trashed.push(TrashedFile {
+                    path: path.clone(),
+                    trashed: file,
+                }),
+                Err(e) => {
+                    result = Err(e);
+                    break;
+                }
+            }
+        }
+        if !trashed.is_empty() {
+            self.steps.push(Step::Trash {
+                image_id: None,
+                annotations: Annotations::default(),
+                files: trashed,
+            });
+        }
+        result
+    }
+
+    /// Brings trashed files back, and the image they made up with what was
+    /// said about it.
+    fn restore(
+        &mut self,
+        image: bool,
+        annotations: &Annotations,
+        files: &[TrashedFile],
+    ) -> Result<(), String> {
+        // Check them all first so a pair isn't left half restored
+        for file in files {
+            if fs::symlink_metadata(&file.path).is_ok() {
+                return Err(format!(
+                    "{} is taken by another file now",
+                    file.path.display()
+                ));
+            }
+            if fs::symlink_metadata(&file.trashed.file).is_err() {
+                return Err(format!("{} is no longer in the trash", file.path.display()));
+            }
+        }
+
+        let mut restored = Vec::new();
+        let mut result = Ok(());
+        for file in files {
+            if let Err(e) = file.trashed.restore(&file.path) {
+                result = Err(e);
+                break;
+            }
+            restored.push(file.path.clone());
+        }
+
+        let mut image_id = None;
+        if let (Ok(()), true, Some(primary)) = (&result, image, restored.first()) {
+            // Same contents, so the record comes back under the same id
+            result = root_containing(self.catalog, primary.parent().unwrap_or(Path::new("")))
+                .and_then(|root| self.add_to_library(&root, primary, annotations))
+                .map(|id| image_id = Some(id));
+        }
+        if !restored.is_empty() {
+            self.steps.push(Step::Restore {
+                image_id,
+                files: restored,
+            });
+        }
+        result
+    }
+}
+
+/// Reverses one step of a file operation, returning the step that
+/// reverses it back. A step that fails partway is rolled back.
+pub(crate) fn reverse(catalog: &Catalog, trash: &Trash, step: &Step) -> Result<Step, String> {
+    let mut batch = Batch::new(catalog, trash, Collision::Skip);
+    let result = match step {
+        Step::Move { image_id, files } => batch.move_back(image_id, files),
+        Step::Copy { image_id, files } => batch.take_back(Some(image_id), files),
+        Step::Restore { image_id, files } => batch.take_back(image_id.as_deref(), files),
+        Step::Trash {
+            image_id,
+            annotations,
+            files,
+        } => batch.restore(image_id.is_some(), annotations, files),
+        Step::Tags { .. } | Step::Description { .. } => {
+            return Err("Not a file operation".to_string())
+        }
+    };
+
+    // Nothing left to reverse is an empty step, which reverses to nothing
+    let back = batch.steps.pop().unwrap_or(Step::Restore {
+        image_id: None,
+        files: Vec::new(),
+    });
+    match result {
+        Ok(()) => Ok(back),
+        Err(e) => {
+            if let Err(e) = reverse(catalog, trash, &back) {
+                log::warn!("Failed to roll back a partly reversed step: {}", e);
+            }
+            Err(e)
+        }
+    }
+}
+
+/// The scanned folder `folder` is in, the innermost if several are.
+fn root_containing(catalog: &Catalog, folder: &Path) -> Result<PathBuf, String> {
+    catalog
+        .roots()?
+        .into_iter()
+        .map(PathBuf::from)
+        .filter(|root| folder.starts_with(root))
+        .max_by_key(|root| root.components().count())
+        .ok_or_else(|| format!("{} isn't inside a scanned folder", folder.display()))
+}
+
+/// An image's files: its own, then its RAW partner's.
+fn files_of(image: &ImageInfo) -> Vec<PathBuf> {
+    std::iter::once(&image.path)
+        .chain(&image.raw_path)
+        .map(PathBuf::from)
+        .collect()
+}
+
+/// Where each of `files` goes when the first goes to `target`. A RAW
+/// partner follows under the same name, keeping its own extension.
+fn targets(files: &[PathBuf], target: &Path) -> Vec<PathBuf> {
+    let folder = target.parent().unwrap_or(Path::new(""));
+    let stem = target
+        .file_stem()
+        .map(|stem| stem.to_string_lossy().to_string())
+        .unwrap_or_default();
+    std::iter::once(target.to_path_buf())
+        .chain(files[1..].iter().map(|file| match file.extension() {
+            Some(extension) => folder.join(format!("{}.{}", stem, extension.to_string_lossy())),
+            None => folder.join(&stem),
+        }))
+        .collect()
+}
+
+/// Fails when two of an image's files would get one name, or names that
+/// only differ in case, which some drives treat as the same.
+fn distinct(targets: &[PathBuf]) -> Result<(), String> {
+    let mut seen = HashSet::new();
+    for target in targets {
+        if !seen.insert(target.to_string_lossy().to_lowercase()) {
+            return Err(format!(
+                "The image and its RAW file would both be named {}",
+                file_name(target)
+            ));
+        }
+    }
+    Ok(())
+}
+
+/// `beach.jpg` as `beach (n).jpg`.
+fn with_suffix(path: &Path, n: u32) -> PathBuf {
+    let stem = path
+        .file_stem()
+        .map(|stem| stem.to_string_lossy().to_string())
+        .unwrap_or_default();
+    let name = match path.extension() {
+        Some(extension) => format!("{} ({}).{}", stem, n, extension.to_string_lossy()),
+        None => format!("{} ({})", stem, n),
+    };
+    path.with_file_name(name)
+}
+
+/// Moves each of `files` to its target, putting back the ones already
+/// moved if one can't be so a pair stays together.
+fn move_all(files: &[PathBuf], targets: &[PathBuf]) -> Result<Vec<Relocation>, String> {
+    let mut moved: Vec<Relocation> = Vec::new();
+    for (from, to) in files.iter().zip(targets) {
+        if let Err(e) = move_file(from, to) {
+            for done in moved.iter().rev() {
+                let _ = move_file(&done.to, &done.from);
+            }
+            return Err(e);
+        }
+        moved.push(Relocation {
+            from: from.clone(),
+            to: to.clone(),
+        });
+    }
+    Ok(moved)
+}
+
+/// Renames a file, copying it across drives when a rename can't. Never
+/// replaces another file; only a change of case may land on `from` itself.
+fn move_file(from: &Path, to: &Path) -> Result<(), String> {
+    let failed = |e: std::io::Error| format!("Failed to move {}: {}", from.display(), e);
+    if fs::symlink_metadata(to).is_ok() && !same_file(from, to) {
+        return Err(format!(
+            "Failed to move {}: {} already exists",
+            from.display(),
+            to.display()
+        ));
+    }
+    match fs::rename(from, to) {
+        Ok(()) => Ok(()),
+        Err(e) if from.is_file() && fs::symlink_metadata(to).is_err() => {
+            if fs::copy(from, to).is_err() {
+                let _ = fs::remove_file(to);
+                return Err(failed(e));
+            }
+            fs::remove_file(from).map_err(failed)
+        }
+        Err(e) => Err(failed(e)),
+    }
+}
+
+#[cfg(unix)]
+fn same_file(a: &Path, b: &Path) -> bool {
+    use std::os::unix::fs::MetadataExt;
+
+    match (fs::symlink_metadata(a), fs::symlink_metadata(b)) {
+        (Ok(a), Ok(b)) => (a.dev(), a.ino()) == (b.dev(), b.ino()),
+        _ => false,
+    }
+}
+
+#[cfg(not(unix))]
+fn same_file(a: &Path, b: &Path) -> bool {
+    match (fs::canonicalize(a), fs::canonicalize(b)) {
+        (Ok(a), Ok(b)) => a == b,
+        _ => false,
+    }
+}
+
+fn checked_name(name: &str) -> Result<&str, String> {
+    let name = name.trim();
+    if name.is_empty() || name == "." || name == ".." || name.contains(['/', '\\']) {
+        return Err(format!("'{}' isn't a valid file name", name));
+    }
+    Ok(name)
+}
+
+fn count(n: usize) -> String {
+    if n == 1 {
+        "1 image".to_string()
+    } else {
+        format!("{} images", n)
+    }
+}
+
+fn file_name(path: &Path) -> String {
+    path.file_name()
+        .map(|name| name.to_string_lossy().to_string())
+        .unwrap_or_else(|| path.display().to_string())
+}
+
+fn rename(
+    catalog: &Catalog,
+    trash: &Trash,
+    renames: Vec<Rename>,
+    collision: Collision,
+) -> Result<FileOpReport, String> {
+    let mut batch = Batch::new(catalog, trash, collision);
+    for Rename { image_id, name } in renames {
+        if let Err(message) = batch.rename_image(&image_id, &name) {
+            batch
+                .report
+                .failed
+                .push(FileOpProblem { image_id, message });
+        }
+    }
+
+    // One rename reads best with its names
+    let moves: Vec<&Relocation> = batch
+        .steps
+        .iter()
+        .filter_map(|step| match step {
+            Step::Move { files, .. } => files.first(),
+            _ => None,
+        })
+        .collect();
+    let summary = match moves[..] {
+        [only] => format!(
+            "Renamed {} to {}",
+            file_name(&only.from),
+            file_name(&only.to)
+        ),
+        _ => format!("Renamed {}", count(batch.placed.len())),
+    };
+    batch.finish(&summary)
+}
+
+fn relocate(
+    catalog: &Catalog,
+    trash: &Trash,
+    image_ids: Vec<String>,
+    folder: &Path,
+    collision: Collision,
+    copy: bool,
+) -> Result<FileOpReport, String> {
+    scan::check_folder(folder)?;
+    let destination = Destination::containing(catalog, folder)?;
+
+    let mut batch = Batch::new(catalog, trash, collision);
+    batch.each(image_ids, |batch, image_id| {
+        let name = batch.image(image_id)?.name;
+        let target = folder.join(name);
+        if copy {
+            batch.copy_image(image_id, &destination, &target)
+        } else {
+            batch.move_image(image_id, &destination, &target)
+        }
+    });
+
+    let verb = if copy { "Copied" } else { "Moved" };
+    let summary = format!(
+        "{} {} to {}",
+        verb,
+        count(batch.placed.len()),
+        file_name(folder)
+    );
+    batch.finish(&summary)
+}
+
+fn delete(
+    catalog: &Catalog,
+    trash: &Trash,
+    image_ids: Vec<String>,
+) -> Result<FileOpReport, String> {
+    let mut batch = Batch::new(catalog, trash, Collision::default());
+    batch.each(image_ids, |batch, image_id| batch.trash_image(image_id));
+    let summary = format!("Deleted {}", count(batch.report.removed.len()));
+    batch.finish(&summary)
+}
+
+/// Renames images in place, RAW partners along with them.
+#[tauri::command]
+pub fn rename_images(
+    catalog: tauri::State<'_, Catalog>,
+    renames: Vec<Rename>,
+    collision: Option<Collision>,
+) -> Result<FileOpReport, String> {
+    rename(
+        &catalog,
+        &Trash::home()?,
+        renames,
+        collision.unwrap_or_default(),
+    )
+}
+
+/// Moves images into `folder`, which must be inside a scanned folder.
+/// They keep their ids, tags and descriptions.
+#[tauri::command]
+pub fn move_images(
+    catalog: tauri::State<'_, Catalog>,
+    image_ids: Vec<String>,
+    folder: String,
+    collision: Option<Collision>,
+) -> Result<FileOpReport, String> {
+    relocate(
+        &catalog,
+        &Trash::home()?,
+        image_ids,
+        Path::new(&folder),
+        collision.unwrap_or_default(),
+        false,
+    )
+}
+
+/// Copies images into `folder`, which must be inside a scanned folder.
+/// The copies get records of their own, with the originals' tags and
+/// descriptions.
+#[tauri::command]
+pub fn copy_images(
+    catalog: tauri::State<'_, Catalog>,
+    image_ids: Vec<String>,
+    folder: String,
+    collision: Option<Collision>,
+) -> Result<FileOpReport, String> {
+    relocate(
+        &catalog,
+        &Trash::home()?,
+        image_ids,
+        Path::new(&folder),
+        collision.unwrap_or_default(),
+        true,
+    )
+}
+
+/// Sends images' files to the trash and drops their records.
+#[tauri::command]
+pub fn delete_images(
+    catalog: tauri::State<'_, Catalog>,
+    image_ids: Vec<String>,
+) -> Result<FileOpReport, String> {
+    delete(&catalog, &Trash::home()?, image_ids)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::scan::{scan_root, ScanOptions};
+    use crate::test_support::temp_dir;
+    use image::{Rgb, RgbImage};
+
+    struct Library {
+        dir: PathBuf,
+        root: PathBuf,
+        catalog: Catalog,
+        trash: Trash,
+    }
+
+    impl Library {
+        /// A scanned folder holding `files`, each a distinct little image.
+        fn new(name: &str, files: &[&str]) -> Library {
+            let dir = temp_dir(&format!("file_ops_{}", name));
+            let root = dir.join("photos");
+            for (n, file) in files.iter().enumerate() {
+                let path = root.join(file);
+                fs::create_dir_all(path.parent().unwrap()).unwrap();
+                let image = RgbImage::from_pixel(8, 8, Rgb([n as u8 * 20, 100, 50]));
+                // RAW files are TIFF inside
+                let format = if file.ends_with(".nef") {
+                    image::ImageFormat::Tiff
+                } else {
+                    image::ImageFormat::from_path(&path).unwrap()
+                };
+                image.save_with_format(&path, format).unwrap();
+            }
+
+            let catalog = Catalog::open_in_memory().unwrap();
+            let options = ScanOptions {
+                recursive: true,
+                ..ScanOptions::default()
+            };
+            scan_root(&catalog, &root, &options).unwrap();
+            Library {
+                trash: Trash::at(dir.join("Trash")),
+                dir,
+                root,
+                catalog,
+            }
+        }
+
+        fn id(&self, file: &str) -> String {
+            self.catalog
+                .image_owning(&self.root.join(file).to_string_lossy())
+                .unwrap()
+                .unwrap()
+        }
+
+        fn image(&self, id: &str) -> ImageInfo {
+            self.catalog
+                .images_by_ids(&[id.to_string()])
+                .unwrap()
+                .pop()
+                .unwrap()
+        }
+
+        fn journal(&self) -> Vec<(String, Vec<Step>)> {
+            let conn = self.catalog.conn();
+            let mut stmt = conn
+                .prepare("SELECT summary, steps FROM journal ORDER BY id")
+                .unwrap();
+            let rows = stmt
+                .query_map([], |r| Ok((r.get(0)?, r.get::<_, String>(1)?)))
+                .unwrap();
+            rows.map(|row| {
+                let (summary, steps) = row.unwrap();
+                (summary, serde_json::from_str(&steps).unwrap())
+            })
+            .collect()
+        }
+    }
+
+    impl Drop for Library {
+        fn drop(&mut self) {
+            let _ = fs::remove_dir_all(&self.dir);
+        }
+    }
+
+    #[test]
+    fn moves_and_renames_keep_records_and_pairs_together() {
+        let lib = Library::new(
+            "move",
+            &["beach.jpg", "beach.nef", "city.png", "sorted/city.png"],
+        );
+        let beach = lib.id("beach.jpg");
+        let city = lib.id("city.png");
+        lib.catalog.set_tags(&beach, &["sea".to_string()]).unwrap();
+        assert_eq!(lib.id("beach.nef"), beach);
+
+        let report = relocate(
+            &lib.catalog,
+            &lib.trash,
+            vec![beach.clone(), city.clone()],
+            &lib.root.join("sorted"),
+            Collision::Suffix,
+            false,
+        )
+        .unwrap();
+        assert!(report.failed.is_empty());
+        let moved = lib.image(&beach);
+        assert_eq!(moved.relative_path, "sorted/beach.jpg");
+        assert_eq!(
+            moved.raw_path,
+            Some(
+                lib.root
+                    .join("sorted/beach.nef")
+                    .to_string_lossy()
+                    .to_string()
+            )
+        );
+        assert_eq!(moved.tags, ["sea"]);
+        assert!(!lib.root.join("beach.nef").exists());
+        assert_eq!(lib.image(&city).relative_path, "sorted/city (2).png");
+
+        let report = rename(
+            &lib.catalog,
+            &lib.trash,
+            vec![Rename {
+                image_id: beach.clone(),
+                name: "sunset".to_string(),
+            }],
+            Collision::Suffix,
+        )
+        .unwrap();
+        assert_eq!(report.images[0].name, "sunset.jpg");
+        assert!(lib.root.join("sorted/sunset.nef").exists());
+        assert_eq!(lib.id("sorted/sunset.nef"), beach);
+
+        // Taken names are skipped when asked to
+        let report = rename(
+            &lib.catalog,
+            &lib.trash,
+            vec![Rename {
+                image_id: city.clone(),
+                name: "city.png".to_string(),
+            }],
+            Collision::Skip,
+        )
+        .unwrap();
+        assert_eq!(report.skipped.len(), 1);
+        assert_eq!(lib.image(&city).name, "city (2).png");
+
+        let journal = lib.journal();
+        let summaries: Vec<&str> = journal.iter().map(|(s, _)| s.as_str()).collect();
+        assert_eq!(
+            summaries,
+            [
+                "Moved 2 images to sorted",
+                "Renamed beach.jpg to sunset.jpg"
+            ]
+        );
+        assert_eq!(
+            journal[1].1,
+            [Step::Move {
+                image_id: beach,
+                files: vec![
+                    Relocation {
+                        from: lib.root.join("sorted/beach.jpg"),
+                        to: lib.root.join("sorted/sunset.jpg"),
+                    },
+                    Relocation {
+                        from: lib.root.join("sorted/beach.nef"),
+                        to: lib.root.join("sorted/sunset.nef"),
+                    },
+                ],
+            }]
+        );
+
+        let outside = lib.dir.join("elsewhere");
+        fs::create_dir_all(&outside).unwrap();
+        let error = relocate(
+            &lib.catalog,
+            &lib.trash,
+            vec![city],
+            &outside,
+            Collision::Suffix,
+            false,
+        )
+        .err()
+        .unwrap();
+        assert!(error.contains("isn't inside a scanned folder"));
+    }
+
+    #[test]
+    fn nothing_is_overwritten_by_a_pair_or_a_refused_target() {
+        let lib = Library::new(
+            "clash",
+            &["beach.jpg", "beach.nef", "city.png", "hidden/city.png"],
+        );
+        let options = ScanOptions {
+            recursive: true,
+            exclude: vec!["hidden".to_string()],
+            ..ScanOptions::default()
+        };
+        scan_root(&lib.catalog, &lib.root, &options).unwrap();
+        let beach = lib.id("beach.jpg");
+        let city = lib.id("city.png");
+
+        // The RAW file's extension would give both files one name
+        let report = rename(
+            &lib.catalog,
+            &lib.trash,
+            vec![Rename {
+                image_id: beach.clone(),
+                name: "Sunset.NEF".to_string(),
+            }],
+            Collision::Suffix,
+        )
+        .unwrap();
+        assert!(report.failed[0].message.contains("would both be named"));
+        assert!(lib.root.join("beach.jpg").exists());
+        assert!(lib.root.join("beach.nef").exists());
+        assert_eq!(lib.image(&beach).relative_path, "beach.jpg");
+
+        // A target the scans leave out is refused before anything is trashed
+        let report = relocate(
+            &lib.catalog,
+            &lib.trash,
+            vec![city.clone()],
+            &lib.root.join("hidden"),
+            Collision::Overwrite,
+            false,
+        )
+        .unwrap();
+        assert!(report.failed[0].message.contains("leave out"));
+        assert!(lib.root.join("hidden/city.png").exists());
+        assert!(!lib.dir.join("Trash/files/city.png").exists());
+
+        let error = move_file(
+            &lib.root.join("city.png"),
+            &lib.root.join("hidden/city.png"),
+        )
+        .err()
+        .unwrap();
+        assert!(error.contains("already exists"));
+        assert!(lib.root.join("city.png").exists());
+        assert!(journal::history(&lib.catalog).unwrap().is_empty());
+    }
+
+    #[test]
+    fn copies_carry_annotations_and_deletes_go_to_the_trash() {
+        let lib = Library::new("copy", &["city.png", "copies/city.png", "night.png"]);
+        let city = lib.id("city.png");
+        let occupant = lib.id("copies/city.png");
+        lib.catalog
+            .set_tags(&city, &["street".to_string()])
+            .unwrap();
+        lib.catalog.set_description(&city, "Rush hour").unwrap();
+
+        // The copy replaces what was in its way, which goes to the trash
+        let report = relocate(
+            &lib.catalog,
+            &lib.trash,
+            vec![city.clone()],
+            &lib.root.join("copies"),
+            Collision::Overwrite,
+            true,
+        )
+        .unwrap();
+        assert_eq!(report.removed, [occupant.as_str()]);
+        let copy = &report.images[0];
+        assert_ne!(copy.id, city);
+        assert_eq!(copy.relative_path, "copies/city.png");
+        assert_eq!(copy.tags, ["street"]);
+        assert_eq!(copy.description, "Rush hour");
+        assert!(lib.dir.join("Trash/files/city.png").exists());
+        assert!(lib.catalog.images_by_ids(&[occupant]).unwrap().is_empty());
+
+        let night = lib.id("night.png");
+        let report = delete(&lib.catalog, &lib.trash, vec![night.clone(), city.clone()]).unwrap();
+        assert_eq!(report.removed, [night.clone(), city]);
+        assert!(!lib.root.join("night.png").exists());
+        assert!(lib.dir.join("Trash/files/night.png").exists());
+        // The overwritten city.png was there first
+        assert!(lib.dir.join("Trash/files/city.2.png").exists());
+
+        let journal = lib.journal();
+        assert_eq!(journal[0].0, "Copied 1 image to copies");
+        assert!(matches!(
+            journal[0].1[..],
+            [Step::Trash { .. }, Step::Copy { .. }]
+        ));
+        assert_eq!(journal[1].0, "Deleted 2 images");
+        match &journal[1].1[1] {
+            Step::Trash {
+                image_id,
+                annotations,
+                files,
+            } => {
+                assert_eq!(annotations.tags, ["street"]);
+                assert_eq!(annotations.description, "Rush hour");
+                assert_eq!(files[0].path, lib.root.join("city.png"));
+                assert!(image_id.is_some());
+            }
+            other => panic!("not a trash step: {:?}", other),
+        }
+        assert!(matches!(&journal[1].1[0], Step::Trash { image_id: Some(id), .. } if *id == night));
+    }
+
+    #[test]
+    fn file_operations_undo_and_redo() {
+        let lib = Library::new(
+            "undo",
+            &["beach.jpg", "beach.nef", "city.png", "sorted/keep.png"],
+        );
+        let beach = lib.id("beach.jpg");
+        let city = lib.id("city.png");
+        lib.catalog.set_tags(&beach, &["sea".to_string()]).unwrap();
+        let sorted = lib.root.join("sorted");
+
+        relocate(
+            &lib.catalog,
+            &lib.trash,
+            vec![beach.clone()],
+            &sorted,
+            Collision::Suffix,
+            false,
+        )
+        .unwrap();
+        journal::undo_last(&lib.catalog, &lib.trash)
+            .unwrap()
+            .unwrap();
+        assert!(lib.root.join("beach.jpg").exists());
+        assert!(lib.root.join("beach.nef").exists());
+        assert!(!sorted.join("beach.jpg").exists());
+        assert_eq!(lib.image(&beach).relative_path, "beach.jpg");
+        assert_eq!(lib.id("beach.nef"), beach);
+        journal::redo_next(&lib.catalog, &lib.trash)
+            .unwrap()
+            .unwrap();
+        assert_eq!(lib.image(&beach).relative_path, "sorted/beach.jpg");
+
+        // Deleted images come back from the trash with what was said about them
+        delete(&lib.catalog, &lib.trash, vec![beach.clone()]).unwrap();
+        journal::undo_last(&lib.catalog, &lib.trash)
+            .unwrap()
+            .unwrap();
+        let restored = lib.image(&beach);
+        assert_eq!(restored.relative_path, "sorted/beach.jpg");
+        assert_eq!(
+            restored.raw_path,
+            Some(sorted.join("beach.nef").to_string_lossy().to_string())
+        );
+        assert_eq!(restored.tags, ["sea"]);
+        assert!(!lib.dir.join("Trash/files/beach.jpg").exists());
+        journal::redo_next(&lib.catalog, &lib.trash)
+            .unwrap()
+            .unwrap();
+        assert!(lib
+            .catalog
+            .images_by_ids(std::slice::from_ref(&beach))
+            .unwrap()
+            .is_empty());
+        assert!(!sorted.join("beach.nef").exists());
+
+        // Copies go to the trash, the original stays
+        let report = relocate(
+            &lib.catalog,
+            &lib.trash,
+            vec![city.clone()],
+            &sorted,
+            Collision::Suffix,
+            true,
+        )
+        .unwrap();
+        let copy = report.images[0].id.clone();
+        journal::undo_last(&lib.catalog, &lib.trash)
+            .unwrap()
+            .unwrap();
+        assert!(!sorted.join("city.png").exists());
+        assert!(lib.catalog.images_by_ids(&[copy]).unwrap().is_empty());
+        assert_eq!(lib.image(&city).relative_path, "city.png");
+
+        // Something in the way stops an undo without changing anything
+        fs::copy(lib.root.join("city.png"), sorted.join("city.png")).unwrap();
+        let error = journal::redo_next(&lib.catalog, &lib.trash).err().unwrap();
+        assert!(error.contains("taken by another file"), "{}", error);
+
+        let history: Vec<(String, bool)> = journal::history(&lib.catalog)
+            .unwrap()
+            .into_iter()
+            .map(|entry| (entry.summary, entry.undone))
+            .collect();
+        assert_eq!(
+            history,
+            [
+                ("Copied 1 image to sorted".to_string(), true),
+                ("Deleted 1 image".to_string(), false),
+                ("Moved 1 image to sorted".to_string(), false),
+            ]
+        );
+    }
+}
@@ -0,0 +1,204 @@
+use image::DynamicImage;
+use std::f64::consts::PI;
+use std::path::Path;
+
+use crate::colours::{self, PaletteColour};
+use crate::model_image;
+
+/// Longest side SVGs are rendered at for fingerprinting; nothing here looks
+/// at more than a 64x64 reduction anyway.
+const RENDER_EDGE: u32 = 256;
+/// Levels per channel in a colour histogram.
+const LEVELS: usize = 4;
+const BINS: usize = LEVELS * LEVELS * LEVELS;
+
+/// Perceptual hashes of an image's pixels. Unlike the content hash they
+/// survive resizing, re-encoding and small edits, so copies exported at
+/// other sizes or qualities hash close to the original.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Fingerprint {
+    /// Difference hash: whether brightness rises between neighbouring pixels.
+    pub dhash: u64,
+    /// DCT hash: which low frequencies are above the median.
+    pub phash: u64,
+}
+
+impl Fingerprint {
+    /// How many bits differ, taking the worse of the two hashes so both have
+    /// to agree before images count as the same picture. Being the maximum
+    /// of two metrics, this is a metric too.
+    pub fn distance(&self, other: &Fingerprint) -> u32 {
+        let dhash = (self.dhash ^ other.dhash).count_ones();
+        let phash = (self.phash ^ other.phash).count_ones();
+        dhash.max(phash)
+    }
+}
+
+/// How an image's pixels are spread over a coarse RGB grid, each bin's share
+/// scaled to `u16::MAX`. Ignores layout entirely, so it matches pictures
+/// with the same colours that the hashes would call different.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Histogram([u16; BINS]);
+
+impl Histogram {
+    pub fn of_image(image: &DynamicImage) -> Histogram {
+        let small = image.thumbnail(64, 64).to_rgb8();
+        let mut counts = [0u64; BINS];
+        for pixel in small.pixels() {
+            let [r, g, b] = pixel.0.map(|c| usize::from(c) * LEVELS / 256);
+            counts[(r * LEVELS + g) * LEVELS + b] += 1;
+        }
+        let total = counts.iter().sum::<u64>().max(1);
+        Histogram(counts.map(|count| (count * u64::from(u16::MAX) / total) as u16))
+    }
+
+    /// Histogram intersection: the share of pixels the two have in common,
+    /// from 0 for no colours shared to 1 for the same spread.
+    pub fn similarity(&self, other: &Histogram) -> f64 {
+        let common: u32 = self
+            .0
+            .iter()
+            .zip(&other.0)
+            .map(|(&a, &b)| u32::from(a.min(b)))
+            .sum();
+        f64::from(common) / f64::from(u16::MAX)
+    }
+
+    pub fn to_blob(&self) -> Vec<u8> {
+        self.0.iter().flat_map(|bin| bin.to_le_bytes()).collect()
+    }
+
+    /// `None` for blobs of the wrong size, which a later change to the
+    /// binning would leave behind.
+    pub fn from_blob(blob: &[u8]) -> Option<Histogram> {
+        if blob.len() != BINS * 2 {
+            return None;
+        }
+        let mut bins = [0; BINS];
+        for (bin, bytes) in bins.iter_mut().zip(blob.chunks_exact(2)) {
+            *bin = u16::from_le_bytes([bytes[0], bytes[1]]);
+        }
+        Some(Histogram(bins))
+    }
+}
+
+/// Everything a scan works out from an image's pixels.
+pub struct Appearance {
+    pub fingerprint: Fingerprint,
+    pub histogram: Histogram,
+    pub palette: Vec<PaletteColour>,
+}
+
+/// Decodes the image at `path` upright and works out how it looks.
+pub fn compute(path: &Path) -> Result<Appearance, String> {
+    let image = model_image::load(path, RENDER_EDGE)?;
+    Ok(Appearance {
+        fingerprint: of_image(&image),
+        histogram: Histogram::of_image(&image),
+        palette: colours::palette(&image),
+    })
+}
+
+pub fn of_image(image: &DynamicImage) -> Fingerprint {
+    Fingerprint {
+        dhash: dhash(image),
+        phash: phash(image),
+    }
+}
+
+fn dhash(image: &DynamicImage) -> u64 {
+    let small = image.thumbnail_exact(9, 8).to_luma8();
+    let mut hash = 0;
+    for y in 0..8 {
+        for x in 0..8 {
+            let rises = small.get_pixel(x, y).0[0] < small.get_pixel(x + 1, y).0[0];
+            hash = hash << 1 | u64::from(rises);
+        }
+    }
+    hash
+}
+
+fn phash(image: &DynamicImage) -> u64 {
+    let small = image.thumbnail_exact(32, 32).to_luma8();
+
+    // The 8x8 lowest frequencies of a 32x32 DCT-II, one axis at a time
+    let cosines: Vec<[f64; 32]> = (0..8)
+        .map(|u| std::array::from_fn(|x| ((2 * x + 1) as f64 * u as f64 * PI / 64.0).cos()))
+        .collect();
+    let mut rows = [[0.0; 8]; 32];
+    for (y, row) in rows.iter_mut().enumerate() {
+        for (u, value) in row.iter_mut().enumerate() {
+            *value = (0..32)
+                .map(|x| f64::from(small.get_pixel(x as u32, y as u32).0[0]) * cosines[u][x])
+                .sum();
+        }
+    }
+    let mut coefficients = [0.0; 64];
+    for v in 0..8 {
+        for u in 0..8 {
+            coefficients[v * 8 + u] = (0..32).map(|y| rows[y][u] * cosines[v][y]).sum();
+        }
+    }
+
+    // The DC term is overall brightness, which would skew the median
+    let mut ac = coefficients[1..].to_vec();
+    ac.sort_by(f64::total_cmp);
+    let median = ac[ac.len() / 2];
+    coefficients
+        .iter()
+        .fold(0, |hash, &c| hash << 1 | u64::from(c > median))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::imageops::FilterType;
+    use image::{Rgb, RgbImage};
+
+    fn scene(width: u32, height: u32, shift: u32) -> DynamicImage {
+        DynamicImage::ImageRgb8(RgbImage::from_fn(width, height, |x, y| {
+            let (x, y) = (x * 256 / width, y * 256 / height);
+            let disc = (x as i32 - 96).pow(2) + (y as i32 - 128).pow(2) < 60 * 60;
+            if disc {
+                Rgb([230, 200, 40])
+            } else {
+                Rgb([(x + shift) as u8, (y / 2) as u8, 120])
+            }
+        }))
+    }
+
+    #[test]
+    fn resized_and_reencoded_copies_stay_close() {
+        let original = scene(800, 600, 0);
+        let fingerprint = of_image(&original);
+
+        let smaller = original.resize(200, 150, FilterType::Lanczos3);
+        let mut jpeg = Vec::new();
+        smaller
+            .write_to(
+                &mut std::io::Cursor::new(&mut jpeg),
+                image::ImageFormat::Jpeg,
+            )
+            .unwrap();
+        let reencoded = image::load_from_memory(&jpeg).unwrap();
+        assert!(fingerprint.distance(&of_image(&reencoded)) <= 4);
+
+        let mirrored = original.fliph();
+        assert!(fingerprint.distance(&of_image(&mirrored)) > 16);
+    }
+
+    #[test]
+    fn histograms_compare_colours_regardless_of_layout() {
+        let original = Histogram::of_image(&scene(800, 600, 0));
+        let mirrored = Histogram::of_image(&scene(800, 600, 0).fliph());
+        let mut inverted = scene(800, 600, 0);
+        inverted.invert();
+        let recoloured = Histogram::of_image(&inverted);
+
+        assert!(original.similarity(&original) > 0.99);
+        assert!(original.similarity(&mirrored) > 0.99);
+        assert!(original.similarity(&recoloured) < 0.2);
+        assert_eq!(Histogram::from_blob(&original.to_blob()), Some(original));
+        assert_eq!(Histogram::from_blob(&[0; 3]), None);
+    }
+}
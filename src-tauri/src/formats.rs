@@ -0,0 +1,311 @@
+use serde::Serialize;
+use std::fs::File;
+use std::io::{self, Read, Seek, SeekFrom};
+use std::path::Path;
+
+/// How much of the start of a file is read to recognise its format. SVGs
+/// may have an XML declaration, comments and a doctype before `<svg`.
+const HEAD_LEN: u64 = 1024;
+/// How much of the end of a file is read to check it is complete. Some
+/// cameras and editors pad or append data after the end marker.
+const TAIL_LEN: u64 = 1024;
+
+/// Image formats recognised by their contents rather than their extension.
+#[derive(Serialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Format {
+    Jpeg,
+    Png,
+    Gif,
+    WebP,
+    Bmp,
+    Tiff,
+    Svg,
+    Heic,
+    Avif,
+    /// Camera RAW of any make. Only the embedded preview and metadata are
+    /// read; the sensor data itself is never decoded.
+    Raw,
+}
+
+impl Format {
+    pub fn name(self) -> &'static str {
+        match self {
+            Format::Jpeg => "JPEG",
+            Format::Png => "PNG",
+            Format::Gif => "GIF",
+            Format::WebP => "WebP",
+            Format::Bmp => "BMP",
+            Format::Tiff => "TIFF",
+            Format::Svg => "SVG",
+            Format::Heic => "HEIC",
+            Format::Avif => "AVIF",
+            Format::Raw => "RAW",
+        }
+    }
+
+    pub fn mime(self) -> &'static str {
+        match self {
+            Format::Jpeg => "image/jpeg",
+            Format::Png => "image/png",
+            Format::Gif => "image/gif",
+            Format::WebP => "image/webp",
+            Format::Bmp => "image/bmp",
+            Format::Tiff => "image/tiff",
+            Format::Svg => "image/svg+xml",
+            Format::Heic => "image/heic",
+            Format::Avif => "image/avif",
+            Format::Raw => "image/x-raw",
+        }
+    }
+
+    /// Lowercase extensions files of this format normally have.
+    pub fn extensions(self) -> &'static [&'static str] {
+        match self {
+            Format::Jpeg => &["jpg", "jpeg"],
+            Format::Png => &["png"],
+            Format::Gif => &["gif"],
+            Format::WebP => &["webp"],
+            Format::Bmp => &["bmp"],
+            Format::Tiff => &["tiff", "tif"],
+            Format::Svg => &["svg"],
+            Format::Heic => &["heic", "heif", "hif"],
+            Format::Avif => &["avif"],
+            Format::Raw => RAW_EXTENSIONS,
+        }
+    }
+
+    /// Formats the image crate can't decode, whose pixels come from an
+    /// embedded preview or an external converter instead.
+    pub fn needs_preview(self) -> bool {
+        matches!(self, Format::Heic | Format::Avif | Format::Raw)
+    }
+}
+
+/// Camera RAW extensions. Most RAW formats are TIFF underneath, so the
+/// extension is what tells a NEF or DNG apart from a plain TIFF.
+pub const RAW_EXTENSIONS: &[&str] = &[
+    "cr2", "cr3", "nef", "nrw", "arw", "srf", "sr2", "dng", "orf", "rw2", "pef", "raf",
+];
+
+/// ISO base media brands of HEIF images with HEVC-coded pixels.
+const HEIC_BRANDS: &[&[u8]] = &[
+    b"heic", b"heix", b"heim", b"heis", b"hevc", b"hevx", b"hevm", b"hevs", b"mif1", b"msf1",
+];
+
+/// Recognises a format from the first bytes of a file.
+pub fn sniff(head: &[u8]) -> Option<Format> {
+    if head.starts_with(&[0xFF, 0xD8, 0xFF]) {
+        Some(Format::Jpeg)
+    } else if head.starts_with(b"\x89PNG\r\n\x1a\n") {
+        Some(Format::Png)
+    } else if head.starts_with(b"GIF87a") || head.starts_with(b"GIF89a") {
+        Some(Format::Gif)
+    } else if head.len() >= 12 && head.starts_with(b"RIFF") && &head[8..12] == b"WEBP" {
+        Some(Format::WebP)
+    } else if is_raw(head) {
+        Some(Format::Raw)
+    } else if head.starts_with(b"II*\0") || head.starts_with(b"MM\0*") {
+        Some(Format::Tiff)
+    } else if let Some(format) = iso_media_format(head) {
+        Some(format)
+    } else if is_bmp(head) {
+        Some(Format::Bmp)
+    } else if is_svg(head) {
+        Some(Format::Svg)
+    } else {
+        None
+    }
+}
+
+/// `BM` alone is too weak a signature, so the DIB header size must be one
+/// of the known versions as well.
+fn is_bmp(head: &[u8]) -> bool {
+    head.len() >= 18
+        && head.starts_with(b"BM")
+        && matches!(
+            u32::from_le_bytes([head[14], head[15], head[16], head[17]]),
+            12 | 40 | 52 | 56 | 64 | 108 | 124
+        )
+}
+
+/// RAW formats with a signature of their own. NEF, ARW, DNG and the like
+/// look exactly like TIFF and are only told apart by `detect`.
+fn is_raw(head: &[u8]) -> bool {
+    // Canon CR2: a TIFF header followed by `CR` and the format version
+    let cr2 = head.starts_with(b"II*\0") && head.get(8..10) == Some(b"CR");
+    // Olympus ORF and Panasonic RW2 have their own TIFF magic numbers
+    let tiff_variant = [b"IIRO", b"IIRS", b"MMOR", b"IIU\0"]
+        .iter()
+        .any(|magic| head.starts_with(*magic));
+    cr2 || tiff_variant || head.starts_with(b"FUJIFILMCCD-RAW")
+}
+
+/// HEIC, AVIF and Canon CR3 share the ISO base media file format and are
+/// told apart by the brands in its leading `ftyp` box.
+fn iso_media_format(head: &[u8]) -> Option<Format> {
+    if head.get(4..8) != Some(b"ftyp") {
+        return None;
+    }
+    let size = u32::from_be_bytes(head.get(..4)?.try_into().ok()?) as usize;
+    let major = head.get(8..12)?;
+    // The major brand, then the compatible brands after the minor version
+    let compatible = head.get(16..size.min(head.len())).unwrap_or_default();
+    let brands: Vec<&[u8]> = std::iter::once(major)
+        .chain(compatible.chunks_exact(4))
+        .collect();
+
+    if major == b"crx " {
+        Some(Format::Raw)
+    } else if brands.iter().any(|b| *b == b"avif" || *b == b"avis") {
+        Some(Format::Avif)
+    } else if brands.iter().any(|b| HEIC_BRANDS.contains(b)) {
+        Some(Format::Heic)
+    } else {
+        None
+    }
+}
+
+fn is_svg(head: &[u8]) -> bool {
+    let text = String::from_utf8_lossy(head);
+    let text = text.trim_start_matches('\u{feff}').trim_start();
+    text.starts_with('<') && text.contains("<svg")
+}
+
+/// Reads the start of the file at `path` and recognises its format. A TIFF
+/// with a RAW extension is taken to be that RAW format.
+pub fn detect(path: &Path) -> io::Result<Option<Format>> {
+    let mut head = Vec::new();
+    File::open(path)?.take(HEAD_LEN).read_to_end(&mut head)?;
+    Ok(match sniff(&head) {
+        Some(Format::Tiff) if has_raw_extension(path) => Some(Format::Raw),
+        format => format,
+    })
+}
+
+pub fn has_raw_extension(path: &Path) -> bool {
+    path.extension()
+        .map(|ext| RAW_EXTENSIONS.contains(&ext.to_string_lossy().to_lowercase().as_str()))
+        .unwrap_or(false)
+}
+
+/// Whether a file of `format` is missing the marker complete files end with.
+/// Formats without such a marker are never reported as truncated.
+pub fn is_truncated(path: &Path, format: Format) -> io::Result<bool> {
+    if !matches!(format, Format::Jpeg | Format::Png | Format::Gif) {
+        return Ok(false);
+    }
+
+    let mut file = File::open(path)?;
+    let len = file.metadata()?.len();
+    file.seek(SeekFrom::Start(len.saturating_sub(TAIL_LEN)))?;
+    let mut tail = Vec::new();
+    file.read_to_end(&mut tail)?;
+    Ok(!has_end_marker(&tail, format))
+}
+
+fn has_end_marker(tail: &[u8], format: Format) -> bool {
+    match format {
+        // End of image; 0xFF can't otherwise be followed by 0xD9 in the data
+        Format::Jpeg => tail.windows(2).any(|w| w == [0xFF, 0xD9]),
+        Format::Png => tail.windows(4).any(|w| w == b"IEND"),
+        // The trailer byte, possibly followed by zero padding
+        Format::Gif => tail.iter().rev().find(|&&b| b != 0) == Some(&0x3B),
+        _ => true,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::temp_dir;
+
+    fn encode(format: image::ImageFormat) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        image::RgbImage::new(8, 8)
+            .write_to(&mut std::io::Cursor::new(&mut bytes), format)
+            .unwrap();
+        bytes
+    }
+
+    #[test]
+    fn formats_are_recognised_by_content() {
+        for (format, expected) in [
+            (image::ImageFormat::Jpeg, Format::Jpeg),
+            (image::ImageFormat::Png, Format::Png),
+            (image::ImageFormat::Gif, Format::Gif),
+            (image::ImageFormat::WebP, Format::WebP),
+            (image::ImageFormat::Bmp, Format::Bmp),
+            (image::ImageFormat::Tiff, Format::Tiff),
+        ] {
+            assert_eq!(sniff(&encode(format)), Some(expected), "{:?}", format);
+        }
+
+        let svg = b"\xEF\xBB\xBF<?xml version=\"1.0\"?>\n<!-- logo -->\n<svg xmlns=\"http://www.w3.org/2000/svg\"/>";
+        assert_eq!(sniff(svg), Some(Format::Svg));
+        assert_eq!(sniff(b"BM is not a bitmap, just a note"), None);
+        assert_eq!(sniff(b"<html><body>no</body></html>"), None);
+        assert_eq!(sniff(b""), None);
+    }
+
+    fn ftyp(major: &[u8; 4], compatible: &[&[u8; 4]]) -> Vec<u8> {
+        let mut bytes = ((16 + 4 * compatible.len()) as u32).to_be_bytes().to_vec();
+        bytes.extend(b"ftyp");
+        bytes.extend(major);
+        bytes.extend([0; 4]);
+        for brand in compatible {
+            bytes.extend(*brand);
+        }
+        bytes
+    }
+
+    #[test]
+    fn camera_and_phone_formats_are_recognised() {
+        assert_eq!(
+            sniff(&ftyp(b"heic", &[b"mif1", b"heic"])),
+            Some(Format::Heic)
+        );
+        assert_eq!(
+            sniff(&ftyp(b"mif1", &[b"mif1", b"avif"])),
+            Some(Format::Avif)
+        );
+        assert_eq!(sniff(&ftyp(b"avif", &[])), Some(Format::Avif));
+        assert_eq!(sniff(&ftyp(b"crx ", &[b"isom"])), Some(Format::Raw));
+        assert_eq!(sniff(&ftyp(b"isom", &[b"mp41"])), None);
+
+        assert_eq!(sniff(b"II*\0\x10\0\0\0CR\x02\0"), Some(Format::Raw));
+        assert_eq!(sniff(b"IIRO\x08\0\0\0"), Some(Format::Raw));
+        assert_eq!(sniff(b"IIU\0\x08\0\0\0"), Some(Format::Raw));
+        assert_eq!(sniff(b"FUJIFILMCCD-RAW 0201"), Some(Format::Raw));
+
+        // NEF, ARW and DNG are plain TIFF inside; the extension decides
+        let dir = temp_dir("formats_raw");
+        let tiff = encode(image::ImageFormat::Tiff);
+        for (name, expected) in [("shot.NEF", Format::Raw), ("scan.tif", Format::Tiff)] {
+            std::fs::write(dir.join(name), &tiff).unwrap();
+            assert_eq!(detect(&dir.join(name)).unwrap(), Some(expected), "{}", name);
+        }
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn missing_end_markers_mean_truncation() {
+        for (format, expected) in [
+            (image::ImageFormat::Jpeg, Format::Jpeg),
+            (image::ImageFormat::Png, Format::Png),
+            (image::ImageFormat::Gif, Format::Gif),
+        ] {
+            let bytes = encode(format);
+            assert!(has_end_marker(&bytes, expected), "{:?}", format);
+            assert!(
+                !has_end_marker(&bytes[..bytes.len() - 8], expected),
+                "{:?}",
+                format
+            );
+        }
+
+        let mut padded = encode(image::ImageFormat::Gif);
+        padded.extend([0, 0, 0]);
+        assert!(has_end_marker(&padded, Format::Gif));
+    }
+}
@@ -0,0 +1,461 @@
+use rusqlite::{params, Connection};
+use serde::{Deserialize, Serialize};
+
+use crate::catalog::{
+    attach_tags, db_err, row_to_image, Catalog, IMAGE_COLUMNS, IMAGE_COLUMN_COUNT,
+};
+use crate::scan::ImageInfo;
+
+const DEFAULT_PAGE_SIZE: u32 = 50;
+const MAX_PAGE_SIZE: u32 = 500;
+/// Similar index terms tried for a word that matches nothing.
+const MAX_TYPO_CANDIDATES: usize = 3;
+
+/// BM25 column weights for `image_id, name, tags, description`. A tag hit
+/// says more about an image than a word somewhere in its description.
+const BM25_WEIGHTS: &str = "0.0, 5.0, 10.0, 2.0";
+
+/// Markers put around matches by `highlight()` and `snippet()`. Control
+/// characters never appear in names, tags or cleaned-up descriptions.
+const MATCH_START: char = '\u{1}';
+const MATCH_END: char = '\u{2}';
+
+#[derive(Deserialize, Default)]
+#[serde(rename_all = "camelCase", default)]
+pub struct PageRequest {
+    /// Zero-based.
+    pub page: u32,
+    pub page_size: Option<u32>,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TextSearchPage {
+    /// Matches across all pages.
+    total: usize,
+    page: u32,
+    page_size: u32,
+    results: Vec<TextMatch>,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TextMatch {
+    image: ImageInfo,
+    /// Higher is more relevant.
+    score: f64,
+    name: Vec<Segment>,
+    /// A short excerpt of the description around the matches.
+    description: Vec<Segment>,
+    matched_tags: Vec<String>,
+}
+
+/// A run of text, either matched by the query or not. The UI renders the
+/// segments in order and styles the matched ones; no markup involved.
+#[derive(Serialize, Debug, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct Segment {
+    text: String,
+    matched: bool,
+}
+
+/// Ranked full-text search over names, tags and descriptions. Words are
+/// stemmed (`beaches` finds `beach`), `*` makes a word a prefix and so does
+/// leaving the last word unfinished, quotes match a phrase, and a word that
+/// matches nothing is swapped for close spellings from the index.
+pub fn search(catalog: &Catalog, text: &str, page: &PageRequest) -> Result<TextSearchPage, String> {
+    let page_size = page
+        .page_size
+        .unwrap_or(DEFAULT_PAGE_SIZE)
+        .clamp(1, MAX_PAGE_SIZE);
+    let mut result = TextSearchPage {
+        total: 0,
+        page: page.page,
+        page_size,
+        results: Vec::new(),
+    };
+
+    let conn = catalog.conn();
+    let Some(query) = fts_query(&conn, text)? else {
+        return Ok(result);
+    };
+
+    result.total = conn
+        .query_row(
+            "SELECT COUNT(*) FROM image_text WHERE image_text MATCH ?1",
+            [&query],
+            |r| r.get(0),
+        )
+        .map_err(db_err)?;
+
+    let mut stmt = conn
+        .prepare(&format!(
+            "SELECT {columns},
+                    bm25(image_text, {weights}) AS rank,
+                    highlight(image_text, 1, char(1), char(2)),
+                    highlight(image_text, 2, char(1), char(2)),
+                    snippet(image_text, 3, char(1), char(2), '…', 24)
+             FROM image_text JOIN images i ON i.id = image_text.image_id
+             WHERE image_text MATCH ?1
+             ORDER BY rank, i.relative_path COLLATE natural_order
+             LIMIT ?2 OFFSET ?3",
+            columns = IMAGE_COLUMNS,
+            weights = BM25_WEIGHTS
+        ))
+        .map_err(db_err)?;
+    let rows = stmt
+        .query_map(
+            params![
+                query,
+                page_size,
+                u64::from(page.page) * u64::from(page_size)
+            ],
+            |row| {
+                // Ranking columns come after the image's own
+                let rank: f64 = row.get(IMAGE_COLUMN_COUNT)?;
+                let tags: String = row.get(IMAGE_COLUMN_COUNT + 2)?;
+                Ok(TextMatch {
+                    image: row_to_image(row)?,
+                    // bm25() is negative, more so for better matches
+                    score: -rank,
+                    name: segments(&row.get::<_, String>(IMAGE_COLUMN_COUNT + 1)?),
+                    description: segments(&row.get::<_, String>(IMAGE_COLUMN_COUNT + 3)?),
+                    matched_tags: tags
+                        .split(", ")
+                        .filter(|tag| tag.contains(MATCH_START))
+                        .map(|tag| tag.replace([MATCH_START, MATCH_END], ""))
+                        .collect(),
+                })
+            },
+        )
+        .map_err(db_err)?;
+    result.results = rows.collect::<Result<_, _>>().map_err(db_err)?;
+    drop(stmt);
+
+    let mut images: Vec<ImageInfo> = result.results.iter().map(|m| m.image.clone()).collect();
+    attach_tags(&conn, &mut images)?;
+    for (matched, image) in result.results.iter_mut().zip(images) {
+        matched.image = image;
+    }
+
+    Ok(result)
+}
+
+/// One word or phrase of the user's query.
+#[derive(Debug, PartialEq)]
+struct QueryTerm {
+    text: String,
+    prefix: bool,
+    phrase: bool,
+}
+
+/// Splits the user's text into words and quoted phrases. Operators and
+/// punctuation are not passed through, so any input is a valid FTS5 query.
+fn query_terms(text: &str) -> Vec<QueryTerm> {
+    let mut terms = Vec::new();
+    let mut rest = text;
+
+    while let Some(start) = rest.find(|c: char| c.is_alphanumeric() || c == '"') {
+        rest = &rest[start..];
+
+        if let Some(quoted) = rest.strip_prefix('"') {
+            let end = quoted.find('"').unwrap_or(quoted.len());
+            let phrase = words(&quoted[..end]).join(" ");
+            if !phrase.is_empty() {
+                terms.push(QueryTerm {
+                    text: phrase,
+                    prefix: false,
+                    phrase: true,
+                });
+            }
+            rest = quoted.get(end + 1..).unwrap_or("");
+            continue;
+        }
+
+        let end = rest
+            .find(|c: char| !c.is_alphanumeric())
+            .unwrap_or(rest.len());
+        let prefix = rest[end..].starts_with('*');
+        terms.push(QueryTerm {
+            text: rest[..end].to_lowercase(),
+            prefix,
+            phrase: false,
+        });
+        rest = &rest[end..];
+    }
+
+    // Still typing: treat the last word as a prefix
+    let unfinished = text.ends_with(|c: char| c.is_alphanumeric());
+    if let Some(last) = terms.last_mut().filter(|t| !t.phrase && unfinished) {
+        last.prefix = true;
+    }
+    terms
+}
+
+fn words(text: &str) -> Vec<String> {
+    text.split(|c: char| !c.is_alphanumeric())
+        .filter(|w| !w.is_empty())
+        .map(str::to_lowercase)
+        .collect()
+}
+
+/// Builds the FTS5 `MATCH` expression, or `None` if there is nothing to search for.
+fn fts_query(conn: &Connection, text: &str) -> Result<Option<String>, String> {
+    let terms = query_terms(text);
+    if terms.is_empty() {
+        return Ok(None);
+    }
+
+    let mut parts = Vec::with_capacity(terms.len());
+    for term in &terms {
+        let exact = quote(&term.text, term.prefix);
+        if term.prefix || term.phrase || has_match(conn, &exact)? {
+            parts.push(exact);
+            continue;
+        }
+
+        let candidates = typo_candidates(conn, &term.text)?;
+        if candidates.is_empty() {
+            parts.push(exact);
+        } else {
+            let alternatives: Vec<String> = std::iter::once(exact)
+                .chain(candidates.iter().map(|c| quote(c, false)))
+                .collect();
+            parts.push(format!("({})", alternatives.join(" OR ")));
+        }
+    }
+
+    // FTS5 only allows an implicit AND between plain phrases
+    Ok(Some(parts.join(" AND ")))
+}
+
+fn quote(text: &str, prefix: bool) -> String {
+    let quoted = format!("\"{}\"", text.replace('"', "\"\""));
+    if prefix {
+        quoted + "*"
+    } else {
+        quoted
+    }
+}
+
+fn has_match(conn: &Connection, query: &str) -> Result<bool, String> {
+    conn.query_row(
+        "SELECT EXISTS (SELECT 1 FROM image_text WHERE image_text MATCH ?1)",
+        [query],
+        |r| r.get(0),
+    )
+    .map_err(db_err)
+}
+
+/// Index terms within a small edit distance of `word`, most common first.
+fn typo_candidates(conn: &Connection, word: &str) -> Result<Vec<String>, String> {
+    let len = word.chars().count();
+    let max_edits = match len {
+        0..=3 => return Ok(Vec::new()),
+        4..=7 => 1,
+        _ => 2,
+    };
+
+    let mut stmt = conn
+        .prepare(
+            "SELECT term, doc FROM image_terms
+             WHERE length(term) BETWEEN ?1 AND ?2",
+        )
+        .map_err(db_err)?;
+    let rows = stmt
+        .query_map(params![len - max_edits, len + max_edits], |r| {
+            Ok((r.get::<_, String>(0)?, r.get::<_, i64>(1)?))
+        })
+        .map_err(db_err)?;
+
+    let mut candidates: Vec<(usize, i64, String)> = Vec::new();
+    for row in rows {
+        let (term, docs) = row.map_err(db_err)?;
+        let distance = edit_distance(word, &term);
+        if distance <= max_edits {
+            candidates.push((distance, -docs, term));
+        }
+    }
+    candidates.sort();
+
+    Ok(candidates
+        .into_iter()
+        .take(MAX_TYPO_CANDIDATES)
+        .map(|(_, _, term)| term)
+        .collect())
+}
+
+/// Optimal string alignment distance: Levenshtein plus adjacent
+/// transpositions, so "mountian" is one edit from "mountain".
+fn edit_distance(a: &str, b: &str) -> usize {
+    let a: Vec<char> = a.chars().collect();
+    let b: Vec<char> = b.chars().collect();
+    let mut rows = vec![vec![0usize; b.len() + 1]; a.len() + 1];
+
+    for (i, row) in rows.iter_mut().enumerate() {
+        row[0] = i;
+    }
+    for (j, cell) in rows[0].iter_mut().enumerate() {
+        *cell = j;
+    }
+    for i in 1..=a.len() {
+        for j in 1..=b.len() {
+            let cost = usize::from(a[i - 1] != b[j - 1]);
+            let mut best = (rows[i - 1][j] + 1)
+                .min(rows[i][j - 1] + 1)
+                .min(rows[i - 1][j - 1] + cost);
+            if i > 1 && j > 1 && a[i - 1] == b[j - 2] && a[i - 2] == b[j - 1] {
+                best = best.min(rows[i - 2][j - 2] + 1);
+            }
+            rows[i][j] = best;
+        }
+    }
+    rows[a.len()][b.len()]
+}
+
+/// Splits `highlight()`/`snippet()` output into plain and matched runs.
+fn segments(marked: &str) -> Vec<Segment> {
+    let mut segments = Vec::new();
+    let mut matched = false;
+    for part in marked.split([MATCH_START, MATCH_END]) {
+        if !part.is_empty() {
+            segments.push(Segment {
+                text: part.to_string(),
+                matched,
+            });
+        }
+        matched = !matched;
+    }
+    segments
+}
+
+#[tauri::command]
+pub fn text_search(
+    catalog: tauri::State<'_, Catalog>,
+    text: String,
+    page: Option<PageRequest>,
+) -> Result<TextSearchPage, String> {
+    search(&catalog, &text, &page.unwrap_or_default())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{add_image, ImageRow};
+
+    fn catalog() -> Catalog {
+        let catalog = Catalog::open_in_memory().unwrap();
+        for (id, name, description) in [
+            (
+                "a",
+                "IMG_0001.jpg",
+                "Waves crashing on the beaches at sunset",
+            ),
+            (
+                "b",
+                "IMG_0002.jpg",
+                "Snowy mountain peaks under a clear sky",
+            ),
+            ("c", "beach_day.jpg", ""),
+        ] {
+            add_image(
+                &catalog,
+                ImageRow {
+                    id,
+                    relative_path: name,
+                    description,
+                    ..ImageRow::default()
+                },
+            );
+        }
+        catalog
+            .set_tags("b", &["mountain".to_string(), "snow".to_string()])
+            .unwrap();
+        catalog
+            .set_tags("c", &["beach".to_string(), "family".to_string()])
+            .unwrap();
+        catalog
+    }
+
+    fn ids(catalog: &Catalog, text: &str) -> Vec<String> {
+        search(catalog, text, &PageRequest::default())
+            .unwrap()
+            .results
+            .into_iter()
+            .map(|m| m.image.id)
+            .collect()
+    }
+
+    #[test]
+    fn stemming_prefixes_and_typos() {
+        let catalog = catalog();
+        // Tag match outranks a word in a description
+        assert_eq!(ids(&catalog, "beaches "), ["c", "a"]);
+        assert_eq!(ids(&catalog, "mount"), ["b"]);
+        assert_eq!(ids(&catalog, "mountian snow "), ["b"]);
+        assert_eq!(ids(&catalog, "\"clear sky\" "), ["b"]);
+        assert_eq!(ids(&catalog, "\"sky clear\" "), Vec::<String>::new());
+        assert_eq!(ids(&catalog, "  ( OR * "), Vec::<String>::new());
+    }
+
+    #[test]
+    fn index_follows_catalog_changes() {
+        let catalog = catalog();
+        catalog.set_tags("a", &["ocean".to_string()]).unwrap();
+        catalog.set_description("b", "A quiet lake").unwrap();
+        catalog
+            .conn()
+            .execute("DELETE FROM images WHERE id = 'c'", [])
+            .unwrap();
+
+        assert_eq!(ids(&catalog, "ocean "), ["a"]);
+        assert_eq!(ids(&catalog, "lake "), ["b"]);
+        assert_eq!(ids(&catalog, "peaks "), Vec::<String>::new());
+        assert_eq!(ids(&catalog, "family "), Vec::<String>::new());
+    }
+
+    #[test]
+    fn highlights_and_paging() {
+        let catalog = catalog();
+        let page = search(
+            &catalog,
+            "sunset",
+            &PageRequest {
+                page: 0,
+                page_size: Some(1),
+            },
+        )
+        .unwrap();
+
+        assert_eq!(page.total, 1);
+        let hit = &page.results[0];
+        assert!(hit.description.contains(&Segment {
+            text: "sunset".to_string(),
+            matched: true,
+        }));
+
+        let page = search(
+            &catalog,
+            "beach ",
+            &PageRequest {
+                page: 1,
+                page_size: Some(1),
+            },
+        )
+        .unwrap();
+        assert_eq!(page.total, 2);
+        assert_eq!(page.results.len(), 1);
+        assert_eq!(page.results[0].image.id, "a");
+
+        let tagged = search(&catalog, "family", &PageRequest::default()).unwrap();
+        assert_eq!(tagged.results[0].matched_tags, ["family"]);
+        assert_eq!(tagged.results[0].image.tags, ["beach", "family"]);
+    }
+
+    #[test]
+    fn edit_distances() {
+        assert_eq!(edit_distance("mountian", "mountain"), 1);
+        assert_eq!(edit_distance("beach", "bech"), 1);
+        assert_eq!(edit_distance("cat", "dog"), 3);
+    }
+}
@@ -0,0 +1,51 @@
+use std::fs::{self, File};
+use std::io;
+use std::path::Path;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Size and modification time, used to skip rehashing files that haven't changed.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct FileStamp {
+    pub size: u64,
+    pub modified: i64,
+}
+
+pub fn file_stamp(metadata: &fs::Metadata) -> FileStamp {
+    FileStamp {
+        size: metadata.len(),
+        modified: millis(metadata.modified()).unwrap_or(0),
+    }
+}
+
+/// When the file was created, on filesystems that record it.
+pub fn created(metadata: &fs::Metadata) -> Option<i64> {
+    millis(metadata.created())
+}
+
+fn millis(time: io::Result<SystemTime>) -> Option<i64> {
+    time.ok()
+        .and_then(|t| t.duration_since(UNIX_EPOCH).ok())
+        .map(|d| d.as_millis() as i64)
+}
+
+/// BLAKE3 hash of the file contents as lowercase hex.
+pub fn content_hash(path: &Path) -> io::Result<String> {
+    let mut hasher = blake3::Hasher::new();
+    let mut file = File::open(path)?;
+    io::copy(&mut file, &mut hasher)?;
+    Ok(hasher.finalize().to_hex().to_string())
+}
+
+/// Image id derived from the content hash, so it survives rescans, renames and moves.
+pub fn image_id(content_hash: &str) -> String {
+    format!("img_{}", &content_hash[..16])
+}
+
+/// Id for a byte-identical copy of an image that already owns `image_id(hash)`.
+/// Mixing in the path keeps it stable for that copy across rescans.
+pub fn copy_image_id(content_hash: &str, path: &str) -> String {
+    let mut hasher = blake3::Hasher::new();
+    hasher.update(content_hash.as_bytes());
+    hasher.update(path.as_bytes());
+    image_id(hasher.finalize().to_hex().as_str())
+}
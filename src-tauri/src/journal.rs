@@ -0,0 +1,566 @@
+use rusqlite::OptionalExtension;
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::path::PathBuf;
+use std::sync::Mutex;
+
+use crate::catalog::{db_err, now_secs, Annotations, Catalog};
+use crate::file_ops;
+use crate::trash::{Trash, Trashed};
+
+/// Held while an operation is being undone or redone, so two at once
+/// don't both pick the same one.
+static REVERSING: Mutex<()> = Mutex::new(());
+
+/// One change to the library, with what it takes to reverse it.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(tag = "kind", rename_all = "camelCase")]
+pub enum Step {
+    /// An image's files moved or renamed, its RAW partner after it.
+    #[serde(rename_all = "camelCase")]
+    Move {
+        image_id: String,
+        files: Vec<Relocation>,
+    },
+    /// Copies made of an image's files, which got a record of their own.
+    #[serde(rename_all = "camelCase")]
+    Copy {
+        image_id: String,
+        files: Vec<PathBuf>,
+    },
+    /// Files sent to the trash. When they were an image's, its record was
+    /// dropped; what only the record knew is kept here.
+    #[serde(rename_all = "camelCase")]
+    Trash {
+        image_id: Option<String>,
+        annotations: Annotations,
+        files: Vec<TrashedFile>,
+    },
+    /// Files brought back from the trash, and the record made for them
+    /// if they were an image's.
+    #[serde(rename_all = "camelCase")]
+    Restore {
+        image_id: Option<String>,
+        files: Vec<PathBuf>,
+    },
+    /// An image's tags replaced. `file_tags` are those that came from the
+    /// file's keywords and `model_tags` those the model suggested, which
+    /// stay marked as such when put back.
+    #[serde(rename_all = "camelCase")]
+    Tags {
+        image_id: String,
+        before: Vec<String>,
+        after: Vec<String>,
+        file_tags: Vec<String>,
+        #[serde(default)]
+        model_tags: Vec<String>,
+    },
+    #[serde(rename_all = "camelCase")]
+    Description {
+        image_id: String,
+        before: String,
+        after: String,
+    },
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct Relocation {
+    pub from: PathBuf,
+    pub to: PathBuf,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct TrashedFile {
+    pub path: PathBuf,
+    pub trashed: Trashed,
+}
+
+/// An operation as the history lists it.
+#[derive(Serialize, Clone, Debug, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct HistoryEntry {
+    pub id: i64,
+    /// When it was done, in seconds since the epoch.
+    pub at: i64,
+    pub summary: String,
+    /// Undone and waiting to be redone.
+    pub undone: bool,
+}
+
+/// Records a finished operation. Nothing is recorded if it changed nothing.
+/// Anything undone is forgotten, since it can no longer be redone on top.
+pub fn record(catalog: &Catalog, summary: &str, steps: &[Step]) -> Result<(), String> {
+    record_in_batch(catalog, None, |_| summary.to_string(), steps)
+}
+
+/// Records a finished operation as part of `batch`. While the batch's
+/// operation is still the latest one, the steps are added to it, so the
+/// whole batch is undone at once; `summary` is given how many images it
+/// then covers.
+fn record_in_batch(
+    catalog: &Catalog,
+    batch: Option<i64>,
+    summary: impl Fn(usize) -> String,
+    steps: &[Step],
+) -> Result<(), String> {
+    if steps.is_empty() {
+        return Ok(());
+    }
+    let mut conn = catalog.conn();
+    let tx = conn.transaction().map_err(db_err)?;
+
+    let latest = match batch {
+        Some(batch) => tx
+            .query_row(
+                "SELECT id, steps FROM journal
+                 WHERE id = (SELECT max(id) FROM journal) AND batch = ?1 AND undone = 0",
+                [batch],
+                |r| Ok((r.get::<_, i64>(0)?, r.get::<_, String>(1)?)),
+            )
+            .optional()
+            .map_err(db_err)?,
+        None => None,
+    };
+
+    if let Some((id, earlier)) = latest {
+        let mut all: Vec<Step> =
+            serde_json::from_str(&earlier).map_err(|e| format!("Failed to read history: {}", e))?;
+        all.extend_from_slice(steps);
+        let json = serde_json::to_string(&all).map_err(|e| e.to_string())?;
+        tx.execute(
+            "UPDATE journal SET summary = ?2, steps = ?3 WHERE id = ?1",
+            rusqlite::params![id, summary(image_count(&all)), json],
+        )
+        .map_err(db_err)?;
+    } else {
+        let json = serde_json::to_string(steps).map_err(|e| e.to_string())?;
+        tx.execute("DELETE FROM journal WHERE undone = 1", [])
+            .map_err(db_err)?;
+        tx.execute(
+            "INSERT INTO journal (at, summary, steps, batch) VALUES (?1, ?2, ?3, ?4)",
+            rusqlite::params![now_secs(), summary(image_count(steps)), json, batch],
+        )
+        .map_err(db_err)?;
+    }
+    tx.commit().map_err(db_err)
+}
+
+/// How many images `steps` edit the tags or description of.
+fn image_count(steps: &[Step]) -> usize {
+    let ids: HashSet<&str> = steps
+        .iter()
+        .filter_map(|step| match step {
+            Step::Tags { image_id, .. } | Step::Description { image_id, .. } => {
+                Some(image_id.as_str())
+            }
+            _ => None,
+        })
+        .collect();
+    ids.len()
+}
+
+/// Runs `change` and records what it did to the tags and descriptions of
+/// `image_ids` as one operation.
+pub fn edit<T, E: From<String>>(
+    catalog: &Catalog,
+    summary: &str,
+    image_ids: &[String],
+    change: impl FnOnce() -> Result<T, E>,
+) -> Result<T, E> {
+    edit_in_batch(catalog, None, |_| summary.to_string(), image_ids, change)
+}
+
+/// Like `edit`, but records the change as part of `batch`, as
+/// `record_in_batch` does.
+pub fn edit_in_batch<T, E: From<String>>(
+    catalog: &Catalog,
+    batch: Option<i64>,
+    summary: impl Fn(usize) -> String,
+    image_ids: &[String],
+    change: impl FnOnce() -> Result<T, E>,
+) -> Result<T, E> {
+    let before = catalog.images_by_ids(image_ids)?;
+    let result = change()?;
+    let after = catalog.images_by_ids(image_ids)?;
+
+    let mut steps = Vec::new();
+    for old in &before {
+        let Some(new) = after.iter().find(|new| new.id == old.id) else {
+            continue;
+        };
+        if old.tags != new.tags {
+            let both = |old: &[String], new: &[String]| -> Vec<String> {
+                let mut both = old.to_vec();
+                both.extend(new.iter().filter(|tag| !old.contains(tag)).cloned());
+                both
+            };
+            steps.push(Step::Tags {
+                image_id: old.id.clone(),
+                before: old.tags.clone(),
+                after: new.tags.clone(),
+                file_tags: both(&old.file_tags, &new.file_tags),
+                model_tags: both(&old.model_tags, &new.model_tags),
+            });
+        }
+        if old.description != new.description {
+            steps.push(Step::Description {
+                image_id: old.id.clone(),
+                before: old.description.clone(),
+                after: new.description.clone(),
+            });
+        }
+    }
+    record_in_batch(catalog, batch, summary, &steps)?;
+    Ok(result)
+}
+
+/// An image's file name, for summaries.
+pub fn name_of(catalog: &Catalog, image_id: &str) -> Result<String, String> {
+    catalog
+        .images_by_ids(&[image_id.to_string()])?
+        .pop()
+        .map(|image| image.name)
+        .ok_or_else(|| format!("Unknown image: {}", image_id))
+}
+
+/// Every operation kept, newest first.
+pub fn history(catalog: &Catalog) -> Result<Vec<HistoryEntry>, String> {
+    let conn = catalog.conn();
+    let mut stmt = conn
+        .prepare("SELECT id, at, summary, undone FROM journal ORDER BY id DESC")
+        .map_err(db_err)?;
+    let rows = stmt
+        .query_map([], |r| {
+            Ok(HistoryEntry {
+                id: r.get(0)?,
+                at: r.get(1)?,
+                summary: r.get(2)?,
+                undone: r.get(3)?,
+            })
+        })
+        .map_err(db_err)?;
+    rows.collect::<Result<_, _>>().map_err(db_err)
+}
+
+/// Forgets operations done more than `days` ago. Returns how many.
+pub fn prune(catalog: &Catalog, days: u32) -> Result<usize, String> {
+    let cutoff = now_secs() - i64::from(days) * 86_400;
+    catalog
+        .conn()
+        .execute("DELETE FROM journal WHERE at < ?1", [cutoff])
+        .map_err(db_err)
+}
+
+/// Undoes the latest operation not already undone. `None` if there is none.
+pub fn undo_last(catalog: &Catalog, trash: &Trash) -> Result<Option<HistoryEntry>, String> {
+    reverse_entry(
+        catalog,
+        trash,
+        "SELECT id, at, summary, steps FROM journal WHERE undone = 0 ORDER BY id DESC LIMIT 1",
+        true,
+    )
+}
+
+/// Redoes the earliest undone operation. `None` if there is none.
+pub fn redo_next(catalog: &Catalog, trash: &Trash) -> Result<Option<HistoryEntry>, String> {
+    reverse_entry(
+        catalog,
+        trash,
+        "SELECT id, at, summary, steps FROM journal WHERE undone = 1 ORDER BY id LIMIT 1",
+        false,
+    )
+}
+
+/// Reverses the operation `query` picks. Its steps are replaced by those
+/// that reverse it back, so redoing is undoing the undo.
+fn reverse_entry(
+    catalog: &Catalog,
+    trash: &Trash,
+    query: &str,
+    undone: bool,
+) -> Result<Option<HistoryEntry>, String> {
+    let _reversing = REVERSING.lock().unwrap_or_else(|e| e.into_inner());
+
+    let entry = catalog
+        .conn()
+        .query_row(query, [], |r| {
+            Ok((
+                r.get::<_, i64>(0)?,
+                r.get::<_, i64>(1)?,
+                r.get::<_, String>(2)?,
+                r.get::<_, String>(3)?,
+            ))
+        })
+        .optional()
+        .map_err(db_err)?;
+    let Some((id, at, summary, steps)) = entry else {
+        return Ok(None);
+    };
+    let steps: Vec<Step> =
+        serde_json::from_str(&steps).map_err(|e| format!("Failed to read history: {}", e))?;
+
+    let verb = if undone { "undo" } else { "redo" };
+    let reversed = reverse_all(catalog, trash, &steps)
+        .map_err(|e| format!("Couldn't {} \"{}\": {}", verb, summary, e))?;
+    let reversed = serde_json::to_string(&reversed).map_err(|e| e.to_string())?;
+    catalog
+        .conn()
+        .execute(
+            "UPDATE journal SET steps = ?2, undone = ?3 WHERE id = ?1",
+            rusqlite::params![id, reversed, undone],
+        )
+        .map_err(db_err)?;
+
+    Ok(Some(HistoryEntry {
+        id,
+        at,
+        summary,
+        undone,
+    }))
+}
+
+/// Reverses `steps`, last first, returning the steps that reverse them
+/// back. If one fails, those already reversed are put back first.
+fn reverse_all(catalog: &Catalog, trash: &Trash, steps: &[Step]) -> Result<Vec<Step>, String> {
+    let mut done = Vec::new();
+    for step in steps.iter().rev() {
+        match reverse(catalog, trash, step) {
+            Ok(back) => done.push(back),
+            Err(e) => {
+                for back in done.iter().rev() {
+                    if let Err(e) = reverse(catalog, trash, back) {
+                        log::warn!("Failed to put back a partly reversed operation: {}", e);
+                    }
+                }
+                return Err(e);
+            }
+        }
+    }
+    Ok(done)
+}
+
+fn reverse(catalog: &Catalog, trash: &Trash, step: &Step) -> Result<Step, String> {
+    match step {
+        Step::Tags {
+            image_id,
+            before,
+            after,
+            file_tags,
+            model_tags,
+        } => {
+            catalog.restore_tags(image_id, before, file_tags, model_tags)?;
+            Ok(Step::Tags {
+                image_id: image_id.clone(),
+                before: after.clone(),
+                after: before.clone(),
+                file_tags: file_tags.clone(),
+                model_tags: model_tags.clone(),
+            })
+        }
+        Step::Description {
+            image_id,
+            before,
+            after,
+        } => {
+            catalog.set_description(image_id, before)?;
+            Ok(Step::Description {
+                image_id: image_id.clone(),
+                before: after.clone(),
+                after: before.clone(),
+            })
+        }
+        _ => file_ops::reverse(catalog, trash, step),
+    }
+}
+
+#[tauri::command]
+pub fn list_history(catalog: tauri::State<'_, Catalog>) -> Result<Vec<HistoryEntry>, String> {
+    history(&catalog)
+}
+
+/// Undoes the latest operation, returning it, or nothing if there's nothing to undo.
+#[tauri::command]
+pub fn undo(catalog: tauri::State<'_, Catalog>) -> Result<Option<HistoryEntry>, String> {
+    undo_last(&catalog, &Trash::home()?)
+}
+
+#[tauri::command]
+pub fn redo(catalog: tauri::State<'_, Catalog>) -> Result<Option<HistoryEntry>, String> {
+    redo_next(&catalog, &Trash::home()?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::formats::Format;
+    use crate::identity::FileStamp;
+    use crate::metadata::{FileDetails, ImageMetadata};
+    use crate::scan::{ScanOptions, ScannedFile};
+    use std::path::Path;
+
+    fn strings(tags: &[&str]) -> Vec<String> {
+        tags.iter().map(|t| t.to_string()).collect()
+    }
+
+    /// A catalog with one image whose file has the keyword "beach".
+    fn catalog() -> (Catalog, String) {
+        let catalog = Catalog::open_in_memory().unwrap();
+        let root = Path::new("/photos");
+        catalog.begin_scan(root, &ScanOptions::default()).unwrap();
+        let file = ScannedFile {
+            path: "/photos/a.jpg".to_string(),
+            relative_path: "a.jpg".to_string(),
+            name: "a.jpg".to_string(),
+            content_hash: "0123456789abcdef0123".to_string(),
+            stamp: FileStamp {
+                size: 1,
+                modified: 0,
+            },
+            created: None,
+            format: Format::Jpeg,
+            appearance: None,
+            issues: Vec::new(),
+            raw_path: None,
+            details: Some(FileDetails {
+                metadata: ImageMetadata {
+                    keywords: strings(&["Beach"]),
+                    ..ImageMetadata::default()
+                },
+                ..FileDetails::default()
+            }),
+        };
+        let id = catalog.merge_batch(root, &[file]).unwrap()[0].id.clone();
+        (catalog, id)
+    }
+
+    fn summaries(catalog: &Catalog) -> Vec<(String, bool)> {
+        history(catalog)
+            .unwrap()
+            .into_iter()
+            .map(|entry| (entry.summary, entry.undone))
+            .collect()
+    }
+
+    #[test]
+    fn edits_undo_and_redo_in_order() {
+        let (catalog, id) = catalog();
+        let trash = Trash::at(std::env::temp_dir().join("journal_unused_trash"));
+        let ids = [id.clone()];
+        let image = |catalog: &Catalog| catalog.images_by_ids(&ids).unwrap().remove(0);
+
+        edit(&catalog, "Edited tags of a.jpg", &ids, || {
+            catalog.set_tags(&id, &strings(&["boat"]))
+        })
+        .unwrap();
+        edit(&catalog, "Edited description of a.jpg", &ids, || {
+            catalog.set_description(&id, "A boat")
+        })
+        .unwrap();
+        // Changing nothing records nothing
+        edit(&catalog, "Edited tags of a.jpg", &ids, || {
+            catalog.set_tags(&id, &strings(&["boat"]))
+        })
+        .unwrap();
+        assert_eq!(
+            summaries(&catalog),
+            [
+                ("Edited description of a.jpg".to_string(), false),
+                ("Edited tags of a.jpg".to_string(), false)
+            ]
+        );
+
+        let undone = undo_last(&catalog, &trash).unwrap().unwrap();
+        assert_eq!(undone.summary, "Edited description of a.jpg");
+        assert!(undone.undone);
+        assert_eq!(image(&catalog).description, "");
+        undo_last(&catalog, &trash).unwrap().unwrap();
+        // The keyword comes back as the file's own
+        assert_eq!(image(&catalog).tags, ["beach"]);
+        assert_eq!(image(&catalog).file_tags, ["beach"]);
+        assert_eq!(undo_last(&catalog, &trash).unwrap(), None);
+
+        let redone = redo_next(&catalog, &trash).unwrap().unwrap();
+        assert_eq!(redone.summary, "Edited tags of a.jpg");
+        assert_eq!(image(&catalog).tags, ["boat"]);
+        assert_eq!(image(&catalog).description, "");
+
+        // Something new gives up on redoing the rest
+        edit(&catalog, "Edited tags of a.jpg", &ids, || {
+            catalog.set_tags(&id, &strings(&["boat", "sea"]))
+        })
+        .unwrap();
+        assert_eq!(redo_next(&catalog, &trash).unwrap(), None);
+        assert_eq!(
+            summaries(&catalog),
+            [
+                ("Edited tags of a.jpg".to_string(), false),
+                ("Edited tags of a.jpg".to_string(), false)
+            ]
+        );
+    }
+
+    #[test]
+    fn a_batch_is_one_operation_while_it_is_the_latest() {
+        let (catalog, id) = catalog();
+        let trash = Trash::at(std::env::temp_dir().join("journal_unused_trash"));
+        let ids = [id.clone()];
+        let summary = |n: usize| format!("Tagged images: {}", n);
+
+        for tags in [["boat"], ["sea"]] {
+            edit_in_batch(&catalog, Some(1), summary, &ids, || {
+                catalog.set_tags(&id, &strings(&tags))
+            })
+            .unwrap();
+        }
+        assert_eq!(
+            summaries(&catalog),
+            [("Tagged images: 1".to_string(), false)]
+        );
+        undo_last(&catalog, &trash).unwrap().unwrap();
+        let image = catalog.images_by_ids(&ids).unwrap().remove(0);
+        assert_eq!(image.tags, ["beach"]);
+        redo_next(&catalog, &trash).unwrap().unwrap();
+
+        // Once something else is done, the batch carries on in a new operation
+        edit(&catalog, "Described a.jpg", &ids, || {
+            catalog.set_description(&id, "A boat")
+        })
+        .unwrap();
+        edit_in_batch(&catalog, Some(1), summary, &ids, || {
+            catalog.set_tags(&id, &strings(&["harbour"]))
+        })
+        .unwrap();
+        assert_eq!(
+            summaries(&catalog),
+            [
+                ("Tagged images: 1".to_string(), false),
+                ("Described a.jpg".to_string(), false),
+                ("Tagged images: 1".to_string(), false)
+            ]
+        );
+    }
+
+    #[test]
+    fn old_operations_are_pruned() {
+        let (catalog, id) = catalog();
+        for tags in [["old"], ["new"]] {
+            edit(
+                &catalog,
+                &format!("Tagged {}", tags[0]),
+                std::slice::from_ref(&id),
+                || catalog.set_tags(&id, &strings(&tags)),
+            )
+            .unwrap();
+        }
+        catalog
+            .conn()
+            .execute(
+                "UPDATE journal SET at = at - 31 * 86400 WHERE summary = 'Tagged old'",
+                [],
+            )
+            .unwrap();
+
+        assert_eq!(prune(&catalog, 30).unwrap(), 1);
+        assert_eq!(summaries(&catalog), [("Tagged new".to_string(), false)]);
+    }
+}
@@ -1,142 +1,53 @@
-use base64::{engine::general_purpose::STANDARD, Engine};
-use serde::{Deserialize, Serialize};
 use std::fs;
-use std::path::PathBuf;
-
-#[derive(Serialize)]
-pub struct ImageInfo {
-    id: String,
-    path: String,
-    name: String,
-    tags: Vec<String>,
-    description: String,
-}
-
-#[derive(Serialize)]
-struct OllamaRequest {
-    model: String,
-    prompt: String,
-    images: Vec<String>,
-    stream: bool,
-}
-
-#[derive(Deserialize)]
-struct OllamaResponse {
-    response: String,
-}
-
-const IMAGE_EXTENSIONS: &[&str] = &["jpg", "jpeg", "png", "gif", "webp", "bmp", "tiff", "tif", "svg"];
-
-#[tauri::command]
-fn scan_folder(folder_path: String) -> Result<Vec<ImageInfo>, String> {
-    let path = PathBuf::from(&folder_path);
-
-    if !path.exists() {
-        return Err("Folder does not exist".to_string());
-    }
-
-    if !path.is_dir() {
-        return Err("Path is not a directory".to_string());
-    }
-
-    let mut images: Vec<ImageInfo> = Vec::new();
-
-    match fs::read_dir(&path) {
-        Ok(entries) => {
-            for (index, entry) in entries.enumerate() {
-                if let Ok(entry) = entry {
-                    let file_path = entry.path();
-
-                    if file_path.is_file() {
-                        if let Some(extension) = file_path.extension() {
-                            let ext = extension.to_string_lossy().to_lowercase();
-
-                            if IMAGE_EXTENSIONS.contains(&ext.as_str()) {
-                                let file_name = file_path
-                                    .file_name()
-                                    .map(|n| n.to_string_lossy().to_string())
-                                    .unwrap_or_default();
-
-                                images.push(ImageInfo {
-                                    id: format!("img_{}", index),
-                                    path: file_path.to_string_lossy().to_string(),
-                                    name: file_name,
-                                    tags: Vec::new(),
-                                    description: String::new(),
-                                });
-                            }
-                        }
-                    }
-                }
-            }
-        }
-        Err(e) => return Err(format!("Failed to read directory: {}", e)),
-    }
-
-    // Sort by filename
-    images.sort_by(|a, b| a.name.to_lowercase().cmp(&b.name.to_lowercase()));
-
-    Ok(images)
-}
-
-#[tauri::command]
-fn generate_tags(image_path: String) -> Result<Vec<String>, String> {
-    // Read the image file
-    let image_bytes = fs::read(&image_path)
-        .map_err(|e| format!("Failed to read image: {}", e))?;
-
-    // Base64 encode the image
-    let image_base64 = STANDARD.encode(&image_bytes);
-
-    // Create request to Ollama
-    let request = OllamaRequest {
-        model: "moondream".to_string(),
-        prompt: "List 5-10 descriptive tags for this image. Output only the tags separated by commas, nothing else. Example: nature, sunset, mountain, peaceful, orange sky".to_string(),
-        images: vec![image_base64],
-        stream: false,
-    };
-
-    // Call Ollama API
-    let client = reqwest::blocking::Client::new();
-    let response = client
-        .post("http://localhost:11434/api/generate")
-        .json(&request)
-        .send()
-        .map_err(|e| format!("Failed to call Ollama: {}. Is Ollama running?", e))?;
-
-    if !response.status().is_success() {
-        return Err(format!("Ollama returned error: {}", response.status()));
-    }
-
-    let ollama_response: OllamaResponse = response
-        .json()
-        .map_err(|e| format!("Failed to parse Ollama response: {}", e))?;
-
-    // Parse tags from response
-    let tags: Vec<String> = ollama_response
-        .response
-        .split(',')
-        .map(|s| s.trim().to_lowercase())
-        .filter(|s| !s.is_empty() && s.len() < 50) // Filter out empty and overly long strings
-        .collect();
-
-    Ok(tags)
-}
-
-#[tauri::command]
-fn check_ollama() -> Result<bool, String> {
-    let client = reqwest::blocking::Client::new();
-    match client.get("http://localhost:11434/api/tags").send() {
-        Ok(response) => Ok(response.status().is_success()),
-        Err(_) => Ok(false),
-    }
-}
+use tauri::Manager;
+
+mod ai;
+mod catalog;
+mod colours;
+mod duplicates;
+mod embeddings;
+mod file_ops;
+mod fingerprint;
+mod formats;
+mod fulltext;
+mod identity;
+mod journal;
+mod metadata;
+mod model_image;
+mod natural;
+mod ollama;
+mod previews;
+mod scan;
+mod scan_jobs;
+mod search;
+mod settings;
+mod similar;
+mod tag_output;
+mod tagging_queue;
+mod tags;
+#[cfg(test)]
+mod test_support;
+mod thumbnails;
+mod trash;
+mod watcher;
+
+use catalog::Catalog;
+use embeddings::Embeddings;
+use ollama::Ollama;
+use scan_jobs::ScanJobs;
+use settings::SettingsStore;
+use tagging_queue::TaggingQueue;
+use thumbnails::Thumbnails;
+use watcher::Watchers;
 
 #[cfg_attr(mobile, tauri::mobile_entry_point)]
 pub fn run() {
     tauri::Builder::default()
         .plugin(tauri_plugin_dialog::init())
         .plugin(tauri_plugin_fs::init())
+        .register_asynchronous_uri_scheme_protocol(thumbnails::SCHEME, |ctx, request, responder| {
+            thumbnails::serve(ctx.app_handle(), request, responder)
+        })
         .setup(|app| {
             if cfg!(debug_assertions) {
                 app.handle().plugin(
@@ -145,9 +56,81 @@ pub fn run() {
                         .build(),
                 )?;
             }
+
+            let config_dir = app.path().app_config_dir()?;
+            let settings = SettingsStore::load(config_dir.join("settings.json"));
+            let cache_limit = settings.get().thumbnail_cache_mb * 1024 * 1024;
+            let history_days = settings.get().history_days;
+            app.manage(settings);
+            app.manage(Ollama::default());
+
+            let data_dir = app.path().app_data_dir()?;
+            fs::create_dir_all(&data_dir)?;
+            let catalog = Catalog::open(&data_dir.join("catalog.db"))?;
+            journal::prune(&catalog, history_days)?;
+            let roots = catalog.roots()?;
+            app.manage(catalog);
+
+            let watchers = Watchers::default();
+            for root in roots {
+                watchers.start(app.handle(), root.into())?;
+            }
+            app.manage(watchers);
+            app.manage(ScanJobs::default());
+            app.manage(TaggingQueue::default());
+            tagging_queue::start(app.handle());
+            app.manage(Embeddings::default());
+
+            let cache_dir = app.path().app_cache_dir()?;
+            app.manage(Thumbnails::open(cache_dir.join("thumbnails"), cache_limit)?);
+
             Ok(())
         })
-        .invoke_handler(tauri::generate_handler![scan_folder, generate_tags, check_ollama])
+        .invoke_handler(tauri::generate_handler![
+            scan::scan_folder,
+            scan::list_scan_issues,
+            scan_jobs::start_scan,
+            scan_jobs::cancel_scan,
+            scan_jobs::list_scans,
+            catalog::load_library,
+            catalog::query_images,
+            catalog::set_image_tags,
+            catalog::set_image_description,
+            catalog::list_roots,
+            search::search_images,
+            fulltext::text_search,
+            embeddings::semantic_search,
+            embeddings::build_embeddings,
+            embeddings::cancel_embeddings,
+            embeddings::embedding_status,
+            tags::list_tags,
+            tags::merge_tags,
+            tags::rename_tag,
+            tags::delete_tag,
+            thumbnails::clear_thumbnail_cache,
+            duplicates::find_duplicates,
+            similar::find_similar,
+            file_ops::rename_images,
+            file_ops::move_images,
+            file_ops::copy_images,
+            file_ops::delete_images,
+            journal::list_history,
+            journal::undo,
+            journal::redo,
+            settings::get_settings,
+            settings::update_settings,
+            watcher::start_watching,
+            watcher::stop_watching,
+            watcher::list_watched,
+            ai::generate_tags,
+            ai::generate_description,
+            ai::check_ollama,
+            tagging_queue::enqueue_tagging,
+            tagging_queue::pause_tagging,
+            tagging_queue::resume_tagging,
+            tagging_queue::cancel_tagging,
+            tagging_queue::tagging_status
+        ])
         .run(tauri::generate_context!())
         .expect("error while running tauri application");
 }
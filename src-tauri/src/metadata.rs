@@ -0,0 +1,1108 @@
+use image::{ImageDecoder, ImageFormat, ImageReader};
+use resvg::usvg;
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::io::Cursor;
+use std::path::Path;
+
+use crate::formats::{self, Format};
+use crate::previews;
+use crate::search::{days_from_civil, MS_PER_DAY};
+
+/// Camera and descriptive metadata embedded in an image file: EXIF for the
+/// capture details, XMP and IPTC for keywords and captions.
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq)]
+#[serde(rename_all = "camelCase", default)]
+pub struct ImageMetadata {
+    /// Capture time in milliseconds since the epoch. Cameras that don't
+    /// record a UTC offset get their local clock time read as UTC.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub taken: Option<i64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub camera_make: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub camera_model: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub lens: Option<String>,
+    /// Seconds.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub exposure_time: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub f_number: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub iso: Option<u32>,
+    /// Millimetres.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub focal_length: Option<f64>,
+    /// EXIF orientation, 1 to 8.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub orientation: Option<u16>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub gps: Option<GpsPosition>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub caption: Option<String>,
+    /// Keywords from XMP `dc:subject` and IPTC, as written in the file.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub keywords: Vec<String>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct GpsPosition {
+    pub latitude: f64,
+    pub longitude: f64,
+    /// Metres above sea level.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub altitude: Option<f64>,
+}
+
+/// What a scan reads from inside an image file: its pixel size and embedded
+/// metadata. Only headers and containers are read, never the pixel data.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct FileDetails {
+    /// Size as displayed, i.e. with the EXIF orientation applied.
+    pub width: Option<u32>,
+    pub height: Option<u32>,
+    pub metadata: ImageMetadata,
+}
+
+/// Reads whatever details the file has. Formats without metadata support
+/// and unreadable files give a partial or empty record; metadata is never
+/// a reason to skip an image.
+pub fn read(path: &Path) -> FileDetails {
+    let format = formats::detect(path).ok().flatten();
+
+    let mut details = FileDetails::default();
+    let read = match format {
+        Some(Format::Svg) => read_svg(path, &mut details),
+        Some(format) if format.needs_preview() => read_embedded(path, &mut details),
+        _ => read_raster(path, &mut details),
+    };
+    if let Err(e) = read {
+        log::debug!("Incomplete details for {}: {}", path.display(), e);
+    }
+    details
+}
+
+fn read_raster(path: &Path, details: &mut FileDetails) -> Result<(), String> {
+    let reader = ImageReader::open(path)
+        .and_then(|reader| reader.with_guessed_format())
+        .map_err(|e| e.to_string())?;
+    let mut decoder = reader.into_decoder().map_err(|e| e.to_string())?;
+
+    let exif = decoder.exif_metadata().ok().flatten();
+    let xmp = decoder.xmp_metadata().ok().flatten();
+    let iptc = decoder.iptc_metadata().ok().flatten();
+    details.metadata = from_chunks(&Chunks {
+        exif: exif.as_deref(),
+        exif_ifd: None,
+        xmp: xmp.as_deref(),
+        iptc: iptc.as_deref(),
+    });
+
+    details.set_size(decoder.dimensions());
+    Ok(())
+}
+
+/// RAW, HEIC and AVIF: metadata from the container, and the size from the
+/// container, the EXIF or, failing both, the embedded preview.
+fn read_embedded(path: &Path, details: &mut FileDetails) -> Result<(), String> {
+    let embedded = previews::read(path)?;
+    let chunks = Chunks {
+        exif: embedded.exif(),
+        exif_ifd: embedded.exif_ifd(),
+        xmp: embedded.xmp(),
+        iptc: None,
+    };
+    details.metadata = from_chunks(&chunks);
+
+    let size = embedded
+        .size
+        .or_else(|| pixel_size(chunks.exif?, chunks.exif_ifd))
+        .or_else(|| {
+            ImageReader::with_format(Cursor::new(embedded.preview()?), ImageFormat::Jpeg)
+                .into_dimensions()
+                .ok()
+        })
+        .ok_or("No image size in the file")?;
+    details.set_size(size);
+    Ok(())
+}
+
+impl FileDetails {
+    /// Sets the size from the stored one, once the orientation is known.
+    fn set_size(&mut self, (width, height): (u32, u32)) {
+        // Orientations 5 to 8 turn the image on its side
+        let (width, height) = match self.metadata.orientation {
+            Some(5..=8) => (height, width),
+            _ => (width, height),
+        };
+        self.width = Some(width);
+        self.height = Some(height);
+    }
+}
+
+fn read_svg(path: &Path, details: &mut FileDetails) -> Result<(), String> {
+    let data = fs::read(path).map_err(|e| e.to_string())?;
+    let tree =
+        usvg::Tree::from_data(&data, &usvg::Options::default()).map_err(|e| e.to_string())?;
+
+    let size = tree.size().to_int_size();
+    details.width = Some(size.width());
+    details.height = Some(size.height());
+    Ok(())
+}
+
+/// The raw EXIF, XMP and IPTC blocks of a file.
+#[derive(Default)]
+struct Chunks<'a> {
+    exif: Option<&'a [u8]>,
+    /// An Exif IFD kept apart from `exif`, as Canon CR3 does.
+    exif_ifd: Option<&'a [u8]>,
+    xmp: Option<&'a [u8]>,
+    iptc: Option<&'a [u8]>,
+}
+
+fn from_chunks(chunks: &Chunks) -> ImageMetadata {
+    let mut metadata = chunks
+        .exif
+        .map(|exif| parse_exif(exif, chunks.exif_ifd))
+        .unwrap_or_default();
+
+    // XMP is the newer standard, so it wins over IPTC where both are present
+    let xmp = chunks.xmp.map(parse_xmp).unwrap_or_default();
+    let iptc = chunks.iptc.map(parse_iptc).unwrap_or_default();
+
+    metadata.taken = metadata.taken.or(xmp.taken).or(iptc.taken);
+    metadata.caption = xmp.caption.or(iptc.caption);
+    for keyword in xmp.keywords.into_iter().chain(iptc.keywords) {
+        let keyword = keyword.trim().to_string();
+        if !keyword.is_empty() && !metadata.keywords.contains(&keyword) {
+            metadata.keywords.push(keyword);
+        }
+    }
+    metadata
+}
+
+/// What the XMP and IPTC blocks contribute.
+#[derive(Default)]
+struct Descriptive {
+    taken: Option<i64>,
+    caption: Option<String>,
+    keywords: Vec<String>,
+}
+
+// EXIF tags, by IFD
+const TAG_MAKE: u16 = 0x010F;
+const TAG_MODEL: u16 = 0x0110;
+const TAG_ORIENTATION: u16 = 0x0112;
+const TAG_EXIF_IFD: u16 = 0x8769;
+const TAG_GPS_IFD: u16 = 0x8825;
+const TAG_EXPOSURE_TIME: u16 = 0x829A;
+const TAG_F_NUMBER: u16 = 0x829D;
+const TAG_ISO: u16 = 0x8827;
+const TAG_DATE_ORIGINAL: u16 = 0x9003;
+const TAG_DATE_DIGITIZED: u16 = 0x9004;
+const TAG_OFFSET_ORIGINAL: u16 = 0x9011;
+const TAG_OFFSET_DIGITIZED: u16 = 0x9012;
+const TAG_FOCAL_LENGTH: u16 = 0x920A;
+const TAG_PIXEL_WIDTH: u16 = 0xA002;
+const TAG_PIXEL_HEIGHT: u16 = 0xA003;
+const TAG_LENS_MODEL: u16 = 0xA434;
+const TAG_GPS_LATITUDE_REF: u16 = 0x0001;
+const TAG_GPS_LATITUDE: u16 = 0x0002;
+const TAG_GPS_LONGITUDE_REF: u16 = 0x0003;
+const TAG_GPS_LONGITUDE: u16 = 0x0004;
+const TAG_GPS_ALTITUDE_REF: u16 = 0x0005;
+const TAG_GPS_ALTITUDE: u16 = 0x0006;
+
+/// IFD0 and the Exif IFD of an EXIF block, each with the TIFF structure
+/// its offsets are relative to.
+struct ExifDirs<'a> {
+    tiff: Tiff<'a>,
+    ifd0: Ifd,
+    exif_tiff: Tiff<'a>,
+    exif: Ifd,
+}
+
+/// Opens an EXIF block (a TIFF structure, optionally preceded by the
+/// `Exif\0\0` marker). `exif_ifd`, when given, is a TIFF structure whose
+/// first IFD is the Exif IFD, used instead of the one IFD0 points to.
+fn exif_dirs<'a>(data: &'a [u8], exif_ifd: Option<&'a [u8]>) -> Option<ExifDirs<'a>> {
+    let data = data.strip_prefix(b"Exif\0\0").unwrap_or(data);
+    let tiff = Tiff::new(data)?;
+    let ifd0 = tiff.u32(4).and_then(|offset| tiff.ifd(offset))?;
+
+    let separate = exif_ifd.and_then(Tiff::new).and_then(|exif_tiff| {
+        let exif = exif_tiff.u32(4).and_then(|offset| exif_tiff.ifd(offset))?;
+        Some((exif_tiff, exif))
+    });
+    let (exif_tiff, exif) = separate.unwrap_or_else(|| {
+        let exif = ifd0
+            .find(TAG_EXIF_IFD)
+            .and_then(|e| tiff.uint(e))
+            .and_then(|offset| tiff.ifd(offset))
+            .unwrap_or_default();
+        (tiff, exif)
+    });
+
+    Some(ExifDirs {
+        tiff,
+        ifd0,
+        exif_tiff,
+        exif,
+    })
+}
+
+/// Reads the fields we care about from an EXIF block.
+fn parse_exif(data: &[u8], exif_ifd: Option<&[u8]>) -> ImageMetadata {
+    let Some(ExifDirs {
+        tiff,
+        ifd0,
+        exif_tiff,
+        exif,
+    }) = exif_dirs(data, exif_ifd)
+    else {
+        return ImageMetadata::default();
+    };
+
+    let gps = ifd0
+        .find(TAG_GPS_IFD)
+        .and_then(|e| tiff.uint(e))
+        .and_then(|offset| tiff.ifd(offset))
+        .unwrap_or_default();
+
+    let text = |tag| ifd0.find(tag).and_then(|e| tiff.ascii(e));
+    let exif_text = |tag| exif.find(tag).and_then(|e| exif_tiff.ascii(e));
+    let rational = |tag| exif.find(tag).and_then(|e| exif_tiff.rational(e, 0));
+
+    let taken = [
+        (TAG_DATE_ORIGINAL, TAG_OFFSET_ORIGINAL),
+        (TAG_DATE_DIGITIZED, TAG_OFFSET_DIGITIZED),
+    ]
+    .iter()
+    .find_map(|&(date, offset)| exif_time(&exif_text(date)?, exif_text(offset).as_deref()));
+
+    ImageMetadata {
+        taken,
+        camera_make: text(TAG_MAKE),
+        camera_model: text(TAG_MODEL),
+        lens: exif_text(TAG_LENS_MODEL),
+        exposure_time: rational(TAG_EXPOSURE_TIME),
+        f_number: rational(TAG_F_NUMBER),
+        iso: exif.find(TAG_ISO).and_then(|e| exif_tiff.uint(e)),
+        focal_length: rational(TAG_FOCAL_LENGTH),
+        orientation: ifd0
+            .find(TAG_ORIENTATION)
+            .and_then(|e| tiff.uint(e))
+            .and_then(|o| u16::try_from(o).ok())
+            .filter(|o| (1..=8).contains(o)),
+        gps: gps_position(&tiff, &gps),
+        ..ImageMetadata::default()
+    }
+}
+
+/// The EXIF orientation, 1 to 8, for images decoded from an embedded preview.
+pub(crate) fn orientation(exif: &[u8]) -> Option<u16> {
+    parse_exif(exif, None).orientation
+}
+
+/// The full image size the camera recorded in the Exif IFD.
+fn pixel_size(exif: &[u8], exif_ifd: Option<&[u8]>) -> Option<(u32, u32)> {
+    let dirs = exif_dirs(exif, exif_ifd)?;
+    let dimension = |tag| {
+        dirs.exif
+            .find(tag)
+            .and_then(|e| dirs.exif_tiff.uint(e))
+            .filter(|&d| d > 0)
+    };
+    Some((dimension(TAG_PIXEL_WIDTH)?, dimension(TAG_PIXEL_HEIGHT)?))
+}
+
+fn gps_position(tiff: &Tiff, gps: &Ifd) -> Option<GpsPosition> {
+    let coordinate = |value_tag, ref_tag, negative: &str, limit: f64| {
+        let entry = gps.find(value_tag)?;
+        let degrees = tiff.rational(entry, 0)?
+            + tiff.rational(entry, 1).unwrap_or(0.0) / 60.0
+            + tiff.rational(entry, 2).unwrap_or(0.0) / 3600.0;
+        let sign = match gps.find(ref_tag).and_then(|e| tiff.ascii(e)) {
+            Some(r) if r.eq_ignore_ascii_case(negative) => -1.0,
+            _ => 1.0,
+        };
+        Some(sign * degrees).filter(|d| d.abs() <= limit)
+    };
+
+    let latitude = coordinate(TAG_GPS_LATITUDE, TAG_GPS_LATITUDE_REF, "S", 90.0)?;
+    let longitude = coordinate(TAG_GPS_LONGITUDE, TAG_GPS_LONGITUDE_REF, "W", 180.0)?;
+    let altitude = gps
+        .find(TAG_GPS_ALTITUDE)
+        .and_then(|e| tiff.rational(e, 0))
+        .map(|altitude| {
+            let below_sea_level =
+                gps.find(TAG_GPS_ALTITUDE_REF).and_then(|e| tiff.uint(e)) == Some(1);
+            if below_sea_level {
+                -altitude
+            } else {
+                altitude
+            }
+        });
+
+    // Cameras without a fix write zeros
+    (latitude != 0.0 || longitude != 0.0).then_some(GpsPosition {
+        latitude,
+        longitude,
+        altitude,
+    })
+}
+
+/// Just enough of a TIFF reader for EXIF and RAW files: IFDs and their entries.
+#[derive(Clone, Copy)]
+pub(crate) struct Tiff<'a> {
+    data: &'a [u8],
+    little_endian: bool,
+}
+
+#[derive(Clone, Copy)]
+pub(crate) struct Entry {
+    tag: u16,
+    kind: u16,
+    count: u32,
+    /// Where the entry's value field starts.
+    offset: usize,
+}
+
+#[derive(Default)]
+pub(crate) struct Ifd(Vec<Entry>);
+
+impl Ifd {
+    pub(crate) fn find(&self, tag: u16) -> Option<Entry> {
+        self.0.iter().copied().find(|e| e.tag == tag)
+    }
+}
+
+impl<'a> Tiff<'a> {
+    /// Also accepts the TIFF variants Olympus ORF and Panasonic RW2 use.
+    pub(crate) fn new(data: &'a [u8]) -> Option<Self> {
+        let little_endian = match data.get(..4)? {
+            b"II*\0" | b"IIRO" | b"IIRS" | b"IIU\0" => true,
+            b"MM\0*" | b"MMOR" => false,
+            _ => return None,
+        };
+        Some(Tiff {
+            data,
+            little_endian,
+        })
+    }
+
+    fn bytes<const N: usize>(&self, offset: usize) -> Option<[u8; N]> {
+        self.data
+            .get(offset..offset.checked_add(N)?)?
+            .try_into()
+            .ok()
+    }
+
+    fn u16(&self, offset: usize) -> Option<u16> {
+        let bytes = self.bytes(offset)?;
+        Some(if self.little_endian {
+            u16::from_le_bytes(bytes)
+        } else {
+            u16::from_be_bytes(bytes)
+        })
+    }
+
+    pub(crate) fn u32(&self, offset: usize) -> Option<u32> {
+        let bytes = self.bytes(offset)?;
+        Some(if self.little_endian {
+            u32::from_le_bytes(bytes)
+        } else {
+            u32::from_be_bytes(bytes)
+        })
+    }
+
+    pub(crate) fn ifd(&self, offset: u32) -> Option<Ifd> {
+        let offset = offset as usize;
+        let count = self.u16(offset)? as usize;
+        let entries = (0..count)
+            .map_while(|i| {
+                let at = offset + 2 + i * 12;
+                Some(Entry {
+                    tag: self.u16(at)?,
+                    kind: self.u16(at + 2)?,
+                    count: self.u32(at + 4)?,
+                    offset: at + 8,
+                })
+            })
+            .collect();
+        Some(Ifd(entries))
+    }
+
+    /// Offset of the IFD after the one at `offset`, if there is one.
+    pub(crate) fn next_ifd(&self, offset: u32) -> Option<u32> {
+        let count = self.u16(offset as usize)? as usize;
+        self.u32(offset as usize + 2 + count * 12)
+            .filter(|&next| next != 0)
+    }
+
+    /// The entry's value bytes: inline when they fit in four bytes,
+    /// elsewhere in the file otherwise.
+    fn value(&self, entry: Entry) -> Option<&'a [u8]> {
+        let unit = match entry.kind {
+            1 | 2 | 6 | 7 => 1,
+            3 | 8 => 2,
+            4 | 9 | 13 => 4,
+            5 | 10 => 8,
+            _ => return None,
+        };
+        let len = unit * entry.count as usize;
+        let start = if len <= 4 {
+            entry.offset
+        } else {
+            self.u32(entry.offset)? as usize
+        };
+        self.data.get(start..start.checked_add(len)?)
+    }
+
+    fn ascii(&self, entry: Entry) -> Option<String> {
+        if entry.kind != 2 {
+            return None;
+        }
+        let text = String::from_utf8_lossy(self.value(entry)?);
+        let text = text.trim_matches(|c: char| c == '\0' || c.is_whitespace());
+        (!text.is_empty()).then(|| text.to_string())
+    }
+
+    pub(crate) fn uint(&self, entry: Entry) -> Option<u32> {
+        match entry.kind {
+            1 | 7 => self.value(entry)?.first().map(|b| u32::from(*b)),
+            3 => self.u16(entry.offset).map(u32::from),
+            4 | 13 => self.u32(entry.offset),
+            _ => None,
+        }
+    }
+
+    /// All values of a SHORT, LONG or IFD entry, e.g. strip or SubIFD offsets.
+    pub(crate) fn uints(&self, entry: Entry) -> Vec<u32> {
+        let Some(value) = self.value(entry) else {
+            return Vec::new();
+        };
+        match entry.kind {
+            3 => value
+                .chunks_exact(2)
+                .map(|b| {
+                    let b = [b[0], b[1]];
+                    u32::from(if self.little_endian {
+                        u16::from_le_bytes(b)
+                    } else {
+                        u16::from_be_bytes(b)
+                    })
+                })
+                .collect(),
+            4 | 13 => value
+                .chunks_exact(4)
+                .map(|b| {
+                    let b = [b[0], b[1], b[2], b[3]];
+                    if self.little_endian {
+                        u32::from_le_bytes(b)
+                    } else {
+                        u32::from_be_bytes(b)
+                    }
+                })
+                .collect(),
+            _ => Vec::new(),
+        }
+    }
+
+    fn rational(&self, entry: Entry, index: usize) -> Option<f64> {
+        if !matches!(entry.kind, 5 | 10) || index >= entry.count as usize {
+            return None;
+        }
+        let start = self.u32(entry.offset)? as usize + index * 8;
+        let (numerator, denominator) = (self.u32(start)?, self.u32(start + 4)?);
+        let value = if entry.kind == 10 {
+            f64::from(numerator as i32) / f64::from(denominator as i32)
+        } else {
+            f64::from(numerator) / f64::from(denominator)
+        };
+        value.is_finite().then_some(value)
+    }
+}
+
+/// `2023:06:01 14:30:00` with an optional `+02:00` offset.
+fn exif_time(value: &str, offset: Option<&str>) -> Option<i64> {
+    let (date, time) = value.trim().split_once(' ')?;
+    let mut date = date.split(':').map(|p| p.parse::<i64>().ok());
+    let (year, month, day) = (date.next()??, date.next()??, date.next()??);
+    let local = civil_ms(year, month, day, time)?;
+    Some(local - offset.and_then(offset_ms).unwrap_or(0))
+}
+
+/// ISO 8601 as found in XMP: `2023-06-01`, `2023-06-01T14:30`,
+/// `2023-06-01T14:30:00.25+02:00` or with a `Z`.
+fn iso_time(value: &str) -> Option<i64> {
+    let value = value.trim();
+    let (date, rest) = value.split_once('T').unwrap_or((value, ""));
+    let mut parts = date.split('-').map(|p| p.parse::<i64>().ok());
+    let year = parts.next()??;
+    let month = parts.next().unwrap_or(Some(1))?;
+    let day = parts.next().unwrap_or(Some(1))?;
+
+    let zone_at = rest.find(['Z', '+', '-']).unwrap_or(rest.len());
+    let (time, zone) = rest.split_at(zone_at);
+    let local = civil_ms(year, month, day, time)?;
+    let offset = match zone {
+        "" | "Z" => 0,
+        zone => offset_ms(zone)?,
+    };
+    Some(local - offset)
+}
+
+/// Date plus an `HH:MM[:SS[.fff]]` time (which may be empty) in milliseconds.
+/// Years outside 1800 to 9999 are taken as garbage rather than computed with.
+fn civil_ms(year: i64, month: i64, day: i64, time: &str) -> Option<i64> {
+    if !(1..=12).contains(&month) || !(1..=31).contains(&day) || !(1800..=9999).contains(&year) {
+        return None;
+    }
+    let mut fields = time.split(':').filter(|p| !p.is_empty());
+    let hour: i64 = fields.next().map_or(Some(0), |h| h.parse().ok())?;
+    let minute: i64 = fields.next().map_or(Some(0), |m| m.parse().ok())?;
+    let seconds: f64 = fields.next().map_or(Some(0.0), |s| s.parse().ok())?;
+    if hour > 23 || minute > 59 || !(0.0..61.0).contains(&seconds) {
+        return None;
+    }
+
+    let days = days_from_civil(year, month, day);
+    Some(days * MS_PER_DAY + (hour * 60 + minute) * 60_000 + (seconds * 1000.0) as i64)
+}
+
+/// `+02:00` or `-0530` in milliseconds.
+fn offset_ms(offset: &str) -> Option<i64> {
+    let offset = offset.trim();
+    let sign = match offset.chars().next()? {
+        '+' => 1,
+        '-' => -1,
+        _ => return None,
+    };
+    let digits: String = offset[1..].chars().filter(char::is_ascii_digit).collect();
+    if digits.len() != 4 {
+        return None;
+    }
+    let hours: i64 = digits[..2].parse().ok()?;
+    let minutes: i64 = digits[2..].parse().ok()?;
+    Some(sign * (hours * 60 + minutes) * 60_000)
+}
+
+const DC: &str = "http://purl.org/dc/elements/1.1/";
+const EXIF_NS: &str = "http://ns.adobe.com/exif/1.0/";
+const PHOTOSHOP: &str = "http://ns.adobe.com/photoshop/1.0/";
+const XMP_NS: &str = "http://ns.adobe.com/xap/1.0/";
+const RDF: &str = "http://www.w3.org/1999/02/22-rdf-syntax-ns#";
+const XML: &str = "http://www.w3.org/XML/1998/namespace";
+
+/// Keywords (`dc:subject`), caption (`dc:description`) and capture date from
+/// an XMP packet. Properties may be written as elements or as attributes.
+fn parse_xmp(data: &[u8]) -> Descriptive {
+    let text = String::from_utf8_lossy(data);
+    let text = text.trim_matches(|c: char| c == '\0' || c.is_whitespace());
+    let Ok(doc) = roxmltree::Document::parse(text) else {
+        return Descriptive::default();
+    };
+
+    let mut result = Descriptive::default();
+    for node in doc.descendants().filter(|n| n.is_element()) {
+        let name = node.tag_name();
+        match (name.namespace(), name.name()) {
+            (Some(DC), "subject") => result.keywords.extend(list_items(node)),
+            (Some(DC), "description") if result.caption.is_none() => {
+                result.caption = alt_text(node);
+            }
+            _ => {}
+        }
+    }
+
+    // Capture date: the EXIF copy first, then Photoshop's, then creation time
+    let dates = [
+        (EXIF_NS, "DateTimeOriginal"),
+        (PHOTOSHOP, "DateCreated"),
+        (XMP_NS, "CreateDate"),
+    ];
+    result.taken = dates.iter().find_map(|&(ns, local)| {
+        doc.descendants().find_map(|node| {
+            let value = node.attribute((ns, local)).or_else(|| {
+                let name = node.tag_name();
+                (node.is_element() && name.namespace() == Some(ns) && name.name() == local)
+                    .then(|| node.text())
+                    .flatten()
+            })?;
+            iso_time(value)
+        })
+    });
+    result
+}
+
+/// The `rdf:li` texts of a bag or sequence.
+fn list_items(node: roxmltree::Node) -> Vec<String> {
+    node.descendants()
+        .filter(|n| n.tag_name().namespace() == Some(RDF) && n.tag_name().name() == "li")
+        .filter_map(|n| n.text())
+        .map(|t| t.trim().to_string())
+        .filter(|t| !t.is_empty())
+        .collect()
+}
+
+/// The `x-default` entry of a language alternative, or its first entry.
+fn alt_text(node: roxmltree::Node) -> Option<String> {
+    let items: Vec<roxmltree::Node> = node
+        .descendants()
+        .filter(|n| n.tag_name().namespace() == Some(RDF) && n.tag_name().name() == "li")
+        .collect();
+    items
+        .iter()
+        .find(|n| n.attribute((XML, "lang")) == Some("x-default"))
+        .or(items.first())
+        .and_then(|n| n.text())
+        .or_else(|| node.text())
+        .map(|t| t.trim().to_string())
+        .filter(|t| !t.is_empty())
+}
+
+// IPTC-IIM application record datasets
+const IPTC_KEYWORDS: u8 = 25;
+const IPTC_DATE_CREATED: u8 = 55;
+const IPTC_TIME_CREATED: u8 = 60;
+const IPTC_CAPTION: u8 = 120;
+/// Photoshop image resource holding the IPTC-IIM records.
+const IRB_IPTC: u16 = 0x0404;
+
+/// Keywords, caption and creation date from IPTC-IIM data, either bare or
+/// wrapped in Photoshop image resource blocks as found in JPEG files.
+fn parse_iptc(data: &[u8]) -> Descriptive {
+    let records = if data.starts_with(b"8BIM") {
+        match photoshop_resource(data, IRB_IPTC) {
+            Some(records) => records,
+            None => return Descriptive::default(),
+        }
+    } else {
+        data
+    };
+
+    let mut result = Descriptive::default();
+    let (mut date, mut time) = (None, None);
+    let mut at = 0;
+    while let Some(header) = records.get(at..at + 5) {
+        if header[0] != 0x1C {
+            break;
+        }
+        let len = usize::from(u16::from_be_bytes([header[3], header[4]]));
+        // Extended lengths (high bit set) only occur in huge binary records
+        if len & 0x8000 != 0 {
+            break;
+        }
+        let Some(value) = records.get(at + 5..at + 5 + len) else {
+            break;
+        };
+        at += 5 + len;
+
+        if header[1] != 2 {
+            continue;
+        }
+        let value = String::from_utf8_lossy(value).trim().to_string();
+        if value.is_empty() {
+            continue;
+        }
+        match header[2] {
+            IPTC_KEYWORDS => result.keywords.push(value),
+            IPTC_CAPTION => result.caption = Some(value),
+            IPTC_DATE_CREATED => date = Some(value),
+            IPTC_TIME_CREATED => time = Some(value),
+            _ => {}
+        }
+    }
+
+    // CCYYMMDD and HHMMSS±HHMM
+    result.taken = date.filter(|d| d.len() == 8).and_then(|d| {
+        let num = |range: std::ops::Range<usize>| d.get(range)?.parse::<i64>().ok();
+        let time = time.unwrap_or_default();
+        let clock = time
+            .get(..6)
+            .filter(|t| t.chars().all(|c| c.is_ascii_digit()));
+        let clock = clock.map(|t| format!("{}:{}:{}", &t[..2], &t[2..4], &t[4..]));
+        let local = civil_ms(
+            num(0..4)?,
+            num(4..6)?,
+            num(6..8)?,
+            clock.as_deref().unwrap_or(""),
+        )?;
+        let offset = time.get(6..).and_then(offset_ms).unwrap_or(0);
+        Some(local - offset)
+    });
+    result
+}
+
+/// The payload of the Photoshop image resource with `id`.
+fn photoshop_resource(data: &[u8], id: u16) -> Option<&[u8]> {
+    let mut at = 0;
+    while data.get(at..at + 4)? == b"8BIM" {
+        let resource = u16::from_be_bytes(data.get(at + 4..at + 6)?.try_into().ok()?);
+        // Pascal string name, padded to an even length including its length byte
+        let name_len = usize::from(*data.get(at + 6)?);
+        let name_size = (name_len + 2) & !1;
+        let size_at = at + 6 + name_size;
+        let size = u32::from_be_bytes(data.get(size_at..size_at + 4)?.try_into().ok()?) as usize;
+        let start = size_at + 4;
+        let payload = data.get(start..start.checked_add(size)?)?;
+        if resource == id {
+            return Some(payload);
+        }
+        at = start + ((size + 1) & !1);
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A little-endian TIFF with the given IFD0, Exif IFD and GPS IFD
+    /// entries. Values longer than four bytes go after the IFDs.
+    fn exif_block(
+        ifd0: &[(u16, u16, Vec<u8>)],
+        exif: &[(u16, u16, Vec<u8>)],
+        gps: &[(u16, u16, Vec<u8>)],
+    ) -> Vec<u8> {
+        fn unit(kind: u16) -> usize {
+            match kind {
+                3 => 2,
+                4 => 4,
+                5 | 10 => 8,
+                _ => 1,
+            }
+        }
+        fn ifd_len(entries: usize) -> usize {
+            2 + entries * 12 + 4
+        }
+
+        let mut ifd0 = ifd0.to_vec();
+        if !exif.is_empty() {
+            ifd0.push((TAG_EXIF_IFD, 4, vec![0; 4]));
+        }
+        if !gps.is_empty() {
+            ifd0.push((TAG_GPS_IFD, 4, vec![0; 4]));
+        }
+
+        let ifd0_at = 8;
+        let exif_at = ifd0_at + ifd_len(ifd0.len());
+        let gps_at = exif_at
+            + if exif.is_empty() {
+                0
+            } else {
+                ifd_len(exif.len())
+            };
+        let mut extra_at = gps_at
+            + if gps.is_empty() {
+                0
+            } else {
+                ifd_len(gps.len())
+            };
+
+        let mut out = b"II*\0".to_vec();
+        out.extend(8u32.to_le_bytes());
+        let mut extra = Vec::new();
+        for (entries, is_ifd0) in [
+            (&ifd0, true),
+            (&exif.to_vec(), false),
+            (&gps.to_vec(), false),
+        ] {
+            if entries.is_empty() {
+                continue;
+            }
+            out.extend((entries.len() as u16).to_le_bytes());
+            for (tag, kind, value) in entries.iter() {
+                let value = match (*tag, is_ifd0) {
+                    (TAG_EXIF_IFD, true) => (exif_at as u32).to_le_bytes().to_vec(),
+                    (TAG_GPS_IFD, true) => (gps_at as u32).to_le_bytes().to_vec(),
+                    _ => value.clone(),
+                };
+                out.extend(tag.to_le_bytes());
+                out.extend(kind.to_le_bytes());
+                out.extend(((value.len() / unit(*kind)) as u32).to_le_bytes());
+                if value.len() <= 4 {
+                    let mut inline = value.clone();
+                    inline.resize(4, 0);
+                    out.extend(inline);
+                } else {
+                    out.extend((extra_at as u32).to_le_bytes());
+                    extra_at += value.len();
+                    extra.extend(value);
+                }
+            }
+            out.extend(0u32.to_le_bytes());
+        }
+        out.extend(extra);
+        out
+    }
+
+    fn ascii(text: &str) -> Vec<u8> {
+        let mut bytes = text.as_bytes().to_vec();
+        bytes.push(0);
+        bytes
+    }
+
+    fn rationals(values: &[(u32, u32)]) -> Vec<u8> {
+        values
+            .iter()
+            .flat_map(|(n, d)| n.to_le_bytes().into_iter().chain(d.to_le_bytes()))
+            .collect()
+    }
+
+    fn ms(year: i64, month: i64, day: i64, hour: i64, minute: i64) -> i64 {
+        days_from_civil(year, month, day) * MS_PER_DAY + (hour * 60 + minute) * 60_000
+    }
+
+    #[test]
+    fn exif_fields_are_read() {
+        let data = exif_block(
+            &[
+                (TAG_MAKE, 2, ascii("Canon")),
+                (TAG_MODEL, 2, ascii("Canon EOS R5")),
+                (TAG_ORIENTATION, 3, 6u16.to_le_bytes().to_vec()),
+            ],
+            &[
+                (TAG_DATE_ORIGINAL, 2, ascii("2023:06:01 14:30:00")),
+                (TAG_OFFSET_ORIGINAL, 2, ascii("+02:00")),
+                (TAG_EXPOSURE_TIME, 5, rationals(&[(1, 250)])),
+                (TAG_F_NUMBER, 5, rationals(&[(28, 10)])),
+                (TAG_ISO, 3, 400u16.to_le_bytes().to_vec()),
+                (TAG_FOCAL_LENGTH, 5, rationals(&[(50, 1)])),
+                (TAG_LENS_MODEL, 2, ascii("RF50mm F1.8 STM")),
+            ],
+            &[
+                (TAG_GPS_LATITUDE_REF, 2, ascii("N")),
+                (TAG_GPS_LATITUDE, 5, rationals(&[(48, 1), (51, 1), (30, 1)])),
+                (TAG_GPS_LONGITUDE_REF, 2, ascii("W")),
+                (TAG_GPS_LONGITUDE, 5, rationals(&[(2, 1), (21, 1), (0, 1)])),
+                (TAG_GPS_ALTITUDE, 5, rationals(&[(35, 1)])),
+            ],
+        );
+
+        let metadata = parse_exif(&data, None);
+        assert_eq!(metadata.camera_make.as_deref(), Some("Canon"));
+        assert_eq!(metadata.camera_model.as_deref(), Some("Canon EOS R5"));
+        assert_eq!(metadata.lens.as_deref(), Some("RF50mm F1.8 STM"));
+        assert_eq!(metadata.orientation, Some(6));
+        assert_eq!(metadata.iso, Some(400));
+        assert_eq!(metadata.exposure_time, Some(0.004));
+        assert_eq!(metadata.f_number, Some(2.8));
+        assert_eq!(metadata.focal_length, Some(50.0));
+        assert_eq!(metadata.taken, Some(ms(2023, 6, 1, 12, 30)));
+
+        let gps = metadata.gps.unwrap();
+        assert!((gps.latitude - 48.858_333).abs() < 1e-5);
+        assert!((gps.longitude + 2.35).abs() < 1e-9);
+        assert_eq!(gps.altitude, Some(35.0));
+    }
+
+    #[test]
+    fn broken_exif_is_ignored() {
+        assert_eq!(parse_exif(b"", None), ImageMetadata::default());
+        assert_eq!(
+            parse_exif(b"II*\0\xff\xff\xff\xff", None),
+            ImageMetadata::default()
+        );
+
+        // Entry pointing past the end of the data
+        let mut data = exif_block(&[(TAG_MAKE, 2, ascii("Nikon Corporation"))], &[], &[]);
+        data.truncate(data.len() - 4);
+        assert_eq!(parse_exif(&data, None).camera_make, None);
+    }
+
+    #[test]
+    fn xmp_keywords_caption_and_date() {
+        let xmp = r#"<?xpacket begin="" id="W5M0MpCehiHzreSzNTczkc9d"?>
+            <x:xmpmeta xmlns:x="adobe:ns:meta/">
+              <rdf:RDF xmlns:rdf="http://www.w3.org/1999/02/22-rdf-syntax-ns#">
+                <rdf:Description rdf:about=""
+                    xmlns:dc="http://purl.org/dc/elements/1.1/"
+                    xmlns:photoshop="http://ns.adobe.com/photoshop/1.0/"
+                    photoshop:DateCreated="2021-08-14T09:15:00Z">
+                  <dc:subject><rdf:Bag>
+                    <rdf:li>Beach</rdf:li><rdf:li>Family</rdf:li>
+                  </rdf:Bag></dc:subject>
+                  <dc:description><rdf:Alt>
+                    <rdf:li xml:lang="de">Am Strand</rdf:li>
+                    <rdf:li xml:lang="x-default">At the beach</rdf:li>
+                  </rdf:Alt></dc:description>
+                </rdf:Description>
+              </rdf:RDF>
+            </x:xmpmeta>
+            <?xpacket end="w"?>"#;
+
+        let parsed = parse_xmp(xmp.as_bytes());
+        assert_eq!(parsed.keywords, ["Beach", "Family"]);
+        assert_eq!(parsed.caption.as_deref(), Some("At the beach"));
+        assert_eq!(parsed.taken, Some(ms(2021, 8, 14, 9, 15)));
+        assert!(parse_xmp(b"<not xml").keywords.is_empty());
+    }
+
+    #[test]
+    fn iptc_records_inside_photoshop_resources() {
+        let mut records = Vec::new();
+        for (dataset, value) in [
+            (IPTC_KEYWORDS, "sunset"),
+            (IPTC_KEYWORDS, "sea"),
+            (IPTC_CAPTION, "Evening at the harbour"),
+            (IPTC_DATE_CREATED, "20190704"),
+            (IPTC_TIME_CREATED, "201500+0100"),
+        ] {
+            records.extend([0x1C, 2, dataset]);
+            records.extend((value.len() as u16).to_be_bytes());
+            records.extend(value.as_bytes());
+        }
+
+        let mut irb = b"8BIM".to_vec();
+        irb.extend(0x03EDu16.to_be_bytes()); // some other resource first
+        irb.extend([0, 0]);
+        irb.extend(1u32.to_be_bytes());
+        irb.extend([7, 0]);
+        irb.extend(b"8BIM");
+        irb.extend(IRB_IPTC.to_be_bytes());
+        irb.extend([0, 0]);
+        irb.extend((records.len() as u32).to_be_bytes());
+        irb.extend(&records);
+
+        for data in [&irb, &records] {
+            let parsed = parse_iptc(data);
+            assert_eq!(parsed.keywords, ["sunset", "sea"]);
+            assert_eq!(parsed.caption.as_deref(), Some("Evening at the harbour"));
+            assert_eq!(parsed.taken, Some(ms(2019, 7, 4, 19, 15)));
+        }
+    }
+
+    #[test]
+    fn details_are_read_from_a_jpeg_file() {
+        let mut jpeg = Vec::new();
+        image::RgbImage::new(4, 3)
+            .write_to(
+                &mut std::io::Cursor::new(&mut jpeg),
+                image::ImageFormat::Jpeg,
+            )
+            .unwrap();
+
+        // Splice an APP1 Exif segment in right after the SOI marker
+        let mut payload = b"Exif\0\0".to_vec();
+        payload.extend(exif_block(
+            &[
+                (TAG_MODEL, 2, ascii("Pixel 8")),
+                (TAG_ORIENTATION, 3, 6u16.to_le_bytes().to_vec()),
+            ],
+            &[],
+            &[],
+        ));
+        let mut segment = vec![0xFF, 0xE1];
+        segment.extend(((payload.len() + 2) as u16).to_be_bytes());
+        segment.extend(payload);
+        jpeg.splice(2..2, segment);
+
+        let path = std::env::temp_dir().join(format!("metadata-{}.jpg", std::process::id()));
+        std::fs::write(&path, &jpeg).unwrap();
+        let details = read(&path);
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(details.metadata.camera_model.as_deref(), Some("Pixel 8"));
+        // Rotated a quarter turn by the orientation tag
+        assert_eq!((details.width, details.height), (Some(3), Some(4)));
+        assert_eq!(read(Path::new("/nonexistent.jpg")), FileDetails::default());
+    }
+
+    #[test]
+    fn svg_size_is_read() {
+        let path = std::env::temp_dir().join(format!("metadata-{}.svg", std::process::id()));
+        std::fs::write(
+            &path,
+            r#"<svg xmlns="http://www.w3.org/2000/svg" width="120" height="80"/>"#,
+        )
+        .unwrap();
+        let details = read(&path);
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!((details.width, details.height), (Some(120), Some(80)));
+    }
+
+    #[test]
+    fn keywords_are_imported_as_file_tags() {
+        use crate::catalog::Catalog;
+        use crate::identity::FileStamp;
+        use crate::scan::{ScanOptions, ScannedFile};
+
+        let catalog = Catalog::open_in_memory().unwrap();
+        let root = Path::new("/photos");
+        catalog.begin_scan(root, &ScanOptions::default()).unwrap();
+        let file = |keywords: &[&str]| ScannedFile {
+            path: "/photos/a.jpg".to_string(),
+            relative_path: "a.jpg".to_string(),
+            name: "a.jpg".to_string(),
+            content_hash: "0123456789abcdef0123".to_string(),
+            stamp: FileStamp {
+                size: 1,
+                modified: 0,
+            },
+            created: None,
+            format: Format::Jpeg,
+            appearance: None,
+            issues: Vec::new(),
+            raw_path: None,
+            details: Some(FileDetails {
+                metadata: ImageMetadata {
+                    taken: Some(1_000),
+                    keywords: keywords.iter().map(|k| k.to_string()).collect(),
+                    ..ImageMetadata::default()
+                },
+                ..FileDetails::default()
+            }),
+        };
+
+        let image = catalog
+            .merge_batch(root, &[file(&["Beach", "Sea"])])
+            .unwrap()[0]
+            .clone();
+        assert_eq!(image.tags, ["beach", "sea"]);
+        assert_eq!(image.file_tags, ["beach", "sea"]);
+        assert_eq!(image.metadata.unwrap().taken, Some(1_000));
+
+        // Model tags sit next to the keywords; a shared name stays a file tag
+        catalog
+            .set_tags(&image.id, &["sea".to_string(), "boat".to_string()])
+            .unwrap();
+        let image = &catalog.images_by_ids(&[image.id]).unwrap()[0];
+        assert_eq!(image.tags, ["sea", "boat"]);
+        assert_eq!(image.file_tags, ["sea"]);
+
+        // Keywords removed from the file go away, other tags stay
+        let image = catalog.merge_batch(root, &[file(&["Harbour"])]).unwrap()[0].clone();
+        assert_eq!(image.tags, ["boat", "harbour"]);
+        assert_eq!(image.file_tags, ["harbour"]);
+    }
+
+    #[test]
+    fn dates_and_offsets() {
+        assert_eq!(
+            exif_time("2023:06:01 14:30:00", None),
+            Some(ms(2023, 6, 1, 14, 30))
+        );
+        assert_eq!(
+            exif_time("2023:06:01 14:30:00", Some("-05:30")),
+            Some(ms(2023, 6, 1, 20, 0))
+        );
+        // Unset dates are written as blanks or zeros
+        assert_eq!(exif_time("0000:00:00 00:00:00", None), None);
+        assert_eq!(exif_time("    :  :     :  :  ", None), None);
+        assert_eq!(iso_time("2020-02"), Some(ms(2020, 2, 1, 0, 0)));
+        assert_eq!(
+            iso_time("2020-02-03T04:05+01:00"),
+            Some(ms(2020, 2, 3, 3, 5))
+        );
+        // Crafted years are dropped before they can overflow
+        assert_eq!(exif_time("300000000:01:01 00:00:00", None), None);
+        assert_eq!(iso_time("9223372036854775807-01-01"), None);
+        assert_eq!(iso_time("9999-12-31"), Some(ms(9999, 12, 31, 0, 0)));
+    }
+}
@@ -0,0 +1,182 @@
+use image::codecs::jpeg::JpegEncoder;
+use image::imageops::FilterType;
+use image::{DynamicImage, ImageDecoder, ImageReader, Rgb, RgbImage, RgbaImage};
+use resvg::{tiny_skia, usvg};
+use std::fs;
+use std::path::Path;
+
+use crate::formats::{self, Format};
+use crate::previews;
+
+const JPEG_QUALITY: u8 = 85;
+
+/// Loads the image at `path` and turns it into what the vision model gets:
+/// upright per its EXIF orientation, no larger than `max_edge` on its longest
+/// side, and re-encoded as JPEG. This also covers formats Ollama can't read
+/// itself (TIFF, BMP, WebP, SVG, RAW, HEIC, AVIF).
+pub fn prepare(path: &Path, max_edge: u32) -> Result<Vec<u8>, String> {
+    encode_jpeg(&shrink(load(path, max_edge)?, max_edge))
+}
+
+/// Decodes the image at `path` upright per its EXIF orientation. SVGs are
+/// rasterized to fit `max_edge`; RAW, HEIC and AVIF come from their embedded
+/// preview; other formats come back at full size.
+pub(crate) fn load(path: &Path, max_edge: u32) -> Result<DynamicImage, String> {
+    // By contents, so misnamed and extensionless files work too
+    let format = formats::detect(path).map_err(|e| format!("Failed to read image: {}", e))?;
+
+    match format {
+        Some(Format::Svg) => render_svg(path, max_edge),
+        Some(format) if format.needs_preview() => previews::decode(path),
+        _ => decode_upright(path),
+    }
+}
+
+fn decode_upright(path: &Path) -> Result<DynamicImage, String> {
+    let mut decoder = ImageReader::open(path)
+        .and_then(|reader| reader.with_guessed_format())
+        .map_err(|e| format!("Failed to read image: {}", e))?
+        .into_decoder()
+        .map_err(|e| format!("Failed to decode image: {}", e))?;
+
+    // A broken orientation tag shouldn't stop the image from being tagged
+    let orientation = decoder.orientation().ok();
+    let mut image = DynamicImage::from_decoder(decoder)
+        .map_err(|e| format!("Failed to decode image: {}", e))?;
+    if let Some(orientation) = orientation {
+        image.apply_orientation(orientation);
+    }
+    Ok(image)
+}
+
+/// Rasterizes an SVG so its longest side is `max_edge`, on a white background.
+fn render_svg(path: &Path, max_edge: u32) -> Result<DynamicImage, String> {
+    let data = fs::read(path).map_err(|e| format!("Failed to read image: {}", e))?;
+    let tree = usvg::Tree::from_data(&data, &usvg::Options::default())
+        .map_err(|e| format!("Failed to parse SVG: {}", e))?;
+
+    let size = tree.size();
+    let scale = max_edge as f32 / size.width().max(size.height());
+    let width = ((size.width() * scale).round() as u32).max(1);
+    let height = ((size.height() * scale).round() as u32).max(1);
+
+    let mut pixmap = tiny_skia::Pixmap::new(width, height)
+        .ok_or_else(|| format!("Invalid SVG size {}x{}", width, height))?;
+    // Opaque background, so the premultiplied pixels can be used as plain RGBA
+    pixmap.fill(tiny_skia::Color::WHITE);
+    resvg::render(
+        &tree,
+        tiny_skia::Transform::from_scale(scale, scale),
+        &mut pixmap.as_mut(),
+    );
+
+    RgbaImage::from_raw(width, height, pixmap.take())
+        .map(DynamicImage::ImageRgba8)
+        .ok_or_else(|| "Failed to render SVG".to_string())
+}
+
+pub(crate) fn shrink(image: DynamicImage, max_edge: u32) -> DynamicImage {
+    if image.width().max(image.height()) <= max_edge {
+        return image;
+    }
+    // `resize` keeps the aspect ratio and fits within the given box
+    image.resize(max_edge, max_edge, FilterType::Triangle)
+}
+
+pub(crate) fn encode_jpeg(image: &DynamicImage) -> Result<Vec<u8>, String> {
+    let rgb = flatten(image);
+    let mut jpeg = Vec::new();
+    JpegEncoder::new_with_quality(&mut jpeg, JPEG_QUALITY)
+        .encode_image(&rgb)
+        .map_err(|e| format!("Failed to encode image: {}", e))?;
+    Ok(jpeg)
+}
+
+/// Drops alpha by compositing onto white; JPEG has no transparency and
+/// transparent pixels would otherwise come out black.
+fn flatten(image: &DynamicImage) -> RgbImage {
+    if !image.color().has_alpha() {
+        return image.to_rgb8();
+    }
+
+    let rgba = image.to_rgba8();
+    RgbImage::from_fn(rgba.width(), rgba.height(), |x, y| {
+        let [r, g, b, a] = rgba.get_pixel(x, y).0;
+        let blend = |c: u8| ((c as u16 * a as u16 + 255 * (255 - a as u16)) / 255) as u8;
+        Rgb([blend(r), blend(g), blend(b)])
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::temp_dir;
+    use image::ImageEncoder;
+
+    fn temp_file(name: &str, bytes: &[u8]) -> std::path::PathBuf {
+        let path = temp_dir(&format!("model_image_{}", name)).join(name);
+        fs::write(&path, bytes).unwrap();
+        path
+    }
+
+    fn dimensions(jpeg: &[u8]) -> (u32, u32) {
+        let image = image::load_from_memory_with_format(jpeg, image::ImageFormat::Jpeg).unwrap();
+        (image.width(), image.height())
+    }
+
+    #[test]
+    fn large_images_are_shrunk_to_max_edge() {
+        let mut png = Vec::new();
+        DynamicImage::new_rgba8(2000, 1000)
+            .write_to(&mut std::io::Cursor::new(&mut png), image::ImageFormat::Png)
+            .unwrap();
+        let path = temp_file("wide.png", &png);
+
+        assert_eq!(dimensions(&prepare(&path, 768).unwrap()), (768, 384));
+        fs::remove_dir_all(path.parent().unwrap()).unwrap();
+    }
+
+    #[test]
+    fn small_images_keep_their_size() {
+        let mut bmp = Vec::new();
+        DynamicImage::new_rgb8(300, 200)
+            .write_to(&mut std::io::Cursor::new(&mut bmp), image::ImageFormat::Bmp)
+            .unwrap();
+        let path = temp_file("small.bmp", &bmp);
+
+        assert_eq!(dimensions(&prepare(&path, 768).unwrap()), (300, 200));
+        fs::remove_dir_all(path.parent().unwrap()).unwrap();
+    }
+
+    #[test]
+    fn exif_orientation_is_applied() {
+        // Big-endian TIFF header with a single Orientation = 6 (rotate 90° clockwise) entry
+        let exif = [
+            b'M', b'M', 0, 42, 0, 0, 0, 8, // header
+            0, 1, // one entry
+            0x01, 0x12, 0, 3, 0, 0, 0, 1, 0, 6, 0, 0, // orientation, SHORT, 1, 6
+            0, 0, 0, 0, // no next IFD
+        ];
+        let mut jpeg = Vec::new();
+        let mut encoder = JpegEncoder::new(&mut jpeg);
+        encoder.set_exif_metadata(exif.to_vec()).unwrap();
+        encoder
+            .write_image(&[128; 40 * 20 * 3], 40, 20, image::ExtendedColorType::Rgb8)
+            .unwrap();
+        let path = temp_file("rotated.jpg", &jpeg);
+
+        assert_eq!(dimensions(&prepare(&path, 768).unwrap()), (20, 40));
+        fs::remove_dir_all(path.parent().unwrap()).unwrap();
+    }
+
+    #[test]
+    fn svg_is_rendered_at_max_edge() {
+        let svg = br#"<svg xmlns="http://www.w3.org/2000/svg" width="100" height="50">
+            <rect width="100" height="50" fill="red"/>
+        </svg>"#;
+        let path = temp_file("shape.svg", svg);
+
+        assert_eq!(dimensions(&prepare(&path, 512).unwrap()), (512, 256));
+        fs::remove_dir_all(path.parent().unwrap()).unwrap();
+    }
+}
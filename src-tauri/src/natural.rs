@@ -0,0 +1,98 @@
+use std::cmp::Ordering;
+use std::iter::Peekable;
+use std::str::Chars;
+
+/// Name of the SQLite collation registered by the catalog.
+pub const COLLATION: &str = "natural_order";
+
+/// Compares file names the way people read them: case-insensitively, with
+/// runs of digits compared by value, so `img2` sorts before `img10`.
+///
+/// Strings that only differ in case or leading zeros fall back to a plain
+/// comparison, so this is a total order and equal only for equal strings.
+pub fn compare(a: &str, b: &str) -> Ordering {
+    compare_loosely(a, b).then_with(|| a.cmp(b))
+}
+
+fn compare_loosely(a: &str, b: &str) -> Ordering {
+    let (mut a, mut b) = (a.chars().peekable(), b.chars().peekable());
+    loop {
+        match (a.peek().copied(), b.peek().copied()) {
+            (None, None) => return Ordering::Equal,
+            (None, Some(_)) => return Ordering::Less,
+            (Some(_), None) => return Ordering::Greater,
+            (Some(x), Some(y)) if x.is_ascii_digit() && y.is_ascii_digit() => {
+                let ordering = compare_numbers(&digits(&mut a), &digits(&mut b));
+                if ordering != Ordering::Equal {
+                    return ordering;
+                }
+            }
+            (Some(x), Some(y)) => {
+                let ordering = x.to_lowercase().cmp(y.to_lowercase());
+                if ordering != Ordering::Equal {
+                    return ordering;
+                }
+                a.next();
+                b.next();
+            }
+        }
+    }
+}
+
+fn digits(chars: &mut Peekable<Chars>) -> String {
+    let mut run = String::new();
+    while let Some(c) = chars.next_if(char::is_ascii_digit) {
+        run.push(c);
+    }
+    run
+}
+
+/// Compares two digit runs by value, without parsing, so long runs can't overflow.
+fn compare_numbers(a: &str, b: &str) -> Ordering {
+    let a = a.trim_start_matches('0');
+    let b = b.trim_start_matches('0');
+    a.len().cmp(&b.len()).then_with(|| a.cmp(b))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn numbers_sort_by_value() {
+        let mut names = vec![
+            "img10.jpg",
+            "IMG2.jpg",
+            "img1.jpg",
+            "img02.jpg",
+            "Beach.png",
+            "img.jpg",
+            "img2b.jpg",
+            "99999999999999999999999.png",
+            "100000000000000000000000.png",
+        ];
+        names.sort_by(|a, b| compare(a, b));
+        assert_eq!(
+            names,
+            [
+                "99999999999999999999999.png",
+                "100000000000000000000000.png",
+                "Beach.png",
+                "img.jpg",
+                "img1.jpg",
+                "IMG2.jpg",
+                "img02.jpg",
+                "img2b.jpg",
+                "img10.jpg",
+            ]
+        );
+    }
+
+    #[test]
+    fn only_equal_strings_compare_equal() {
+        assert_eq!(compare("a1", "a1"), Ordering::Equal);
+        assert_ne!(compare("a1", "A1"), Ordering::Equal);
+        assert_ne!(compare("a01", "a1"), Ordering::Equal);
+        assert_eq!(compare("a/b2", "a/b10"), Ordering::Less);
+    }
+}
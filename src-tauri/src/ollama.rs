@@ -0,0 +1,518 @@
+use reqwest::StatusCode;
+use serde::ser::SerializeStruct;
+use serde::{Deserialize, Serialize, Serializer};
+use serde_json::Value;
+use std::fmt;
+use std::sync::Mutex;
+use std::time::Duration;
+
+use crate::settings::Settings;
+
+const BASE_BACKOFF: Duration = Duration::from_millis(500);
+const MAX_BACKOFF: Duration = Duration::from_secs(8);
+
+/// Errors from the vision backend. Serialized as `{ kind, message }` so the
+/// UI can tell a model that is still loading apart from a server that is down.
+#[derive(Debug, Clone, PartialEq)]
+pub enum AiError {
+    /// The server couldn't be reached at all.
+    Unavailable(String),
+    Timeout,
+    /// The model is still being loaded into memory; trying again later should work.
+    ModelLoading(String),
+    ModelNotFound(String),
+    Backend {
+        status: u16,
+        message: String,
+    },
+    InvalidResponse(String),
+    Other(String),
+}
+
+impl AiError {
+    fn kind(&self) -> &'static str {
+        match self {
+            AiError::Unavailable(_) => "unavailable",
+            AiError::Timeout => "timeout",
+            AiError::ModelLoading(_) => "modelLoading",
+            AiError::ModelNotFound(_) => "modelNotFound",
+            AiError::Backend { .. } => "backend",
+            AiError::InvalidResponse(_) => "invalidResponse",
+            AiError::Other(_) => "other",
+        }
+    }
+
+    /// Whether the same request might succeed if retried shortly.
+    pub fn is_transient(&self) -> bool {
+        match self {
+            AiError::Unavailable(_) | AiError::Timeout | AiError::ModelLoading(_) => true,
+            AiError::Backend { status, .. } => matches!(status, 429 | 502 | 503 | 504),
+            _ => false,
+        }
+    }
+}
+
+impl fmt::Display for AiError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AiError::Unavailable(e) => {
+                write!(f, "Failed to call Ollama: {}. Is Ollama running?", e)
+            }
+            AiError::Timeout => write!(f, "Ollama did not respond in time"),
+            AiError::ModelLoading(model) => {
+                write!(
+                    f,
+                    "Model '{}' is still loading, try again in a moment",
+                    model
+                )
+            }
+            AiError::ModelNotFound(model) => write!(
+                f,
+                "Model '{}' is not installed. Run `ollama pull {}` first",
+                model, model
+            ),
+            AiError::Backend { status, message } => {
+                write!(f, "Ollama returned error {}: {}", status, message)
+            }
+            AiError::InvalidResponse(e) => write!(f, "Failed to parse Ollama response: {}", e),
+            AiError::Other(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl Serialize for AiError {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut state = serializer.serialize_struct("AiError", 2)?;
+        state.serialize_field("kind", self.kind())?;
+        state.serialize_field("message", &self.to_string())?;
+        state.end()
+    }
+}
+
+impl From<String> for AiError {
+    fn from(e: String) -> Self {
+        AiError::Other(e)
+    }
+}
+
+#[derive(Serialize)]
+struct GenerateRequest<'a> {
+    model: &'a str,
+    prompt: &'a str,
+    images: &'a [String],
+    stream: bool,
+    /// JSON schema the answer must follow.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    format: Option<&'a Value>,
+}
+
+#[derive(Deserialize)]
+struct GenerateResponse {
+    response: String,
+}
+
+#[derive(Serialize)]
+struct EmbedRequest<'a> {
+    model: &'a str,
+    input: &'a [String],
+}
+
+#[derive(Deserialize)]
+struct EmbedResponse {
+    embeddings: Vec<Vec<f32>>,
+}
+
+#[derive(Deserialize)]
+struct ErrorBody {
+    error: String,
+}
+
+/// Shared HTTP client for the vision backend, held in Tauri managed state.
+/// The underlying client is rebuilt only when the timeouts in the settings change.
+pub struct Ollama {
+    client: Mutex<Option<((u64, u64), reqwest::Client)>>,
+    base_backoff: Duration,
+}
+
+impl Default for Ollama {
+    fn default() -> Self {
+        Self::with_backoff(BASE_BACKOFF)
+    }
+}
+
+impl Ollama {
+    pub fn with_backoff(base_backoff: Duration) -> Self {
+        Ollama {
+            client: Mutex::new(None),
+            base_backoff,
+        }
+    }
+
+    fn client(&self, settings: &Settings) -> Result<reqwest::Client, AiError> {
+        let timeouts = (settings.connect_timeout_secs, settings.request_timeout_secs);
+        let mut cached = self.client.lock().unwrap_or_else(|e| e.into_inner());
+
+        if let Some((cached_timeouts, client)) = cached.as_ref() {
+            if *cached_timeouts == timeouts {
+                return Ok(client.clone());
+            }
+        }
+
+        let client = reqwest::Client::builder()
+            .connect_timeout(Duration::from_secs(settings.connect_timeout_secs))
+            .read_timeout(Duration::from_secs(settings.request_timeout_secs))
+            .build()
+            .map_err(|e| AiError::Other(format!("Failed to create HTTP client: {}", e)))?;
+        *cached = Some((timeouts, client.clone()));
+        Ok(client)
+    }
+
+    /// Runs a non-streaming `/api/generate` call and returns the model's answer.
+    pub async fn generate(
+        &self,
+        settings: &Settings,
+        prompt: &str,
+        images: &[String],
+    ) -> Result<String, AiError> {
+        self.generate_with_format(settings, prompt, images, None)
+            .await
+    }
+
+    /// Like `generate`, but asks for an answer matching the JSON `schema`.
+    /// Not every model honours it, so the answer still needs tolerant parsing.
+    pub async fn generate_json(
+        &self,
+        settings: &Settings,
+        prompt: &str,
+        images: &[String],
+        schema: &Value,
+    ) -> Result<String, AiError> {
+        self.generate_with_format(settings, prompt, images, Some(schema))
+            .await
+    }
+
+    async fn generate_with_format(
+        &self,
+        settings: &Settings,
+        prompt: &str,
+        images: &[String],
+        format: Option<&Value>,
+    ) -> Result<String, AiError> {
+        let request = GenerateRequest {
+            model: &settings.model,
+            prompt,
+            images,
+            stream: false,
+            format,
+        };
+
+        let response = self
+            .post_with_retry(settings, &settings.model, "api/generate", &request)
+            .await?;
+        let body: GenerateResponse = response
+            .json()
+            .await
+            .map_err(|e| AiError::InvalidResponse(e.to_string()))?;
+        Ok(body.response)
+    }
+
+    /// Embeds each of `texts` with `settings.embedding_model`, one vector per text.
+    pub async fn embed(
+        &self,
+        settings: &Settings,
+        texts: &[String],
+    ) -> Result<Vec<Vec<f32>>, AiError> {
+        let model = &settings.embedding_model;
+        let request = EmbedRequest {
+            model,
+            input: texts,
+        };
+
+        let response = self
+            .post_with_retry(settings, model, "api/embed", &request)
+            .await?;
+        let body: EmbedResponse = response
+            .json()
+            .await
+            .map_err(|e| AiError::InvalidResponse(e.to_string()))?;
+
+        if body.embeddings.len() != texts.len() {
+            return Err(AiError::InvalidResponse(format!(
+                "expected {} embeddings, got {}",
+                texts.len(),
+                body.embeddings.len()
+            )));
+        }
+        Ok(body.embeddings)
+    }
+
+    /// Whether the backend answers at all. Never retries.
+    pub async fn is_available(&self, settings: &Settings) -> bool {
+        let Ok(client) = self.client(settings) else {
+            return false;
+        };
+        match client.get(settings.endpoint("api/tags")).send().await {
+            Ok(response) => response.status().is_success(),
+            Err(_) => false,
+        }
+    }
+
+    /// POSTs `body`, retrying transient failures with exponential backoff
+    /// up to `settings.max_retries` times. `model` is only used in errors.
+    async fn post_with_retry<T: Serialize>(
+        &self,
+        settings: &Settings,
+        model: &str,
+        path: &str,
+        body: &T,
+    ) -> Result<reqwest::Response, AiError> {
+        let client = self.client(settings)?;
+        let url = settings.endpoint(path);
+
+        let mut attempt = 0;
+        loop {
+            let error = match client.post(&url).json(body).send().await {
+                Ok(response) if response.status().is_success() => return Ok(response),
+                Ok(response) => error_from_response(response, model).await,
+                Err(e) => error_from_request(e),
+            };
+
+            if attempt >= settings.max_retries || !error.is_transient() {
+                return Err(error);
+            }
+
+            let delay = backoff(self.base_backoff, attempt);
+            log::info!("{}; retrying in {:?}", error, delay);
+            tokio::time::sleep(delay).await;
+            attempt += 1;
+        }
+    }
+}
+
+fn backoff(base: Duration, attempt: u32) -> Duration {
+    base.saturating_mul(1 << attempt.min(16)).min(MAX_BACKOFF)
+}
+
+fn error_from_request(e: reqwest::Error) -> AiError {
+    if e.is_timeout() {
+        AiError::Timeout
+    } else {
+        AiError::Unavailable(e.to_string())
+    }
+}
+
+async fn error_from_response(response: reqwest::Response, model: &str) -> AiError {
+    let status = response.status();
+    let text = response.text().await.unwrap_or_default();
+    let message = serde_json::from_str::<ErrorBody>(&text)
+        .map(|body| body.error)
+        .unwrap_or(text);
+    let lower = message.to_lowercase();
+
+    if status == StatusCode::NOT_FOUND && lower.contains("not found") {
+        AiError::ModelNotFound(model.to_string())
+    } else if lower.contains("loading") {
+        AiError::ModelLoading(model.to_string())
+    } else {
+        AiError::Backend {
+            status: status.as_u16(),
+            message,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn settings_for(server: &mockito::ServerGuard) -> Settings {
+        Settings {
+            backend_url: server.url(),
+            max_retries: 2,
+            ..Settings::default()
+        }
+    }
+
+    fn ollama() -> Ollama {
+        Ollama::with_backoff(Duration::from_millis(1))
+    }
+
+    #[tokio::test]
+    async fn generate_returns_model_response() {
+        let mut server = mockito::Server::new_async().await;
+        let mock = server
+            .mock("POST", "/api/generate")
+            .match_body(mockito::Matcher::PartialJsonString(
+                r#"{"model": "moondream", "stream": false}"#.to_string(),
+            ))
+            .with_body(r#"{"response": "sky, dog", "done": true}"#)
+            .create_async()
+            .await;
+
+        let answer = ollama()
+            .generate(&settings_for(&server), "tags?", &["aW1n".to_string()])
+            .await
+            .unwrap();
+
+        assert_eq!(answer, "sky, dog");
+        mock.assert_async().await;
+    }
+
+    #[tokio::test]
+    async fn generate_json_sends_the_schema() {
+        let mut server = mockito::Server::new_async().await;
+        let mock = server
+            .mock("POST", "/api/generate")
+            .match_body(mockito::Matcher::PartialJsonString(
+                r#"{"format": {"type": "object"}}"#.to_string(),
+            ))
+            .with_body(r#"{"response": "{\"tags\": []}"}"#)
+            .create_async()
+            .await;
+
+        let schema = serde_json::json!({"type": "object"});
+        let answer = ollama()
+            .generate_json(&settings_for(&server), "tags?", &[], &schema)
+            .await
+            .unwrap();
+
+        assert_eq!(answer, r#"{"tags": []}"#);
+        mock.assert_async().await;
+    }
+
+    #[tokio::test]
+    async fn embed_returns_one_vector_per_text() {
+        let mut server = mockito::Server::new_async().await;
+        let mock = server
+            .mock("POST", "/api/embed")
+            .match_body(mockito::Matcher::PartialJsonString(
+                r#"{"model": "nomic-embed-text", "input": ["a dog", "a cat"]}"#.to_string(),
+            ))
+            .with_body(r#"{"embeddings": [[0.1, 0.2], [0.3, 0.4]]}"#)
+            .create_async()
+            .await;
+
+        let texts = ["a dog".to_string(), "a cat".to_string()];
+        let vectors = ollama()
+            .embed(&settings_for(&server), &texts)
+            .await
+            .unwrap();
+
+        assert_eq!(vectors, [[0.1, 0.2], [0.3, 0.4]]);
+        mock.assert_async().await;
+        mock.remove_async().await;
+
+        let short = server
+            .mock("POST", "/api/embed")
+            .with_body(r#"{"embeddings": [[0.1, 0.2]]}"#)
+            .create_async()
+            .await;
+        let error = ollama()
+            .embed(&settings_for(&server), &texts)
+            .await
+            .unwrap_err();
+        assert!(matches!(error, AiError::InvalidResponse(_)));
+        short.assert_async().await;
+    }
+
+    #[tokio::test]
+    async fn transient_errors_are_retried() {
+        let mut server = mockito::Server::new_async().await;
+        let busy = server
+            .mock("POST", "/api/generate")
+            .with_status(503)
+            .with_body(r#"{"error": "server busy, please try again"}"#)
+            .expect(2)
+            .create_async()
+            .await;
+        let ok = server
+            .mock("POST", "/api/generate")
+            .with_body(r#"{"response": "cat"}"#)
+            .expect(1)
+            .create_async()
+            .await;
+
+        let answer = ollama()
+            .generate(&settings_for(&server), "tags?", &[])
+            .await
+            .unwrap();
+
+        assert_eq!(answer, "cat");
+        busy.assert_async().await;
+        ok.assert_async().await;
+    }
+
+    #[tokio::test]
+    async fn retries_are_bounded_and_loading_is_reported() {
+        let mut server = mockito::Server::new_async().await;
+        let loading = server
+            .mock("POST", "/api/generate")
+            .with_status(503)
+            .with_body(r#"{"error": "model is loading"}"#)
+            .expect(3)
+            .create_async()
+            .await;
+
+        let error = ollama()
+            .generate(&settings_for(&server), "tags?", &[])
+            .await
+            .unwrap_err();
+
+        assert_eq!(error, AiError::ModelLoading("moondream".to_string()));
+        loading.assert_async().await;
+    }
+
+    #[tokio::test]
+    async fn missing_model_is_not_retried() {
+        let mut server = mockito::Server::new_async().await;
+        let missing = server
+            .mock("POST", "/api/generate")
+            .with_status(404)
+            .with_body(r#"{"error": "model \"llava\" not found, try pulling it first"}"#)
+            .expect(1)
+            .create_async()
+            .await;
+
+        let settings = Settings {
+            model: "llava".to_string(),
+            ..settings_for(&server)
+        };
+        let error = ollama()
+            .generate(&settings, "tags?", &[])
+            .await
+            .unwrap_err();
+
+        assert_eq!(error, AiError::ModelNotFound("llava".to_string()));
+        missing.assert_async().await;
+    }
+
+    #[tokio::test]
+    async fn unreachable_server_is_unavailable() {
+        // Grab a free port, then close it so nothing is listening
+        let port = std::net::TcpListener::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap()
+            .port();
+        let settings = Settings {
+            backend_url: format!("http://127.0.0.1:{}", port),
+            max_retries: 0,
+            ..Settings::default()
+        };
+
+        let error = ollama()
+            .generate(&settings, "tags?", &[])
+            .await
+            .unwrap_err();
+
+        assert!(matches!(error, AiError::Unavailable(_)));
+        assert!(!ollama().is_available(&settings).await);
+    }
+
+    #[test]
+    fn backoff_grows_and_is_capped() {
+        assert_eq!(backoff(BASE_BACKOFF, 0), Duration::from_millis(500));
+        assert_eq!(backoff(BASE_BACKOFF, 2), Duration::from_secs(2));
+        assert_eq!(backoff(BASE_BACKOFF, 10), MAX_BACKOFF);
+    }
+}
@@ -0,0 +1,833 @@
+use image::{DynamicImage, ImageFormat};
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::io;
+use std::ops::Range;
+use std::path::Path;
+use std::process::{Command, Stdio};
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use crate::metadata::{self, Tiff};
+
+// TIFF tags used to find images inside RAW files
+const TAG_IMAGE_WIDTH: u16 = 0x0100;
+const TAG_IMAGE_HEIGHT: u16 = 0x0101;
+const TAG_COMPRESSION: u16 = 0x0103;
+const TAG_STRIP_OFFSETS: u16 = 0x0111;
+const TAG_STRIP_BYTE_COUNTS: u16 = 0x0117;
+const TAG_SUB_IFDS: u16 = 0x014A;
+const TAG_JPEG_OFFSET: u16 = 0x0201;
+const TAG_JPEG_LENGTH: u16 = 0x0202;
+
+/// Caps the IFDs visited in a RAW file, against offsets that loop.
+const MAX_IFDS: usize = 64;
+
+/// The Canon box inside CR3's `moov` holding the EXIF as CMT1 to CMT4.
+const CANON_UUID: [u8; 16] = [
+    0x85, 0xC0, 0xB6, 0x87, 0x82, 0x0F, 0x11, 0xE0, 0x81, 0x11, 0xF4, 0xCE, 0x46, 0x2B, 0x6A, 0x48,
+];
+/// The top-level CR3 box holding the PRVW preview.
+const CANON_PREVIEW_UUID: [u8; 16] = [
+    0xEA, 0xF4, 0x2B, 0x5E, 0x1C, 0x98, 0x4B, 0x88, 0xB9, 0xFB, 0xB7, 0xDC, 0x40, 0x6E, 0x4D, 0x16,
+];
+/// XMP in ISO base media files.
+const XMP_UUID: [u8; 16] = [
+    0xBE, 0x7A, 0xCF, 0xCB, 0x97, 0xA9, 0x42, 0xE8, 0x9C, 0x71, 0x99, 0x94, 0x91, 0xE3, 0xAF, 0xAC,
+];
+
+/// What can be had from a RAW, HEIC or AVIF file without decoding its main
+/// image, which the image crate can't do for any of them.
+#[derive(Default)]
+pub struct Embedded {
+    data: Vec<u8>,
+    exif: Option<Range<usize>>,
+    exif_ifd: Option<Range<usize>>,
+    xmp: Option<Range<usize>>,
+    preview: Option<Range<usize>>,
+    /// Size of the main image as stored, before any orientation is applied.
+    pub size: Option<(u32, u32)>,
+}
+
+impl Embedded {
+    /// EXIF as a TIFF structure.
+    pub fn exif(&self) -> Option<&[u8]> {
+        self.slice(&self.exif)
+    }
+
+    /// Canon CR3 keeps the Exif IFD apart from IFD0, in a TIFF of its own.
+    pub fn exif_ifd(&self) -> Option<&[u8]> {
+        self.slice(&self.exif_ifd)
+    }
+
+    pub fn xmp(&self) -> Option<&[u8]> {
+        self.slice(&self.xmp)
+    }
+
+    /// The largest embedded JPEG the image crate can decode.
+    pub fn preview(&self) -> Option<&[u8]> {
+        self.slice(&self.preview)
+    }
+
+    fn slice(&self, range: &Option<Range<usize>>) -> Option<&[u8]> {
+        self.data.get(range.clone()?)
+    }
+}
+
+/// Reads the file at `path` and finds its EXIF, XMP and largest usable
+/// preview, going by the container rather than the extension.
+pub fn read(path: &Path) -> Result<Embedded, String> {
+    let data = fs::read(path).map_err(|e| format!("Failed to read image: {}", e))?;
+    Ok(parse(data))
+}
+
+fn parse(data: Vec<u8>) -> Embedded {
+    let mut embedded = if data.starts_with(b"FUJIFILMCCD-RAW") {
+        fujifilm(&data)
+    } else if data.get(4..12) == Some(b"ftypcrx ") {
+        canon_cr3(&data)
+    } else if data.get(4..8) == Some(b"ftyp") {
+        heif(&data)
+    } else {
+        tiff_raw(&data)
+    };
+
+    // Fujifilm, for one, only has EXIF inside its preview
+    if embedded.exif.is_none() {
+        embedded.exif = embedded
+            .preview
+            .clone()
+            .and_then(|preview| jpeg_exif(&data, preview));
+    }
+    embedded.data = data;
+    embedded
+}
+
+/// Decodes a RAW, HEIC or AVIF file upright, from its embedded preview when
+/// it has one and through an external converter otherwise.
+pub fn decode(path: &Path) -> Result<DynamicImage, String> {
+    let embedded = read(path)?;
+    let Some(preview) = embedded.preview() else {
+        let jpeg = convert_externally(path)?;
+        // Converters apply the orientation themselves
+        return image::load_from_memory_with_format(&jpeg, ImageFormat::Jpeg)
+            .map_err(|e| format!("Failed to decode converted image: {}", e));
+    };
+
+    let mut image = image::load_from_memory_with_format(preview, ImageFormat::Jpeg)
+        .map_err(|e| format!("Failed to decode preview: {}", e))?;
+    // Previews are stored the way the sensor saw the scene
+    let orientation = embedded
+        .exif()
+        .and_then(metadata::orientation)
+        .and_then(|o| image::metadata::Orientation::from_exif(o as u8));
+    if let Some(orientation) = orientation {
+        image.apply_orientation(orientation);
+    }
+    Ok(image)
+}
+
+/// Converters tried in turn for files without a usable preview, typically
+/// HEIC and AVIF whose pixels are HEVC or AV1. `sips` comes with macOS,
+/// `heif-convert` with libheif and `magick` with ImageMagick.
+const CONVERTERS: &[(&str, &[&str])] = &[
+    ("sips", &["-s", "format", "jpeg", "{in}", "--out", "{out}"]),
+    ("heif-convert", &["{in}", "{out}"]),
+    ("magick", &["{in}", "{out}"]),
+];
+
+fn convert_externally(path: &Path) -> Result<Vec<u8>, String> {
+    static NEXT: AtomicU64 = AtomicU64::new(0);
+    let out = std::env::temp_dir().join(format!(
+        "image-gallery-{}-{}.jpg",
+        std::process::id(),
+        NEXT.fetch_add(1, Ordering::Relaxed)
+    ));
+
+    let mut failures = Vec::new();
+    for (program, args) in CONVERTERS {
+        let args = args.iter().map(|arg| match *arg {
+            "{in}" => path.as_os_str(),
+            "{out}" => out.as_os_str(),
+            arg => arg.as_ref(),
+        });
+        let status = Command::new(program)
+            .args(args)
+            .stdin(Stdio::null())
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .status();
+        match status {
+            Ok(status) if status.success() => {
+                let jpeg = fs::read(&out);
+                let _ = fs::remove_file(&out);
+                match jpeg {
+                    Ok(jpeg) => return Ok(jpeg),
+                    Err(e) => failures.push(format!("{}: {}", program, e)),
+                }
+            }
+            Ok(status) => {
+                let _ = fs::remove_file(&out);
+                failures.push(format!("{}: {}", program, status));
+            }
+            Err(e) if e.kind() == io::ErrorKind::NotFound => {}
+            Err(e) => failures.push(format!("{}: {}", program, e)),
+        }
+    }
+
+    if failures.is_empty() {
+        Err(
+            "No embedded preview, and no converter (sips, heif-convert or magick) is installed"
+                .to_string(),
+        )
+    } else {
+        Err(format!(
+            "No embedded preview, and conversion failed: {}",
+            failures.join("; ")
+        ))
+    }
+}
+
+/// NEF, ARW, DNG, CR2, ORF, RW2, PEF and the like: TIFF structures whose
+/// IFDs and SubIFDs hold the raw data next to one or more JPEG previews.
+fn tiff_raw(data: &[u8]) -> Embedded {
+    let Some(tiff) = Tiff::new(data) else {
+        return Embedded::default();
+    };
+    let mut embedded = Embedded {
+        exif: Some(0..data.len()),
+        ..Embedded::default()
+    };
+
+    let mut pending: Vec<u32> = tiff.u32(4).into_iter().collect();
+    let mut visited = HashSet::new();
+    while let Some(offset) = pending.pop() {
+        if visited.len() >= MAX_IFDS || !visited.insert(offset) {
+            continue;
+        }
+        let Some(ifd) = tiff.ifd(offset) else {
+            continue;
+        };
+        pending.extend(tiff.next_ifd(offset));
+        if let Some(entry) = ifd.find(TAG_SUB_IFDS) {
+            pending.extend(tiff.uints(entry));
+        }
+
+        let uint = |tag| ifd.find(tag).and_then(|e| tiff.uint(e));
+        // The raw data is the largest image in the file
+        if let (Some(width), Some(height)) = (uint(TAG_IMAGE_WIDTH), uint(TAG_IMAGE_HEIGHT)) {
+            let area = |(w, h): (u32, u32)| u64::from(w) * u64::from(h);
+            if embedded
+                .size
+                .map_or(true, |size| area((width, height)) > area(size))
+            {
+                embedded.size = Some((width, height));
+            }
+        }
+
+        let jpeg_compressed = matches!(uint(TAG_COMPRESSION), Some(6 | 7));
+        let strip = match (
+            ifd.find(TAG_STRIP_OFFSETS).map(|e| tiff.uints(e)),
+            ifd.find(TAG_STRIP_BYTE_COUNTS).map(|e| tiff.uints(e)),
+        ) {
+            (Some(offsets), Some(counts)) if jpeg_compressed && offsets.len() == 1 => {
+                Some((offsets[0], *counts.first().unwrap_or(&0)))
+            }
+            _ => None,
+        };
+        let interchange = uint(TAG_JPEG_OFFSET).zip(uint(TAG_JPEG_LENGTH));
+
+        for (start, len) in strip.into_iter().chain(interchange) {
+            let range = start as usize..start as usize + len as usize;
+            let larger = embedded
+                .preview
+                .as_ref()
+                .map_or(true, |p| range.len() > p.len());
+            if larger && data.get(range.clone()).is_some_and(is_decodable_jpeg) {
+                embedded.preview = Some(range);
+            }
+        }
+    }
+    embedded
+}
+
+/// Fujifilm RAF: a header pointing at a JPEG preview that carries the EXIF.
+fn fujifilm(data: &[u8]) -> Embedded {
+    let mut reader = Reader { data, at: 84 };
+    let preview = reader
+        .uint(4)
+        .zip(reader.uint(4))
+        .map(|(start, len)| start as usize..(start + len) as usize)
+        .filter(|range| data.get(range.clone()).is_some_and(is_decodable_jpeg));
+    Embedded {
+        preview,
+        ..Embedded::default()
+    }
+}
+
+/// Canon CR3: ISO base media with Canon boxes for the EXIF, the XMP and a
+/// preview of about 1620 pixels wide.
+fn canon_cr3(data: &[u8]) -> Embedded {
+    let mut embedded = Embedded::default();
+    for (kind, payload) in boxes(data, 0..data.len()) {
+        match &kind {
+            b"moov" => {
+                let canon = boxes(data, payload)
+                    .into_iter()
+                    .find_map(|(kind, payload)| uuid_payload(data, &kind, payload, CANON_UUID));
+                for (kind, payload) in canon.map(|c| boxes(data, c)).unwrap_or_default() {
+                    match &kind {
+                        b"CMT1" => embedded.exif = Some(payload),
+                        b"CMT2" => embedded.exif_ifd = Some(payload),
+                        _ => {}
+                    }
+                }
+            }
+            b"uuid" => {
+                if let Some(xmp) = uuid_payload(data, &kind, payload.clone(), XMP_UUID) {
+                    embedded.xmp = Some(xmp);
+                } else if let Some(preview) = uuid_payload(data, &kind, payload, CANON_PREVIEW_UUID)
+                {
+                    // Eight bytes of Canon's own before the PRVW box
+                    let prvw = boxes(data, preview.start + 8..preview.end)
+                        .into_iter()
+                        .find(|(kind, _)| kind == b"PRVW");
+                    if let Some((_, prvw)) = prvw {
+                        // The JPEG follows a short header of sizes
+                        embedded.preview = data[prvw.clone()]
+                            .windows(3)
+                            .position(|w| w == [0xFF, 0xD8, 0xFF])
+                            .map(|at| prvw.start + at..prvw.end)
+                            .filter(|range| is_decodable_jpeg(&data[range.clone()]));
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+    embedded
+}
+
+/// HEIC and AVIF: items described in the `meta` box, found through `iinf`
+/// and `iloc`. The image items are HEVC or AV1, but the EXIF, the XMP and
+/// sometimes a JPEG thumbnail are items of their own.
+fn heif(data: &[u8]) -> Embedded {
+    let mut embedded = Embedded::default();
+    let Some(meta) = find_box(data, 0..data.len(), b"meta") else {
+        return embedded;
+    };
+
+    let mut items = Vec::new();
+    let mut locations = HashMap::new();
+    // `meta` is a full box: version and flags come before its children
+    for (kind, payload) in boxes(data, meta.start + 4..meta.end) {
+        match &kind {
+            b"iinf" => items = item_infos(data, payload).unwrap_or_default(),
+            b"iloc" => locations = item_locations(data, payload).unwrap_or_default(),
+            b"iprp" => embedded.size = largest_spatial_extent(data, payload),
+            _ => {}
+        }
+    }
+
+    for item in items {
+        let Some(range) = locations.get(&item.id).cloned() else {
+            continue;
+        };
+        match &item.kind {
+            // Starts with the offset of the TIFF header from after itself
+            b"Exif" => {
+                let offset = Reader::new(data, range.start).uint(4).unwrap_or(0) as usize;
+                embedded.exif = range
+                    .start
+                    .checked_add(4)
+                    .and_then(|start| start.checked_add(offset))
+                    .map(|start| start..range.end);
+            }
+            b"mime" if item.content_type == "application/rdf+xml" => embedded.xmp = Some(range),
+            b"jpeg" => {
+                let larger = embedded
+                    .preview
+                    .as_ref()
+                    .map_or(true, |p| range.len() > p.len());
+                if larger && data.get(range.clone()).is_some_and(is_decodable_jpeg) {
+                    embedded.preview = Some(range);
+                }
+            }
+            _ => {}
+        }
+    }
+    embedded
+}
+
+struct ItemInfo {
+    id: u32,
+    kind: [u8; 4],
+    content_type: String,
+}
+
+fn item_infos(data: &[u8], iinf: Range<usize>) -> Option<Vec<ItemInfo>> {
+    let version = *data.get(iinf.start)?;
+    let entries = iinf.start + 4 + if version == 0 { 2 } else { 4 };
+    let mut items = Vec::new();
+    for (kind, payload) in boxes(data, entries..iinf.end) {
+        if &kind != b"infe" {
+            continue;
+        }
+        let mut reader = Reader::new(data, payload.start);
+        // Versions 0 and 1 predate item types and never describe EXIF
+        let id = match reader.uint(1)? {
+            2 => reader.skip(3).uint(2)? as u32,
+            3 => reader.skip(3).uint(4)? as u32,
+            _ => continue,
+        };
+        let kind: [u8; 4] = reader.skip(2).bytes(4)?.try_into().ok()?;
+        let content_type = if &kind == b"mime" {
+            let _name = reader.c_string(payload.end);
+            reader.c_string(payload.end)
+        } else {
+            String::new()
+        };
+        items.push(ItemInfo {
+            id,
+            kind,
+            content_type,
+        });
+    }
+    Some(items)
+}
+
+/// File ranges of items stored as a single extent in the file itself.
+fn item_locations(data: &[u8], iloc: Range<usize>) -> Option<HashMap<u32, Range<usize>>> {
+    let mut reader = Reader::new(data, iloc.start);
+    let version = reader.uint(1)?;
+    reader.skip(3);
+    let sizes = reader.uint(1)? as usize;
+    let (offset_size, length_size) = (sizes >> 4, sizes & 0xF);
+    let sizes = reader.uint(1)? as usize;
+    let base_offset_size = sizes >> 4;
+    let index_size = if version == 0 { 0 } else { sizes & 0xF };
+    let count = reader.uint(if version < 2 { 2 } else { 4 })?;
+
+    let mut locations = HashMap::new();
+    for _ in 0..count {
+        let id = reader.uint(if version < 2 { 2 } else { 4 })? as u32;
+        let construction = if version == 0 {
+            0
+        } else {
+            reader.uint(2)? & 0xF
+        };
+        reader.skip(2);
+        let base = reader.uint(base_offset_size)? as usize;
+        let extents = reader.uint(2)?;
+        let mut ranges = Vec::new();
+        for _ in 0..extents {
+            reader.skip(index_size);
+            let offset = reader.uint(offset_size)? as usize;
+            let length = reader.uint(length_size)? as usize;
+            ranges.push((base.checked_add(offset), length));
+        }
+        // Construction method 0: the extents are offsets into the file.
+        // Ranges that don't fit in memory can't be in the file either.
+        if let ([(Some(start), length)], 0) = (ranges.as_slice(), construction) {
+            let end = if *length == 0 {
+                Some(data.len())
+            } else {
+                start.checked_add(*length)
+            };
+            if let Some(end) = end {
+                locations.insert(id, *start..end);
+            }
+        }
+    }
+    Some(locations)
+}
+
+/// The largest `ispe` property: the full image rather than one of its tiles
+/// or the thumbnail.
+fn largest_spatial_extent(data: &[u8], iprp: Range<usize>) -> Option<(u32, u32)> {
+    let ipco = find_box(data, iprp, b"ipco")?;
+    boxes(data, ipco)
+        .into_iter()
+        .filter(|(kind, _)| kind == b"ispe")
+        .filter_map(|(_, payload)| {
+            let mut reader = Reader::new(data, payload.start + 4);
+            Some((reader.uint(4)? as u32, reader.uint(4)? as u32))
+        })
+        .max_by_key(|&(width, height)| u64::from(width) * u64::from(height))
+}
+
+/// The ISO base media boxes directly inside `range`, as type and payload.
+fn boxes(data: &[u8], range: Range<usize>) -> Vec<([u8; 4], Range<usize>)> {
+    let mut found = Vec::new();
+    let mut at = range.start;
+    while at + 8 <= range.end.min(data.len()) {
+        let mut reader = Reader::new(data, at);
+        let (Some(size), Some(kind)) = (reader.uint(4), reader.bytes(4)) else {
+            break;
+        };
+        let kind: [u8; 4] = kind.try_into().unwrap_or_default();
+        let (header, size) = match size {
+            0 => (8, range.end - at),
+            1 => match reader.uint(8) {
+                Some(size) => (16, size as usize),
+                None => break,
+            },
+            size => (8, size as usize),
+        };
+        let Some(end) = at
+            .checked_add(size)
+            .filter(|&end| size >= header && end <= range.end)
+        else {
+            break;
+        };
+        found.push((kind, at + header..end));
+        at = end;
+    }
+    found
+}
+
+fn find_box(data: &[u8], range: Range<usize>, kind: &[u8; 4]) -> Option<Range<usize>> {
+    boxes(data, range)
+        .into_iter()
+        .find(|(k, _)| k == kind)
+        .map(|(_, payload)| payload)
+}
+
+/// The payload after the identifier of a `uuid` box with the given one.
+fn uuid_payload(
+    data: &[u8],
+    kind: &[u8; 4],
+    payload: Range<usize>,
+    uuid: [u8; 16],
+) -> Option<Range<usize>> {
+    (kind == b"uuid" && data.get(payload.start..payload.start + 16)? == uuid)
+        .then(|| payload.start + 16..payload.end)
+}
+
+/// Whether `data` is a JPEG in one of the DCT modes the image crate
+/// decodes. RAW files also embed lossless JPEG, which it can't.
+fn is_decodable_jpeg(data: &[u8]) -> bool {
+    if !data.starts_with(&[0xFF, 0xD8]) {
+        return false;
+    }
+    let mut at = 2;
+    while let (Some(0xFF), Some(&marker)) = (data.get(at), data.get(at + 1)) {
+        match marker {
+            // Baseline, extended and progressive
+            0xC0..=0xC2 => return true,
+            // Lossless, hierarchical, arithmetic coded, or the data already
+            0xC3 | 0xC5..=0xC7 | 0xC9..=0xCB | 0xCD..=0xCF | 0xDA => return false,
+            // Fill byte
+            0xFF => at += 1,
+            _ => match Reader::new(data, at + 2).uint(2) {
+                Some(len) => at += 2 + len as usize,
+                None => return false,
+            },
+        }
+    }
+    false
+}
+
+/// Where the EXIF of the JPEG at `jpeg` is, as a TIFF structure.
+fn jpeg_exif(data: &[u8], jpeg: Range<usize>) -> Option<Range<usize>> {
+    let mut at = jpeg.start + 2;
+    while at + 4 <= jpeg.end {
+        let marker = *data.get(at + 1)?;
+        if data[at] != 0xFF || marker == 0xDA {
+            return None;
+        }
+        let len = Reader::new(data, at + 2).uint(2)? as usize;
+        let payload = at + 4..at + 2 + len;
+        if marker == 0xE1 && data.get(payload.start..payload.start + 6)? == b"Exif\0\0" {
+            return Some(payload.start + 6..payload.end);
+        }
+        at += 2 + len;
+    }
+    None
+}
+
+/// Reads big-endian integers, as ISO base media and RAF headers store them.
+struct Reader<'a> {
+    data: &'a [u8],
+    at: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn new(data: &'a [u8], at: usize) -> Self {
+        Reader { data, at }
+    }
+
+    fn skip(&mut self, len: usize) -> &mut Self {
+        self.at += len;
+        self
+    }
+
+    fn bytes(&mut self, len: usize) -> Option<&'a [u8]> {
+        let bytes = self.data.get(self.at..self.at.checked_add(len)?)?;
+        self.at += len;
+        Some(bytes)
+    }
+
+    /// A `len`-byte unsigned integer; zero bytes read as 0.
+    fn uint(&mut self, len: usize) -> Option<u64> {
+        if len > 8 {
+            return None;
+        }
+        let bytes = self.bytes(len)?;
+        Some(bytes.iter().fold(0, |value, &b| value << 8 | u64::from(b)))
+    }
+
+    fn c_string(&mut self, end: usize) -> String {
+        let rest = self.data.get(self.at..end).unwrap_or_default();
+        let len = rest.iter().position(|&b| b == 0).unwrap_or(rest.len());
+        self.at += len + 1;
+        String::from_utf8_lossy(&rest[..len]).into_owned()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::metadata as file_metadata;
+    use crate::test_support::temp_dir;
+
+    fn jpeg(width: u32, height: u32) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        image::RgbImage::new(width, height)
+            .write_to(&mut std::io::Cursor::new(&mut bytes), ImageFormat::Jpeg)
+            .unwrap();
+        bytes
+    }
+
+    /// A little-endian IFD of (tag, type, count, inline value or offset).
+    fn ifd(entries: &[(u16, u16, u32, u32)]) -> Vec<u8> {
+        let mut out = (entries.len() as u16).to_le_bytes().to_vec();
+        for &(tag, kind, count, value) in entries {
+            out.extend(tag.to_le_bytes());
+            out.extend(kind.to_le_bytes());
+            out.extend(count.to_le_bytes());
+            out.extend(value.to_le_bytes());
+        }
+        out.extend(0u32.to_le_bytes());
+        out
+    }
+
+    /// A TIFF with one IFD whose make and orientation are set, and any
+    /// other inline entries.
+    fn exif(make: &str, orientation: u32, more: &[(u16, u16, u32, u32)]) -> Vec<u8> {
+        let mut entries = vec![
+            (0x010F, 2, make.len() as u32 + 1, 0),
+            (0x0112, 3, 1, orientation),
+        ];
+        entries.extend(more);
+        entries[0].3 = 8 + (2 + 12 * entries.len() as u32 + 4);
+
+        let mut out = b"II*\0".to_vec();
+        out.extend(8u32.to_le_bytes());
+        out.extend(ifd(&entries));
+        out.extend(make.as_bytes());
+        out.push(0);
+        out
+    }
+
+    /// An ISO base media box.
+    fn bx(kind: &[u8; 4], payload: &[u8]) -> Vec<u8> {
+        let mut out = ((payload.len() + 8) as u32).to_be_bytes().to_vec();
+        out.extend(kind);
+        out.extend(payload);
+        out
+    }
+
+    /// `bytes` written to a file called `name`, in a folder of its own
+    /// for the test to remove.
+    fn temp_file(name: &str, bytes: &[u8]) -> std::path::PathBuf {
+        let path = temp_dir(&format!("previews_{}", name)).join(name);
+        fs::write(&path, bytes).unwrap();
+        path
+    }
+
+    #[test]
+    fn largest_decodable_jpeg_is_taken_from_tiff_raws() {
+        let thumbnail = jpeg(16, 12);
+        let preview = jpeg(64, 48);
+        // Lossless JPEG as raw data, bigger than both but not decodable
+        let mut lossless = vec![0xFF, 0xD8, 0xFF, 0xC3, 0, 11];
+        lossless.resize(20_000, 0);
+
+        let ifd0_at = 8;
+        let sub_a_at = ifd0_at + 2 + 12 * 7 + 4;
+        let sub_b_at = sub_a_at + 2 + 12 * 2 + 4;
+        let make_at = sub_b_at + 2 + 12 * 5 + 4;
+        let sub_ifds_at = make_at + 6;
+        let thumbnail_at = sub_ifds_at + 8;
+        let preview_at = thumbnail_at + thumbnail.len() as u32;
+        let lossless_at = preview_at + preview.len() as u32;
+
+        let mut data = b"II*\0".to_vec();
+        data.extend(ifd0_at.to_le_bytes());
+        data.extend(ifd(&[
+            (TAG_IMAGE_WIDTH, 3, 1, 160),
+            (TAG_IMAGE_HEIGHT, 3, 1, 120),
+            (0x010F, 2, 6, make_at),
+            (0x0112, 3, 1, 6),
+            (TAG_SUB_IFDS, 4, 2, sub_ifds_at),
+            (TAG_JPEG_OFFSET, 4, 1, thumbnail_at),
+            (TAG_JPEG_LENGTH, 4, 1, thumbnail.len() as u32),
+        ]));
+        data.extend(ifd(&[
+            (TAG_JPEG_OFFSET, 4, 1, preview_at),
+            (TAG_JPEG_LENGTH, 4, 1, preview.len() as u32),
+        ]));
+        data.extend(ifd(&[
+            (TAG_IMAGE_WIDTH, 4, 1, 6000),
+            (TAG_IMAGE_HEIGHT, 4, 1, 4000),
+            (TAG_COMPRESSION, 3, 1, 7),
+            (TAG_STRIP_OFFSETS, 4, 1, lossless_at),
+            (TAG_STRIP_BYTE_COUNTS, 4, 1, lossless.len() as u32),
+        ]));
+        data.extend(b"NIKON\0");
+        data.extend(sub_a_at.to_le_bytes());
+        data.extend(sub_b_at.to_le_bytes());
+        data.extend(&thumbnail);
+        data.extend(&preview);
+        data.extend(&lossless);
+        let path = temp_file("DSC_0001.NEF", &data);
+
+        let embedded = read(&path).unwrap();
+        assert_eq!(embedded.preview(), Some(preview.as_slice()));
+        assert_eq!(embedded.size, Some((6000, 4000)));
+
+        // Upright per the orientation of the RAW, not of the preview
+        let image = decode(&path).unwrap();
+        assert_eq!((image.width(), image.height()), (48, 64));
+
+        let details = file_metadata::read(&path);
+        assert_eq!((details.width, details.height), (Some(4000), Some(6000)));
+        assert_eq!(details.metadata.camera_make.as_deref(), Some("NIKON"));
+        fs::remove_dir_all(path.parent().unwrap()).unwrap();
+    }
+
+    #[test]
+    fn canon_cr3_boxes_are_read() {
+        let preview = jpeg(64, 48);
+        let cmt1 = exif("Canon", 8, &[]);
+        // CMT2 is the Exif IFD on its own: the full size lives there
+        let mut cmt2 = b"II*\0".to_vec();
+        cmt2.extend(8u32.to_le_bytes());
+        cmt2.extend(ifd(&[(0xA002, 4, 1, 6000), (0xA003, 4, 1, 4000)]));
+
+        let mut canon = CANON_UUID.to_vec();
+        canon.extend(bx(b"CMT1", &cmt1));
+        canon.extend(bx(b"CMT2", &cmt2));
+        let mut prvw = vec![0; 16];
+        prvw.extend(&preview);
+        let mut preview_box = CANON_PREVIEW_UUID.to_vec();
+        preview_box.extend([0; 8]);
+        preview_box.extend(bx(b"PRVW", &prvw));
+
+        let mut data = bx(b"ftyp", b"crx \0\0\0\x01crx isom");
+        data.extend(bx(b"moov", &bx(b"uuid", &canon)));
+        data.extend(bx(b"uuid", &preview_box));
+        data.extend(bx(b"mdat", &[0; 64]));
+        let path = temp_file("IMG_0001.CR3", &data);
+
+        let image = decode(&path).unwrap();
+        assert_eq!((image.width(), image.height()), (48, 64));
+
+        let details = file_metadata::read(&path);
+        assert_eq!((details.width, details.height), (Some(4000), Some(6000)));
+        assert_eq!(details.metadata.camera_make.as_deref(), Some("Canon"));
+        fs::remove_dir_all(path.parent().unwrap()).unwrap();
+    }
+
+    #[test]
+    fn heif_exif_xmp_and_size_come_from_items() {
+        let tiff = exif("Apple", 6, &[]);
+        let mut exif_item = 0u32.to_be_bytes().to_vec();
+        exif_item.extend(&tiff);
+        let xmp = br#"<x:xmpmeta xmlns:x="adobe:ns:meta/"><rdf:RDF xmlns:rdf="http://www.w3.org/1999/02/22-rdf-syntax-ns#"><rdf:Description xmlns:dc="http://purl.org/dc/elements/1.1/"><dc:subject><rdf:Bag><rdf:li>harbour</rdf:li></rdf:Bag></dc:subject></rdf:Description></rdf:RDF></x:xmpmeta>"#;
+
+        let infe = |id: u16, kind: &[u8; 4], extra: &[u8]| {
+            let mut payload = vec![2, 0, 0, 0];
+            payload.extend(id.to_be_bytes());
+            payload.extend([0, 0]);
+            payload.extend(kind);
+            payload.push(0);
+            payload.extend(extra);
+            bx(b"infe", &payload)
+        };
+        let ispe = |width: u32, height: u32| {
+            let mut payload = vec![0; 4];
+            payload.extend(width.to_be_bytes());
+            payload.extend(height.to_be_bytes());
+            bx(b"ispe", &payload)
+        };
+        let meta = |mdat_at: u32| {
+            let mut iinf = vec![0, 0, 0, 0, 0, 3];
+            iinf.extend(infe(1, b"hvc1", b""));
+            iinf.extend(infe(2, b"Exif", b""));
+            iinf.extend(infe(3, b"mime", b"application/rdf+xml\0"));
+
+            let mut iloc = vec![0, 0, 0, 0, 0x44, 0x00, 0, 3];
+            for (id, offset, length) in [
+                (1u16, mdat_at, 16u32),
+                (2, mdat_at + 16, exif_item.len() as u32),
+                (3, mdat_at + 16 + exif_item.len() as u32, xmp.len() as u32),
+            ] {
+                iloc.extend(id.to_be_bytes());
+                iloc.extend([0, 0, 0, 1]);
+                iloc.extend(offset.to_be_bytes());
+                iloc.extend(length.to_be_bytes());
+            }
+
+            let mut ipco = ispe(512, 512);
+            ipco.extend(ispe(4032, 3024));
+            let mut payload = vec![0; 4];
+            payload.extend(bx(b"iinf", &iinf));
+            payload.extend(bx(b"iloc", &iloc));
+            payload.extend(bx(b"iprp", &bx(b"ipco", &ipco)));
+            bx(b"meta", &payload)
+        };
+
+        let ftyp = bx(b"ftyp", b"heic\0\0\0\0mif1heic");
+        let meta_len = meta(0).len() as u32;
+        let mut data = ftyp.clone();
+        data.extend(meta(ftyp.len() as u32 + meta_len + 8));
+        let mut mdat = vec![0; 16];
+        mdat.extend(&exif_item);
+        mdat.extend(xmp);
+        data.extend(bx(b"mdat", &mdat));
+        let path = temp_file("IMG_1234.HEIC", &data);
+
+        let embedded = read(&path).unwrap();
+        assert_eq!(embedded.exif(), Some(tiff.as_slice()));
+        assert_eq!(embedded.size, Some((4032, 3024)));
+        assert_eq!(embedded.preview(), None);
+
+        let details = file_metadata::read(&path);
+        assert_eq!((details.width, details.height), (Some(3024), Some(4032)));
+        assert_eq!(details.metadata.camera_make.as_deref(), Some("Apple"));
+        assert_eq!(details.metadata.keywords, ["harbour"]);
+    }
+
+    #[test]
+    fn item_locations_past_the_end_of_memory_are_skipped() {
+        // Version 0 with eight-byte offsets, lengths and base offsets
+        let mut iloc = vec![0, 0, 0, 0, 0x88, 0x80, 0, 3];
+        for (id, base, offset, length) in [
+            (1u16, u64::MAX, 1u64, 1u64),
+            (2, 0, 10, u64::MAX),
+            (3, 100, 20, 30),
+        ] {
+            iloc.extend(id.to_be_bytes());
+            iloc.extend([0, 0]);
+            iloc.extend(base.to_be_bytes());
+            iloc.extend(1u16.to_be_bytes());
+            iloc.extend(offset.to_be_bytes());
+            iloc.extend(length.to_be_bytes());
+        }
+
+        let locations = item_locations(&iloc, 0..iloc.len()).unwrap();
+        assert_eq!(locations.len(), 1);
+        assert_eq!(locations[&3], 120..150);
+    }
+}
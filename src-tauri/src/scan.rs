@@ -0,0 +1,972 @@
+use globset::{Glob, GlobSet, GlobSetBuilder};
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{Duration, Instant};
+use walkdir::{DirEntry, WalkDir};
+
+use crate::catalog::{Catalog, KnownFile};
+use crate::colours::PaletteColour;
+use crate::fingerprint::{self, Appearance};
+use crate::formats::{self, Format};
+use crate::identity::{self, FileStamp};
+use crate::metadata::{self, FileDetails, ImageMetadata};
+use crate::natural;
+use crate::watcher::Watchers;
+
+pub const IMAGE_EXTENSIONS: &[&str] = &[
+    "jpg", "jpeg", "png", "gif", "webp", "bmp", "tiff", "tif", "svg", "heic", "heif", "hif",
+    "avif", "cr2", "cr3", "nef", "nrw", "arw", "srf", "sr2", "dng", "orf", "rw2", "pef", "raf",
+];
+
+#[derive(Serialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct ImageInfo {
+    pub id: String,
+    pub path: String,
+    pub relative_path: String,
+    pub name: String,
+    pub tags: Vec<String>,
+    /// Those of `tags` imported from keywords embedded in the file.
+    pub file_tags: Vec<String>,
+    /// Those of `tags` the vision model suggested.
+    pub model_tags: Vec<String>,
+    pub description: String,
+    /// Pixel size as displayed. Like everything read from inside the file,
+    /// `None` until it has been read or if it couldn't be.
+    pub width: Option<u32>,
+    pub height: Option<u32>,
+    /// File size in bytes.
+    pub size: Option<u64>,
+    /// Creation and modification times in milliseconds since the epoch.
+    pub created: Option<i64>,
+    pub modified: Option<i64>,
+    pub mime: Option<String>,
+    /// The RAW file shot alongside this JPEG or HEIC, which the pair shares
+    /// a record with.
+    pub raw_path: Option<String>,
+    /// Embedded EXIF/XMP/IPTC metadata.
+    pub metadata: Option<ImageMetadata>,
+    /// Dominant colours, most common first.
+    pub palette: Option<Vec<PaletteColour>>,
+}
+
+/// A file found on disk, identified by its content hash.
+pub struct ScannedFile {
+    pub path: String,
+    pub relative_path: String,
+    pub name: String,
+    pub content_hash: String,
+    pub stamp: FileStamp,
+    pub created: Option<i64>,
+    /// Format recognised from the contents, whatever the extension says.
+    pub format: Format,
+    /// Freshly read details, or `None` when the stored ones are still current.
+    pub details: Option<FileDetails>,
+    /// Perceptual hashes, colour histogram and palette, computed along
+    /// with the details.
+    pub appearance: Option<Appearance>,
+    /// Problems found while reading the details; only meaningful with them.
+    pub issues: Vec<ScanIssue>,
+    /// The RAW half of a RAW+JPEG pair.
+    pub raw_path: Option<String>,
+}
+
+/// Something wrong with a file found by a scan.
+#[derive(Serialize, Clone, Debug, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct ScanIssue {
+    pub path: String,
+    pub relative_path: String,
+    pub kind: IssueKind,
+    pub message: String,
+}
+
+#[derive(Serialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub enum IssueKind {
+    /// Named like an image but the contents aren't a recognised format. The
+    /// file is left out of the library.
+    NotAnImage,
+    /// The extension is missing or belongs to another format than the contents.
+    ExtensionMismatch,
+    /// The file stops before its format's end marker.
+    Truncated,
+    /// Recognised, but the header couldn't be decoded.
+    Unreadable,
+}
+
+impl IssueKind {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            IssueKind::NotAnImage => "notAnImage",
+            IssueKind::ExtensionMismatch => "extensionMismatch",
+            IssueKind::Truncated => "truncated",
+            IssueKind::Unreadable => "unreadable",
+        }
+    }
+
+    pub fn parse(kind: &str) -> Option<Self> {
+        [
+            IssueKind::NotAnImage,
+            IssueKind::ExtensionMismatch,
+            IssueKind::Truncated,
+            IssueKind::Unreadable,
+        ]
+        .into_iter()
+        .find(|k| k.as_str() == kind)
+    }
+}
+
+/// What a scan makes of a file.
+pub enum Inspection {
+    /// An image for the library, with any issues found.
+    Image(Box<ScannedFile>),
+    /// Named like an image but isn't one.
+    Rejected(ScanIssue),
+    /// The RAW half of a RAW+JPEG pair, recorded with the JPEG.
+    Paired,
+    /// Neither named nor formatted like an image.
+    Other,
+}
+
+#[derive(Serialize, Deserialize, Default, Clone)]
+#[serde(rename_all = "camelCase", default)]
+pub struct ScanOptions {
+    /// Descend into subfolders instead of only reading the top level.
+    pub recursive: bool,
+    /// How many folder levels below the root to visit (0 = root only). `None` means unlimited.
+    pub max_depth: Option<usize>,
+    /// If non-empty, only files matching one of these globs are returned.
+    pub include: Vec<String>,
+    /// Files and folders matching any of these globs are skipped, e.g. `@eaDir` or `**/.thumbnails`.
+    pub exclude: Vec<String>,
+    /// Follow symlinked files and folders. Symlink loops are detected and skipped.
+    pub follow_symlinks: bool,
+}
+
+#[derive(Clone)]
+pub struct Filters {
+    include: Option<GlobSet>,
+    exclude: GlobSet,
+    // In walkdir terms: the root is depth 0 and its files are depth 1
+    max_depth: usize,
+}
+
+impl Filters {
+    pub fn new(options: &ScanOptions) -> Result<Self, String> {
+        let include = if options.include.is_empty() {
+            None
+        } else {
+            Some(build_glob_set(&options.include)?)
+        };
+
+        let max_depth = if options.recursive {
+            options
+                .max_depth
+                .map(|d| d.saturating_add(1))
+                .unwrap_or(usize::MAX)
+        } else {
+            1
+        };
+
+        Ok(Filters {
+            include,
+            exclude: build_glob_set(&options.exclude)?,
+            max_depth,
+        })
+    }
+
+    /// Whether a scan of `root` would pick up the image at `path`. Used for
+    /// single files reported by the watcher, where there is no walk to prune.
+    /// Whether the file is an image is left to `inspect_file`.
+    pub fn accepts_file(&self, root: &Path, path: &Path) -> bool {
+        let Ok(relative) = path.strip_prefix(root) else {
+            return false;
+        };
+        let components: Vec<String> = relative
+            .components()
+            .map(|c| c.as_os_str().to_string_lossy().to_string())
+            .collect();
+
+        if components.is_empty() || components.len() > self.max_depth {
+            return false;
+        }
+
+        for depth in 1..=components.len() {
+            if self.is_excluded(&components[..depth].join("/"), &components[depth - 1]) {
+                return false;
+            }
+        }
+
+        self.is_included(&components.join("/"), &components[components.len() - 1])
+    }
+
+    // Patterns are checked against both the root-relative path and the bare
+    // name, so `@eaDir` works as well as `photos/**/@eaDir`.
+    fn is_excluded(&self, relative: &str, name: &str) -> bool {
+        self.exclude.is_match(relative) || self.exclude.is_match(name)
+    }
+
+    fn is_included(&self, relative: &str, name: &str) -> bool {
+        match &self.include {
+            Some(set) => set.is_match(relative) || set.is_match(name),
+            None => true,
+        }
+    }
+}
+
+fn build_glob_set(patterns: &[String]) -> Result<GlobSet, String> {
+    let mut builder = GlobSetBuilder::new();
+    for pattern in patterns {
+        let glob =
+            Glob::new(pattern).map_err(|e| format!("Invalid glob pattern '{}': {}", pattern, e))?;
+        builder.add(glob);
+    }
+    builder
+        .build()
+        .map_err(|e| format!("Failed to build glob patterns: {}", e))
+}
+
+/// Path relative to the scan root, always using `/` so the UI can group on it.
+pub fn relative_path(root: &Path, path: &Path) -> String {
+    let relative = path.strip_prefix(root).unwrap_or(path);
+    relative
+        .components()
+        .map(|c| c.as_os_str().to_string_lossy())
+        .collect::<Vec<_>>()
+        .join("/")
+}
+
+pub fn is_image_path(path: &Path) -> bool {
+    path.extension()
+        .map(|ext| IMAGE_EXTENSIONS.contains(&ext.to_string_lossy().to_lowercase().as_str()))
+        .unwrap_or(false)
+}
+
+/// Extensions of the half of a RAW+JPEG pair that represents it: what
+/// the camera rendered itself, so it shows the shot as intended.
+const PAIR_PRIMARY_EXTENSIONS: &[&str] = &["jpg", "jpeg", "heic", "heif", "hif"];
+
+/// Files that may be half of a RAW+JPEG pair, by folder and lowercase
+/// stem, so pairs are found without listing a folder once per file.
+/// Only files a scan of `root` picks up as images count.
+pub struct Siblings {
+    root: PathBuf,
+    filters: Filters,
+    listings: HashMap<PathBuf, HashMap<String, Vec<PathBuf>>>,
+}
+
+impl Siblings {
+    pub fn new(root: &Path, filters: Filters) -> Self {
+        Siblings {
+            root: root.to_path_buf(),
+            filters,
+            listings: HashMap::new(),
+        }
+    }
+
+    /// The other half of the pair `path` belongs to, if any: RAW files for
+    /// a JPEG or HEIC, and the JPEG or HEIC for a RAW file. A partner the
+    /// scan leaves out or can't read as an image doesn't count, so neither
+    /// half is lost to the other.
+    pub fn partners(&mut self, path: &Path) -> Vec<PathBuf> {
+        let (Some(dir), Some(stem)) = (path.parent(), pair_stem(path)) else {
+            return Vec::new();
+        };
+        let is_raw = formats::has_raw_extension(path);
+        if !is_raw && !is_pair_primary(path) {
+            return Vec::new();
+        }
+
+        let listing = self
+            .listings
+            .entry(dir.to_path_buf())
+            .or_insert_with(|| list_pair_candidates(dir));
+        listing
+            .get(&stem)
+            .into_iter()
+            .flatten()
+            .filter(|other| {
+                if is_raw {
+                    is_pair_primary(other)
+                } else {
+                    formats::has_raw_extension(other)
+                }
+            })
+            .filter(|other| {
+                self.filters.accepts_file(&self.root, other)
+                    && matches!(formats::detect(other), Ok(Some(_)))
+            })
+            .cloned()
+            .collect()
+    }
+}
+
+fn list_pair_candidates(dir: &Path) -> HashMap<String, Vec<PathBuf>> {
+    let mut listing: HashMap<String, Vec<PathBuf>> = HashMap::new();
+    let Ok(entries) = std::fs::read_dir(dir) else {
+        return listing;
+    };
+    for path in entries.filter_map(Result::ok).map(|entry| entry.path()) {
+        if formats::has_raw_extension(&path) || is_pair_primary(&path) {
+            if let Some(stem) = pair_stem(&path) {
+                listing.entry(stem).or_default().push(path);
+            }
+        }
+    }
+    listing
+}
+
+fn pair_stem(path: &Path) -> Option<String> {
+    path.file_stem()
+        .map(|stem| stem.to_string_lossy().to_lowercase())
+}
+
+fn is_pair_primary(path: &Path) -> bool {
+    path.extension()
+        .map(|ext| PAIR_PRIMARY_EXTENSIONS.contains(&ext.to_string_lossy().to_lowercase().as_str()))
+        .unwrap_or(false)
+}
+
+/// What `walk_images` reports for each entry it visits.
+pub enum WalkItem<'a> {
+    Dir(&'a Path),
+    /// Any regular file; `included` says whether it passed the include filters.
+    /// Whether it is an image is only known once its contents are inspected.
+    File {
+        path: &'a Path,
+        included: bool,
+    },
+}
+
+/// Walks `root` according to `options`, calling `visit` for every folder and
+/// file. Returning `false` from `visit` stops the walk early.
+pub fn walk_images(
+    root: &Path,
+    options: &ScanOptions,
+    mut visit: impl FnMut(WalkItem) -> bool,
+) -> Result<(), String> {
+    let filters = Filters::new(options)?;
+
+    let walker = WalkDir::new(root)
+        .follow_links(options.follow_symlinks)
+        .max_depth(filters.max_depth)
+        .into_iter()
+        .filter_entry(|entry| keep_entry(root, entry, &filters, options.follow_symlinks));
+
+    for entry in walker {
+        let entry = match entry {
+            Ok(entry) => entry,
+            Err(e) => {
+                if let Some(ancestor) = e.loop_ancestor() {
+                    log::warn!("Skipping symlink loop back to {}", ancestor.display());
+                } else if e.depth() == 0 {
+                    return Err(format!("Failed to read directory: {}", e));
+                } else {
+                    log::warn!("Skipping unreadable entry: {}", e);
+                }
+                continue;
+            }
+        };
+
+        let keep_going = if entry.file_type().is_dir() {
+            visit(WalkItem::Dir(entry.path()))
+        } else if entry.file_type().is_file() {
+            let relative = relative_path(root, entry.path());
+            let included = filters.is_included(&relative, &entry.file_name().to_string_lossy());
+            visit(WalkItem::File {
+                path: entry.path(),
+                included,
+            })
+        } else {
+            true
+        };
+
+        if !keep_going {
+            break;
+        }
+    }
+
+    Ok(())
+}
+
+/// Walks `root` according to `options` and returns every file that passes
+/// the filters, to be inspected for whether it is an image.
+pub fn collect_candidate_paths(root: &Path, options: &ScanOptions) -> Result<Vec<PathBuf>, String> {
+    let mut paths = Vec::new();
+    walk_images(root, options, |item| {
+        if let WalkItem::File {
+            path,
+            included: true,
+        } = item
+        {
+            paths.push(path.to_path_buf());
+        }
+        true
+    })?;
+    Ok(paths)
+}
+
+fn keep_entry(root: &Path, entry: &DirEntry, filters: &Filters, follow_symlinks: bool) -> bool {
+    if entry.depth() == 0 {
+        return true;
+    }
+
+    if !follow_symlinks && entry.path_is_symlink() {
+        return false;
+    }
+
+    let relative = relative_path(root, entry.path());
+    let name = entry.file_name().to_string_lossy();
+    !filters.is_excluded(&relative, &name)
+}
+
+/// Recognises a file's format from its contents, then stats and hashes it,
+/// reusing the stored hash when size and mtime are unchanged. Dimensions
+/// and embedded metadata are read, and the file checked for problems, for
+/// new and changed files and for files scanned before they were read at all;
+/// those are also decoded to fingerprint them.
+///
+/// A RAW file with a JPEG or HEIC of the same name next to it is left to
+/// that file's record.
+pub fn inspect_file(
+    root: &Path,
+    file_path: &Path,
+    known: &HashMap<String, KnownFile>,
+    siblings: &mut Siblings,
+) -> std::io::Result<Inspection> {
+    let path = file_path.to_string_lossy().to_string();
+    let issue = |kind, message: String| ScanIssue {
+        path: path.clone(),
+        relative_path: relative_path(root, file_path),
+        kind,
+        message,
+    };
+
+    let Some(format) = formats::detect(file_path)? else {
+        if !is_image_path(file_path) {
+            return Ok(Inspection::Other);
+        }
+        let message = if std::fs::metadata(file_path)?.len() == 0 {
+            "The file is empty".to_string()
+        } else {
+            "The contents aren't a recognised image format".to_string()
+        };
+        return Ok(Inspection::Rejected(issue(IssueKind::NotAnImage, message)));
+    };
+
+    let partners = siblings.partners(file_path);
+    let raw_path = if format == Format::Raw {
+        if !partners.is_empty() {
+            return Ok(Inspection::Paired);
+        }
+        None
+    } else {
+        partners
+            .first()
+            .map(|raw| raw.to_string_lossy().to_string())
+    };
+
+    let stat = std::fs::metadata(file_path)?;
+    let stamp = identity::file_stamp(&stat);
+
+    let unchanged = known.get(&path).filter(|k| k.stamp == stamp);
+    let content_hash = match unchanged {
+        Some(known) => known.content_hash.clone(),
+        None => identity::content_hash(file_path)?,
+    };
+
+    let mut issues = Vec::new();
+    let (details, appearance) = match unchanged {
+        Some(known) if known.has_metadata => (None, None),
+        _ => {
+            let extension = file_path
+                .extension()
+                .map(|ext| ext.to_string_lossy().to_lowercase());
+            match &extension {
+                Some(ext) if format.extensions().contains(&ext.as_str()) => {}
+                Some(ext) => issues.push(issue(
+                    IssueKind::ExtensionMismatch,
+                    format!(
+                        "Contents are {} but the extension is .{}",
+                        format.name(),
+                        ext
+                    ),
+                )),
+                None => issues.push(issue(
+                    IssueKind::ExtensionMismatch,
+                    format!("No extension; contents are {}", format.name()),
+                )),
+            }
+            if formats::is_truncated(file_path, format)? {
+                issues.push(issue(
+                    IssueKind::Truncated,
+                    format!("The {} data ends early", format.name()),
+                ));
+            }
+
+            let details = metadata::read(file_path);
+            if details.width.is_none() {
+                issues.push(issue(
+                    IssueKind::Unreadable,
+                    format!("The {} header couldn't be decoded", format.name()),
+                ));
+            }
+
+            let appearance = match fingerprint::compute(file_path) {
+                Ok(appearance) => Some(appearance),
+                Err(e) => {
+                    log::debug!("No fingerprint for {}: {}", file_path.display(), e);
+                    None
+                }
+            };
+            (Some(details), appearance)
+        }
+    };
+
+    Ok(Inspection::Image(Box::new(ScannedFile {
+        name: file_path
+            .file_name()
+            .map(|n| n.to_string_lossy().to_string())
+            .unwrap_or_default(),
+        relative_path: relative_path(root, file_path),
+        path,
+        content_hash,
+        stamp,
+        created: identity::created(&stat),
+        format,
+        details,
+        appearance,
+        issues,
+        raw_path,
+    })))
+}
+
+/// Counters reported while a scan runs.
+#[derive(Serialize, Clone, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct ScanProgress {
+    pub files_seen: u64,
+    pub images_found: u64,
+    pub current_dir: String,
+}
+
+pub enum ScanUpdate {
+    Progress(ScanProgress),
+    /// Records for the latest batch of images, already merged into the catalog.
+    Partial(Vec<ImageInfo>),
+}
+
+/// Images are hashed and merged into the catalog in batches of this size.
+const SCAN_BATCH_SIZE: usize = 100;
+/// Minimum time between two progress updates.
+const PROGRESS_INTERVAL: Duration = Duration::from_millis(200);
+
+/// Scans `root` and merges the result into the catalog, reporting progress
+/// and each merged batch through `report`. The options are stored with the
+/// root so the watcher and later rescans apply the same filters.
+///
+/// When `cancel` is set the walk stops after the current file. Batches merged
+/// so far are kept, but nothing is pruned since the scan didn't see everything.
+/// Returns whether the scan ran to completion.
+pub fn scan_root_with(
+    catalog: &Catalog,
+    root: &Path,
+    options: &ScanOptions,
+    cancel: &AtomicBool,
+    mut report: impl FnMut(ScanUpdate),
+) -> Result<bool, String> {
+    catalog.begin_scan(root, options)?;
+    let known = catalog.known_files()?;
+
+    let mut progress = ScanProgress {
+        current_dir: root.to_string_lossy().to_string(),
+        ..ScanProgress::default()
+    };
+    let mut last_report = Instant::now();
+    let mut seen: HashSet<String> = HashSet::new();
+    let mut rejected_paths: HashSet<String> = HashSet::new();
+    let mut batch: Vec<ScannedFile> = Vec::new();
+    let mut rejected: Vec<ScanIssue> = Vec::new();
+    let mut failure: Option<String> = None;
+    let mut siblings = Siblings::new(root, Filters::new(options)?);
+
+    let flush = |batch: &mut Vec<ScannedFile>,
+                 rejected: &mut Vec<ScanIssue>,
+                 report: &mut dyn FnMut(ScanUpdate)| {
+        catalog.record_rejected(root, rejected)?;
+        rejected.clear();
+        if batch.is_empty() {
+            return Ok(());
+        }
+        let images = catalog.merge_batch(root, batch)?;
+        batch.clear();
+        report(ScanUpdate::Partial(images));
+        Ok::<(), String>(())
+    };
+
+    walk_images(root, options, |item| {
+        if cancel.load(Ordering::Relaxed) {
+            return false;
+        }
+
+        match item {
+            WalkItem::Dir(dir) => progress.current_dir = dir.to_string_lossy().to_string(),
+            WalkItem::File { path, included } => {
+                progress.files_seen += 1;
+                if included {
+                    match inspect_file(root, path, &known, &mut siblings) {
+                        Ok(Inspection::Image(file)) => {
+                            progress.images_found += 1;
+                            seen.insert(file.path.clone());
+                            batch.push(*file);
+                        }
+                        Ok(Inspection::Rejected(issue)) => {
+                            rejected_paths.insert(issue.path.clone());
+                            rejected.push(issue);
+                        }
+                        Ok(Inspection::Paired | Inspection::Other) => {}
+                        Err(e) => log::warn!("Skipping {}: {}", path.display(), e),
+                    }
+                }
+            }
+        }
+
+        if batch.len() >= SCAN_BATCH_SIZE {
+            if let Err(e) = flush(&mut batch, &mut rejected, &mut report) {
+                failure = Some(e);
+                return false;
+            }
+        }
+        if last_report.elapsed() >= PROGRESS_INTERVAL {
+            report(ScanUpdate::Progress(progress.clone()));
+            last_report = Instant::now();
+        }
+        true
+    })?;
+
+    if let Some(e) = failure {
+        return Err(e);
+    }
+    flush(&mut batch, &mut rejected, &mut report)?;
+    report(ScanUpdate::Progress(progress));
+
+    if cancel.load(Ordering::Relaxed) {
+        return Ok(false);
+    }
+
+    catalog.prune_missing(root, &seen)?;
+    catalog.prune_issues(root, &seen, &rejected_paths)?;
+    Ok(true)
+}
+
+/// Blocking scan of `root` that returns every image found, in natural path order.
+pub fn scan_root(
+    catalog: &Catalog,
+    root: &Path,
+    options: &ScanOptions,
+) -> Result<Vec<ImageInfo>, String> {
+    let mut images = Vec::new();
+    scan_root_with(catalog, root, options, &AtomicBool::new(false), |update| {
+        if let ScanUpdate::Partial(batch) = update {
+            images.extend(batch);
+        }
+    })?;
+
+    // Sort by folder, then filename
+    images.sort_by(|a, b| natural::compare(&a.relative_path, &b.relative_path));
+
+    Ok(images)
+}
+
+pub fn check_folder(path: &Path) -> Result<(), String> {
+    if !path.exists() {
+        return Err("Folder does not exist".to_string());
+    }
+
+    if !path.is_dir() {
+        return Err("Path is not a directory".to_string());
+    }
+
+    Ok(())
+}
+
+#[tauri::command]
+pub fn scan_folder(
+    app: tauri::AppHandle,
+    catalog: tauri::State<'_, Catalog>,
+    watchers: tauri::State<'_, Watchers>,
+    folder_path: String,
+    options: Option<ScanOptions>,
+) -> Result<Vec<ImageInfo>, String> {
+    let path = PathBuf::from(&folder_path);
+
+    check_folder(&path)?;
+
+    let images = scan_root(&catalog, &path, &options.unwrap_or_default())?;
+
+    // The folder is part of the library now, so keep it up to date
+    if let Err(e) = watchers.start(&app, path) {
+        log::warn!("{}", e);
+    }
+
+    Ok(images)
+}
+
+/// Problems found in the library's files, optionally only under `root`.
+#[tauri::command]
+pub fn list_scan_issues(
+    catalog: tauri::State<'_, Catalog>,
+    root: Option<String>,
+) -> Result<Vec<ScanIssue>, String> {
+    catalog.scan_issues(root.as_deref())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::temp_dir;
+    use std::fs;
+
+    fn encode(format: image::ImageFormat) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        image::RgbImage::from_fn(256, 256, |x, y| {
+            image::Rgb([x as u8, y as u8, (x ^ y) as u8])
+        })
+        .write_to(&mut std::io::Cursor::new(&mut bytes), format)
+        .unwrap();
+        bytes
+    }
+
+    #[test]
+    fn formats_are_sniffed_and_problems_reported() {
+        let root = temp_dir("scan_sniff");
+
+        let jpeg = encode(image::ImageFormat::Jpeg);
+        let png = encode(image::ImageFormat::Png);
+        fs::write(root.join("good.jpg"), &jpeg).unwrap();
+        fs::write(root.join("photo.JPG.download"), &jpeg).unwrap();
+        fs::write(root.join("scan"), &png).unwrap();
+        fs::write(root.join("misnamed.jpg"), &png).unwrap();
+        fs::write(root.join("cut.jpg"), &jpeg[..jpeg.len() - 100]).unwrap();
+        fs::write(root.join("broken.jpg"), b"<html>404 Not Found</html>").unwrap();
+        fs::write(root.join("empty.png"), b"").unwrap();
+        fs::write(root.join("notes.txt"), b"just text").unwrap();
+
+        let catalog = Catalog::open_in_memory().unwrap();
+        let images = scan_root(&catalog, &root, &ScanOptions::default()).unwrap();
+        let names: Vec<&str> = images.iter().map(|i| i.name.as_str()).collect();
+        assert_eq!(
+            names,
+            [
+                "cut.jpg",
+                "good.jpg",
+                "misnamed.jpg",
+                "photo.JPG.download",
+                "scan"
+            ]
+        );
+        let mime = |name: &str| images.iter().find(|i| i.name == name).unwrap().mime.clone();
+        assert_eq!(mime("misnamed.jpg").as_deref(), Some("image/png"));
+        assert_eq!(mime("photo.JPG.download").as_deref(), Some("image/jpeg"));
+
+        let issues = |catalog: &Catalog| -> Vec<(String, IssueKind)> {
+            catalog
+                .scan_issues(None)
+                .unwrap()
+                .into_iter()
+                .map(|issue| (issue.relative_path, issue.kind))
+                .collect()
+        };
+        let expected = vec![
+            ("broken.jpg".to_string(), IssueKind::NotAnImage),
+            ("cut.jpg".to_string(), IssueKind::Truncated),
+            ("empty.png".to_string(), IssueKind::NotAnImage),
+            ("misnamed.jpg".to_string(), IssueKind::ExtensionMismatch),
+            (
+                "photo.JPG.download".to_string(),
+                IssueKind::ExtensionMismatch,
+            ),
+            ("scan".to_string(), IssueKind::ExtensionMismatch),
+        ];
+        assert_eq!(issues(&catalog), expected);
+
+        // Issues of unchanged files survive a rescan; those of removed files don't
+        fs::remove_file(root.join("broken.jpg")).unwrap();
+        scan_root(&catalog, &root, &ScanOptions::default()).unwrap();
+        assert_eq!(issues(&catalog), expected[1..]);
+
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn walks_respect_depth_globs_and_symlinks() {
+        let base = temp_dir("scan_walk");
+        let root = base.join("root");
+        for dir in ["sub/deep", "@eaDir", "sub/.thumbnails"] {
+            fs::create_dir_all(root.join(dir)).unwrap();
+        }
+        for file in [
+            "a.jpg",
+            "sub/b.png",
+            "sub/notes.txt",
+            "sub/deep/c.jpg",
+            "@eaDir/a.jpg",
+            "sub/.thumbnails/b.png",
+        ] {
+            fs::write(root.join(file), b"").unwrap();
+        }
+
+        let found = |options: &ScanOptions| -> Vec<String> {
+            let mut paths: Vec<String> = collect_candidate_paths(&root, options)
+                .unwrap()
+                .iter()
+                .map(|path| relative_path(&root, path))
+                .collect();
+            paths.sort();
+            paths
+        };
+        let recursive = ScanOptions {
+            recursive: true,
+            exclude: vec!["@eaDir".to_string(), "**/.thumbnails".to_string()],
+            ..ScanOptions::default()
+        };
+
+        assert_eq!(found(&ScanOptions::default()), ["a.jpg"]);
+        assert_eq!(
+            found(&recursive),
+            ["a.jpg", "sub/b.png", "sub/deep/c.jpg", "sub/notes.txt"]
+        );
+        let shallow = ScanOptions {
+            max_depth: Some(1),
+            ..recursive.clone()
+        };
+        assert_eq!(found(&shallow), ["a.jpg", "sub/b.png", "sub/notes.txt"]);
+        let root_only = ScanOptions {
+            max_depth: Some(0),
+            ..recursive.clone()
+        };
+        assert_eq!(found(&root_only), ["a.jpg"]);
+        let unlimited = ScanOptions {
+            max_depth: Some(usize::MAX),
+            ..recursive.clone()
+        };
+        assert_eq!(found(&unlimited), found(&recursive));
+        let images_only = ScanOptions {
+            include: vec!["*.jpg".to_string(), "*.png".to_string()],
+            ..recursive.clone()
+        };
+        assert_eq!(
+            found(&images_only),
+            ["a.jpg", "sub/b.png", "sub/deep/c.jpg"]
+        );
+        // Folders can be excluded by their path from the root too
+        let no_deep = ScanOptions {
+            exclude: vec!["sub/deep".to_string()],
+            include: vec!["*.jpg".to_string()],
+            ..recursive.clone()
+        };
+        assert_eq!(found(&no_deep), ["@eaDir/a.jpg", "a.jpg"]);
+
+        // The watcher's single-file check agrees with the walk
+        let filters = Filters::new(&shallow).unwrap();
+        assert!(filters.accepts_file(&root, &root.join("sub/b.png")));
+        assert!(!filters.accepts_file(&root, &root.join("sub/deep/c.jpg")));
+        assert!(!filters.accepts_file(&root, &root.join("@eaDir/a.jpg")));
+        assert!(!filters.accepts_file(&root, &base.join("a.jpg")));
+
+        #[cfg(unix)]
+        {
+            // A link back up to the root, and one to a folder outside it
+            fs::create_dir_all(base.join("outside")).unwrap();
+            fs::write(base.join("outside/d.jpg"), b"").unwrap();
+            std::os::unix::fs::symlink(&root, root.join("sub/loop")).unwrap();
+            std::os::unix::fs::symlink(base.join("outside"), root.join("linked")).unwrap();
+
+            assert_eq!(
+                found(&recursive),
+                ["a.jpg", "sub/b.png", "sub/deep/c.jpg", "sub/notes.txt"]
+            );
+            let following = ScanOptions {
+                follow_symlinks: true,
+                ..recursive.clone()
+            };
+            assert_eq!(
+                found(&following),
+                [
+                    "a.jpg",
+                    "linked/d.jpg",
+                    "sub/b.png",
+                    "sub/deep/c.jpg",
+                    "sub/notes.txt"
+                ]
+            );
+        }
+
+        fs::remove_dir_all(&base).unwrap();
+    }
+
+    #[test]
+    fn raw_and_jpeg_pairs_share_a_record() {
+        let root = temp_dir("scan_pairs");
+
+        // A plain TIFF stands in for the NEF; RAW is told by the extension
+        let jpeg = encode(image::ImageFormat::Jpeg);
+        let nef = encode(image::ImageFormat::Tiff);
+        fs::write(root.join("DSC_0001.JPG"), &jpeg).unwrap();
+        fs::write(root.join("DSC_0001.NEF"), &nef).unwrap();
+        fs::write(root.join("DSC_0002.nef"), &nef).unwrap();
+
+        let catalog = Catalog::open_in_memory().unwrap();
+        let images = scan_root(&catalog, &root, &ScanOptions::default()).unwrap();
+        let summary: Vec<(&str, Option<String>)> = images
+            .iter()
+            .map(|i| (i.name.as_str(), i.raw_path.clone()))
+            .collect();
+        let raw_path = |name: &str| Some(root.join(name).to_string_lossy().to_string());
+        assert_eq!(
+            summary,
+            [
+                ("DSC_0001.JPG", raw_path("DSC_0001.NEF")),
+                ("DSC_0002.nef", None)
+            ]
+        );
+        assert_eq!(images[1].mime.as_deref(), Some("image/x-raw"));
+
+        // A JPEG turning up later takes over the RAW's record and its tags
+        catalog
+            .set_tags(&images[1].id, &["keeper".to_string()])
+            .unwrap();
+        fs::write(root.join("DSC_0002.jpg"), &jpeg).unwrap();
+        let images = scan_root(&catalog, &root, &ScanOptions::default()).unwrap();
+        let names: Vec<&str> = images.iter().map(|i| i.name.as_str()).collect();
+        assert_eq!(names, ["DSC_0001.JPG", "DSC_0002.jpg"]);
+        assert_eq!(images[1].raw_path, raw_path("DSC_0002.nef"));
+        assert_eq!(images[1].tags, ["keeper"]);
+
+        // A JPEG the scan leaves out, or can't read, doesn't take the RAW with it
+        fs::write(root.join("DSC_0003.NEF"), &nef).unwrap();
+        fs::write(root.join("DSC_0003.JPG"), b"<html>404 Not Found</html>").unwrap();
+        let options = ScanOptions {
+            exclude: vec!["DSC_0001.JPG".to_string()],
+            ..ScanOptions::default()
+        };
+        let images = scan_root(&catalog, &root, &options).unwrap();
+        let summary: Vec<(&str, Option<String>)> = images
+            .iter()
+            .map(|i| (i.name.as_str(), i.raw_path.clone()))
+            .collect();
+        assert_eq!(
+            summary,
+            [
+                ("DSC_0001.NEF", None),
+                ("DSC_0002.jpg", raw_path("DSC_0002.nef")),
+                ("DSC_0003.NEF", None)
+            ]
+        );
+
+        fs::remove_dir_all(&root).unwrap();
+    }
+}
@@ -0,0 +1,316 @@
+use serde::Serialize;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use tauri::{AppHandle, Emitter, Manager};
+
+use crate::catalog::Catalog;
+use crate::scan::{self, ImageInfo, ScanOptions, ScanProgress, ScanUpdate};
+use crate::watcher::Watchers;
+
+/// Background scans in flight, keyed by job id.
+#[derive(Default)]
+pub struct ScanJobs {
+    next_id: AtomicU64,
+    running: Mutex<HashMap<String, RunningScan>>,
+}
+
+struct RunningScan {
+    root: PathBuf,
+    cancel: Arc<AtomicBool>,
+}
+
+#[derive(Serialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct ScanJob {
+    job_id: String,
+    root: String,
+}
+
+#[derive(Serialize, Clone)]
+#[serde(rename_all = "camelCase")]
+struct ProgressEvent {
+    job_id: String,
+    root: String,
+    #[serde(flatten)]
+    progress: ScanProgress,
+}
+
+#[derive(Serialize, Clone)]
+#[serde(rename_all = "camelCase")]
+struct PartialEvent {
+    job_id: String,
+    root: String,
+    images: Vec<ImageInfo>,
+}
+
+#[derive(Serialize, Clone, Copy, PartialEq, Debug)]
+#[serde(rename_all = "lowercase")]
+enum ScanStatus {
+    Completed,
+    Cancelled,
+    Failed,
+}
+
+#[derive(Serialize, Clone)]
+#[serde(rename_all = "camelCase")]
+struct FinishedEvent {
+    job_id: String,
+    root: String,
+    status: ScanStatus,
+    error: Option<String>,
+}
+
+impl ScanJobs {
+    fn lock(&self) -> std::sync::MutexGuard<'_, HashMap<String, RunningScan>> {
+        self.running.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Starts scanning `root` on a background thread. Different roots can be
+    /// scanned at the same time, but a root that is already being scanned is refused.
+    pub fn start(
+        &self,
+        app: &AppHandle,
+        root: PathBuf,
+        options: ScanOptions,
+    ) -> Result<ScanJob, String> {
+        let (job, cancel) = self.register(root.clone())?;
+
+        let app = app.clone();
+        let thread_job = job.clone();
+        let spawned = thread::Builder::new()
+            .name(job.job_id.clone())
+            .spawn(move || run_scan(app, thread_job, root, options, cancel));
+        if let Err(e) = spawned {
+            self.finish(&job.job_id);
+            return Err(format!("Failed to start scan: {}", e));
+        }
+        Ok(job)
+    }
+
+    /// Adds a job for `root` to the list, unless one is already running,
+    /// and returns it with the flag that cancels it.
+    fn register(&self, root: PathBuf) -> Result<(ScanJob, Arc<AtomicBool>), String> {
+        let mut running = self.lock();
+        if running.values().any(|job| job.root == root) {
+            return Err(format!("{} is already being scanned", root.display()));
+        }
+
+        let job_id = format!("scan_{}", self.next_id.fetch_add(1, Ordering::Relaxed) + 1);
+        let cancel = Arc::new(AtomicBool::new(false));
+        let job = ScanJob {
+            job_id: job_id.clone(),
+            root: root.to_string_lossy().to_string(),
+        };
+        running.insert(
+            job_id,
+            RunningScan {
+                root,
+                cancel: cancel.clone(),
+            },
+        );
+        Ok((job, cancel))
+    }
+
+    /// Asks a running scan to stop. Returns false if no such scan is running.
+    pub fn cancel(&self, job_id: &str) -> bool {
+        match self.lock().get(job_id) {
+            Some(job) => {
+                job.cancel.store(true, Ordering::Relaxed);
+                true
+            }
+            None => false,
+        }
+    }
+
+    pub fn list(&self) -> Vec<ScanJob> {
+        self.lock()
+            .iter()
+            .map(|(job_id, job)| ScanJob {
+                job_id: job_id.clone(),
+                root: job.root.to_string_lossy().to_string(),
+            })
+            .collect()
+    }
+
+    fn finish(&self, job_id: &str) {
+        self.lock().remove(job_id);
+    }
+}
+
+fn run_scan(
+    app: AppHandle,
+    job: ScanJob,
+    root: PathBuf,
+    options: ScanOptions,
+    cancel: Arc<AtomicBool>,
+) {
+    let catalog = app.state::<Catalog>();
+
+    let (status, error) = scan(&catalog, &root, &options, &cancel, |update| match update {
+        ScanUpdate::Progress(progress) => {
+            let _ = app.emit(
+                "scan://progress",
+                ProgressEvent {
+                    job_id: job.job_id.clone(),
+                    root: job.root.clone(),
+                    progress,
+                },
+            );
+        }
+        ScanUpdate::Partial(images) => {
+            let _ = app.emit(
+                "scan://partial",
+                PartialEvent {
+                    job_id: job.job_id.clone(),
+                    root: job.root.clone(),
+                    images,
+                },
+            );
+        }
+    });
+
+    if let ScanStatus::Completed = status {
+        // The folder is part of the library now, so keep it up to date
+        if let Err(e) = app.state::<Watchers>().start(&app, root) {
+            log::warn!("{}", e);
+        }
+    }
+
+    app.state::<ScanJobs>().finish(&job.job_id);
+    let _ = app.emit(
+        "scan://finished",
+        FinishedEvent {
+            job_id: job.job_id,
+            root: job.root,
+            status,
+            error,
+        },
+    );
+}
+
+/// Runs the scan of a job and tells how it ended.
+fn scan(
+    catalog: &Catalog,
+    root: &Path,
+    options: &ScanOptions,
+    cancel: &AtomicBool,
+    report: impl FnMut(ScanUpdate),
+) -> (ScanStatus, Option<String>) {
+    match scan::scan_root_with(catalog, root, options, cancel, report) {
+        Ok(true) => (ScanStatus::Completed, None),
+        Ok(false) => (ScanStatus::Cancelled, None),
+        Err(e) => (ScanStatus::Failed, Some(e)),
+    }
+}
+
+/// Starts a background scan. Results arrive as `scan://progress`,
+/// `scan://partial` and finally `scan://finished` events.
+#[tauri::command]
+pub fn start_scan(
+    app: AppHandle,
+    jobs: tauri::State<'_, ScanJobs>,
+    folder_path: String,
+    options: Option<ScanOptions>,
+) -> Result<ScanJob, String> {
+    let path = PathBuf::from(&folder_path);
+    scan::check_folder(&path)?;
+    jobs.start(&app, path, options.unwrap_or_default())
+}
+
+#[tauri::command]
+pub fn cancel_scan(jobs: tauri::State<'_, ScanJobs>, job_id: String) -> bool {
+    jobs.cancel(&job_id)
+}
+
+#[tauri::command]
+pub fn list_scans(jobs: tauri::State<'_, ScanJobs>) -> Vec<ScanJob> {
+    jobs.list()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::temp_dir;
+    use std::fs;
+
+    fn listed(jobs: &ScanJobs) -> Vec<(String, String)> {
+        let mut listed: Vec<(String, String)> = jobs
+            .list()
+            .into_iter()
+            .map(|job| (job.job_id, job.root))
+            .collect();
+        listed.sort();
+        listed
+    }
+
+    #[test]
+    fn jobs_are_listed_until_they_finish() {
+        let jobs = ScanJobs::default();
+        let (first, cancel) = jobs.register(PathBuf::from("/photos")).unwrap();
+        assert_eq!(first.job_id, "scan_1");
+        assert!(jobs.register(PathBuf::from("/photos")).is_err());
+        let (second, _) = jobs.register(PathBuf::from("/scans")).unwrap();
+        assert_eq!(
+            listed(&jobs),
+            [
+                ("scan_1".to_string(), "/photos".to_string()),
+                ("scan_2".to_string(), "/scans".to_string())
+            ]
+        );
+
+        assert!(jobs.cancel(&first.job_id));
+        assert!(cancel.load(Ordering::Relaxed));
+        assert!(!jobs.cancel("scan_9"));
+
+        jobs.finish(&first.job_id);
+        assert!(!jobs.cancel(&first.job_id));
+        assert_eq!(
+            listed(&jobs),
+            [("scan_2".to_string(), "/scans".to_string())]
+        );
+        // A finished root can be scanned again, under a new id
+        let (again, _) = jobs.register(PathBuf::from("/photos")).unwrap();
+        assert_eq!(again.job_id, "scan_3");
+        jobs.finish(&second.job_id);
+        jobs.finish(&again.job_id);
+        assert!(jobs.list().is_empty());
+    }
+
+    #[test]
+    fn scans_complete_cancel_or_fail() {
+        let root = temp_dir("scan_jobs");
+        let mut bytes = Vec::new();
+        image::RgbImage::new(8, 8)
+            .write_to(
+                &mut std::io::Cursor::new(&mut bytes),
+                image::ImageFormat::Png,
+            )
+            .unwrap();
+        fs::write(root.join("a.png"), bytes).unwrap();
+
+        let catalog = Catalog::open_in_memory().unwrap();
+        let options = ScanOptions::default();
+        let run = |root: &Path, cancelled: bool| {
+            let mut found = 0;
+            let cancel = AtomicBool::new(cancelled);
+            let outcome = scan(&catalog, root, &options, &cancel, |update| {
+                if let ScanUpdate::Partial(images) = update {
+                    found += images.len();
+                }
+            });
+            (outcome, found)
+        };
+
+        // Cancelled before the first file: nothing is merged
+        assert_eq!(run(&root, true), ((ScanStatus::Cancelled, None), 0));
+        assert_eq!(run(&root, false), ((ScanStatus::Completed, None), 1));
+        let ((status, error), _) = run(&root.join("missing"), false);
+        assert_eq!(status, ScanStatus::Failed);
+        assert!(error.is_some());
+
+        fs::remove_dir_all(&root).unwrap();
+    }
+}
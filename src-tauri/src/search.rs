@@ -0,0 +1,973 @@
+use rusqlite::ToSql;
+use serde::{Deserialize, Serialize};
+
+use crate::catalog::{attach_tags, db_err, row_to_image, Catalog, IMAGE_COLUMNS};
+use crate::colours;
+use crate::scan::ImageInfo;
+use crate::tags;
+
+pub(crate) const MS_PER_DAY: i64 = 86_400_000;
+/// Years a date in a query may have, which keeps the arithmetic on it in range.
+const YEARS: std::ops::RangeInclusive<i64> = 1..=9999;
+
+// Embedded metadata fields, see `metadata::ImageMetadata`
+const CAPTION: &str = "lower(coalesce(json_extract(i.metadata, '$.caption'), ''))";
+const CAMERA: &str = "lower(coalesce(json_extract(i.metadata, '$.cameraMake'), '') || ' ' || coalesce(json_extract(i.metadata, '$.cameraModel'), ''))";
+const LENS: &str = "lower(coalesce(json_extract(i.metadata, '$.lens'), ''))";
+const ISO: &str = "json_extract(i.metadata, '$.iso')";
+const FOCAL_LENGTH: &str = "json_extract(i.metadata, '$.focalLength')";
+
+/// Why a search failed. Parse errors carry the character range of the
+/// offending part of the query so the UI can highlight it.
+#[derive(Serialize, Debug, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct SearchError {
+    message: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    span: Option<Span>,
+}
+
+/// Character offsets into the query; `end` is exclusive.
+#[derive(Serialize, Clone, Copy, Debug, PartialEq)]
+pub struct Span {
+    start: usize,
+    end: usize,
+}
+
+impl SearchError {
+    fn at(message: impl Into<String>, span: Span) -> Self {
+        SearchError {
+            message: message.into(),
+            span: Some(span),
+        }
+    }
+}
+
+impl From<String> for SearchError {
+    fn from(message: String) -> Self {
+        SearchError {
+            message,
+            span: None,
+        }
+    }
+}
+
+#[derive(Deserialize, Default, Clone, Copy, Debug, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub enum SortField {
+    /// Root folder, then path within it.
+    #[default]
+    Path,
+    Name,
+    Size,
+    Modified,
+    Created,
+    Taken,
+    /// Capture time where known, modification time otherwise.
+    Date,
+    Width,
+    Height,
+    /// Pixel count.
+    Resolution,
+}
+
+#[derive(Deserialize, Default)]
+#[serde(rename_all = "camelCase", default)]
+pub struct SearchOptions {
+    /// Only images scanned from this root folder.
+    pub root: Option<String>,
+    pub sort: SortField,
+    pub descending: bool,
+    pub limit: Option<u32>,
+    pub offset: Option<u32>,
+}
+
+/// Parsed query. Range bounds are half-open: `lo <= value < hi`.
+#[derive(Debug, PartialEq)]
+enum Expr {
+    All,
+    And(Vec<Expr>),
+    Or(Vec<Expr>),
+    Not(Box<Expr>),
+    /// Unqualified words and phrases: name, description or tag.
+    Text(String),
+    Tag(String),
+    Name(String),
+    Desc(String),
+    Ext(String),
+    Folder(String),
+    /// Camera make or model.
+    Camera(String),
+    Lens(String),
+    /// A dominant colour within `distance` of `lab`.
+    Colour {
+        lab: [f64; 3],
+        distance: f64,
+    },
+    Range {
+        column: &'static str,
+        lo: Option<i64>,
+        hi: Option<i64>,
+    },
+}
+
+#[derive(Debug, PartialEq)]
+enum TokenKind {
+    /// `sunset`, `"blue sky"`, `tag:dog`, `width:>3000`.
+    Term {
+        field: Option<String>,
+        value: String,
+    },
+    And,
+    Or,
+    Not,
+    Open,
+    Close,
+}
+
+#[derive(Debug)]
+struct Token {
+    kind: TokenKind,
+    span: Span,
+}
+
+#[derive(Clone, Copy)]
+enum ValueKind {
+    Integer,
+    Bytes,
+    Date,
+}
+
+fn tokenize(query: &str) -> Result<Vec<Token>, SearchError> {
+    let chars: Vec<char> = query.chars().collect();
+    let mut tokens = Vec::new();
+    let mut i = 0;
+
+    while i < chars.len() {
+        let start = i;
+        let single = |kind| Token {
+            kind,
+            span: Span {
+                start,
+                end: start + 1,
+            },
+        };
+
+        match chars[i] {
+            c if c.is_whitespace() => i += 1,
+            '(' => {
+                tokens.push(single(TokenKind::Open));
+                i += 1;
+            }
+            ')' => {
+                tokens.push(single(TokenKind::Close));
+                i += 1;
+            }
+            // `-word` is shorthand for `NOT word`
+            '-' if chars
+                .get(i + 1)
+                .is_some_and(|c| !c.is_whitespace() && *c != ')') =>
+            {
+                tokens.push(single(TokenKind::Not));
+                i += 1;
+            }
+            _ => {
+                let mut field = None;
+                let name_len = chars[i..]
+                    .iter()
+                    .take_while(|c| c.is_ascii_alphabetic())
+                    .count();
+                if name_len > 0 && chars.get(i + name_len) == Some(&':') {
+                    field = Some(chars[i..i + name_len].iter().collect::<String>());
+                    i += name_len + 1;
+                }
+
+                let quoted = chars.get(i) == Some(&'"');
+                let value: String = if quoted {
+                    let Some(len) = chars[i + 1..].iter().position(|c| *c == '"') else {
+                        return Err(SearchError::at(
+                            "Missing closing quote",
+                            Span {
+                                start,
+                                end: chars.len(),
+                            },
+                        ));
+                    };
+                    let value = chars[i + 1..i + 1 + len].iter().collect();
+                    i += len + 2;
+                    value
+                } else {
+                    let len = chars[i..]
+                        .iter()
+                        .take_while(|c| !c.is_whitespace() && !matches!(c, '(' | ')' | '"'))
+                        .count();
+                    let value = chars[i..i + len].iter().collect();
+                    i += len;
+                    value
+                };
+
+                let kind = match (&field, quoted, value.as_str()) {
+                    (None, false, "AND") => TokenKind::And,
+                    (None, false, "OR") => TokenKind::Or,
+                    (None, false, "NOT") => TokenKind::Not,
+                    _ => TokenKind::Term { field, value },
+                };
+                tokens.push(Token {
+                    kind,
+                    span: Span { start, end: i },
+                });
+            }
+        }
+    }
+
+    Ok(tokens)
+}
+
+/// Parses a query such as `tag:beach (sunset OR "golden hour") -desc:blurry width:>3000`.
+///
+/// Words are ANDed unless joined with `OR`; `NOT` or a leading `-` negates;
+/// parentheses group. `AND`, `OR` and `NOT` are only operators in capitals.
+fn parse(query: &str) -> Result<Expr, SearchError> {
+    let tokens = tokenize(query)?;
+    if tokens.is_empty() {
+        return Ok(Expr::All);
+    }
+
+    let mut parser = Parser {
+        tokens,
+        pos: 0,
+        end: query.chars().count(),
+    };
+    let expr = parser.or()?;
+    match parser.tokens.get(parser.pos) {
+        Some(token) => Err(SearchError::at("Unexpected ')'", token.span)),
+        None => Ok(expr),
+    }
+}
+
+struct Parser {
+    tokens: Vec<Token>,
+    pos: usize,
+    /// Length of the query, for errors at the very end.
+    end: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&TokenKind> {
+        self.tokens.get(self.pos).map(|t| &t.kind)
+    }
+
+    fn or(&mut self) -> Result<Expr, SearchError> {
+        let mut items = vec![self.and()?];
+        while self.peek() == Some(&TokenKind::Or) {
+            self.pos += 1;
+            items.push(self.and()?);
+        }
+        Ok(flatten(items, Expr::Or))
+    }
+
+    fn and(&mut self) -> Result<Expr, SearchError> {
+        let mut items = vec![self.unary()?];
+        loop {
+            match self.peek() {
+                Some(TokenKind::And) => {
+                    self.pos += 1;
+                    items.push(self.unary()?);
+                }
+                // Juxtaposition means AND
+                Some(TokenKind::Term { .. } | TokenKind::Not | TokenKind::Open) => {
+                    items.push(self.unary()?)
+                }
+                _ => break,
+            }
+        }
+        Ok(flatten(items, Expr::And))
+    }
+
+    fn unary(&mut self) -> Result<Expr, SearchError> {
+        if self.peek() == Some(&TokenKind::Not) {
+            self.pos += 1;
+            return Ok(Expr::Not(Box::new(self.unary()?)));
+        }
+        self.primary()
+    }
+
+    fn primary(&mut self) -> Result<Expr, SearchError> {
+        let Some(token) = self.tokens.get(self.pos) else {
+            let end = Span {
+                start: self.end,
+                end: self.end,
+            };
+            return Err(SearchError::at("Expected a search term", end));
+        };
+        let span = token.span;
+        self.pos += 1;
+
+        match &token.kind {
+            TokenKind::Open => {
+                let expr = self.or()?;
+                if self.peek() != Some(&TokenKind::Close) {
+                    return Err(SearchError::at("Missing ')'", span));
+                }
+                self.pos += 1;
+                Ok(expr)
+            }
+            TokenKind::Close => Err(SearchError::at("Unexpected ')'", span)),
+            TokenKind::And | TokenKind::Or => Err(SearchError::at(
+                "Expected a search term before this operator",
+                span,
+            )),
+            TokenKind::Not => unreachable!("handled by unary"),
+            TokenKind::Term { field, value, .. } => term(field.as_deref(), value, span),
+        }
+    }
+}
+
+fn flatten(mut items: Vec<Expr>, combine: fn(Vec<Expr>) -> Expr) -> Expr {
+    if items.len() == 1 {
+        items.remove(0)
+    } else {
+        combine(items)
+    }
+}
+
+fn term(field: Option<&str>, value: &str, span: Span) -> Result<Expr, SearchError> {
+    let Some(field) = field else {
+        return Ok(Expr::Text(value.to_string()));
+    };
+
+    let field_len = field.chars().count();
+    let value_span = Span {
+        start: span.start + field_len + 1,
+        end: span.end,
+    };
+    if value.trim().is_empty() {
+        return Err(SearchError::at(format!("'{}:' needs a value", field), span));
+    }
+
+    let value = value.trim().to_string();
+    let range = |column, kind| {
+        parse_range(&value, kind)
+            .map(|(lo, hi)| Expr::Range { column, lo, hi })
+            .map_err(|message| SearchError::at(message, value_span))
+    };
+
+    match field.to_lowercase().as_str() {
+        "tag" | "tags" => Ok(Expr::Tag(value)),
+        "name" => Ok(Expr::Name(value)),
+        "desc" | "description" => Ok(Expr::Desc(value)),
+        "ext" => Ok(Expr::Ext(value.trim_start_matches('.').to_string())),
+        "folder" | "in" => Ok(Expr::Folder(value.trim_matches(['/', '\\']).to_string())),
+        "camera" => Ok(Expr::Camera(value)),
+        "lens" => Ok(Expr::Lens(value)),
+        "color" | "colour" => {
+            parse_colour(&value).map_err(|message| SearchError::at(message, value_span))
+        }
+        "iso" => range(ISO, ValueKind::Integer),
+        "focal" => range(FOCAL_LENGTH, ValueKind::Integer),
+        "width" => range("i.width", ValueKind::Integer),
+        "height" => range("i.height", ValueKind::Integer),
+        "size" => range("i.size", ValueKind::Bytes),
+        "taken" => range("i.taken", ValueKind::Date),
+        "modified" => range("i.modified", ValueKind::Date),
+        "created" => range("i.created", ValueKind::Date),
+        _ => Err(SearchError::at(
+            format!(
+                "Unknown field '{}'. Try tag, name, desc, ext, folder, width, height, size, taken, modified, created, camera, lens, iso, focal or colour",
+                field
+            ),
+            Span {
+                start: span.start,
+                end: span.start + field_len,
+            },
+        )),
+    }
+}
+
+/// `orange`, `#ff8800` or `f80`, optionally with a distance: `orange~15`.
+fn parse_colour(value: &str) -> Result<Expr, String> {
+    let (colour, distance) = match value.split_once('~') {
+        Some((colour, distance)) => {
+            let distance = distance
+                .trim()
+                .parse::<f64>()
+                .ok()
+                .filter(|d| d.is_finite() && *d >= 0.0)
+                .ok_or_else(|| format!("'{}' is not a colour distance", distance))?;
+            (colour, distance)
+        }
+        None => (value, colours::DEFAULT_DISTANCE),
+    };
+    Ok(Expr::Colour {
+        lab: colours::to_lab(colours::parse(colour)?),
+        distance,
+    })
+}
+
+/// `>3000`, `<=2MB`, `2023-01..2023-06`, `2023..`, or a single value.
+fn parse_range(value: &str, kind: ValueKind) -> Result<(Option<i64>, Option<i64>), String> {
+    if let Some((from, to)) = value.split_once("..") {
+        let lo = match from.trim() {
+            "" => None,
+            from => Some(interval(from, kind)?.0),
+        };
+        let hi = match to.trim() {
+            "" => None,
+            to => Some(interval(to, kind)?.1),
+        };
+        return match (lo, hi) {
+            (None, None) => Err("A range needs at least one end".to_string()),
+            (Some(lo), Some(hi)) if lo >= hi => {
+                Err("The start of the range is after its end".to_string())
+            }
+            bounds => Ok(bounds),
+        };
+    }
+
+    let (op, rest) = [">=", "<=", ">", "<", "="]
+        .iter()
+        .find_map(|op| value.strip_prefix(op).map(|rest| (*op, rest)))
+        .unwrap_or(("=", value));
+    let (start, end) = interval(rest.trim(), kind)?;
+
+    Ok(match op {
+        ">" => (Some(end), None),
+        ">=" => (Some(start), None),
+        "<" => (None, Some(start)),
+        "<=" => (None, Some(end)),
+        _ => (Some(start), Some(end)),
+    })
+}
+
+/// The half-open interval a single value stands for. A date covers its
+/// whole year, month or day.
+fn interval(value: &str, kind: ValueKind) -> Result<(i64, i64), String> {
+    match kind {
+        ValueKind::Integer => {
+            let n = value
+                .parse::<i64>()
+                .map_err(|_| format!("'{}' is not a whole number", value))?;
+            next(n, value)
+        }
+        ValueKind::Bytes => next(parse_bytes(value)?, value),
+        ValueKind::Date => parse_date(value),
+    }
+}
+
+/// `n` as the interval holding only it.
+fn next(n: i64, value: &str) -> Result<(i64, i64), String> {
+    n.checked_add(1)
+        .map(|end| (n, end))
+        .ok_or_else(|| format!("'{}' is too large", value))
+}
+
+fn parse_bytes(value: &str) -> Result<i64, String> {
+    let lower = value.to_lowercase();
+    let split = lower
+        .find(|c: char| !c.is_ascii_digit() && c != '.')
+        .unwrap_or(lower.len());
+    let (number, unit) = lower.split_at(split);
+
+    let multiplier: f64 = match unit.trim() {
+        "" | "b" => 1.0,
+        "k" | "kb" => 1024.0,
+        "m" | "mb" => 1024.0 * 1024.0,
+        "g" | "gb" => 1024.0 * 1024.0 * 1024.0,
+        _ => return Err(format!("Unknown size unit '{}'. Use B, KB, MB or GB", unit)),
+    };
+    let number: f64 = number
+        .parse()
+        .map_err(|_| format!("'{}' is not a size", value))?;
+    let bytes = (number * multiplier).round();
+    // i64::MAX isn't exact as a float; anything from there up won't fit
+    if bytes >= i64::MAX as f64 {
+        return Err(format!("'{}' is too large", value));
+    }
+    Ok(bytes as i64)
+}
+
+/// `YYYY`, `YYYY-MM` or `YYYY-MM-DD` as milliseconds since the epoch (UTC).
+fn parse_date(value: &str) -> Result<(i64, i64), String> {
+    let invalid = || format!("'{}' is not a date. Use YYYY, YYYY-MM or YYYY-MM-DD", value);
+    let parts: Vec<i64> = value
+        .split('-')
+        .map(|p| p.parse::<i64>().map_err(|_| invalid()))
+        .collect::<Result<_, _>>()?;
+
+    if !YEARS.contains(&parts[0]) {
+        return Err(format!(
+            "'{}' is out of range. Years go from {} to {}",
+            value,
+            YEARS.start(),
+            YEARS.end()
+        ));
+    }
+
+    let days = match parts[..] {
+        [year] => (days_from_civil(year, 1, 1), days_from_civil(year + 1, 1, 1)),
+        [year, month] if (1..=12).contains(&month) => month_bounds(year, month),
+        [year, month, day] if (1..=12).contains(&month) && (1..=31).contains(&day) => {
+            let (month_start, month_end) = month_bounds(year, month);
+            let start = month_start + day - 1;
+            // Rejects the 31st of a 30-day month and so on
+            if start >= month_end {
+                return Err(invalid());
+            }
+            (start, start + 1)
+        }
+        _ => return Err(invalid()),
+    };
+    Ok((days.0 * MS_PER_DAY, days.1 * MS_PER_DAY))
+}
+
+/// First day of the month and first day of the next, in days since the epoch.
+fn month_bounds(year: i64, month: i64) -> (i64, i64) {
+    let next = if month == 12 {
+        days_from_civil(year + 1, 1, 1)
+    } else {
+        days_from_civil(year, month + 1, 1)
+    };
+    (days_from_civil(year, month, 1), next)
+}
+
+/// Days since 1970-01-01 for a proleptic Gregorian date.
+pub(crate) fn days_from_civil(year: i64, month: i64, day: i64) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let year_of_era = year - era * 400;
+    let day_of_year = (153 * (month + if month > 2 { -3 } else { 9 }) + 2) / 5 + day - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    era * 146_097 + day_of_era - 719_468
+}
+
+/// Escapes `%`, `_` and `\` for `LIKE ... ESCAPE '\'`.
+fn like_escape(text: &str) -> String {
+    text.replace('\\', "\\\\")
+        .replace('%', "\\%")
+        .replace('_', "\\_")
+}
+
+/// A LIKE pattern: `*` is a wildcard; without one the text may appear anywhere.
+fn like_pattern(text: &str) -> String {
+    let escaped = like_escape(&text.to_lowercase());
+    if text.contains('*') {
+        escaped.replace('*', "%")
+    } else {
+        format!("%{}%", escaped)
+    }
+}
+
+fn compile(expr: &Expr, args: &mut Vec<Box<dyn ToSql>>) -> String {
+    let mut arg = |value: String| {
+        args.push(Box::new(value));
+        format!("?{}", args.len())
+    };
+
+    match expr {
+        Expr::All => "1 = 1".to_string(),
+        Expr::And(items) | Expr::Or(items) => {
+            let joiner = if matches!(expr, Expr::And(_)) {
+                " AND "
+            } else {
+                " OR "
+            };
+            let parts: Vec<String> = items.iter().map(|item| compile(item, args)).collect();
+            format!("({})", parts.join(joiner))
+        }
+        Expr::Not(inner) => format!("NOT {}", compile(inner, args)),
+        Expr::Text(text) => {
+            let p = arg(like_pattern(text));
+            format!(
+                "(lower(i.name) LIKE {p} ESCAPE '\\' OR lower(i.description) LIKE {p} ESCAPE '\\'
+                  OR {CAPTION} LIKE {p} ESCAPE '\\'
+                  OR EXISTS (SELECT 1 FROM image_tags it JOIN tags t ON t.id = it.tag_id
+                             WHERE it.image_id = i.id AND t.name LIKE {p} ESCAPE '\\'))"
+            )
+        }
+        Expr::Tag(tag) if tag.contains('*') => {
+            let p = arg(like_pattern(tag));
+            format!(
+                "EXISTS (SELECT 1 FROM image_tags it JOIN tags t ON t.id = it.tag_id
+                         WHERE it.image_id = i.id AND t.name LIKE {p} ESCAPE '\\')"
+            )
+        }
+        Expr::Tag(tag) => {
+            // Match the tag as typed and in normalized form, so `tag:dogs` finds "dog"
+            let typed = tag.trim().to_lowercase();
+            let normalized = tags::normalize(tag).unwrap_or_else(|| typed.clone());
+            let (a, b) = (arg(typed), arg(normalized));
+            format!(
+                "EXISTS (SELECT 1 FROM image_tags it JOIN tags t ON t.id = it.tag_id
+                         WHERE it.image_id = i.id AND t.name IN ({a}, {b}))"
+            )
+        }
+        Expr::Name(name) => {
+            let p = arg(like_pattern(name));
+            format!("lower(i.name) LIKE {p} ESCAPE '\\'")
+        }
+        Expr::Desc(desc) => {
+            let p = arg(like_pattern(desc));
+            format!("(lower(i.description) LIKE {p} ESCAPE '\\' OR {CAPTION} LIKE {p} ESCAPE '\\')")
+        }
+        Expr::Camera(camera) => {
+            let p = arg(like_pattern(camera));
+            format!("{CAMERA} LIKE {p} ESCAPE '\\'")
+        }
+        Expr::Lens(lens) => {
+            let p = arg(like_pattern(lens));
+            format!("{LENS} LIKE {p} ESCAPE '\\'")
+        }
+        Expr::Ext(ext) => {
+            let p = arg(format!("%.{}", like_escape(&ext.to_lowercase())));
+            format!("lower(i.name) LIKE {p} ESCAPE '\\'")
+        }
+        Expr::Folder(folder) => {
+            // Any folder inside the library, at any depth; the folders the
+            // root itself sits in don't count
+            let folder = folder.replace('\\', "/").to_lowercase();
+            let p = arg(format!("%/{}/%", like_escape(&folder)));
+            format!("('/' || lower(i.relative_path)) LIKE {p} ESCAPE '\\'")
+        }
+        Expr::Colour { lab, distance } => {
+            // CIE76: straight-line distance in Lab
+            let mut terms = Vec::new();
+            for (channel, value) in lab.iter().enumerate() {
+                args.push(Box::new(*value));
+                let diff = format!(
+                    "(json_extract(c.value, '$.lab[{}]') - ?{})",
+                    channel,
+                    args.len()
+                );
+                terms.push(format!("{diff} * {diff}"));
+            }
+            args.push(Box::new(colours::MIN_SHARE));
+            let share = args.len();
+            args.push(Box::new(distance * distance));
+            let limit = args.len();
+            // Unknown (NULL) for images without a palette yet, so neither
+            // this nor its NOT matches them
+            format!(
+                "(CASE WHEN i.palette IS NOT NULL THEN EXISTS (
+                     SELECT 1 FROM json_each(i.palette) c
+                     WHERE json_extract(c.value, '$.share') >= ?{share}
+                       AND {} <= ?{limit}) END)",
+                terms.join(" + ")
+            )
+        }
+        Expr::Range { column, lo, hi } => {
+            // Never NULL, so NOT behaves for images without the value
+            let mut parts = vec![format!("{} IS NOT NULL", column)];
+            if let Some(lo) = lo {
+                args.push(Box::new(*lo));
+                parts.push(format!("{} >= ?{}", column, args.len()));
+            }
+            if let Some(hi) = hi {
+                args.push(Box::new(*hi));
+                parts.push(format!("{} < ?{}", column, args.len()));
+            }
+            format!("({})", parts.join(" AND "))
+        }
+    }
+}
+
+fn order_by(sort: SortField, descending: bool) -> String {
+    let dir = if descending { "DESC" } else { "ASC" };
+    let by_value = |column: &str| {
+        // Images without the value go last either way
+        format!(
+            "{c} IS NULL, {c} {dir}, r.path, i.relative_path COLLATE natural_order",
+            c = column
+        )
+    };
+
+    match sort {
+        SortField::Path => format!("r.path {dir}, i.relative_path COLLATE natural_order {dir}"),
+        SortField::Name => format!(
+            "i.name COLLATE natural_order {dir}, r.path, i.relative_path COLLATE natural_order"
+        ),
+        SortField::Size => by_value("i.size"),
+        SortField::Modified => by_value("i.modified"),
+        SortField::Created => by_value("i.created"),
+        SortField::Taken => by_value("i.taken"),
+        SortField::Date => by_value("coalesce(i.taken, i.modified)"),
+        SortField::Width => by_value("i.width"),
+        SortField::Height => by_value("i.height"),
+        SortField::Resolution => by_value("(i.width * i.height)"),
+    }
+}
+
+pub fn search(
+    catalog: &Catalog,
+    query: &str,
+    options: &SearchOptions,
+) -> Result<Vec<ImageInfo>, SearchError> {
+    let expr = parse(query)?;
+
+    let mut args: Vec<Box<dyn ToSql>> = Vec::new();
+    let mut sql = format!(
+        "SELECT {} FROM images i LEFT JOIN roots r ON r.id = i.root_id WHERE {}",
+        IMAGE_COLUMNS,
+        compile(&expr, &mut args)
+    );
+    if let Some(root) = &options.root {
+        args.push(Box::new(root.clone()));
+        sql.push_str(&format!(" AND r.path = ?{}", args.len()));
+    }
+    sql.push_str(&format!(
+        " ORDER BY {} LIMIT {} OFFSET {}",
+        order_by(options.sort, options.descending),
+        options.limit.map(i64::from).unwrap_or(-1),
+        options.offset.unwrap_or(0)
+    ));
+
+    let conn = catalog.conn();
+    let mut stmt = conn.prepare(&sql).map_err(db_err)?;
+    let rows = stmt
+        .query_map(rusqlite::params_from_iter(args.iter()), row_to_image)
+        .map_err(db_err)?;
+    let mut images: Vec<ImageInfo> = rows.collect::<Result<_, _>>().map_err(db_err)?;
+    drop(stmt);
+
+    attach_tags(&conn, &mut images)?;
+    Ok(images)
+}
+
+#[tauri::command]
+pub fn search_images(
+    catalog: tauri::State<'_, Catalog>,
+    query: String,
+    options: Option<SearchOptions>,
+) -> Result<Vec<ImageInfo>, SearchError> {
+    search(&catalog, &query, &options.unwrap_or_default())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::colours::PaletteColour;
+    use crate::test_support::{add_image, ImageRow};
+
+    fn text(s: &str) -> Expr {
+        Expr::Text(s.to_string())
+    }
+
+    fn span_of(query: &str) -> (usize, usize) {
+        let span = parse(query).unwrap_err().span.unwrap();
+        (span.start, span.end)
+    }
+
+    fn ms(year: i64, month: i64, day: i64) -> i64 {
+        days_from_civil(year, month, day) * MS_PER_DAY
+    }
+
+    #[test]
+    fn and_binds_tighter_than_or() {
+        assert_eq!(
+            parse("a b OR c").unwrap(),
+            Expr::Or(vec![Expr::And(vec![text("a"), text("b")]), text("c")])
+        );
+        assert_eq!(
+            parse("a AND (b OR c)").unwrap(),
+            Expr::And(vec![text("a"), Expr::Or(vec![text("b"), text("c")])])
+        );
+        assert_eq!(parse("").unwrap(), Expr::All);
+    }
+
+    #[test]
+    fn negation_phrases_and_fields() {
+        assert_eq!(
+            parse(r#"NOT tag:"blue sky" -desc:blurry "golden hour""#).unwrap(),
+            Expr::And(vec![
+                Expr::Not(Box::new(Expr::Tag("blue sky".to_string()))),
+                Expr::Not(Box::new(Expr::Desc("blurry".to_string()))),
+                text("golden hour"),
+            ])
+        );
+        // Lowercase operators and quoted operators are plain words
+        assert_eq!(
+            parse(r#"cats and "OR""#).unwrap(),
+            Expr::And(vec![text("cats"), text("and"), text("OR")])
+        );
+        assert_eq!(parse("ext:.JPG").unwrap(), Expr::Ext("JPG".to_string()));
+    }
+
+    #[test]
+    fn ranges() {
+        let range = |query: &str| match parse(query).unwrap() {
+            Expr::Range { lo, hi, .. } => (lo, hi),
+            other => panic!("not a range: {:?}", other),
+        };
+
+        assert_eq!(range("width:>3000"), (Some(3001), None));
+        assert_eq!(range("height:<=1080"), (None, Some(1081)));
+        assert_eq!(range("size:<2MB"), (None, Some(2 * 1024 * 1024)));
+        assert_eq!(range("size:1.5kb.."), (Some(1536), None));
+        assert_eq!(
+            range("taken:2023-01..2023-06"),
+            (Some(ms(2023, 1, 1)), Some(ms(2023, 7, 1)))
+        );
+        assert_eq!(
+            range("modified:2024-02-29"),
+            (Some(ms(2024, 2, 29)), Some(ms(2024, 3, 1)))
+        );
+        assert_eq!(range("taken:>2023"), (Some(ms(2024, 1, 1)), None));
+    }
+
+    #[test]
+    fn errors_point_at_the_problem() {
+        assert_eq!(span_of("dog AND (cat"), (8, 9));
+        assert_eq!(span_of("dog )"), (4, 5));
+        assert_eq!(span_of("hue:red"), (0, 3));
+        assert_eq!(span_of("colour:rust"), (7, 11));
+        assert_eq!(span_of("color:red~far"), (6, 13));
+        assert_eq!(span_of("beach width:>abc"), (12, 16));
+        assert_eq!(span_of("taken:2023-02-30"), (6, 16));
+        assert_eq!(span_of(r#"tag:"blue sky"#), (0, 13));
+        assert_eq!(span_of("dog OR"), (6, 6));
+        assert_eq!(span_of("OR dog"), (0, 2));
+        assert_eq!(span_of("size:10..2"), (5, 10));
+
+        // Values too large to compute with are errors, not overflows
+        assert_eq!(span_of("width:9223372036854775807"), (6, 25));
+        assert_eq!(span_of("size:99999999999GB"), (5, 18));
+        assert_eq!(span_of("size:<9223372036854775807"), (5, 25));
+        assert_eq!(span_of("taken:1000000000"), (6, 16));
+        assert_eq!(span_of("taken:2020-01-9223372036854775807"), (6, 33));
+        assert_eq!(span_of("taken:-5..2020"), (6, 14));
+        assert!(parse("taken:9999-12-31").is_ok());
+    }
+
+    #[test]
+    fn searches_the_catalog() {
+        let catalog = Catalog::open_in_memory().unwrap();
+        for (id, relative_path, description, width, size) in [
+            ("a", "trips/Beach.JPG", "Waves at sunset", 4000, 3_000_000),
+            ("b", "trips/city_night.png", "Skyline", 1920, 500_000),
+            ("c", "pets/100%_dog.jpg", "", 800, 90_000),
+        ] {
+            add_image(
+                &catalog,
+                ImageRow {
+                    id,
+                    relative_path,
+                    description,
+                    width: Some(width),
+                    size: Some(size),
+                    ..ImageRow::default()
+                },
+            );
+        }
+        catalog.set_tags("a", &["beach".to_string()]).unwrap();
+        catalog.set_tags("c", &["dog".to_string()]).unwrap();
+        catalog
+            .conn()
+            .execute(
+                r#"UPDATE images SET metadata = '{"cameraMake": "FUJIFILM", "cameraModel": "X-T4",
+                   "iso": 3200, "focalLength": 23.0, "caption": "Harbour lights"}' WHERE id = 'b'"#,
+                [],
+            )
+            .unwrap();
+
+        let palette = |colours: &[([u8; 3], f32)]| {
+            let colours: Vec<PaletteColour> = colours
+                .iter()
+                .map(|&(rgb, share)| PaletteColour {
+                    hex: colours::hex(rgb),
+                    lab: colours::to_lab(rgb).map(|v| v as f32),
+                    share,
+                })
+                .collect();
+            serde_json::to_string(&colours).unwrap()
+        };
+        for (id, colours) in [
+            (
+                "a",
+                palette(&[([250, 140, 40], 0.6), ([40, 110, 200], 0.4)]),
+            ),
+            ("b", palette(&[([20, 30, 90], 0.97), ([190, 95, 15], 0.03)])),
+        ] {
+            catalog
+                .conn()
+                .execute(
+                    "UPDATE images SET palette = ?2 WHERE id = ?1",
+                    [id, &colours],
+                )
+                .unwrap();
+        }
+
+        let ids = |query: &str| -> Vec<String> {
+            search(&catalog, query, &SearchOptions::default())
+                .unwrap()
+                .into_iter()
+                .map(|image| image.id)
+                .collect()
+        };
+
+        assert_eq!(ids("sunset"), ["a"]);
+        assert_eq!(ids("tag:dogs"), ["c"]);
+        assert_eq!(ids("folder:trips -ext:png"), ["a"]);
+        // The library's own location isn't one of its folders
+        assert_eq!(ids("folder:lib"), Vec::<String>::new());
+        assert_eq!(ids("folder:beach.jpg"), Vec::<String>::new());
+        assert_eq!(ids("width:>=1920 size:<1MB"), ["b"]);
+        assert_eq!(ids("name:100%"), ["c"]);
+        assert_eq!(ids("name:city* OR tag:beach"), ["a", "b"]);
+        assert_eq!(ids("camera:fuji* iso:>=1600 focal:<35"), ["b"]);
+        assert_eq!(ids("harbour"), ["b"]);
+        assert_eq!(ids("-camera:x-t4"), ["c", "a"]);
+        assert_eq!(ids("colour:orange"), ["a"]);
+        assert_eq!(ids("color:#1e2a5c"), ["b"]);
+        // The dark orange is too small a part of the skyline to count
+        assert_eq!(ids("colour:#c06010~10"), Vec::<String>::new());
+        assert_eq!(ids("colour:#c06010~40"), ["a"]);
+        // c has no palette yet, so it isn't known not to be navy
+        assert_eq!(ids("-colour:navy"), ["a"]);
+
+        let by_width = SearchOptions {
+            sort: SortField::Width,
+            descending: true,
+            ..SearchOptions::default()
+        };
+        let sorted: Vec<String> = search(&catalog, "", &by_width)
+            .unwrap()
+            .into_iter()
+            .map(|image| image.id)
+            .collect();
+        assert_eq!(sorted, ["a", "b", "c"]);
+    }
+
+    #[test]
+    fn sorts_naturally_and_by_resolution() {
+        let catalog = Catalog::open_in_memory().unwrap();
+        for (id, name, width, height) in [
+            ("a", "img10.jpg", 100, 100),
+            ("b", "IMG2.jpg", 400, 300),
+            ("c", "img1.jpg", 200, 200),
+        ] {
+            add_image(
+                &catalog,
+                ImageRow {
+                    id,
+                    relative_path: name,
+                    width: Some(width),
+                    height: Some(height),
+                    ..ImageRow::default()
+                },
+            );
+        }
+
+        let sorted = |sort: SortField| -> Vec<String> {
+            let options = SearchOptions {
+                sort,
+                ..SearchOptions::default()
+            };
+            search(&catalog, "", &options)
+                .unwrap()
+                .into_iter()
+                .map(|image| image.id)
+                .collect()
+        };
+        assert_eq!(sorted(SortField::Name), ["c", "b", "a"]);
+        assert_eq!(sorted(SortField::Resolution), ["a", "c", "b"]);
+    }
+}
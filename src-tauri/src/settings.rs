@@ -0,0 +1,302 @@
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::RwLock;
+
+use crate::tags::TagRules;
+
+const DEFAULT_TAG_PROMPT: &str = "List 5-10 descriptive tags for this image, each with your confidence from 0 to 1, and a one-sentence description. Answer in JSON. Good tags are short, like: nature, sunset, mountain, peaceful, orange sky";
+const DEFAULT_DESCRIPTION_PROMPT: &str = "Describe this image in one short paragraph of two or three sentences. Mention the main subject, the setting and anything notable. Output only the description, nothing else.";
+
+/// User-editable settings, stored as JSON in the app config dir.
+/// Missing fields fall back to their defaults so older files keep loading.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(rename_all = "camelCase", default)]
+pub struct Settings {
+    /// Base URL of the Ollama server, e.g. `http://localhost:11434`.
+    pub backend_url: String,
+    /// Vision model used for tagging, e.g. `moondream`, `llava` or `bakllava`.
+    pub model: String,
+    /// Text embedding model used for semantic search, e.g. `nomic-embed-text`.
+    pub embedding_model: String,
+    pub connect_timeout_secs: u64,
+    /// How long to wait for the server to answer, including the model's generation time.
+    pub request_timeout_secs: u64,
+    /// Retries for transient failures (server unreachable, busy or still loading the model).
+    pub max_retries: u32,
+    pub tag_prompt: String,
+    pub description_prompt: String,
+    /// Normalization, synonyms, blocklist and vocabulary for model tags.
+    pub tag_rules: TagRules,
+    /// Images are shrunk to fit this many pixels on their longest side before being sent.
+    pub max_image_edge: u32,
+    /// How many images the batch tagging queue sends to the backend at once.
+    pub tagging_concurrency: usize,
+    /// Least recently used thumbnails are evicted past this many megabytes.
+    pub thumbnail_cache_mb: u64,
+    /// Images whose perceptual hashes differ in at most this many bits are
+    /// reported as duplicates.
+    pub duplicate_distance: u32,
+    /// Operations can be undone for this many days; older ones are
+    /// forgotten when the app starts.
+    pub history_days: u32,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Settings {
+            backend_url: "http://localhost:11434".to_string(),
+            model: "moondream".to_string(),
+            embedding_model: "nomic-embed-text".to_string(),
+            connect_timeout_secs: 5,
+            request_timeout_secs: 120,
+            max_retries: 3,
+            tag_prompt: DEFAULT_TAG_PROMPT.to_string(),
+            description_prompt: DEFAULT_DESCRIPTION_PROMPT.to_string(),
+            tag_rules: TagRules::default(),
+            max_image_edge: 768,
+            tagging_concurrency: 2,
+            thumbnail_cache_mb: 512,
+            duplicate_distance: 6,
+            history_days: 30,
+        }
+    }
+}
+
+impl Settings {
+    pub fn validate(&self) -> Result<(), String> {
+        let url = reqwest::Url::parse(&self.backend_url)
+            .map_err(|e| format!("Invalid backend URL '{}': {}", self.backend_url, e))?;
+        if url.scheme() != "http" && url.scheme() != "https" {
+            return Err("Backend URL must start with http:// or https://".to_string());
+        }
+        if url.host_str().is_none() {
+            return Err("Backend URL must include a host".to_string());
+        }
+
+        if self.model.trim().is_empty() {
+            return Err("Model name can't be empty".to_string());
+        }
+        if self.model.chars().any(char::is_whitespace) {
+            return Err("Model name can't contain spaces".to_string());
+        }
+        if self.embedding_model.trim().is_empty() {
+            return Err("Embedding model name can't be empty".to_string());
+        }
+        if self.embedding_model.chars().any(char::is_whitespace) {
+            return Err("Embedding model name can't contain spaces".to_string());
+        }
+
+        if !(1..=60).contains(&self.connect_timeout_secs) {
+            return Err("Connect timeout must be between 1 and 60 seconds".to_string());
+        }
+        if !(1..=1800).contains(&self.request_timeout_secs) {
+            return Err("Request timeout must be between 1 and 1800 seconds".to_string());
+        }
+
+        if self.max_retries > 10 {
+            return Err("Retries must be between 0 and 10".to_string());
+        }
+
+        if self.tag_prompt.trim().is_empty() {
+            return Err("Tag prompt can't be empty".to_string());
+        }
+        if self.description_prompt.trim().is_empty() {
+            return Err("Description prompt can't be empty".to_string());
+        }
+        self.tag_rules.validate()?;
+
+        if !(128..=4096).contains(&self.max_image_edge) {
+            return Err("Max image size must be between 128 and 4096 pixels".to_string());
+        }
+
+        if !(1..=8).contains(&self.tagging_concurrency) {
+            return Err("Tagging concurrency must be between 1 and 8".to_string());
+        }
+
+        if !(16..=65536).contains(&self.thumbnail_cache_mb) {
+            return Err("Thumbnail cache must be between 16 MB and 64 GB".to_string());
+        }
+
+        // Beyond a quarter of the bits, unrelated images start to match
+        if self.duplicate_distance > 16 {
+            return Err("Duplicate distance must be between 0 and 16".to_string());
+        }
+
+        if !(1..=365).contains(&self.history_days) {
+            return Err("History must be kept between 1 and 365 days".to_string());
+        }
+
+        Ok(())
+    }
+
+    /// `backend_url` joined with an API path such as `api/generate`.
+    pub fn endpoint(&self, path: &str) -> String {
+        format!("{}/{}", self.backend_url.trim_end_matches('/'), path)
+    }
+}
+
+pub struct SettingsStore {
+    path: PathBuf,
+    current: RwLock<Settings>,
+}
+
+impl SettingsStore {
+    /// Loads settings from `path`. A missing file gives the defaults; an
+    /// unreadable or invalid one is logged and also gives the defaults, so a
+    /// bad edit never stops the app from starting.
+    pub fn load(path: PathBuf) -> Self {
+        let settings = match fs::read_to_string(&path) {
+            Ok(json) => match serde_json::from_str::<Settings>(&json) {
+                Ok(settings) if settings.validate().is_ok() => settings,
+                Ok(settings) => {
+                    log::warn!(
+                        "Ignoring invalid settings in {}: {}",
+                        path.display(),
+                        settings.validate().unwrap_err()
+                    );
+                    Settings::default()
+                }
+                Err(e) => {
+                    log::warn!("Ignoring unreadable settings in {}: {}", path.display(), e);
+                    Settings::default()
+                }
+            },
+            Err(_) => Settings::default(),
+        };
+
+        SettingsStore {
+            path,
+            current: RwLock::new(settings),
+        }
+    }
+
+    pub fn get(&self) -> Settings {
+        self.current
+            .read()
+            .unwrap_or_else(|e| e.into_inner())
+            .clone()
+    }
+
+    /// Validates and persists new settings, then makes them current.
+    pub fn update(&self, settings: Settings) -> Result<Settings, String> {
+        settings.validate()?;
+        write_atomically(&self.path, &settings)?;
+        *self.current.write().unwrap_or_else(|e| e.into_inner()) = settings.clone();
+        Ok(settings)
+    }
+}
+
+fn write_atomically(path: &Path, settings: &Settings) -> Result<(), String> {
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir).map_err(|e| format!("Failed to create config dir: {}", e))?;
+    }
+
+    let json = serde_json::to_string_pretty(settings).map_err(|e| e.to_string())?;
+    let tmp = path.with_extension("json.tmp");
+    fs::write(&tmp, json).map_err(|e| format!("Failed to write settings: {}", e))?;
+    fs::rename(&tmp, path).map_err(|e| format!("Failed to save settings: {}", e))
+}
+
+#[tauri::command]
+pub fn get_settings(store: tauri::State<'_, SettingsStore>) -> Settings {
+    store.get()
+}
+
+#[tauri::command]
+pub fn update_settings(
+    store: tauri::State<'_, SettingsStore>,
+    settings: Settings,
+) -> Result<Settings, String> {
+    store.update(settings)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::temp_dir;
+
+    #[test]
+    fn bad_values_are_refused() {
+        assert_eq!(Settings::default().validate(), Ok(()));
+
+        type Change = fn(&mut Settings);
+        let invalid: Vec<(&str, Change)> = vec![
+            ("not a url", |s| s.backend_url = "localhost".into()),
+            ("ftp", |s| s.backend_url = "ftp://host:21".into()),
+            ("no host", |s| s.backend_url = "http://".into()),
+            ("empty model", |s| s.model = " ".into()),
+            ("spaced model", |s| s.model = "llava 7b".into()),
+            ("connect", |s| s.connect_timeout_secs = 0),
+            ("request", |s| s.request_timeout_secs = 1801),
+            ("retries", |s| s.max_retries = 11),
+            ("prompt", |s| s.tag_prompt = "\n".into()),
+            ("edge", |s| s.max_image_edge = 64),
+            ("concurrency", |s| s.tagging_concurrency = 0),
+            ("cache", |s| s.thumbnail_cache_mb = 65537),
+            ("distance", |s| s.duplicate_distance = 17),
+            ("history", |s| s.history_days = 366),
+        ];
+        for (case, change) in invalid {
+            let mut settings = Settings::default();
+            change(&mut settings);
+            assert!(settings.validate().is_err(), "{}", case);
+        }
+
+        // The bounds themselves are fine
+        let settings = Settings {
+            backend_url: "https://ollama.lan:8443/".into(),
+            max_retries: 0,
+            max_image_edge: 4096,
+            tagging_concurrency: 8,
+            duplicate_distance: 0,
+            history_days: 365,
+            ..Settings::default()
+        };
+        assert_eq!(settings.validate(), Ok(()));
+        assert_eq!(
+            settings.endpoint("api/tags"),
+            "https://ollama.lan:8443/api/tags"
+        );
+    }
+
+    #[test]
+    fn saved_settings_load_again() {
+        let dir = temp_dir("settings");
+        let path = dir.join("config").join("settings.json");
+
+        // Nothing saved yet
+        let store = SettingsStore::load(path.clone());
+        assert_eq!(store.get(), Settings::default());
+
+        let changed = Settings {
+            model: "llava".into(),
+            history_days: 7,
+            ..Settings::default()
+        };
+        assert_eq!(store.update(changed.clone()), Ok(changed.clone()));
+        assert!(!path.with_extension("json.tmp").exists());
+        assert_eq!(SettingsStore::load(path.clone()).get(), changed);
+
+        // Refused settings are neither saved nor made current
+        let invalid = Settings {
+            history_days: 0,
+            ..changed.clone()
+        };
+        assert!(store.update(invalid).is_err());
+        assert_eq!(store.get(), changed);
+        assert_eq!(SettingsStore::load(path.clone()).get(), changed);
+
+        // Older files lacking newer fields fill them in; broken ones give the defaults
+        fs::write(&path, r#"{"model": "bakllava"}"#).unwrap();
+        let loaded = SettingsStore::load(path.clone()).get();
+        assert_eq!(loaded.model, "bakllava");
+        assert_eq!(loaded.history_days, 30);
+        fs::write(&path, r#"{"model": "#).unwrap();
+        assert_eq!(SettingsStore::load(path.clone()).get(), Settings::default());
+        fs::write(&path, r#"{"maxRetries": 99}"#).unwrap();
+        assert_eq!(SettingsStore::load(path).get(), Settings::default());
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
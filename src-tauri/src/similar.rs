@@ -0,0 +1,273 @@
+use serde::Serialize;
+use std::collections::{HashMap, HashSet};
+
+use crate::catalog::{db_err, Catalog};
+use crate::fingerprint::{Fingerprint, Histogram};
+use crate::natural;
+use crate::scan::ImageInfo;
+
+const DEFAULT_RESULTS: usize = 20;
+const MAX_RESULTS: usize = 200;
+
+/// How much each kind of likeness counts towards the score. Tags only
+/// count when the image asked about has some.
+const HASH_WEIGHT: f64 = 0.5;
+const COLOUR_WEIGHT: f64 = 0.3;
+const TAG_WEIGHT: f64 = 0.2;
+/// Fingerprint distance at which images no longer look alike at all;
+/// unrelated pictures land around half of the 64 bits.
+const UNRELATED_DISTANCE: u32 = 32;
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SimilarImage {
+    image: ImageInfo,
+    /// From 0 for nothing in common to 1 for the same picture and tags.
+    score: f64,
+}
+
+/// What ranking needs to know about an image, all of it from the scan.
+struct Features {
+    id: String,
+    path: String,
+    fingerprint: Option<Fingerprint>,
+    histogram: Option<Histogram>,
+    tags: HashSet<String>,
+}
+
+/// How alike `other` is to `query`, or `None` when they have nothing
+/// visual to compare.
+fn score(query: &Features, other: &Features) -> Option<f64> {
+    let mut parts = Vec::new();
+    if let (Some(a), Some(b)) = (&query.fingerprint, &other.fingerprint) {
+        let distance = a.distance(b).min(UNRELATED_DISTANCE);
+        let likeness = 1.0 - f64::from(distance) / f64::from(UNRELATED_DISTANCE);
+        parts.push((HASH_WEIGHT, likeness));
+    }
+    if let (Some(a), Some(b)) = (&query.histogram, &other.histogram) {
+        parts.push((COLOUR_WEIGHT, a.similarity(b)));
+    }
+    if parts.is_empty() {
+        return None;
+    }
+    if !query.tags.is_empty() {
+        let shared = query.tags.intersection(&other.tags).count();
+        let either = query.tags.union(&other.tags).count();
+        parts.push((TAG_WEIGHT, shared as f64 / either as f64));
+    }
+
+    let weight: f64 = parts.iter().map(|(weight, _)| weight).sum();
+    Some(parts.iter().map(|(w, likeness)| w * likeness).sum::<f64>() / weight)
+}
+
+/// The `k` images most like `features[query]` as indices with scores, best
+/// first. Ties go by natural path order so results don't shift between runs.
+fn rank(features: &[Features], query: usize, k: usize) -> Vec<(usize, f64)> {
+    let mut scored: Vec<(usize, f64)> = features
+        .iter()
+        .enumerate()
+        .filter(|&(index, _)| index != query)
+        .filter_map(|(index, other)| score(&features[query], other).map(|s| (index, s)))
+        .collect();
+    scored.sort_by(|a, b| {
+        b.1.total_cmp(&a.1)
+            .then_with(|| natural::compare(&features[a.0].path, &features[b.0].path))
+    });
+    scored.truncate(k);
+    scored
+}
+
+/// Features of every image in the library.
+fn features(catalog: &Catalog) -> Result<Vec<Features>, String> {
+    let conn = catalog.conn();
+
+    let mut tags: HashMap<String, HashSet<String>> = HashMap::new();
+    let mut stmt = conn
+        .prepare("SELECT it.image_id, t.name FROM image_tags it JOIN tags t ON t.id = it.tag_id")
+        .map_err(db_err)?;
+    let rows = stmt
+        .query_map([], |r| Ok((r.get::<_, String>(0)?, r.get::<_, String>(1)?)))
+        .map_err(db_err)?;
+    for row in rows {
+        let (image_id, tag) = row.map_err(db_err)?;
+        tags.entry(image_id).or_default().insert(tag);
+    }
+
+    let mut stmt = conn
+        .prepare("SELECT id, path, dhash, phash, histogram FROM images")
+        .map_err(db_err)?;
+    let rows = stmt
+        .query_map([], |r| {
+            let id: String = r.get(0)?;
+            let dhash: Option<i64> = r.get(2)?;
+            let phash: Option<i64> = r.get(3)?;
+            let histogram: Option<Vec<u8>> = r.get(4)?;
+            Ok(Features {
+                path: r.get(1)?,
+                fingerprint: dhash.zip(phash).map(|(dhash, phash)| Fingerprint {
+                    dhash: dhash as u64,
+                    phash: phash as u64,
+                }),
+                histogram: histogram.and_then(|blob| Histogram::from_blob(&blob)),
+                tags: tags.remove(&id).unwrap_or_default(),
+                id,
+            })
+        })
+        .map_err(db_err)?;
+    rows.collect::<Result<_, _>>().map_err(db_err)
+}
+
+fn find(catalog: &Catalog, image_id: &str, k: usize) -> Result<Vec<SimilarImage>, String> {
+    let features = features(catalog)?;
+    let query = features
+        .iter()
+        .position(|f| f.id == image_id)
+        .ok_or_else(|| format!("Unknown image: {}", image_id))?;
+    if features[query].fingerprint.is_none() && features[query].histogram.is_none() {
+        return Err("This image hasn't been analysed yet; scan its folder again".to_string());
+    }
+
+    let ranked = rank(&features, query, k.clamp(1, MAX_RESULTS));
+    let ids: Vec<String> = ranked
+        .iter()
+        .map(|&(index, _)| features[index].id.clone())
+        .collect();
+    let scores: HashMap<&str, f64> = ranked
+        .iter()
+        .map(|&(index, score)| (features[index].id.as_str(), score))
+        .collect();
+
+    // images_by_ids keeps the order of `ids`
+    Ok(catalog
+        .images_by_ids(&ids)?
+        .into_iter()
+        .map(|image| SimilarImage {
+            score: scores[image.id.as_str()],
+            image,
+        })
+        .collect())
+}
+
+/// Ranks the library by how much each image looks like `image_id`: its
+/// fingerprint, its colours and, if it has tags, the tags they share.
+#[tauri::command]
+pub fn find_similar(
+    catalog: tauri::State<'_, Catalog>,
+    image_id: String,
+    k: Option<usize>,
+) -> Result<Vec<SimilarImage>, String> {
+    find(&catalog, &image_id, k.unwrap_or(DEFAULT_RESULTS))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::scan::{scan_root, ScanOptions};
+    use crate::test_support::temp_dir;
+    use image::imageops::FilterType;
+    use image::{DynamicImage, Rgb, RgbImage};
+
+    /// Stripes of two colours, `width` pixels wide.
+    fn stripes(width: u32, a: [u8; 3], b: [u8; 3]) -> DynamicImage {
+        DynamicImage::ImageRgb8(RgbImage::from_fn(320, 240, |x, y| {
+            if (x / width + y / 60) % 2 == 0 {
+                Rgb(a)
+            } else {
+                Rgb(b)
+            }
+        }))
+    }
+
+    fn names(results: &[SimilarImage]) -> Vec<&str> {
+        results.iter().map(|r| r.image.name.as_str()).collect()
+    }
+
+    #[test]
+    fn fixture_folder_ranks_by_look_colour_and_tags() {
+        let root = temp_dir("similar");
+
+        let orange = [240, 130, 20];
+        let navy = [20, 30, 90];
+        let query = stripes(40, orange, navy);
+        query.save(root.join("query.png")).unwrap();
+        // The same picture, smaller and re-encoded
+        query
+            .resize(160, 120, FilterType::Lanczos3)
+            .save(root.join("resized.jpg"))
+            .unwrap();
+        // Same colours, different layout
+        stripes(7, orange, navy)
+            .save(root.join("same-colours.png"))
+            .unwrap();
+        // Nothing in common, one tagged like the query and one not
+        stripes(7, [90, 200, 90], [250, 250, 250])
+            .save(root.join("green-tagged.png"))
+            .unwrap();
+        stripes(7, [90, 200, 90], [250, 250, 250])
+            .save(root.join("green.png"))
+            .unwrap();
+
+        let catalog = Catalog::open_in_memory().unwrap();
+        scan_root(&catalog, &root, &ScanOptions::default()).unwrap();
+        let id_of = |name: &str| {
+            catalog
+                .image_id_for_path(&root.join(name).to_string_lossy())
+                .unwrap()
+                .unwrap()
+        };
+        let tags = vec!["sunset".to_string(), "beach".to_string()];
+        catalog.set_tags(&id_of("query.png"), &tags).unwrap();
+        catalog.set_tags(&id_of("green-tagged.png"), &tags).unwrap();
+
+        let results = find(&catalog, &id_of("query.png"), 10).unwrap();
+        assert_eq!(
+            names(&results),
+            [
+                "resized.jpg",
+                "same-colours.png",
+                "green-tagged.png",
+                "green.png"
+            ]
+        );
+        assert!(results.windows(2).all(|w| w[0].score >= w[1].score));
+        assert!(results[0].score > 0.5);
+
+        // Run again, same answer
+        let again = find(&catalog, &id_of("query.png"), 10).unwrap();
+        assert_eq!(names(&again), names(&results));
+        let scores = |r: &[SimilarImage]| r.iter().map(|r| r.score).collect::<Vec<_>>();
+        assert_eq!(scores(&again), scores(&results));
+
+        assert_eq!(find(&catalog, &id_of("query.png"), 1).unwrap().len(), 1);
+        assert!(find(&catalog, "missing", 10).is_err());
+
+        std::fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn ties_are_broken_by_path_and_unanalysed_images_skipped() {
+        let features = |path: &str, hash: Option<u64>| Features {
+            id: path.to_string(),
+            path: path.to_string(),
+            fingerprint: hash.map(|hash| Fingerprint {
+                dhash: hash,
+                phash: hash,
+            }),
+            histogram: None,
+            tags: HashSet::new(),
+        };
+        let library = [
+            features("query.jpg", Some(0)),
+            features("b10.jpg", Some(0b11)),
+            features("b9.jpg", Some(0b11)),
+            features("a.jpg", Some(0b1)),
+            features("unread.jpg", None),
+        ];
+
+        let ranked: Vec<&str> = rank(&library, 0, 10)
+            .into_iter()
+            .map(|(index, _)| library[index].path.as_str())
+            .collect();
+        assert_eq!(ranked, ["a.jpg", "b9.jpg", "b10.jpg"]);
+    }
+}
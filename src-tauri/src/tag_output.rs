@@ -0,0 +1,285 @@
+use serde::Deserialize;
+use serde_json::{json, Value};
+
+/// Longest tag kept, in characters.
+const MAX_TAG_CHARS: usize = 50;
+/// Anything with more words than this is a sentence, not a tag.
+const MAX_TAG_WORDS: usize = 4;
+/// Tags the model itself rates below this are dropped.
+const MIN_CONFIDENCE: f32 = 0.2;
+
+/// What the model gets asked for through Ollama's `format` field.
+pub fn schema() -> Value {
+    json!({
+        "type": "object",
+        "properties": {
+            "tags": {
+                "type": "array",
+                "items": {
+                    "type": "object",
+                    "properties": {
+                        "name": { "type": "string" },
+                        "confidence": { "type": "number", "minimum": 0, "maximum": 1 }
+                    },
+                    "required": ["name"]
+                }
+            },
+            "description": { "type": "string" }
+        },
+        "required": ["tags"]
+    })
+}
+
+/// Tags (and possibly a description) read from a model's answer.
+#[derive(Debug, Default, PartialEq)]
+pub struct ParsedTags {
+    pub tags: Vec<String>,
+    pub description: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct Structured {
+    tags: TagList,
+    #[serde(default)]
+    description: Option<String>,
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum TagList {
+    Items(Vec<TagItem>),
+    /// `"tags": "sky, sea"` from models that half-follow the schema.
+    Text(String),
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum TagItem {
+    Name(String),
+    Scored {
+        #[serde(alias = "tag", alias = "label")]
+        name: String,
+        #[serde(default)]
+        confidence: Option<f32>,
+    },
+}
+
+/// Reads tags from the model's answer. Schema-conforming JSON is used as is;
+/// otherwise JSON is dug out of code fences or surrounding chatter, and as a
+/// last resort the text is split into tags, coping with bullet lists,
+/// numbered lines, `Tags:` prefixes and trailing periods.
+pub fn parse(answer: &str) -> ParsedTags {
+    json_candidates(answer)
+        .into_iter()
+        .find_map(from_json)
+        .unwrap_or_else(|| ParsedTags {
+            tags: from_text(answer),
+            description: None,
+        })
+}
+
+/// The whole answer, then whatever sits between the outermost braces or brackets.
+fn json_candidates(answer: &str) -> Vec<&str> {
+    let mut candidates = vec![answer.trim()];
+    for (open, close) in [('{', '}'), ('[', ']')] {
+        if let (Some(start), Some(end)) = (answer.find(open), answer.rfind(close)) {
+            if start < end {
+                candidates.push(&answer[start..=end]);
+            }
+        }
+    }
+    candidates
+}
+
+fn from_json(candidate: &str) -> Option<ParsedTags> {
+    // A bare array of tags. Checked first: serde would also read an array as
+    // a `Structured` by position.
+    if let Ok(tags) = serde_json::from_str::<Vec<String>>(candidate) {
+        return Some(ParsedTags {
+            tags: clean_all(tags),
+            description: None,
+        });
+    }
+
+    let structured = serde_json::from_str::<Structured>(candidate).ok()?;
+    let tags = match structured.tags {
+        TagList::Items(items) => clean_all(items.into_iter().filter_map(|item| match item {
+            TagItem::Name(name) => Some(name),
+            TagItem::Scored { name, confidence } => {
+                (confidence.unwrap_or(1.0) >= MIN_CONFIDENCE).then_some(name)
+            }
+        })),
+        TagList::Text(text) => from_text(&text),
+    };
+    let description = structured
+        .description
+        .map(|d| d.trim().to_string())
+        .filter(|d| !d.is_empty());
+    Some(ParsedTags { tags, description })
+}
+
+fn from_text(text: &str) -> Vec<String> {
+    let pieces = text
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.starts_with("```"))
+        .filter_map(strip_label)
+        .flat_map(|line| line.split([',', ';', '|', '•', '#']));
+    clean_all(pieces)
+}
+
+/// Drops `Tags:`-style labels. Lines that are nothing but an introduction
+/// ("Here are some tags for this image:") are dropped entirely.
+fn strip_label(line: &str) -> Option<&str> {
+    match line.split_once(':') {
+        Some((label, rest)) if label.split_whitespace().count() <= MAX_TAG_WORDS + 2 => {
+            let rest = rest.trim();
+            (!rest.is_empty()).then_some(rest)
+        }
+        Some(_) => None,
+        None => Some(line),
+    }
+}
+
+fn clean_all<I, S>(pieces: I) -> Vec<String>
+where
+    I: IntoIterator<Item = S>,
+    S: AsRef<str>,
+{
+    let mut tags: Vec<String> = Vec::new();
+    for piece in pieces {
+        if let Some(tag) = clean_tag(piece.as_ref()) {
+            if !tags.contains(&tag) {
+                tags.push(tag);
+            }
+        }
+    }
+    tags
+}
+
+/// Normalizes one candidate tag, or rejects it.
+fn clean_tag(raw: &str) -> Option<String> {
+    let mut tag = raw.trim();
+
+    // List markers: "-", "*", "1.", "2)"
+    tag = tag.trim_start_matches(['-', '*', '+', '>']);
+    let digits = tag.len() - tag.trim_start_matches(|c: char| c.is_ascii_digit()).len();
+    if digits > 0 && tag[digits..].starts_with(['.', ')']) {
+        tag = &tag[digits + 1..];
+    }
+
+    let tag = tag
+        .trim_matches(|c: char| c.is_whitespace() || "\"'`*_.!?()[]{}“”‘’".contains(c))
+        .split_whitespace()
+        .collect::<Vec<_>>()
+        .join(" ")
+        .to_lowercase();
+
+    let words = tag.split(' ').count();
+    let valid = !tag.is_empty()
+        && tag.chars().count() <= MAX_TAG_CHARS
+        && words <= MAX_TAG_WORDS
+        && tag.chars().any(char::is_alphanumeric);
+    valid.then_some(tag)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tags(answer: &str) -> Vec<String> {
+        parse(answer).tags
+    }
+
+    #[test]
+    fn schema_output_is_used_directly() {
+        let parsed = parse(
+            r#"{"tags": [{"name": "Sunset", "confidence": 0.95}, {"name": "beach"}],
+                "description": "A beach at sunset."}"#,
+        );
+        assert_eq!(parsed.tags, ["sunset", "beach"]);
+        assert_eq!(parsed.description.as_deref(), Some("A beach at sunset."));
+    }
+
+    #[test]
+    fn low_confidence_tags_are_dropped() {
+        let answer = r#"{"tags": [{"name": "dog", "confidence": 0.9}, {"name": "wolf", "confidence": 0.05}]}"#;
+        assert_eq!(tags(answer), ["dog"]);
+    }
+
+    #[test]
+    fn near_miss_json_is_accepted() {
+        // Plain strings instead of objects
+        assert_eq!(tags(r#"{"tags": ["cat", "sofa"]}"#), ["cat", "sofa"]);
+        // "tag" instead of "name"
+        assert_eq!(tags(r#"{"tags": [{"tag": "cat"}]}"#), ["cat"]);
+        // One comma-separated string
+        assert_eq!(
+            tags(r#"{"tags": "cat, sofa, cozy"}"#),
+            ["cat", "sofa", "cozy"]
+        );
+        // A bare array
+        assert_eq!(tags(r#"["Cat", "Sofa"]"#), ["cat", "sofa"]);
+    }
+
+    #[test]
+    fn json_is_found_inside_chatter_and_fences() {
+        let fenced = "Sure! Here you go:\n```json\n{\"tags\": [\"mountain\", \"snow\"]}\n```\nLet me know if you need more.";
+        assert_eq!(tags(fenced), ["mountain", "snow"]);
+
+        let trailing = "{\"tags\": [{\"name\": \"forest\"}]} I hope this helps.";
+        assert_eq!(tags(trailing), ["forest"]);
+    }
+
+    #[test]
+    fn messy_text_answers() {
+        let corpus: &[(&str, &[&str])] = &[
+            // The original comma format, with a trailing period
+            (
+                "nature, sunset, mountain, peaceful, orange sky.",
+                &["nature", "sunset", "mountain", "peaceful", "orange sky"],
+            ),
+            // Label prefix
+            ("Tags: dog, park, frisbee", &["dog", "park", "frisbee"]),
+            // Bullet list under an introduction
+            (
+                "Here are some tags for this image:\n- Dog\n- Grass\n- Sunny day\n",
+                &["dog", "grass", "sunny day"],
+            ),
+            // Numbered list with periods
+            (
+                "1. City.\n2. Skyline.\n3) Night lights.",
+                &["city", "skyline", "night lights"],
+            ),
+            // Markdown bold and asterisks
+            (
+                "* **Car**\n* **Red**\n* **Street**",
+                &["car", "red", "street"],
+            ),
+            // Hashtags
+            ("#beach #waves #surf", &["beach", "waves", "surf"]),
+            // Quoted tags
+            ("\"sky\", 'clouds', `blue`", &["sky", "clouds", "blue"]),
+            // Semicolons and duplicates
+            ("Cat; cat; kitten; KITTEN", &["cat", "kitten"]),
+            // A sentence that isn't a tag gets dropped
+            (
+                "The image shows a small boat floating on a calm lake, boat, lake",
+                &["boat", "lake"],
+            ),
+            // Nothing usable
+            ("", &[]),
+            ("...", &[]),
+        ];
+
+        for (answer, expected) in corpus {
+            assert_eq!(&tags(answer), expected, "answer: {:?}", answer);
+        }
+    }
+
+    #[test]
+    fn overlong_tags_are_rejected() {
+        let long = "x".repeat(MAX_TAG_CHARS + 1);
+        assert_eq!(tags(&format!("ok, {}", long)), ["ok"]);
+    }
+}
@@ -0,0 +1,453 @@
+use rusqlite::params;
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Mutex;
+use tauri::{AppHandle, Emitter, Manager};
+use tokio::sync::Notify;
+
+use crate::ai;
+use crate::catalog::{db_err, now_secs, Catalog, FILE_TAG_SOURCE};
+use crate::ollama::{AiError, Ollama};
+use crate::settings::SettingsStore;
+
+/// Batch tagging queue. Pending jobs live in the catalog's `tagging_queue`
+/// table, so whatever was left when the app closed resumes on the next start.
+/// A single dispatcher task hands jobs to workers, never running more than
+/// `Settings::tagging_concurrency` at once.
+#[derive(Default)]
+pub struct TaggingQueue {
+    paused: AtomicBool,
+    wake: Notify,
+    in_flight: Mutex<HashSet<String>>,
+}
+
+#[derive(Deserialize, Default)]
+#[serde(rename_all = "camelCase", default)]
+pub struct TaggingOptions {
+    /// Retag images that already have tags.
+    pub force: bool,
+}
+
+struct Job {
+    image_id: String,
+    path: String,
+    force: bool,
+    /// The `enqueue` call that queued it; its images are journaled together.
+    batch: Option<i64>,
+}
+
+#[derive(Serialize, Clone, Copy)]
+#[serde(rename_all = "lowercase")]
+enum JobStatus {
+    Started,
+    Tagged,
+    Skipped,
+    Failed,
+}
+
+#[derive(Serialize, Clone)]
+#[serde(rename_all = "camelCase")]
+struct JobEvent {
+    image_id: String,
+    status: JobStatus,
+    tags: Vec<String>,
+    error: Option<AiError>,
+    remaining: usize,
+}
+
+#[derive(Serialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct QueueState {
+    paused: bool,
+    pending: usize,
+    running: usize,
+}
+
+impl TaggingQueue {
+    fn in_flight(&self) -> std::sync::MutexGuard<'_, HashSet<String>> {
+        self.in_flight.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    fn state(&self, catalog: &Catalog) -> Result<QueueState, String> {
+        Ok(QueueState {
+            paused: self.paused.load(Ordering::Relaxed),
+            pending: pending_count(catalog)?,
+            running: self.in_flight().len(),
+        })
+    }
+
+    fn set_paused(&self, paused: bool) {
+        self.paused.store(paused, Ordering::Relaxed);
+        self.wake.notify_one();
+    }
+}
+
+/// Starts the dispatcher. Called once from setup.
+pub fn start(app: &AppHandle) {
+    let app = app.clone();
+    tauri::async_runtime::spawn(async move { dispatch(app).await });
+}
+
+async fn dispatch(app: AppHandle) {
+    let queue = app.state::<TaggingQueue>();
+
+    loop {
+        if !queue.paused.load(Ordering::Relaxed) {
+            let limit = app.state::<SettingsStore>().get().tagging_concurrency;
+            let busy: HashSet<String> = queue.in_flight().clone();
+
+            if busy.len() < limit {
+                match next_jobs(&app.state::<Catalog>(), limit - busy.len(), &busy) {
+                    Ok(jobs) => {
+                        let mut in_flight = queue.in_flight();
+                        for job in jobs {
+                            in_flight.insert(job.image_id.clone());
+                            let app = app.clone();
+                            tauri::async_runtime::spawn(async move { work(app, job).await });
+                        }
+                    }
+                    Err(e) => log::warn!("Failed to read tagging queue: {}", e),
+                }
+            }
+        }
+
+        // Woken by enqueue, resume and every finished job
+        queue.wake.notified().await;
+    }
+}
+
+async fn work(app: AppHandle, job: Job) {
+    let catalog = app.state::<Catalog>();
+    let queue = app.state::<TaggingQueue>();
+
+    emit_job(&app, &job.image_id, JobStatus::Started, Vec::new(), None);
+
+    let already_tagged = !job.force && has_tags(&catalog, &job.image_id).unwrap_or(false);
+    let result = if already_tagged {
+        Ok(None)
+    } else {
+        let settings = app.state::<SettingsStore>().get();
+        ai::tag_image(
+            &catalog,
+            &app.state::<Ollama>(),
+            &settings,
+            &job.path,
+            job.batch,
+        )
+        .await
+        .map(Some)
+    };
+
+    let keep_queued = match &result {
+        // Ollama already retried these; pause rather than burn through the whole queue
+        Err(e) if e.is_transient() => {
+            log::warn!("Pausing tagging queue: {}", e);
+            queue.paused.store(true, Ordering::Relaxed);
+            true
+        }
+        _ => false,
+    };
+    if !keep_queued {
+        if let Err(e) = remove_job(&catalog, &job.image_id) {
+            log::warn!("Failed to update tagging queue: {}", e);
+        }
+    }
+
+    queue.in_flight().remove(&job.image_id);
+
+    match result {
+        Ok(Some(tags)) => emit_job(&app, &job.image_id, JobStatus::Tagged, tags, None),
+        Ok(None) => emit_job(&app, &job.image_id, JobStatus::Skipped, Vec::new(), None),
+        Err(e) => emit_job(&app, &job.image_id, JobStatus::Failed, Vec::new(), Some(e)),
+    }
+    emit_state(&app);
+    queue.wake.notify_one();
+}
+
+fn emit_job(
+    app: &AppHandle,
+    image_id: &str,
+    status: JobStatus,
+    tags: Vec<String>,
+    error: Option<AiError>,
+) {
+    let remaining = pending_count(&app.state::<Catalog>()).unwrap_or(0);
+    let _ = app.emit(
+        "tagging://job",
+        JobEvent {
+            image_id: image_id.to_string(),
+            status,
+            tags,
+            error,
+            remaining,
+        },
+    );
+}
+
+fn emit_state(app: &AppHandle) {
+    if let Ok(state) = app.state::<TaggingQueue>().state(&app.state::<Catalog>()) {
+        let _ = app.emit("tagging://state", state);
+    }
+}
+
+fn enqueue(catalog: &Catalog, image_ids: &[String], force: bool) -> Result<usize, String> {
+    let mut conn = catalog.conn();
+    let tx = conn.transaction().map_err(db_err)?;
+
+    // Never reused, so a batch can't join an old one's journal entry
+    let batch: i64 = tx
+        .query_row(
+            "SELECT max(coalesce((SELECT max(batch) FROM tagging_queue), 0),
+                        coalesce((SELECT max(batch) FROM journal), 0)) + 1",
+            [],
+            |r| r.get(0),
+        )
+        .map_err(db_err)?;
+
+    let now = now_secs();
+    let mut queued = 0;
+    for image_id in image_ids {
+        // Re-queueing an image keeps its place and batch but can upgrade it to forced
+        queued += tx
+            .execute(
+                "INSERT INTO tagging_queue (image_id, force, queued_at, batch)
+                 SELECT id, ?2, ?3, ?4 FROM images WHERE id = ?1
+                 ON CONFLICT(image_id) DO UPDATE SET force = max(force, excluded.force)",
+                params![image_id, force, now, batch],
+            )
+            .map_err(db_err)?;
+    }
+
+    tx.commit().map_err(db_err)?;
+    Ok(queued)
+}
+
+fn next_jobs(catalog: &Catalog, limit: usize, busy: &HashSet<String>) -> Result<Vec<Job>, String> {
+    let conn = catalog.conn();
+    let mut stmt = conn
+        .prepare(
+            "SELECT q.image_id, i.path, q.force, q.batch FROM tagging_queue q
+             JOIN images i ON i.id = q.image_id
+             ORDER BY q.rowid",
+        )
+        .map_err(db_err)?;
+    let rows = stmt
+        .query_map([], |r| {
+            Ok(Job {
+                image_id: r.get(0)?,
+                path: r.get(1)?,
+                force: r.get(2)?,
+                batch: r.get(3)?,
+            })
+        })
+        .map_err(db_err)?;
+
+    let mut jobs = Vec::new();
+    for job in rows {
+        let job = job.map_err(db_err)?;
+        if !busy.contains(&job.image_id) {
+            jobs.push(job);
+            if jobs.len() == limit {
+                break;
+            }
+        }
+    }
+    Ok(jobs)
+}
+
+fn remove_job(catalog: &Catalog, image_id: &str) -> Result<(), String> {
+    catalog
+        .conn()
+        .execute("DELETE FROM tagging_queue WHERE image_id = ?1", [image_id])
+        .map_err(db_err)?;
+    Ok(())
+}
+
+fn clear_pending(catalog: &Catalog, keep: &HashSet<String>) -> Result<usize, String> {
+    let mut conn = catalog.conn();
+    let tx = conn.transaction().map_err(db_err)?;
+    // At most `tagging_concurrency` ids, so one parameter each is fine
+    let placeholders = vec!["?"; keep.len()].join(", ");
+    let removed = tx
+        .execute(
+            &format!(
+                "DELETE FROM tagging_queue WHERE image_id NOT IN ({})",
+                placeholders
+            ),
+            rusqlite::params_from_iter(keep),
+        )
+        .map_err(db_err)?;
+    tx.commit().map_err(db_err)?;
+    Ok(removed)
+}
+
+fn pending_count(catalog: &Catalog) -> Result<usize, String> {
+    catalog
+        .conn()
+        .query_row("SELECT COUNT(*) FROM tagging_queue", [], |r| r.get(0))
+        .map_err(db_err)
+}
+
+fn has_tags(catalog: &Catalog, image_id: &str) -> Result<bool, String> {
+    catalog
+        .conn()
+        .query_row(
+            // Keywords imported from the file don't count as tagged
+            "SELECT EXISTS (SELECT 1 FROM image_tags WHERE image_id = ?1 AND source IS NOT ?2)",
+            params![image_id, FILE_TAG_SOURCE],
+            |r| r.get(0),
+        )
+        .map_err(db_err)
+}
+
+/// Queues images for tagging and returns how many were queued.
+/// Unknown ids are ignored.
+#[tauri::command]
+pub fn enqueue_tagging(
+    app: AppHandle,
+    catalog: tauri::State<'_, Catalog>,
+    queue: tauri::State<'_, TaggingQueue>,
+    image_ids: Vec<String>,
+    options: Option<TaggingOptions>,
+) -> Result<usize, String> {
+    let options = options.unwrap_or_default();
+    let queued = enqueue(&catalog, &image_ids, options.force)?;
+    queue.wake.notify_one();
+    emit_state(&app);
+    Ok(queued)
+}
+
+#[tauri::command]
+pub fn pause_tagging(
+    app: AppHandle,
+    catalog: tauri::State<'_, Catalog>,
+    queue: tauri::State<'_, TaggingQueue>,
+) -> Result<QueueState, String> {
+    queue.set_paused(true);
+    emit_state(&app);
+    queue.state(&catalog)
+}
+
+#[tauri::command]
+pub fn resume_tagging(
+    app: AppHandle,
+    catalog: tauri::State<'_, Catalog>,
+    queue: tauri::State<'_, TaggingQueue>,
+) -> Result<QueueState, String> {
+    queue.set_paused(false);
+    emit_state(&app);
+    queue.state(&catalog)
+}
+
+/// Drops every pending job. Images already being tagged are left to finish.
+#[tauri::command]
+pub fn cancel_tagging(
+    app: AppHandle,
+    catalog: tauri::State<'_, Catalog>,
+    queue: tauri::State<'_, TaggingQueue>,
+) -> Result<usize, String> {
+    let running = queue.in_flight().clone();
+    let removed = clear_pending(&catalog, &running)?;
+    emit_state(&app);
+    Ok(removed)
+}
+
+#[tauri::command]
+pub fn tagging_status(
+    catalog: tauri::State<'_, Catalog>,
+    queue: tauri::State<'_, TaggingQueue>,
+) -> Result<QueueState, String> {
+    queue.state(&catalog)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{add_images, temp_dir};
+
+    fn ids(ids: &[&str]) -> Vec<String> {
+        ids.iter().map(|id| id.to_string()).collect()
+    }
+
+    fn queued(catalog: &Catalog) -> Vec<(String, bool)> {
+        next_jobs(catalog, usize::MAX, &HashSet::new())
+            .unwrap()
+            .into_iter()
+            .map(|job| (job.image_id, job.force))
+            .collect()
+    }
+
+    #[test]
+    fn requeueing_keeps_the_place_and_can_force() {
+        let catalog = Catalog::open_in_memory().unwrap();
+        add_images(&catalog, &["a", "b", "c"]);
+
+        assert_eq!(enqueue(&catalog, &ids(&["a", "b", "gone"]), false), Ok(2));
+        assert_eq!(enqueue(&catalog, &ids(&["c", "a"]), true), Ok(2));
+        assert_eq!(
+            queued(&catalog),
+            [
+                ("a".to_string(), true),
+                ("b".to_string(), false),
+                ("c".to_string(), true)
+            ]
+        );
+        // Queueing again without force doesn't take it back
+        enqueue(&catalog, &ids(&["a"]), false).unwrap();
+        assert_eq!(queued(&catalog)[0], ("a".to_string(), true));
+
+        // Each call is a batch of its own; re-queued images stay in theirs
+        let batch = |id: &str| {
+            next_jobs(&catalog, usize::MAX, &HashSet::new())
+                .unwrap()
+                .into_iter()
+                .find(|job| job.image_id == id)
+                .unwrap()
+                .batch
+        };
+        assert_eq!(batch("a"), batch("b"));
+        assert_ne!(batch("a"), batch("c"));
+    }
+
+    #[test]
+    fn busy_images_are_skipped_and_kept_when_cancelling() {
+        let catalog = Catalog::open_in_memory().unwrap();
+        add_images(&catalog, &["a", "b", "c", "d"]);
+        enqueue(&catalog, &ids(&["a", "b", "c", "d"]), false).unwrap();
+
+        let busy: HashSet<String> = ids(&["a", "c"]).into_iter().collect();
+        let next: Vec<String> = next_jobs(&catalog, 1, &busy)
+            .unwrap()
+            .into_iter()
+            .map(|job| job.image_id)
+            .collect();
+        assert_eq!(next, ["b"]);
+        let next = next_jobs(&catalog, 5, &busy).unwrap();
+        assert_eq!(next.len(), 2);
+
+        remove_job(&catalog, "b").unwrap();
+        assert_eq!(clear_pending(&catalog, &busy), Ok(1));
+        assert_eq!(pending_count(&catalog), Ok(2));
+        assert_eq!(clear_pending(&catalog, &HashSet::new()), Ok(2));
+        assert_eq!(pending_count(&catalog), Ok(0));
+    }
+
+    #[test]
+    fn the_queue_survives_a_restart() {
+        let dir = temp_dir("tagging_queue");
+        let path = dir.join("catalog.db");
+
+        {
+            let catalog = Catalog::open(&path).unwrap();
+            add_images(&catalog, &["a", "b"]);
+            enqueue(&catalog, &ids(&["a", "b"]), true).unwrap();
+            remove_job(&catalog, "a").unwrap();
+        }
+        let catalog = Catalog::open(&path).unwrap();
+        assert_eq!(queued(&catalog), [("b".to_string(), true)]);
+        drop(catalog);
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,602 @@
+use rusqlite::{params, OptionalExtension};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashSet};
+
+use crate::catalog::{db_err, Catalog};
+use crate::journal;
+use crate::settings::SettingsStore;
+
+/// Plurals that don't follow the suffix rules.
+const IRREGULAR_PLURALS: &[(&str, &str)] = &[
+    ("children", "child"),
+    ("feet", "foot"),
+    ("geese", "goose"),
+    ("knives", "knife"),
+    ("leaves", "leaf"),
+    ("lives", "life"),
+    ("men", "man"),
+    ("mice", "mouse"),
+    ("people", "person"),
+    ("shelves", "shelf"),
+    ("teeth", "tooth"),
+    ("wives", "wife"),
+    ("wolves", "wolf"),
+    ("women", "woman"),
+];
+
+/// Plurals ending in "ies" whose singular ends in "ie" rather than "y".
+const IE_PLURALS: &[&str] = &[
+    "aunties",
+    "brownies",
+    "calories",
+    "cookies",
+    "goalies",
+    "hoodies",
+    "lies",
+    "movies",
+    "pies",
+    "pixies",
+    "rookies",
+    "selfies",
+    "smoothies",
+    "ties",
+    "zombies",
+];
+
+/// Plurals of singulars that end in a single "s", which take "es".
+const SES_PLURALS: &[&str] = &[
+    "atlases",
+    "bonuses",
+    "buses",
+    "cactuses",
+    "campuses",
+    "canvases",
+    "circuses",
+    "gases",
+    "irises",
+    "lenses",
+    "octopuses",
+    "viruses",
+    "walruses",
+];
+
+/// Words ending in "s" that are already singular, or the same in both forms.
+const KEEP_AS_IS: &[&str] = &[
+    "aircraft",
+    "bus",
+    "canvas",
+    "chaos",
+    "christmas",
+    "class",
+    "clothes",
+    "deer",
+    "fish",
+    "gas",
+    "glass",
+    "glasses",
+    "grass",
+    "jeans",
+    "lens",
+    "moss",
+    "news",
+    "sheep",
+    "series",
+    "species",
+    "stairs",
+    "sunglasses",
+    "swiss",
+    "texas",
+];
+
+/// How the controlled vocabulary treats model output.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum VocabularyMode {
+    /// No vocabulary; every tag is kept.
+    #[default]
+    Off,
+    /// Tags that match a vocabulary term are mapped onto it; others are kept.
+    Map,
+    /// Tags that don't match a vocabulary term are dropped.
+    Strict,
+}
+
+/// User-editable rules applied to tags coming back from the model.
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq)]
+#[serde(rename_all = "camelCase", default)]
+pub struct TagRules {
+    /// Alias → preferred tag, e.g. `"puppy": "dog"`.
+    pub synonyms: BTreeMap<String, String>,
+    /// Tags that are never stored.
+    pub blocklist: Vec<String>,
+    pub vocabulary: Vec<String>,
+    pub vocabulary_mode: VocabularyMode,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TagCount {
+    name: String,
+    images: u32,
+}
+
+impl TagRules {
+    pub fn validate(&self) -> Result<(), String> {
+        for (alias, tag) in &self.synonyms {
+            if normalize(alias).is_none() || normalize(tag).is_none() {
+                return Err(format!("Invalid synonym '{}' → '{}'", alias, tag));
+            }
+        }
+        if self.vocabulary_mode != VocabularyMode::Off
+            && !self.vocabulary.iter().any(|t| normalize(t).is_some())
+        {
+            return Err("The controlled vocabulary is on but has no terms".to_string());
+        }
+        Ok(())
+    }
+
+    /// Normalizes `tags`, then applies synonyms, the blocklist and the
+    /// vocabulary. The result is deduplicated and keeps the model's order.
+    pub fn apply(&self, tags: &[String]) -> Vec<String> {
+        let synonyms: BTreeMap<String, String> = self
+            .synonyms
+            .iter()
+            .filter_map(|(alias, tag)| Some((normalize(alias)?, normalize(tag)?)))
+            .collect();
+        let blocked: Vec<String> = self.blocklist.iter().filter_map(|t| normalize(t)).collect();
+        let vocabulary: Vec<String> = self
+            .vocabulary
+            .iter()
+            .filter_map(|t| normalize(t))
+            .collect();
+
+        let mut result: Vec<String> = Vec::new();
+        for tag in tags.iter().filter_map(|t| normalize(t)) {
+            let tag = synonyms.get(&tag).cloned().unwrap_or(tag);
+            let tag = match self.vocabulary_mode {
+                VocabularyMode::Off => Some(tag),
+                VocabularyMode::Map => Some(match_vocabulary(&tag, &vocabulary).unwrap_or(tag)),
+                VocabularyMode::Strict => match_vocabulary(&tag, &vocabulary),
+            };
+            if let Some(tag) = tag {
+                if !blocked.contains(&tag) && !result.contains(&tag) {
+                    result.push(tag);
+                }
+            }
+        }
+        result
+    }
+
+    /// `raw` normalized with the synonyms applied, as a tag the model
+    /// suggested would be stored.
+    pub fn canonical(&self, raw: &str) -> Option<String> {
+        let tag = normalize(raw)?;
+        let preferred = self
+            .synonyms
+            .iter()
+            .find(|(alias, _)| normalize(alias).as_deref() == Some(tag.as_str()))
+            .and_then(|(_, preferred)| normalize(preferred));
+        Some(preferred.unwrap_or(tag))
+    }
+}
+
+/// The vocabulary term for `tag`: an exact match, or failing that the term
+/// that is its last word ("blue sky" → "sky").
+fn match_vocabulary(tag: &str, vocabulary: &[String]) -> Option<String> {
+    if vocabulary.iter().any(|term| term == tag) {
+        return Some(tag.to_string());
+    }
+    let head = tag.rsplit(' ').next()?;
+    vocabulary.iter().find(|term| *term == head).cloned()
+}
+
+/// Canonical form of a tag: lowercase, punctuation stripped (inner hyphens
+/// and apostrophes are kept), whitespace collapsed and the last word made
+/// singular. `None` when nothing is left.
+pub fn normalize(raw: &str) -> Option<String> {
+    let cleaned: String = raw
+        .to_lowercase()
+        .chars()
+        .map(|c| {
+            if c.is_alphanumeric() || c == '-' || c == '\'' {
+                c
+            } else {
+                ' '
+            }
+        })
+        .collect();
+
+    let mut words: Vec<&str> = cleaned
+        .split_whitespace()
+        .map(|w| w.trim_matches(['-', '\'']))
+        .filter(|w| !w.is_empty())
+        .collect();
+    let last = words.pop()?;
+    let last = singularize(last);
+
+    words.push(&last);
+    Some(words.join(" "))
+}
+
+/// Rule-based English singular; good enough for the nouns vision models
+/// produce, and conservative where a rule would mangle a word.
+pub fn singularize(word: &str) -> String {
+    if let Some((_, singular)) = IRREGULAR_PLURALS.iter().find(|(plural, _)| *plural == word) {
+        return singular.to_string();
+    }
+    if KEEP_AS_IS.contains(&word) || word.chars().count() <= 3 || !word.ends_with('s') {
+        return word.to_string();
+    }
+
+    if IE_PLURALS.contains(&word) {
+        return word[..word.len() - 1].to_string();
+    }
+    if SES_PLURALS.contains(&word) {
+        return word[..word.len() - 2].to_string();
+    }
+    if let Some(stem) = word.strip_suffix("ies") {
+        return format!("{}y", stem);
+    }
+    for suffix in ["sses", "shes", "ches", "xes", "zzes"] {
+        if word.ends_with(suffix) {
+            return word[..word.len() - 2].to_string();
+        }
+    }
+    if ["ss", "us", "is"].iter().any(|s| word.ends_with(s)) {
+        return word.to_string();
+    }
+    word[..word.len() - 1].to_string()
+}
+
+fn tag_id(conn: &rusqlite::Connection, name: &str) -> Result<Option<i64>, String> {
+    conn.query_row("SELECT id FROM tags WHERE name = ?1", [name], |r| r.get(0))
+        .optional()
+        .map_err(db_err)
+}
+
+fn tag_name(raw: &str) -> Result<String, String> {
+    let name = raw.trim().to_lowercase();
+    if name.is_empty() {
+        return Err("Tag name can't be empty".to_string());
+    }
+    Ok(name)
+}
+
+/// The tag a name the user typed refers to: the name as typed when there
+/// is such a tag, otherwise its canonical form under `rules`, so "Skies"
+/// finds "sky". The tag may not exist yet.
+fn resolve(conn: &rusqlite::Connection, raw: &str, rules: &TagRules) -> Result<String, String> {
+    let typed = tag_name(raw)?;
+    if tag_id(conn, &typed)?.is_some() {
+        return Ok(typed);
+    }
+    Ok(rules.canonical(&typed).unwrap_or(typed))
+}
+
+/// Moves every image tagged with one of `sources` over to `target` and
+/// removes the sources. Returns how many images now carry `target` because
+/// of the merge.
+pub fn merge(
+    catalog: &Catalog,
+    rules: &TagRules,
+    sources: &[String],
+    target: &str,
+) -> Result<usize, String> {
+    let mut conn = catalog.conn();
+    let tx = conn.transaction().map_err(db_err)?;
+    let target = resolve(&tx, target, rules)?;
+    let sources = sources
+        .iter()
+        .map(|source| resolve(&tx, source, rules))
+        .collect::<Result<Vec<_>, _>>()?;
+    let moved = merge_into(&tx, &sources, &target)?;
+    tx.commit().map_err(db_err)?;
+    Ok(moved)
+}
+
+fn merge_into(
+    tx: &rusqlite::Transaction,
+    sources: &[String],
+    target: &str,
+) -> Result<usize, String> {
+    tx.execute("INSERT OR IGNORE INTO tags (name) VALUES (?1)", [target])
+        .map_err(db_err)?;
+    let target_id = tag_id(tx, target)?.ok_or("Failed to create tag")?;
+
+    let mut moved = 0;
+    for source in sources {
+        let Some(source_id) = tag_id(tx, source)? else {
+            continue;
+        };
+        if source_id == target_id {
+            continue;
+        }
+        moved += tx
+            .execute(
+                "INSERT OR IGNORE INTO image_tags (image_id, tag_id, source)
+                 SELECT image_id, ?2, source FROM image_tags WHERE tag_id = ?1",
+                params![source_id, target_id],
+            )
+            .map_err(db_err)?;
+        // Cascades to image_tags
+        tx.execute("DELETE FROM tags WHERE id = ?1", [source_id])
+            .map_err(db_err)?;
+    }
+    Ok(moved)
+}
+
+/// Renames a tag everywhere. Renaming onto an existing tag merges the two.
+pub fn rename(catalog: &Catalog, rules: &TagRules, from: &str, to: &str) -> Result<usize, String> {
+    let mut conn = catalog.conn();
+    let tx = conn.transaction().map_err(db_err)?;
+    let from = resolve(&tx, from, rules)?;
+    if tag_id(&tx, &from)?.is_none() {
+        return Err(format!("Unknown tag: {}", from));
+    }
+    let images = images_with(&tx, &from)?;
+    let to = resolve(&tx, to, rules)?;
+    merge_into(&tx, &[from], &to)?;
+    tx.commit().map_err(db_err)?;
+    Ok(images)
+}
+
+/// Removes a tag from every image. Returns how many images lost it.
+pub fn delete(catalog: &Catalog, rules: &TagRules, name: &str) -> Result<usize, String> {
+    let mut conn = catalog.conn();
+    let tx = conn.transaction().map_err(db_err)?;
+    let name = resolve(&tx, name, rules)?;
+    let images = images_with(&tx, &name)?;
+    tx.execute("DELETE FROM tags WHERE name = ?1", [&name])
+        .map_err(db_err)?;
+    tx.commit().map_err(db_err)?;
+    Ok(images)
+}
+
+fn images_with(conn: &rusqlite::Connection, name: &str) -> Result<usize, String> {
+    conn.query_row(
+        "SELECT COUNT(*) FROM image_tags it JOIN tags t ON t.id = it.tag_id WHERE t.name = ?1",
+        [name],
+        |r| r.get(0),
+    )
+    .map_err(db_err)
+}
+
+/// Ids of the images carrying any of `names`, for journaling changes to them.
+fn tagged(catalog: &Catalog, rules: &TagRules, names: &[String]) -> Result<Vec<String>, String> {
+    let conn = catalog.conn();
+    let mut stmt = conn
+        .prepare(
+            "SELECT it.image_id FROM image_tags it JOIN tags t ON t.id = it.tag_id
+             WHERE t.name = ?1",
+        )
+        .map_err(db_err)?;
+    let mut ids: Vec<String> = Vec::new();
+    let mut seen = HashSet::new();
+    for name in names {
+        let rows = stmt
+            .query_map([resolve(&conn, name, rules)?], |r| r.get(0))
+            .map_err(db_err)?;
+        for id in rows {
+            let id: String = id.map_err(db_err)?;
+            if seen.insert(id.clone()) {
+                ids.push(id);
+            }
+        }
+    }
+    Ok(ids)
+}
+
+/// Every tag in use, most used first.
+pub fn counts(catalog: &Catalog) -> Result<Vec<TagCount>, String> {
+    let conn = catalog.conn();
+    let mut stmt = conn
+        .prepare(
+            "SELECT t.name, COUNT(*) AS images FROM tags t
+             JOIN image_tags it ON it.tag_id = t.id
+             GROUP BY t.id ORDER BY images DESC, t.name",
+        )
+        .map_err(db_err)?;
+    let rows = stmt
+        .query_map([], |r| {
+            Ok(TagCount {
+                name: r.get(0)?,
+                images: r.get(1)?,
+            })
+        })
+        .map_err(db_err)?;
+    rows.collect::<Result<_, _>>().map_err(db_err)
+}
+
+#[tauri::command]
+pub fn list_tags(catalog: tauri::State<'_, Catalog>) -> Result<Vec<TagCount>, String> {
+    counts(&catalog)
+}
+
+#[tauri::command]
+pub fn merge_tags(
+    catalog: tauri::State<'_, Catalog>,
+    settings: tauri::State<'_, SettingsStore>,
+    sources: Vec<String>,
+    target: String,
+) -> Result<usize, String> {
+    let rules = settings.get().tag_rules;
+    let summary = format!("Merged {} into {}", sources.join(", "), tag_name(&target)?);
+    let images = tagged(&catalog, &rules, &sources)?;
+    journal::edit(&catalog, &summary, &images, || {
+        merge(&catalog, &rules, &sources, &target)
+    })
+}
+
+#[tauri::command]
+pub fn rename_tag(
+    catalog: tauri::State<'_, Catalog>,
+    settings: tauri::State<'_, SettingsStore>,
+    from: String,
+    to: String,
+) -> Result<usize, String> {
+    let rules = settings.get().tag_rules;
+    let summary = format!("Renamed tag {} to {}", tag_name(&from)?, tag_name(&to)?);
+    let images = tagged(&catalog, &rules, std::slice::from_ref(&from))?;
+    journal::edit(&catalog, &summary, &images, || {
+        rename(&catalog, &rules, &from, &to)
+    })
+}
+
+#[tauri::command]
+pub fn delete_tag(
+    catalog: tauri::State<'_, Catalog>,
+    settings: tauri::State<'_, SettingsStore>,
+    name: String,
+) -> Result<usize, String> {
+    let rules = settings.get().tag_rules;
+    let summary = format!("Deleted tag {}", tag_name(&name)?);
+    let images = tagged(&catalog, &rules, std::slice::from_ref(&name))?;
+    journal::edit(&catalog, &summary, &images, || {
+        delete(&catalog, &rules, &name)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::add_images;
+
+    fn strings(tags: &[&str]) -> Vec<String> {
+        tags.iter().map(|t| t.to_string()).collect()
+    }
+
+    #[test]
+    fn tags_are_normalized() {
+        assert_eq!(normalize("Sky."), Some("sky".to_string()));
+        assert_eq!(normalize("  Blue   SKIES! "), Some("blue sky".to_string()));
+        assert_eq!(
+            normalize("children's toys"),
+            Some("children's toy".to_string())
+        );
+        assert_eq!(normalize("close-up"), Some("close-up".to_string()));
+        assert_eq!(normalize("..."), None);
+    }
+
+    #[test]
+    fn plurals_become_singular() {
+        for (plural, singular) in [
+            ("dogs", "dog"),
+            ("skies", "sky"),
+            ("beaches", "beach"),
+            ("boxes", "box"),
+            ("glasses", "glasses"),
+            ("grass", "grass"),
+            ("cactus", "cactus"),
+            ("people", "person"),
+            ("leaves", "leaf"),
+            ("sheep", "sheep"),
+            ("bus", "bus"),
+            ("sky", "sky"),
+            ("movies", "movie"),
+            ("cookies", "cookie"),
+            ("ties", "tie"),
+            ("cities", "city"),
+            ("buses", "bus"),
+            ("lenses", "lens"),
+            ("houses", "house"),
+            ("horses", "horse"),
+            ("dresses", "dress"),
+            ("sunglasses", "sunglasses"),
+            ("foxes", "fox"),
+            ("taxes", "tax"),
+        ] {
+            assert_eq!(singularize(plural), singular, "{}", plural);
+        }
+    }
+
+    #[test]
+    fn synonyms_and_blocklist_are_applied() {
+        let rules = TagRules {
+            synonyms: BTreeMap::from([("puppy".to_string(), "dog".to_string())]),
+            blocklist: strings(&["Image"]),
+            ..TagRules::default()
+        };
+        assert_eq!(
+            rules.apply(&strings(&["Puppies", "dog", "image", "grass"])),
+            ["dog", "grass"]
+        );
+    }
+
+    #[test]
+    fn vocabulary_maps_or_constrains() {
+        let mut rules = TagRules {
+            vocabulary: strings(&["sky", "dog"]),
+            vocabulary_mode: VocabularyMode::Map,
+            ..TagRules::default()
+        };
+        let tags = strings(&["blue sky", "Dogs", "tree"]);
+        assert_eq!(rules.apply(&tags), ["sky", "dog", "tree"]);
+
+        rules.vocabulary_mode = VocabularyMode::Strict;
+        assert_eq!(rules.apply(&tags), ["sky", "dog"]);
+    }
+
+    #[test]
+    fn library_wide_merge_rename_and_delete() {
+        let catalog = Catalog::open_in_memory().unwrap();
+        add_images(&catalog, &["a", "b"]);
+        catalog.set_tags("a", &strings(&["puppy", "dog"])).unwrap();
+        catalog.set_tags("b", &strings(&["puppy", "lawn"])).unwrap();
+
+        let rules = TagRules::default();
+        assert_eq!(
+            merge(&catalog, &rules, &strings(&["puppy"]), "dog").unwrap(),
+            1
+        );
+        assert_eq!(rename(&catalog, &rules, "lawn", "grass").unwrap(), 1);
+        assert!(rename(&catalog, &rules, "missing", "x").is_err());
+
+        let names = |c: &Catalog| -> Vec<(String, u32)> {
+            counts(c)
+                .unwrap()
+                .into_iter()
+                .map(|t| (t.name, t.images))
+                .collect()
+        };
+        assert_eq!(
+            names(&catalog),
+            [("dog".to_string(), 2), ("grass".to_string(), 1)]
+        );
+
+        assert_eq!(delete(&catalog, &rules, "dog").unwrap(), 2);
+        assert_eq!(names(&catalog), [("grass".to_string(), 1)]);
+    }
+
+    #[test]
+    fn typed_names_find_their_stored_tag() {
+        let catalog = Catalog::open_in_memory().unwrap();
+        add_images(&catalog, &["a"]);
+        catalog
+            .set_tags("a", &strings(&["sky", "dog", "dogs"]))
+            .unwrap();
+        let rules = TagRules {
+            synonyms: BTreeMap::from([("hound".to_string(), "dog".to_string())]),
+            ..TagRules::default()
+        };
+
+        // A tag stored as typed wins over the normalized form
+        assert_eq!(delete(&catalog, &rules, "Dogs").unwrap(), 1);
+        assert_eq!(rename(&catalog, &rules, "Skies", "Clouds").unwrap(), 1);
+        assert_eq!(delete(&catalog, &rules, "Hounds").unwrap(), 1);
+        let image = catalog.images_by_ids(&strings(&["a"])).unwrap().remove(0);
+        assert_eq!(image.tags, ["cloud"]);
+    }
+
+    #[test]
+    fn merged_keywords_stay_the_files_own() {
+        let catalog = Catalog::open_in_memory().unwrap();
+        add_images(&catalog, &["a"]);
+        catalog
+            .restore_tags("a", &strings(&["puppy", "lawn"]), &strings(&["puppy"]), &[])
+            .unwrap();
+
+        merge(&catalog, &TagRules::default(), &strings(&["puppy"]), "dog").unwrap();
+        let image = catalog.images_by_ids(&strings(&["a"])).unwrap().remove(0);
+        assert_eq!(image.file_tags, ["dog"]);
+    }
+}
@@ -0,0 +1,65 @@
+//! Fixtures shared by the unit tests.
+
+use rusqlite::params;
+use std::fs;
+use std::path::PathBuf;
+
+use crate::catalog::Catalog;
+
+/// An empty folder for one test, named after it and the process so runs
+/// don't trip over each other. Left over from an earlier run, it is emptied
+/// first; the test removes it when done.
+pub fn temp_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("{}_{}", name, std::process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+/// The columns of an image record a test cares about. The record sits
+/// under `/lib` and is named after the last part of `relative_path`.
+#[derive(Default)]
+pub struct ImageRow<'a> {
+    pub id: &'a str,
+    pub relative_path: &'a str,
+    pub description: &'a str,
+    pub width: Option<u32>,
+    pub height: Option<u32>,
+    pub size: Option<u64>,
+}
+
+/// Inserts an image record directly, with no file behind it.
+pub fn add_image(catalog: &Catalog, image: ImageRow) {
+    let name = image.relative_path.rsplit('/').next().unwrap();
+    catalog
+        .conn()
+        .execute(
+            "INSERT INTO images (id, path, relative_path, name, description, width, height, size)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)",
+            params![
+                image.id,
+                format!("/lib/{}", image.relative_path),
+                image.relative_path,
+                name,
+                image.description,
+                image.width,
+                image.height,
+                image.size
+            ],
+        )
+        .unwrap();
+}
+
+/// Inserts bare image records, each at a path named after its id.
+pub fn add_images(catalog: &Catalog, ids: &[&str]) {
+    for id in ids {
+        add_image(
+            catalog,
+            ImageRow {
+                id,
+                relative_path: id,
+                ..ImageRow::default()
+            },
+        );
+    }
+}
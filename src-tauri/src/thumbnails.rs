@@ -0,0 +1,462 @@
+use percent_encoding::percent_decode_str;
+use std::collections::{BTreeMap, HashMap};
+use std::fs::{self, File};
+use std::panic::{self, AssertUnwindSafe};
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::thread;
+use std::time::SystemTime;
+use tauri::http::{header, Request, Response, StatusCode};
+use tauri::{AppHandle, Manager, UriSchemeResponder};
+
+use crate::catalog::{Catalog, SourceFile};
+use crate::model_image;
+use crate::settings::SettingsStore;
+
+/// URI scheme thumbnails are served under: `thumb://localhost/<image id>?size=256`.
+pub const SCHEME: &str = "thumb";
+
+/// Longest edge of each generated size, smallest first. A request gets the
+/// smallest size at least as large as it asked for.
+pub const SIZES: &[u32] = &[256, 1024];
+
+const EXTENSION: &str = "jpg";
+
+/// Called with the thumbnail's JPEG bytes, or why there are none.
+type Reply = Box<dyn FnOnce(Result<Vec<u8>, String>) + Send>;
+
+/// The file to make thumbnails of, and the name they are cached under.
+struct Source {
+    path: PathBuf,
+    key: String,
+}
+
+impl Source {
+    /// Keyed by content hash and mtime, so an edited file gets new thumbnails
+    /// and the stale ones age out of the cache.
+    fn new(image_id: &str, file: SourceFile) -> Self {
+        let key = format!(
+            "{}-{}",
+            file.content_hash.as_deref().unwrap_or(image_id),
+            file.modified.unwrap_or(0)
+        );
+        Source {
+            path: file.path.into(),
+            key,
+        }
+    }
+}
+
+/// Thumbnails on disk under the app cache dir, generated on demand by a
+/// small pool of worker threads. Each source is decoded once for all sizes.
+pub struct Thumbnails {
+    cache: Arc<Cache>,
+    jobs: Mutex<Sender<(Source, u64)>>,
+}
+
+struct Cache {
+    dir: PathBuf,
+    index: Mutex<CacheIndex>,
+    /// Requests for sources being generated, by key, with the size each asked for.
+    waiting: Mutex<HashMap<String, Vec<(u32, Reply)>>>,
+}
+
+impl Thumbnails {
+    /// Opens the cache in `dir`, trimming it to `limit` bytes, and starts the workers.
+    pub fn open(dir: PathBuf, limit: u64) -> Result<Self, String> {
+        fs::create_dir_all(&dir).map_err(|e| format!("Failed to create thumbnail cache: {}", e))?;
+        let cache = Arc::new(Cache {
+            index: Mutex::new(CacheIndex::load(&dir)?),
+            dir,
+            waiting: Mutex::new(HashMap::new()),
+        });
+        cache.evict(limit);
+
+        let (sender, receiver) = mpsc::channel();
+        let receiver = Arc::new(Mutex::new(receiver));
+        let workers = thread::available_parallelism()
+            .map(|n| n.get() / 2)
+            .unwrap_or(1)
+            .clamp(1, 4);
+        for n in 0..workers {
+            let cache = cache.clone();
+            let receiver = receiver.clone();
+            thread::Builder::new()
+                .name(format!("thumbnails-{}", n))
+                .spawn(move || work(&cache, &receiver))
+                .map_err(|e| format!("Failed to start thumbnail worker: {}", e))?;
+        }
+
+        Ok(Thumbnails {
+            cache,
+            jobs: Mutex::new(sender),
+        })
+    }
+
+    /// Replies with the thumbnail of `source` closest to `size`, from the
+    /// cache or once it has been generated. The cache is then trimmed to
+    /// `limit` bytes, least recently used first.
+    fn request(&self, source: Source, size: u32, limit: u64, reply: Reply) {
+        let size = fit_size(size);
+        let name = file_name(&source.key, size);
+        if self.cache.index().touch(&name) {
+            let cache = self.cache.clone();
+            tauri::async_runtime::spawn_blocking(move || reply(cache.read(&name)));
+            return;
+        }
+
+        let mut waiting = self.cache.waiting();
+        let waiters = waiting.entry(source.key.clone()).or_default();
+        waiters.push((size, reply));
+        if waiters.len() > 1 {
+            // Already being generated
+            return;
+        }
+        let key = source.key.clone();
+        let sent = self
+            .jobs
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .send((source, limit));
+        if sent.is_err() {
+            for (_, reply) in waiting.remove(&key).unwrap_or_default() {
+                reply(Err("Thumbnail workers have stopped".to_string()));
+            }
+        }
+    }
+
+    /// Deletes every cached thumbnail and returns how many bytes were freed.
+    pub fn clear(&self) -> Result<u64, String> {
+        let mut index = self.cache.index();
+        let freed = index.total;
+        for entry in fs::read_dir(&self.cache.dir)
+            .map_err(|e| format!("Failed to read thumbnail cache: {}", e))?
+            .flatten()
+        {
+            let path = entry.path();
+            if path.is_file() {
+                fs::remove_file(&path)
+                    .map_err(|e| format!("Failed to delete {}: {}", path.display(), e))?;
+            }
+        }
+        *index = CacheIndex::default();
+        Ok(freed)
+    }
+}
+
+impl Cache {
+    fn index(&self) -> MutexGuard<'_, CacheIndex> {
+        self.index.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    fn waiting(&self) -> MutexGuard<'_, HashMap<String, Vec<(u32, Reply)>>> {
+        self.waiting.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Reads a cached thumbnail, bumping its mtime so the eviction order
+    /// survives restarts.
+    fn read(&self, name: &str) -> Result<Vec<u8>, String> {
+        let path = self.dir.join(name);
+        match fs::read(&path) {
+            Ok(bytes) => {
+                let _ = File::options()
+                    .write(true)
+                    .open(&path)
+                    .and_then(|f| f.set_modified(SystemTime::now()));
+                Ok(bytes)
+            }
+            Err(e) => {
+                // Deleted behind our back; the next request regenerates it
+                self.index().remove(name);
+                Err(format!("Failed to read thumbnail: {}", e))
+            }
+        }
+    }
+
+    /// Generates every size of `source` from a single decode, largest first,
+    /// and returns the JPEG bytes by size.
+    fn generate(&self, source: &Source) -> Result<HashMap<u32, Vec<u8>>, String> {
+        let largest = SIZES[SIZES.len() - 1];
+        let mut image = model_image::load(&source.path, largest)?;
+
+        let mut generated = HashMap::new();
+        for &size in SIZES.iter().rev() {
+            image = model_image::shrink(image, size);
+            let jpeg = model_image::encode_jpeg(&image)?;
+
+            let name = file_name(&source.key, size);
+            let path = self.dir.join(&name);
+            let tmp = path.with_extension("tmp");
+            fs::write(&tmp, &jpeg).map_err(|e| format!("Failed to write thumbnail: {}", e))?;
+            fs::rename(&tmp, &path).map_err(|e| format!("Failed to save thumbnail: {}", e))?;
+            self.index().insert(name, jpeg.len() as u64);
+
+            generated.insert(size, jpeg);
+        }
+        Ok(generated)
+    }
+
+    fn evict(&self, limit: u64) {
+        let evicted = self.index().evict(limit);
+        for name in evicted {
+            if let Err(e) = fs::remove_file(self.dir.join(&name)) {
+                log::warn!("Failed to evict thumbnail {}: {}", name, e);
+            }
+        }
+    }
+}
+
+fn work(cache: &Cache, jobs: &Mutex<Receiver<(Source, u64)>>) {
+    loop {
+        // Hold the lock only while waiting, not while generating
+        let job = jobs.lock().unwrap_or_else(|e| e.into_inner()).recv();
+        let Ok((source, limit)) = job else {
+            return;
+        };
+
+        // A decoder panic must not leave the waiters hanging or kill the worker
+        let generated = panic::catch_unwind(AssertUnwindSafe(|| cache.generate(&source)))
+            .unwrap_or_else(|_| Err("Thumbnail generation panicked".to_string()));
+        if let Err(e) = &generated {
+            log::warn!("No thumbnail for {}: {}", source.path.display(), e);
+        }
+        cache.evict(limit);
+
+        let waiters = cache.waiting().remove(&source.key).unwrap_or_default();
+        for (size, reply) in waiters {
+            reply(match &generated {
+                Ok(generated) => Ok(generated[&size].clone()),
+                Err(e) => Err(e.clone()),
+            });
+        }
+    }
+}
+
+fn fit_size(requested: u32) -> u32 {
+    SIZES
+        .iter()
+        .copied()
+        .find(|&size| size >= requested)
+        .unwrap_or(SIZES[SIZES.len() - 1])
+}
+
+fn file_name(key: &str, size: u32) -> String {
+    format!("{}-{}.{}", key, size, EXTENSION)
+}
+
+/// Sizes and use order of the cached files. Use is tracked with a counter
+/// rather than timestamps so the order is exact within a session.
+#[derive(Default)]
+struct CacheIndex {
+    /// Size in bytes and last use, by file name.
+    entries: HashMap<String, (u64, u64)>,
+    /// File names by last use, oldest first.
+    by_use: BTreeMap<u64, String>,
+    total: u64,
+    clock: u64,
+}
+
+impl CacheIndex {
+    /// Indexes the thumbnails already in `dir`, ordered by mtime. Files left
+    /// half-written by a crash are removed.
+    fn load(dir: &Path) -> Result<Self, String> {
+        let mut files = Vec::new();
+        for entry in fs::read_dir(dir)
+            .map_err(|e| format!("Failed to read thumbnail cache: {}", e))?
+            .flatten()
+        {
+            let path = entry.path();
+            if path.extension().is_some_and(|ext| ext == "tmp") {
+                let _ = fs::remove_file(&path);
+                continue;
+            }
+            let Ok(metadata) = entry.metadata() else {
+                continue;
+            };
+            if metadata.is_file() {
+                let used = metadata.modified().unwrap_or(SystemTime::UNIX_EPOCH);
+                let name = entry.file_name().to_string_lossy().to_string();
+                files.push((used, name, metadata.len()));
+            }
+        }
+
+        files.sort();
+        let mut index = CacheIndex::default();
+        for (_, name, bytes) in files {
+            index.insert(name, bytes);
+        }
+        Ok(index)
+    }
+
+    fn insert(&mut self, name: String, bytes: u64) {
+        self.remove(&name);
+        self.clock += 1;
+        self.by_use.insert(self.clock, name.clone());
+        self.entries.insert(name, (bytes, self.clock));
+        self.total += bytes;
+    }
+
+    /// Marks a file as just used. Returns false if it isn't cached.
+    fn touch(&mut self, name: &str) -> bool {
+        let Some((_, used)) = self.entries.get_mut(name) else {
+            return false;
+        };
+        self.by_use.remove(used);
+        self.clock += 1;
+        *used = self.clock;
+        self.by_use.insert(self.clock, name.to_string());
+        true
+    }
+
+    fn remove(&mut self, name: &str) {
+        if let Some((bytes, used)) = self.entries.remove(name) {
+            self.by_use.remove(&used);
+            self.total -= bytes;
+        }
+    }
+
+    /// Drops least recently used entries until the total fits in `limit`
+    /// and returns their names.
+    fn evict(&mut self, limit: u64) -> Vec<String> {
+        let mut evicted = Vec::new();
+        while self.total > limit {
+            let Some((_, name)) = self.by_use.pop_first() else {
+                break;
+            };
+            if let Some((bytes, _)) = self.entries.remove(&name) {
+                self.total -= bytes;
+            }
+            evicted.push(name);
+        }
+        evicted
+    }
+}
+
+/// Handles a `thumb://` request. The path is the image id; `size` is the
+/// wanted edge length in pixels and defaults to the smallest size.
+pub fn serve(app: &AppHandle, request: Request<Vec<u8>>, responder: UriSchemeResponder) {
+    let uri = request.uri();
+    let id = percent_decode_str(uri.path().trim_start_matches('/'))
+        .decode_utf8_lossy()
+        .to_string();
+    let size = uri
+        .query()
+        .unwrap_or_default()
+        .split('&')
+        .find_map(|pair| pair.strip_prefix("size="))
+        .and_then(|size| size.parse().ok())
+        .unwrap_or(SIZES[0]);
+
+    let file = match app.state::<Catalog>().source_file(&id) {
+        Ok(Some(file)) => file,
+        Ok(None) => return respond(responder, StatusCode::NOT_FOUND, "Unknown image".into()),
+        Err(e) => return respond(responder, StatusCode::INTERNAL_SERVER_ERROR, e.into()),
+    };
+    let limit = app.state::<SettingsStore>().get().thumbnail_cache_mb * 1024 * 1024;
+
+    app.state::<Thumbnails>().request(
+        Source::new(&id, file),
+        size,
+        limit,
+        Box::new(move |result| match result {
+            Ok(jpeg) => respond(responder, StatusCode::OK, jpeg),
+            Err(e) => respond(responder, StatusCode::INTERNAL_SERVER_ERROR, e.into()),
+        }),
+    );
+}
+
+fn respond(responder: UriSchemeResponder, status: StatusCode, body: Vec<u8>) {
+    let content_type = if status.is_success() {
+        "image/jpeg"
+    } else {
+        "text/plain"
+    };
+    let response = Response::builder()
+        .status(status)
+        .header(header::CONTENT_TYPE, content_type)
+        .body(body);
+    match response {
+        Ok(response) => responder.respond(response),
+        Err(e) => log::error!("Failed to build thumbnail response: {}", e),
+    }
+}
+
+#[tauri::command]
+pub fn clear_thumbnail_cache(thumbnails: tauri::State<'_, Thumbnails>) -> Result<u64, String> {
+    thumbnails.clear()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::temp_dir;
+
+    #[test]
+    fn least_recently_used_entries_are_evicted_first() {
+        let mut index = CacheIndex::default();
+        index.insert("a".to_string(), 40);
+        index.insert("b".to_string(), 40);
+        index.insert("c".to_string(), 40);
+        assert!(index.touch("a"));
+        assert!(!index.touch("missing"));
+
+        assert_eq!(index.evict(100), ["b"]);
+        assert_eq!(index.evict(40), ["c"]);
+        assert_eq!(index.total, 40);
+        assert_eq!(index.evict(0), ["a"]);
+        assert!(index.entries.is_empty() && index.by_use.is_empty());
+    }
+
+    #[test]
+    fn requests_get_the_nearest_size() {
+        assert_eq!(fit_size(100), 256);
+        assert_eq!(fit_size(256), 256);
+        assert_eq!(fit_size(300), 1024);
+        assert_eq!(fit_size(4000), 1024);
+    }
+
+    #[test]
+    fn every_size_is_generated_and_cached() {
+        let dir = temp_dir("thumbnails_generate");
+        let original = dir.join("wide.png");
+        image::RgbImage::new(2000, 1000).save(&original).unwrap();
+
+        let thumbnails = Thumbnails::open(dir.join("cache"), u64::MAX).unwrap();
+        let source = || Source {
+            path: original.clone(),
+            key: "abc-1".to_string(),
+        };
+        let (sender, receiver) = mpsc::channel();
+        for size in [256, 1024, 200] {
+            let sender = sender.clone();
+            thumbnails.request(
+                source(),
+                size,
+                u64::MAX,
+                Box::new(move |result| sender.send(result).unwrap()),
+            );
+        }
+
+        let mut sizes: Vec<(u32, u32)> = (0..3)
+            .map(|_| {
+                let jpeg = receiver.recv().unwrap().unwrap();
+                let image = image::load_from_memory(&jpeg).unwrap();
+                (image.width(), image.height())
+            })
+            .collect();
+        sizes.sort();
+        assert_eq!(sizes, [(256, 128), (256, 128), (1024, 512)]);
+        assert!(dir.join("cache/abc-1-256.jpg").is_file());
+        assert!(dir.join("cache/abc-1-1024.jpg").is_file());
+
+        // A new cache over the same dir picks the files up, and clearing removes them
+        drop(thumbnails);
+        let reopened = Thumbnails::open(dir.join("cache"), u64::MAX).unwrap();
+        assert_eq!(reopened.cache.index().entries.len(), 2);
+        assert!(reopened.clear().unwrap() > 0);
+        assert_eq!(fs::read_dir(dir.join("cache")).unwrap().count(), 0);
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,297 @@
+use percent_encoding::{utf8_percent_encode, AsciiSet, CONTROLS};
+use serde::{Deserialize, Serialize};
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+use crate::catalog::now_secs;
+
+/// Characters escaped in a `.trashinfo` path: what URLs escape in a path,
+/// but not the slashes.
+const PATH_ESCAPES: &AsciiSet = &CONTROLS
+    .add(b' ')
+    .add(b'"')
+    .add(b'#')
+    .add(b'%')
+    .add(b'<')
+    .add(b'>')
+    .add(b'?')
+    .add(b'[')
+    .add(b']')
+    .add(b'\\')
+    .add(b'^')
+    .add(b'`')
+    .add(b'{')
+    .add(b'|')
+    .add(b'}');
+
+/// The trash as the freedesktop.org Trash specification lays it out, so
+/// file managers list what we put there and can restore it.
+pub struct Trash {
+    /// `$XDG_DATA_HOME/Trash`, for files on the same drive as the home folder.
+    home: PathBuf,
+}
+
+/// Where a file went in the trash.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct Trashed {
+    /// The file itself, under the trash's `files` folder.
+    pub file: PathBuf,
+    /// Its `.trashinfo`, which records where it came from.
+    pub info: PathBuf,
+}
+
+impl Trash {
+    /// The current user's trash.
+    pub fn home() -> Result<Self, String> {
+        let data = match std::env::var_os("XDG_DATA_HOME").filter(|dir| !dir.is_empty()) {
+            Some(dir) => PathBuf::from(dir),
+            None => std::env::var_os("HOME")
+                .map(|home| PathBuf::from(home).join(".local/share"))
+                .ok_or("Can't find the trash: HOME is not set")?,
+        };
+        Ok(Trash {
+            home: data.join("Trash"),
+        })
+    }
+
+    #[cfg(test)]
+    pub fn at(home: PathBuf) -> Self {
+        Trash { home }
+    }
+
+    /// Moves `path` to the trash: the home trash when it is on the same
+    /// drive, otherwise the drive's own, so nothing is copied.
+    pub fn put(&self, path: &Path) -> Result<Trashed, String> {
+        let failed = |e: std::io::Error| format!("Failed to trash {}: {}", path.display(), e);
+
+        let path = absolute(path).map_err(failed)?;
+        create_trash(&self.home).map_err(failed)?;
+        let (trash, recorded) = self.trash_for(&path).map_err(failed)?;
+        let (file, info) = claim_name(&trash, &path, &recorded).map_err(failed)?;
+
+        if let Err(e) = fs::rename(&path, &file) {
+            let _ = fs::remove_file(&info);
+            return Err(failed(e));
+        }
+        Ok(Trashed { file, info })
+    }
+
+    /// The trash folder for `path`, and the path as its `.trashinfo`
+    /// should record it.
+    #[cfg(unix)]
+    fn trash_for(&self, path: &Path) -> std::io::Result<(PathBuf, String)> {
+        use std::os::unix::fs::MetadataExt;
+
+        let device = fs::symlink_metadata(path)?.dev();
+        let home = fs::metadata(&self.home)?;
+        if home.dev() == device {
+            return Ok((self.home.clone(), path.to_string_lossy().to_string()));
+        }
+
+        // Top of the file's drive: the last ancestor on the same device
+        let mut top = path.parent().unwrap_or(path).to_path_buf();
+        while let Some(parent) = top.parent() {
+            if fs::metadata(parent)?.dev() != device {
+                break;
+            }
+            top = parent.to_path_buf();
+        }
+        // The home trash is ours, so its owner is us
+        let uid = home.uid();
+
+        // An admin-made `.Trash` only counts if it is a sticky folder, not a link
+        let shared = top.join(".Trash");
+        let trash = match fs::symlink_metadata(&shared) {
+            Ok(meta) if meta.is_dir() && meta.mode() & 0o1000 != 0 => shared.join(uid.to_string()),
+            _ => top.join(format!(".Trash-{}", uid)),
+        };
+        create_trash(&trash)?;
+
+        // Paths in a drive's trash are relative to the top of the drive
+        let relative = path.strip_prefix(&top).unwrap_or(path);
+        Ok((trash, relative.to_string_lossy().to_string()))
+    }
+
+    #[cfg(not(unix))]
+    fn trash_for(&self, _path: &Path) -> std::io::Result<(PathBuf, String)> {
+        Err(std::io::Error::new(
+            std::io::ErrorKind::Unsupported,
+            "this system has no freedesktop.org trash",
+        ))
+    }
+}
+
+impl Trashed {
+    /// Moves the file back out of the trash to `to`, which must be free.
+    pub fn restore(&self, to: &Path) -> Result<(), String> {
+        if fs::symlink_metadata(to).is_ok() {
+            return Err(format!("{} is taken by another file now", to.display()));
+        }
+        if fs::symlink_metadata(&self.file).is_err() {
+            return Err(format!("{} is no longer in the trash", to.display()));
+        }
+        let failed = |e: std::io::Error| format!("Failed to restore {}: {}", to.display(), e);
+        if let Some(folder) = to.parent() {
+            fs::create_dir_all(folder).map_err(failed)?;
+        }
+        fs::rename(&self.file, to).map_err(failed)?;
+        let _ = fs::remove_file(&self.info);
+        Ok(())
+    }
+}
+
+/// `path` made absolute with its folder resolved, but not the file itself,
+/// which may be a link to trash rather than follow.
+fn absolute(path: &Path) -> std::io::Result<PathBuf> {
+    match (path.parent(), path.file_name()) {
+        (Some(parent), Some(name)) if !parent.as_os_str().is_empty() => {
+            Ok(fs::canonicalize(parent)?.join(name))
+        }
+        _ => Ok(std::env::current_dir()?.join(path)),
+    }
+}
+
+fn create_trash(trash: &Path) -> std::io::Result<()> {
+    for folder in ["files", "info"] {
+        let mut builder = fs::DirBuilder::new();
+        builder.recursive(true);
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::DirBuilderExt;
+            builder.mode(0o700);
+        }
+        builder.create(trash.join(folder))?;
+    }
+    Ok(())
+}
+
+/// Picks a name nothing else in `trash` has, `beach.jpg` then `beach.2.jpg`
+/// and so on, by creating its `.trashinfo` exclusively. Returns where the
+/// file goes and the info file.
+fn claim_name(trash: &Path, path: &Path, recorded: &str) -> std::io::Result<(PathBuf, PathBuf)> {
+    let name = path
+        .file_name()
+        .map(|name| name.to_string_lossy().to_string())
+        .unwrap_or_else(|| "file".to_string());
+    let (stem, extension) = match name.rsplit_once('.') {
+        Some((stem, extension)) if !stem.is_empty() => (stem, Some(extension)),
+        _ => (name.as_str(), None),
+    };
+
+    let mut n = 1;
+    loop {
+        let candidate = match (n, extension) {
+            (1, _) => name.clone(),
+            (n, Some(extension)) => format!("{}.{}.{}", stem, n, extension),
+            (n, None) => format!("{}.{}", stem, n),
+        };
+        n += 1;
+
+        let file = trash.join("files").join(&candidate);
+        if file.exists() {
+            continue;
+        }
+        let info = trash.join("info").join(format!("{}.trashinfo", candidate));
+        match OpenOptions::new().write(true).create_new(true).open(&info) {
+            Ok(mut out) => {
+                write!(
+                    out,
+                    "[Trash Info]\nPath={}\nDeletionDate={}\n",
+                    utf8_percent_encode(recorded, PATH_ESCAPES),
+                    deletion_date(now_secs())
+                )?;
+                return Ok((file, info));
+            }
+            Err(e) if e.kind() == std::io::ErrorKind::AlreadyExists => continue,
+            Err(e) => return Err(e),
+        }
+    }
+}
+
+/// `YYYY-MM-DDThh:mm:ss` for seconds since the epoch. The specification
+/// wants local time but has no zone, and file managers only display it, so
+/// this is UTC rather than guessing the zone without a time library.
+fn deletion_date(secs: i64) -> String {
+    let (days, time) = (secs.div_euclid(86_400), secs.rem_euclid(86_400));
+    let (year, month, day) = civil_from_days(days);
+    format!(
+        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}",
+        year,
+        month,
+        day,
+        time / 3600,
+        time % 3600 / 60,
+        time % 60
+    )
+}
+
+/// Proleptic Gregorian date of a day since 1970-01-01; the inverse of
+/// `search::days_from_civil`.
+fn civil_from_days(days: i64) -> (i64, i64, i64) {
+    let days = days + 719_468;
+    let era = days.div_euclid(146_097);
+    let day_of_era = days - era * 146_097;
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let mp = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = year_of_era + era * 400 + i64::from(month <= 2);
+    (year, month, day)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::search::days_from_civil;
+    use crate::test_support::temp_dir;
+
+    #[test]
+    fn trashed_files_get_info_and_unique_names() {
+        let dir = temp_dir("trash");
+        fs::create_dir(dir.join("photos")).unwrap();
+        let trash = Trash::at(dir.join("Trash"));
+
+        let photo = dir.join("photos/my beach.jpg");
+        fs::write(&photo, b"first").unwrap();
+        let first = trash.put(&photo).unwrap();
+        fs::write(&photo, b"second").unwrap();
+        let second = trash.put(&photo).unwrap();
+
+        assert!(!photo.exists());
+        assert_eq!(first.file, dir.join("Trash/files/my beach.jpg"));
+        assert_eq!(second.file, dir.join("Trash/files/my beach.2.jpg"));
+        assert_eq!(fs::read(&second.file).unwrap(), b"second");
+        let info = fs::read_to_string(&first.info).unwrap();
+        let canonical = fs::canonicalize(dir.join("photos")).unwrap();
+        assert!(info.starts_with("[Trash Info]\n"));
+        assert!(info.contains(&format!(
+            "Path={}/my%20beach.jpg\n",
+            canonical.to_string_lossy()
+        )));
+        assert!(info.contains("DeletionDate=20"));
+
+        first.restore(&photo).unwrap();
+        assert_eq!(fs::read(&photo).unwrap(), b"first");
+        assert!(!first.info.exists());
+        assert!(second.restore(&photo).is_err());
+        assert!(second.file.exists());
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn deletion_dates_round_trip_the_calendar() {
+        for (year, month, day) in [(1970, 1, 1), (2000, 2, 29), (2024, 12, 31), (1969, 7, 20)] {
+            assert_eq!(
+                civil_from_days(days_from_civil(year, month, day)),
+                (year, month, day)
+            );
+        }
+        let secs = days_from_civil(2026, 3, 9) * 86_400 + 13 * 3600 + 5 * 60 + 7;
+        assert_eq!(deletion_date(secs), "2026-03-09T13:05:07");
+    }
+}
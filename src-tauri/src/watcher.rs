@@ -0,0 +1,480 @@
+use notify_debouncer_full::notify::{EventKind, RecursiveMode};
+use notify_debouncer_full::{new_debouncer, DebounceEventResult, DebouncedEvent};
+use serde::Serialize;
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, RecvTimeoutError};
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+use tauri::{AppHandle, Emitter, Manager};
+
+use crate::catalog::{Catalog, Upserted};
+use crate::scan::{
+    self, Filters, ImageInfo, Inspection, ScanIssue, ScanOptions, ScannedFile, Siblings,
+};
+
+/// Quiet period before a burst of events (e.g. a camera import) is processed as one batch.
+const DEBOUNCE: Duration = Duration::from_secs(2);
+/// How often an unmounted root is checked for coming back.
+const REMOUNT_POLL: Duration = Duration::from_secs(5);
+
+/// One watcher thread per library root, keyed by root path.
+#[derive(Default)]
+pub struct Watchers {
+    active: Mutex<HashMap<PathBuf, Watch>>,
+}
+
+/// A root's watcher thread. A stopped one stays listed until it is joined,
+/// which happens before the root is watched again so two threads never
+/// watch the same root.
+struct Watch {
+    stop: Arc<AtomicBool>,
+    thread: JoinHandle<()>,
+}
+
+impl Watch {
+    fn stopped(&self) -> bool {
+        self.stop.load(Ordering::Relaxed)
+    }
+}
+
+#[derive(Serialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct LibraryChanges {
+    root: String,
+    created: Vec<ImageInfo>,
+    modified: Vec<ImageInfo>,
+    renamed: Vec<RenamedImage>,
+    deleted: Vec<String>,
+}
+
+#[derive(Serialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct RenamedImage {
+    from: String,
+    image: ImageInfo,
+}
+
+#[derive(Serialize, Clone)]
+pub struct WatchStatus {
+    root: String,
+    online: bool,
+}
+
+#[derive(Serialize, Clone)]
+pub struct RootRescanned {
+    root: String,
+    images: Vec<ImageInfo>,
+}
+
+impl Watchers {
+    /// Starts watching `root` unless it is already watched.
+    pub fn start(&self, app: &AppHandle, root: PathBuf) -> Result<(), String> {
+        let mut active = self.active.lock().unwrap_or_else(|e| e.into_inner());
+        match active.get(&root) {
+            Some(watch) if !watch.stopped() => return Ok(()),
+            // Stopped, but the thread may still be finishing its last poll
+            Some(_) => {
+                let watch = active.remove(&root).expect("watch is listed");
+                if watch.thread.join().is_err() {
+                    log::warn!("Watcher for {} panicked", root.display());
+                }
+            }
+            None => {}
+        }
+
+        let stop = Arc::new(AtomicBool::new(false));
+        let app = app.clone();
+        let thread_root = root.clone();
+        let thread_stop = stop.clone();
+        let thread = thread::Builder::new()
+            .name(format!("watch {}", root.display()))
+            .spawn(move || supervise(app, thread_root, thread_stop))
+            .map_err(|e| format!("Failed to start watcher: {}", e))?;
+
+        active.insert(root, Watch { stop, thread });
+        Ok(())
+    }
+
+    /// Stops watching `root`. Returns false if it wasn't being watched.
+    pub fn stop(&self, root: &Path) -> bool {
+        let active = self.active.lock().unwrap_or_else(|e| e.into_inner());
+        match active.get(root) {
+            Some(watch) if !watch.stopped() => {
+                watch.stop.store(true, Ordering::Relaxed);
+                true
+            }
+            _ => false,
+        }
+    }
+
+    pub fn watched(&self) -> Vec<String> {
+        let active = self.active.lock().unwrap_or_else(|e| e.into_inner());
+        active
+            .iter()
+            .filter(|(_, watch)| !watch.stopped())
+            .map(|(p, _)| p.to_string_lossy().to_string())
+            .collect()
+    }
+}
+
+/// Keeps a watch on `root` alive until stopped. When the folder disappears
+/// (unmounted drive, disconnected share) it waits for it to come back and
+/// rescans to catch up on anything that changed in the meantime.
+fn supervise(app: AppHandle, root: PathBuf, stop: Arc<AtomicBool>) {
+    let mut online = true;
+
+    while !stop.load(Ordering::Relaxed) {
+        if !root.is_dir() {
+            if online {
+                log::info!("{} went offline", root.display());
+                emit_status(&app, &root, false);
+                online = false;
+            }
+            thread::sleep(REMOUNT_POLL);
+            continue;
+        }
+
+        if !online {
+            log::info!("{} is back, rescanning", root.display());
+            emit_status(&app, &root, true);
+            online = true;
+            rescan(&app, &root);
+        }
+
+        if let Err(e) = watch(&app, &root, &stop) {
+            log::warn!("Watcher for {} failed: {}", root.display(), e);
+            thread::sleep(REMOUNT_POLL);
+        }
+    }
+}
+
+/// Runs one watch session. Returns `Ok` when stopped or when the root vanished.
+fn watch(app: &AppHandle, root: &Path, stop: &AtomicBool) -> Result<(), String> {
+    let (tx, rx) = mpsc::channel::<DebounceEventResult>();
+    let mut debouncer = new_debouncer(DEBOUNCE, None, tx).map_err(|e| e.to_string())?;
+    debouncer
+        .watch(root, RecursiveMode::Recursive)
+        .map_err(|e| e.to_string())?;
+
+    loop {
+        if stop.load(Ordering::Relaxed) {
+            return Ok(());
+        }
+
+        match rx.recv_timeout(REMOUNT_POLL) {
+            Ok(Ok(events)) => {
+                if let Err(e) = handle_events(app, root, &events) {
+                    log::warn!("Failed to apply changes in {}: {}", root.display(), e);
+                }
+            }
+            Ok(Err(errors)) => {
+                for e in errors {
+                    log::warn!("Watch error in {}: {}", root.display(), e);
+                }
+            }
+            Err(RecvTimeoutError::Timeout) => {}
+            Err(RecvTimeoutError::Disconnected) => {
+                return Err("event channel closed".to_string());
+            }
+        }
+
+        // The watch dies with the mount; let the supervisor wait for it to return
+        if !root.is_dir() {
+            return Ok(());
+        }
+    }
+}
+
+fn handle_events(app: &AppHandle, root: &Path, events: &[DebouncedEvent]) -> Result<(), String> {
+    let touched: Vec<&Path> = events
+        .iter()
+        .filter(|event| !matches!(event.kind, EventKind::Access(_)))
+        .flat_map(|event| event.paths.iter())
+        .map(PathBuf::as_path)
+        .collect();
+
+    if let Some(changes) = apply_events(&app.state::<Catalog>(), root, &touched)? {
+        let _ = app.emit("watcher://changes", changes);
+    }
+    Ok(())
+}
+
+/// Brings the catalog up to date with a debounced burst of changes to
+/// `touched` paths under `root`: what exists now is inspected, what doesn't
+/// is removed. Returns what changed, or `None` if nothing did.
+fn apply_events(
+    catalog: &Catalog,
+    root: &Path,
+    touched: &[&Path],
+) -> Result<Option<LibraryChanges>, String> {
+    let Some(options) = catalog.root_options(root)? else {
+        return Ok(None);
+    };
+    let filters = Filters::new(&options)?;
+
+    let touched: HashSet<&Path> = touched
+        .iter()
+        .copied()
+        .filter(|path| path.starts_with(root) && *path != root)
+        .collect();
+
+    // Removals during an unmount would wipe the library; the rescan on remount handles it
+    if touched.is_empty() || !root.is_dir() {
+        return Ok(None);
+    }
+
+    let known = catalog.known_files()?;
+    let mut pending: HashSet<PathBuf> = HashSet::new();
+    let mut removed: Vec<String> = Vec::new();
+
+    for path in touched {
+        if path.is_dir() {
+            // A folder moved or copied in: pick up everything beneath it
+            let walk = ScanOptions {
+                recursive: true,
+                follow_symlinks: options.follow_symlinks,
+                ..ScanOptions::default()
+            };
+            for file_path in scan::collect_candidate_paths(path, &walk)? {
+                if filters.accepts_file(root, &file_path) {
+                    pending.insert(file_path);
+                }
+            }
+        } else if path.is_file() {
+            if filters.accepts_file(root, path) {
+                pending.insert(path.to_path_buf());
+            }
+        } else {
+            removed.push(path.to_string_lossy().to_string());
+        }
+    }
+
+    // Either half of a RAW+JPEG pair coming or going changes the other
+    let mut siblings = Siblings::new(root, filters);
+    let partners: Vec<PathBuf> = pending
+        .iter()
+        .cloned()
+        .chain(removed.iter().map(PathBuf::from))
+        .flat_map(|path| siblings.partners(&path))
+        .collect();
+    pending.extend(partners);
+
+    let mut files: Vec<ScannedFile> = Vec::new();
+    let mut rejected: Vec<ScanIssue> = Vec::new();
+    for path in &pending {
+        match scan::inspect_file(root, path, &known, &mut siblings) {
+            Ok(Inspection::Image(file)) => files.push(*file),
+            Ok(Inspection::Rejected(issue)) => rejected.push(issue),
+            Ok(Inspection::Paired | Inspection::Other) => {}
+            // Usually a file that vanished again before we got to it
+            Err(e) => log::debug!("Skipping {}: {}", path.display(), e),
+        }
+    }
+
+    let applied = catalog.apply_changes(root, &files, &removed)?;
+    catalog.record_rejected(root, &rejected)?;
+
+    let mut changes = LibraryChanges {
+        root: root.to_string_lossy().to_string(),
+        created: Vec::new(),
+        modified: Vec::new(),
+        renamed: Vec::new(),
+        deleted: applied.deleted,
+    };
+
+    let ids: Vec<String> = applied.upserted.iter().map(|(id, _)| id.clone()).collect();
+    let images: HashMap<String, ImageInfo> = catalog
+        .images_by_ids(&ids)?
+        .into_iter()
+        .map(|image| (image.id.clone(), image))
+        .collect();
+
+    for (id, change) in applied.upserted {
+        let Some(image) = images.get(&id).cloned() else {
+            continue;
+        };
+        match change {
+            Upserted::New => changes.created.push(image),
+            Upserted::Modified => changes.modified.push(image),
+            Upserted::Moved(from) => changes.renamed.push(RenamedImage { from, image }),
+            Upserted::Unchanged => {}
+        }
+    }
+
+    let is_empty = changes.created.is_empty()
+        && changes.modified.is_empty()
+        && changes.renamed.is_empty()
+        && changes.deleted.is_empty();
+    Ok((!is_empty).then_some(changes))
+}
+
+fn rescan(app: &AppHandle, root: &Path) {
+    let catalog = app.state::<Catalog>();
+    let result = catalog
+        .root_options(root)
+        .and_then(|options| scan::scan_root(&catalog, root, &options.unwrap_or_default()));
+
+    match result {
+        Ok(images) => {
+            let _ = app.emit(
+                "watcher://rescanned",
+                RootRescanned {
+                    root: root.to_string_lossy().to_string(),
+                    images,
+                },
+            );
+        }
+        Err(e) => log::warn!("Rescan of {} failed: {}", root.display(), e),
+    }
+}
+
+fn emit_status(app: &AppHandle, root: &Path, online: bool) {
+    let _ = app.emit(
+        "watcher://status",
+        WatchStatus {
+            root: root.to_string_lossy().to_string(),
+            online,
+        },
+    );
+}
+
+#[tauri::command]
+pub fn start_watching(
+    app: AppHandle,
+    catalog: tauri::State<'_, Catalog>,
+    watchers: tauri::State<'_, Watchers>,
+    root: String,
+) -> Result<(), String> {
+    let root = PathBuf::from(root);
+    if catalog.root_options(&root)?.is_none() {
+        return Err("Scan the folder before watching it".to_string());
+    }
+    watchers.start(&app, root)
+}
+
+#[tauri::command]
+pub fn stop_watching(watchers: tauri::State<'_, Watchers>, root: String) -> bool {
+    watchers.stop(Path::new(&root))
+}
+
+#[tauri::command]
+pub fn list_watched(watchers: tauri::State<'_, Watchers>) -> Vec<String> {
+    watchers.watched()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::temp_dir;
+    use std::fs;
+
+    fn jpeg(size: u32) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        image::RgbImage::from_fn(size, size, |x, y| image::Rgb([x as u8, y as u8, 0]))
+            .write_to(
+                &mut std::io::Cursor::new(&mut bytes),
+                image::ImageFormat::Jpeg,
+            )
+            .unwrap();
+        bytes
+    }
+
+    fn names(images: &[ImageInfo]) -> Vec<&str> {
+        images.iter().map(|image| image.name.as_str()).collect()
+    }
+
+    #[test]
+    fn events_become_created_modified_renamed_and_deleted() {
+        let root = temp_dir("watcher_events");
+        fs::write(root.join("a.jpg"), jpeg(32)).unwrap();
+
+        let catalog = Catalog::open_in_memory().unwrap();
+        let options = ScanOptions {
+            recursive: true,
+            exclude: vec!["@eaDir".to_string()],
+            ..ScanOptions::default()
+        };
+        let a = scan::scan_root(&catalog, &root, &options)
+            .unwrap()
+            .remove(0);
+        let apply = |touched: &[&str]| {
+            let paths: Vec<PathBuf> = touched.iter().map(|name| root.join(name)).collect();
+            let paths: Vec<&Path> = paths.iter().map(PathBuf::as_path).collect();
+            apply_events(&catalog, &root, &paths).unwrap()
+        };
+
+        fs::write(root.join("b.jpg"), jpeg(48)).unwrap();
+        let changes = apply(&["b.jpg"]).unwrap();
+        assert_eq!(names(&changes.created), ["b.jpg"]);
+        assert!(changes.modified.is_empty() && changes.renamed.is_empty());
+        let b = changes.created[0].id.clone();
+
+        // A rename arrives as the old path and the new one
+        fs::rename(root.join("a.jpg"), root.join("c.jpg")).unwrap();
+        let changes = apply(&["a.jpg", "c.jpg"]).unwrap();
+        assert!(changes.created.is_empty() && changes.deleted.is_empty());
+        let [renamed] = &changes.renamed[..] else {
+            panic!("one image was renamed");
+        };
+        assert_eq!(renamed.from, root.join("a.jpg").to_string_lossy());
+        assert_eq!(
+            (renamed.image.id.as_str(), renamed.image.name.as_str()),
+            (a.id.as_str(), "c.jpg")
+        );
+
+        fs::write(root.join("c.jpg"), jpeg(64)).unwrap();
+        let changes = apply(&["c.jpg"]).unwrap();
+        assert_eq!(names(&changes.modified), ["c.jpg"]);
+        assert_eq!(changes.modified[0].id, a.id);
+
+        fs::remove_file(root.join("b.jpg")).unwrap();
+        assert_eq!(apply(&["b.jpg"]).unwrap().deleted, [b]);
+
+        // A folder moved in brings everything beneath it, minus what's excluded
+        fs::create_dir_all(root.join("trip/@eaDir")).unwrap();
+        fs::write(root.join("trip/d.jpg"), jpeg(80)).unwrap();
+        fs::write(root.join("trip/@eaDir/d.jpg"), jpeg(16)).unwrap();
+        let changes = apply(&["trip"]).unwrap();
+        assert_eq!(names(&changes.created), ["d.jpg"]);
+
+        // Nothing to report: excluded, untouched, or outside the root
+        assert!(apply(&["trip/@eaDir/d.jpg"]).is_none());
+        assert!(apply(&["trip/d.jpg"]).is_none());
+        assert!(
+            apply_events(&catalog, &root, &[std::env::temp_dir().as_path()])
+                .unwrap()
+                .is_none()
+        );
+
+        // A move across folders keeps the image
+        let d = changes.created[0].id.clone();
+        fs::create_dir_all(root.join("best")).unwrap();
+        fs::rename(root.join("trip/d.jpg"), root.join("best/d.jpg")).unwrap();
+        let changes = apply(&["trip/d.jpg", "best/d.jpg"]).unwrap();
+        assert!(changes.created.is_empty() && changes.deleted.is_empty());
+        let [moved] = &changes.renamed[..] else {
+            panic!("one image was moved");
+        };
+        assert_eq!(moved.from, root.join("trip/d.jpg").to_string_lossy());
+        assert_eq!(
+            (moved.image.id.as_str(), moved.image.relative_path.as_str()),
+            (d.as_str(), "best/d.jpg")
+        );
+
+        // A folder going away takes everything beneath it, and nothing else
+        fs::write(root.join("best/e.jpg"), jpeg(96)).unwrap();
+        let e = apply(&["best/e.jpg"]).unwrap().created[0].id.clone();
+        fs::remove_dir_all(root.join("best")).unwrap();
+        let mut deleted = apply(&["best"]).unwrap().deleted;
+        deleted.sort();
+        let mut expected = vec![d, e];
+        expected.sort();
+        assert_eq!(deleted, expected);
+        assert_eq!(names(&catalog.load_library().unwrap()), ["c.jpg"]);
+
+        fs::remove_dir_all(&root).unwrap();
+    }
+}
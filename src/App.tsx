@@ -2,31 +2,249 @@ import { useState, useEffect, useCallback } from 'react'
 import { motion, AnimatePresence } from 'framer-motion'
 import { invoke } from '@tauri-apps/api/core'
 import { convertFileSrc } from '@tauri-apps/api/core'
+import { listen } from '@tauri-apps/api/event'
 import { open } from '@tauri-apps/plugin-dialog'
 import './App.css'
 
 interface ImageData {
   id: string
   path: string
+  relativePath: string
   displayPath: string
+  // Grid-sized thumbnail served by the backend's thumb:// protocol
+  thumbnailPath: string
   name: string
   tags: string[]
+  // Tags imported from keywords embedded in the file
+  fileTags: string[]
+  // Tags the vision model suggested, which a retag replaces
+  modelTags: string[]
   description: string
+  // Read from the file itself; null until read or if it couldn't be
+  width: number | null
+  height: number | null
+  // Bytes
+  size: number | null
+  // Milliseconds since the epoch
+  created: number | null
+  modified: number | null
+  mime: string | null
+  // The RAW file of a RAW+JPEG pair, shown as this one image
+  rawPath: string | null
+  metadata: ImageMetadata | null
+  // Dominant colours, most common first; searchable with colour:orange or colour:#ff8800
+  palette: PaletteColour[] | null
+}
+
+interface PaletteColour {
+  hex: string
+  lab: [number, number, number]
+  // Fraction of the picture, 0 to 1
+  share: number
+}
+
+type SortOrder = 'path' | 'name' | 'date' | 'size' | 'resolution'
+
+const SORT_LABELS: Record<SortOrder, string> = {
+  path: 'Folder',
+  name: 'Name',
+  date: 'Date',
+  size: 'Size',
+  resolution: 'Resolution',
+}
+
+// Numeric runs compare by value, so img2 comes before img10
+const naturalOrder = new Intl.Collator(undefined, { numeric: true, sensitivity: 'base' })
+
+// Capture date where known, file modification time otherwise
+function imageDate(image: ImageData): number | null {
+  return image.metadata?.taken ?? image.modified
+}
+
+// Largest first for sizes, newest first for dates; unknown values go last
+function sortImages(images: ImageData[], order: SortOrder): ImageData[] {
+  const byPath = (a: ImageData, b: ImageData) => naturalOrder.compare(a.relativePath, b.relativePath)
+  const byValue = (value: (image: ImageData) => number | null) => (a: ImageData, b: ImageData) => {
+    const x = value(a)
+    const y = value(b)
+    if (x === y) return byPath(a, b)
+    if (x === null) return 1
+    if (y === null) return -1
+    return y - x
+  }
+
+  const compare = {
+    path: byPath,
+    name: (a: ImageData, b: ImageData) => naturalOrder.compare(a.name, b.name) || byPath(a, b),
+    date: byValue(imageDate),
+    size: byValue(image => image.size),
+    resolution: byValue(image => (image.width !== null && image.height !== null ? image.width * image.height : null)),
+  }[order]
+  return [...images].sort(compare)
+}
+
+function formatBytes(bytes: number): string {
+  if (bytes < 1024) return `${bytes} B`
+  const units = ['KB', 'MB', 'GB']
+  let value = bytes / 1024
+  let unit = 0
+  while (value >= 1024 && unit < units.length - 1) {
+    value /= 1024
+    unit++
+  }
+  return `${value.toFixed(value < 10 ? 1 : 0)} ${units[unit]}`
+}
+
+// "4000 × 3000 · 3.2 MB · JPEG + NEF"
+function describeFile(image: ImageData): string {
+  const parts: string[] = []
+  if (image.width && image.height) parts.push(`${image.width} × ${image.height}`)
+  if (image.size !== null) parts.push(formatBytes(image.size))
+  let format = image.mime?.replace(/^image\/(x-)?/, '').replace(/\+xml$/, '').toUpperCase() ?? ''
+  if (image.rawPath) format += ` + ${image.rawPath.split('.').pop()?.toUpperCase()}`
+  if (format) parts.push(format)
+  return parts.join(' · ')
+}
+
+// Webviews can't show RAW or (outside Safari) HEIC, so the viewer gets the
+// large thumbnail instead
+const WEBVIEW_UNSUPPORTED = ['image/x-raw', 'image/heic']
+
+function toImageData(img: Omit<ImageData, 'displayPath' | 'thumbnailPath'>): ImageData {
+  const thumbnail = (size: number) =>
+    // The mtime makes an edited file's thumbnail a new URL
+    `${convertFileSrc(img.id, 'thumb')}?size=${size}&v=${img.modified ?? 0}`
+  return {
+    ...img,
+    displayPath: img.mime && WEBVIEW_UNSUPPORTED.includes(img.mime)
+      ? thumbnail(1024)
+      : convertFileSrc(img.path),
+    thumbnailPath: thumbnail(256),
+  }
+}
+
+interface ImageMetadata {
+  // Milliseconds since the epoch
+  taken?: number
+  cameraMake?: string
+  cameraModel?: string
+  lens?: string
+  exposureTime?: number
+  fNumber?: number
+  iso?: number
+  focalLength?: number
+  orientation?: number
+  gps?: { latitude: number; longitude: number; altitude?: number }
+  caption?: string
+  keywords?: string[]
+}
+
+interface ScanProgress {
+  jobId: string
+  root: string
+  filesSeen: number
+  imagesFound: number
+  currentDir: string
+}
+
+interface ScanPartial {
+  jobId: string
+  root: string
+  images: Omit<ImageData, 'displayPath' | 'thumbnailPath'>[]
+}
+
+interface HistoryEntry {
+  id: number
+  at: number
+  summary: string
+  undone: boolean
+}
+
+interface FileOpReport {
+  images: Omit<ImageData, 'displayPath' | 'thumbnailPath'>[]
+  // Ids of images whose files went to the trash
+  removed: string[]
+  skipped: { imageId: string; message: string }[]
+  failed: { imageId: string; message: string }[]
+}
+
+interface ScanFinished {
+  jobId: string
+  root: string
+  status: 'completed' | 'cancelled' | 'failed'
+  error: string | null
+}
+
+interface ScanIssue {
+  path: string
+  relativePath: string
+  kind: 'notAnImage' | 'extensionMismatch' | 'truncated' | 'unreadable'
+  message: string
+}
+
+interface DuplicateGroup {
+  // Byte-identical files, or the same picture at another size or quality
+  kind: 'exact' | 'similar'
+  // Id of the copy worth keeping; it comes first in images
+  keeper: string
+  images: Omit<ImageData, 'displayPath' | 'thumbnailPath'>[]
+}
+
+interface SearchError {
+  message: string
+  // Character offsets into the query, end exclusive
+  span?: { start: number; end: number }
+}
+
+// "Canon EOS R5 · 50mm · f/2.8 · 1/250s · ISO 400 · 1 Jun 2023"
+function describeCapture(metadata: ImageMetadata | null): string {
+  if (!metadata) return ''
+  const parts: string[] = []
+  const { cameraMake, cameraModel } = metadata
+  if (cameraModel) {
+    parts.push(cameraMake && !cameraModel.startsWith(cameraMake) ? `${cameraMake} ${cameraModel}` : cameraModel)
+  } else if (cameraMake) {
+    parts.push(cameraMake)
+  }
+  if (metadata.focalLength) parts.push(`${Math.round(metadata.focalLength)}mm`)
+  if (metadata.fNumber) parts.push(`f/${metadata.fNumber.toFixed(1).replace(/\.0$/, '')}`)
+  if (metadata.exposureTime) {
+    parts.push(metadata.exposureTime >= 1 ? `${metadata.exposureTime}s` : `1/${Math.round(1 / metadata.exposureTime)}s`)
+  }
+  if (metadata.iso) parts.push(`ISO ${metadata.iso}`)
+  if (metadata.taken) {
+    parts.push(new Date(metadata.taken).toLocaleDateString(undefined, { timeZone: 'UTC', dateStyle: 'medium' }))
+  }
+  return parts.join(' · ')
 }
 
 function App() {
   const [images, setImages] = useState<ImageData[]>([])
   const [selectedImage, setSelectedImage] = useState<ImageData | null>(null)
+  // "More like this" for the open image, cleared when another one opens
+  const [similarImages, setSimilarImages] = useState<ImageData[] | null>(null)
   const [searchQuery, setSearchQuery] = useState('')
   const [isScanning, setIsScanning] = useState(false)
+  const [scanProgress, setScanProgress] = useState<ScanProgress | null>(null)
   const [loadedImages, setLoadedImages] = useState<Set<string>>(new Set())
   const [scanVersion, setScanVersion] = useState(0)
   const [showOverlay, setShowOverlay] = useState(false)
   const [isGeneratingTags, setIsGeneratingTags] = useState(false)
-
-  const filteredImages = images.filter(img =>
-    img.name.toLowerCase().includes(searchQuery.toLowerCase()) ||
-    img.tags.some(tag => tag.toLowerCase().includes(searchQuery.toLowerCase()))
+  const [isGeneratingDescription, setIsGeneratingDescription] = useState(false)
+
+  const [searchMatches, setSearchMatches] = useState<Set<string> | null>(null)
+  const [searchError, setSearchError] = useState<SearchError | null>(null)
+  const [sortOrder, setSortOrder] = useState<SortOrder>('path')
+  const [scanIssues, setScanIssues] = useState<ScanIssue[]>([])
+  const [showIssues, setShowIssues] = useState(false)
+  // A file can have more than one issue
+  const issueFileCount = new Set(scanIssues.map(issue => issue.path)).size
+  const [duplicateGroups, setDuplicateGroups] = useState<DuplicateGroup[]>([])
+  const [showDuplicates, setShowDuplicates] = useState(false)
+
+  const filteredImages = sortImages(
+    searchMatches ? images.filter(img => searchMatches.has(img.id)) : images,
+    sortOrder
   )
 
   // Get current image index for navigation
@@ -46,6 +264,33 @@ function App() {
     }
   }, [currentIndex, filteredImages])
 
+  // Run the query through the catalog, debounced while typing
+  useEffect(() => {
+    if (!searchQuery.trim()) {
+      setSearchMatches(null)
+      setSearchError(null)
+      return
+    }
+
+    let cancelled = false
+    const timer = setTimeout(async () => {
+      try {
+        const results = await invoke<ImageData[]>('search_images', { query: searchQuery })
+        if (cancelled) return
+        setSearchMatches(new Set(results.map(img => img.id)))
+        setSearchError(null)
+      } catch (error) {
+        // Keep the last good results while the query is being edited
+        if (!cancelled) setSearchError(error as SearchError)
+      }
+    }, 200)
+
+    return () => {
+      cancelled = true
+      clearTimeout(timer)
+    }
+  }, [searchQuery, images])
+
   // Keyboard navigation
   useEffect(() => {
     const handleKeyDown = (e: KeyboardEvent) => {
@@ -64,6 +309,30 @@ function App() {
     return () => window.removeEventListener('keydown', handleKeyDown)
   }, [selectedImage, goToPrevious, goToNext])
 
+  // Undo and redo the last library change, then reload what it touched
+  useEffect(() => {
+    const handleKeyDown = async (e: KeyboardEvent) => {
+      if (!(e.ctrlKey || e.metaKey) || e.key.toLowerCase() !== 'z') return
+      if (e.target instanceof HTMLInputElement || e.target instanceof HTMLTextAreaElement) return
+      e.preventDefault()
+
+      const command = e.shiftKey ? 'redo' : 'undo'
+      try {
+        const entry = await invoke<HistoryEntry | null>(command)
+        if (!entry) return
+        const library = await invoke<Omit<ImageData, 'displayPath' | 'thumbnailPath'>[]>('load_library')
+        const refreshed = sortImages(library.map(toImageData), sortOrder)
+        setImages(refreshed)
+        setSelectedImage(prev => prev && (refreshed.find(img => img.id === prev.id) ?? null))
+      } catch (error) {
+        console.error(`Failed to ${command}:`, error)
+      }
+    }
+
+    window.addEventListener('keydown', handleKeyDown)
+    return () => window.removeEventListener('keydown', handleKeyDown)
+  }, [sortOrder])
+
   // Show overlay briefly when image changes, then hide
   useEffect(() => {
     if (selectedImage) {
@@ -73,6 +342,10 @@ function App() {
     }
   }, [selectedImage])
 
+  useEffect(() => {
+    setSimilarImages(null)
+  }, [selectedImage?.id])
+
   // Hide overlay after mouse stops moving
   useEffect(() => {
     if (!showOverlay || !selectedImage) return
@@ -94,25 +367,56 @@ function App() {
       }
 
       setIsScanning(true)
+      setImages([])
+      setScanIssues([])
+      setShowIssues(false)
+      setDuplicateGroups([])
+      setShowDuplicates(false)
+      setScanProgress(null)
       setLoadedImages(new Set())
       setScanVersion(v => v + 1)
 
+      // The scan runs in the background; results stream in as events.
+      // Events carry the root we asked for, so listen before starting.
+      let resolveFinished: (result: ScanFinished) => void = () => {}
+      const finished = new Promise<ScanFinished>(resolve => { resolveFinished = resolve })
+      const unlisteners = await Promise.all([
+        listen<ScanProgress>('scan://progress', event => {
+          if (event.payload.root === selectedFolder) setScanProgress(event.payload)
+        }),
+        listen<ScanPartial>('scan://partial', event => {
+          if (event.payload.root !== selectedFolder) return
+          // Convert local paths to displayable URLs
+          const batch: ImageData[] = event.payload.images.map(toImageData)
+          setImages(prev => [...prev, ...batch])
+        }),
+        listen<ScanFinished>('scan://finished', event => {
+          if (event.payload.root === selectedFolder) resolveFinished(event.payload)
+        }),
+      ])
+
       // Call Rust backend to scan folder
-      const result = await invoke<Omit<ImageData, 'displayPath'>[]>('scan_folder', {
-        folderPath: selectedFolder,
-      })
+      let result: ScanFinished
+      try {
+        await invoke('start_scan', { folderPath: selectedFolder })
+        result = await finished
+      } finally {
+        unlisteners.forEach(unlisten => unlisten())
+      }
 
-      // Convert local paths to displayable URLs
-      const imagesWithDisplayPaths: ImageData[] = result.map(img => ({
-        ...img,
-        displayPath: convertFileSrc(img.path),
-      }))
+      if (result.status === 'failed') {
+        console.error('Failed to scan folder:', result.error)
+      }
 
-      setImages(imagesWithDisplayPaths)
+      // Sort by folder, then filename
+      setImages(prev => sortImages(prev, 'path'))
+      setScanIssues(await invoke<ScanIssue[]>('list_scan_issues', { root: selectedFolder }))
+      setDuplicateGroups(await invoke<DuplicateGroup[]>('find_duplicates', { root: selectedFolder }))
     } catch (error) {
       console.error('Failed to scan folder:', error)
     } finally {
       setIsScanning(false)
+      setScanProgress(null)
     }
   }
 
@@ -143,6 +447,54 @@ function App() {
     }
   }
 
+  const handleFindSimilar = async () => {
+    if (!selectedImage) return
+
+    try {
+      const matches = await invoke<{ image: Omit<ImageData, 'displayPath' | 'thumbnailPath'>; score: number }[]>(
+        'find_similar',
+        { imageId: selectedImage.id, k: 12 },
+      )
+      setSimilarImages(matches.map(match => toImageData(match.image)))
+    } catch (error) {
+      console.error('Failed to find similar images:', error)
+    }
+  }
+
+  const handleDelete = async () => {
+    if (!selectedImage) return
+
+    try {
+      const report = await invoke<FileOpReport>('delete_images', { imageIds: [selectedImage.id] })
+      const removed = new Set(report.removed)
+      setImages(prev => prev.filter(img => !removed.has(img.id)))
+      if (removed.has(selectedImage.id)) setSelectedImage(null)
+      report.failed.forEach(problem => console.error('Failed to delete image:', problem.message))
+    } catch (error) {
+      console.error('Failed to delete image:', error)
+    }
+  }
+
+  const handleGenerateDescription = async () => {
+    if (!selectedImage || isGeneratingDescription) return
+
+    setIsGeneratingDescription(true)
+    try {
+      const description = await invoke<string>('generate_description', {
+        imagePath: selectedImage.path,
+      })
+
+      setImages(prev => prev.map(img =>
+        img.id === selectedImage.id ? { ...img, description } : img
+      ))
+      setSelectedImage(prev => prev ? { ...prev, description } : null)
+    } catch (error) {
+      console.error('Failed to generate description:', error)
+    } finally {
+      setIsGeneratingDescription(false)
+    }
+  }
+
   return (
     <div className="min-h-screen bg-zinc-950">
       {/* Animated background gradient */}
@@ -180,14 +532,36 @@ function App() {
                 </svg>
                 <input
                   type="text"
-                  placeholder="Search..."
+                  placeholder="Search... e.g. tag:beach camera:canon colour:orange"
                   value={searchQuery}
                   onChange={(e) => setSearchQuery(e.target.value)}
                   className="w-full pl-16 pr-4 py-2 bg-white/5 border border-white/10 rounded-xl text-white text-sm placeholder-zinc-500 focus:outline-none focus:bg-white/10 focus:border-zinc-600 focus:ring-2 focus:ring-zinc-700/30 transition-all duration-300"
                 />
+                {searchError && (
+                  <p className="absolute left-0 right-0 top-full mt-1 text-xs text-rose-300/80 truncate">
+                    {searchError.message}
+                    {searchError.span && searchError.span.end > searchError.span.start && (
+                      <span className="ml-1 text-rose-200 underline decoration-wavy">
+                        {Array.from(searchQuery).slice(searchError.span.start, searchError.span.end).join('')}
+                      </span>
+                    )}
+                  </p>
+                )}
               </div>
             </motion.div>
 
+            {/* Sort Order */}
+            <select
+              value={sortOrder}
+              onChange={(e) => setSortOrder(e.target.value as SortOrder)}
+              className="px-3 py-2 bg-white/5 border border-white/10 rounded-xl text-zinc-300 text-sm focus:outline-none focus:border-zinc-600"
+              title="Sort by"
+            >
+              {(Object.keys(SORT_LABELS) as SortOrder[]).map(order => (
+                <option key={order} value={order} className="bg-zinc-900">{SORT_LABELS[order]}</option>
+              ))}
+            </select>
+
             {/* Scan Button */}
             <motion.button
               onClick={handleScanFolder}
@@ -215,9 +589,58 @@ function App() {
                   <path strokeLinecap="round" strokeLinejoin="round" strokeWidth={2} d="M3 7v10a2 2 0 002 2h14a2 2 0 002-2V9a2 2 0 00-2-2h-6l-2-2H5a2 2 0 00-2 2z" />
                 </svg>
               )}
-              {isScanning ? 'Scanning...' : 'Scan Folder'}
+              {isScanning
+                ? scanProgress ? `Scanning... ${scanProgress.imagesFound}` : 'Scanning...'
+                : 'Scan Folder'}
             </motion.button>
           </div>
+
+          {/* Files the scan skipped or found problems with */}
+          {scanIssues.length > 0 && (
+            <div className="mt-3 text-xs">
+              <button
+                onClick={() => setShowIssues(prev => !prev)}
+                className="text-amber-300/80 hover:text-amber-200 transition-colors"
+              >
+                {issueFileCount} {issueFileCount === 1 ? 'file needs' : 'files need'} attention
+              </button>
+              {showIssues && (
+                <ul className="mt-2 max-h-48 overflow-y-auto space-y-1 text-zinc-400">
+                  {scanIssues.map(issue => (
+                    <li key={`${issue.path}-${issue.kind}`} className="truncate" title={issue.path}>
+                      <span className="text-zinc-200">{issue.relativePath}</span>
+                      {' — '}
+                      {issue.message}
+                      {issue.kind === 'notAnImage' && ' (skipped)'}
+                    </li>
+                  ))}
+                </ul>
+              )}
+            </div>
+          )}
+
+          {/* The same photo more than once */}
+          {duplicateGroups.length > 0 && (
+            <div className="mt-3 text-xs">
+              <button
+                onClick={() => setShowDuplicates(prev => !prev)}
+                className="text-sky-300/80 hover:text-sky-200 transition-colors"
+              >
+                {duplicateGroups.length} {duplicateGroups.length === 1 ? 'photo has' : 'photos have'} duplicates
+              </button>
+              {showDuplicates && (
+                <ul className="mt-2 max-h-48 overflow-y-auto space-y-1 text-zinc-400">
+                  {duplicateGroups.map(group => (
+                    <li key={group.keeper} className="truncate">
+                      <span className="text-zinc-200" title={group.images[0].path}>{group.images[0].relativePath}</span>
+                      {group.kind === 'exact' ? ' — identical to ' : ' — also as '}
+                      {group.images.slice(1).map(image => image.relativePath).join(', ')}
+                    </li>
+                  ))}
+                </ul>
+              )}
+            </div>
+          )}
         </div>
       </header>
 
@@ -286,7 +709,7 @@ function App() {
 
                   <motion.img
                     key={`${image.id}-${scanVersion}`}
-                    src={image.displayPath}
+                    src={image.thumbnailPath}
                     alt={image.name}
                     className="w-full h-full object-cover"
                     onLoad={() => handleImageLoad(image.id)}
@@ -401,7 +824,43 @@ function App() {
               <div className="bg-gradient-to-t from-black/80 via-black/50 to-transparent pt-16 pb-6 px-6">
                 <div className="max-w-4xl mx-auto">
                   {/* Filename */}
-                  <h2 className="text-white text-xl font-medium mb-3">{selectedImage.name}</h2>
+                  <h2 className="text-white text-xl font-medium mb-1">{selectedImage.name}</h2>
+
+                  {/* Resolution, size and format */}
+                  {describeFile(selectedImage) && (
+                    <p className="text-white/50 text-xs mb-3">{describeFile(selectedImage)}</p>
+                  )}
+
+                  {/* Dominant colours; click one to search for it */}
+                  {selectedImage.palette && selectedImage.palette.length > 0 && (
+                    <div className="flex h-3 w-48 rounded overflow-hidden mb-3">
+                      {selectedImage.palette.map(colour => (
+                        <button
+                          key={colour.hex}
+                          onClick={() => {
+                            setSearchQuery(`colour:${colour.hex}`)
+                            setSelectedImage(null)
+                          }}
+                          style={{ backgroundColor: colour.hex, flexGrow: colour.share }}
+                          title={`${colour.hex} (${Math.round(colour.share * 100)}%)`}
+                        />
+                      ))}
+                    </div>
+                  )}
+
+                  {/* Camera details */}
+                  {describeCapture(selectedImage.metadata) && (
+                    <p className="text-white/60 text-xs mb-3">
+                      {describeCapture(selectedImage.metadata)}
+                    </p>
+                  )}
+
+                  {/* Description */}
+                  {(selectedImage.description || selectedImage.metadata?.caption) && (
+                    <p className="text-white/80 text-sm leading-relaxed mb-3">
+                      {selectedImage.description || selectedImage.metadata?.caption}
+                    </p>
+                  )}
 
                   {/* Tags */}
                   <div className="flex flex-wrap items-center gap-3 mb-4">
@@ -410,6 +869,7 @@ function App() {
                         <span
                           key={tag}
                           className="text-white/70 text-sm"
+                          title={selectedImage.fileTags.includes(tag) ? 'Keyword from the file' : undefined}
                         >
                           {tag}
                         </span>
@@ -419,24 +879,81 @@ function App() {
                     )}
                   </div>
 
-                  {/* Action button */}
-                  <button
-                    onClick={handleGenerateTags}
-                    disabled={isGeneratingTags}
-                    className="text-white/70 hover:text-white text-sm font-medium transition-colors flex items-center gap-2 disabled:opacity-50"
-                  >
-                    {isGeneratingTags ? (
-                      <svg className="w-4 h-4 animate-spin" fill="none" viewBox="0 0 24 24">
-                        <circle className="opacity-25" cx="12" cy="12" r="10" stroke="currentColor" strokeWidth="4" />
-                        <path className="opacity-75" fill="currentColor" d="M4 12a8 8 0 018-8V0C5.373 0 0 5.373 0 12h4z" />
+                  {/* Action buttons */}
+                  <div className="flex items-center gap-6">
+                    <button
+                      onClick={handleGenerateTags}
+                      disabled={isGeneratingTags}
+                      className="text-white/70 hover:text-white text-sm font-medium transition-colors flex items-center gap-2 disabled:opacity-50"
+                    >
+                      {isGeneratingTags ? (
+                        <svg className="w-4 h-4 animate-spin" fill="none" viewBox="0 0 24 24">
+                          <circle className="opacity-25" cx="12" cy="12" r="10" stroke="currentColor" strokeWidth="4" />
+                          <path className="opacity-75" fill="currentColor" d="M4 12a8 8 0 018-8V0C5.373 0 0 5.373 0 12h4z" />
+                        </svg>
+                      ) : (
+                        <svg className="w-4 h-4" fill="none" viewBox="0 0 24 24" stroke="currentColor">
+                          <path strokeLinecap="round" strokeLinejoin="round" strokeWidth={2} d="M13 10V3L4 14h7v7l9-11h-7z" />
+                        </svg>
+                      )}
+                      {isGeneratingTags ? 'Generating...' : 'Generate AI Tags'}
+                    </button>
+                    <button
+                      onClick={handleGenerateDescription}
+                      disabled={isGeneratingDescription}
+                      className="text-white/70 hover:text-white text-sm font-medium transition-colors flex items-center gap-2 disabled:opacity-50"
+                    >
+                      {isGeneratingDescription ? (
+                        <svg className="w-4 h-4 animate-spin" fill="none" viewBox="0 0 24 24">
+                          <circle className="opacity-25" cx="12" cy="12" r="10" stroke="currentColor" strokeWidth="4" />
+                          <path className="opacity-75" fill="currentColor" d="M4 12a8 8 0 018-8V0C5.373 0 0 5.373 0 12h4z" />
+                        </svg>
+                      ) : (
+                        <svg className="w-4 h-4" fill="none" viewBox="0 0 24 24" stroke="currentColor">
+                          <path strokeLinecap="round" strokeLinejoin="round" strokeWidth={2} d="M4 6h16M4 12h16M4 18h10" />
+                        </svg>
+                      )}
+                      {isGeneratingDescription ? 'Describing...' : 'Generate Description'}
+                    </button>
+                    <button
+                      onClick={handleFindSimilar}
+                      className="text-white/70 hover:text-white text-sm font-medium transition-colors flex items-center gap-2"
+                    >
+                      <svg className="w-4 h-4" fill="none" viewBox="0 0 24 24" stroke="currentColor">
+                        <path strokeLinecap="round" strokeLinejoin="round" strokeWidth={2} d="M4 5h7v7H4zM13 12h7v7h-7zM13 5h7v4h-7zM4 15h7v4H4z" />
                       </svg>
-                    ) : (
+                      More like this
+                    </button>
+                    <button
+                      onClick={handleDelete}
+                      className="text-white/70 hover:text-rose-300 text-sm font-medium transition-colors flex items-center gap-2"
+                    >
                       <svg className="w-4 h-4" fill="none" viewBox="0 0 24 24" stroke="currentColor">
-                        <path strokeLinecap="round" strokeLinejoin="round" strokeWidth={2} d="M13 10V3L4 14h7v7l9-11h-7z" />
+                        <path strokeLinecap="round" strokeLinejoin="round" strokeWidth={2} d="M19 7l-.867 12.142A2 2 0 0116.138 21H7.862a2 2 0 01-1.995-1.858L5 7m5 4v6m4-6v6m1-10V4a1 1 0 00-1-1h-4a1 1 0 00-1 1v3M4 7h16" />
                       </svg>
-                    )}
-                    {isGeneratingTags ? 'Generating...' : 'Generate AI Tags'}
-                  </button>
+                      Move to Trash
+                    </button>
+                  </div>
+
+                  {/* Similar images */}
+                  {similarImages && (
+                    <div className="mt-4 flex gap-2 overflow-x-auto">
+                      {similarImages.length > 0 ? (
+                        similarImages.map(image => (
+                          <button
+                            key={image.id}
+                            onClick={() => setSelectedImage(image)}
+                            className="shrink-0 w-20 h-20 rounded overflow-hidden opacity-80 hover:opacity-100 transition-opacity"
+                            title={image.relativePath}
+                          >
+                            <img src={image.thumbnailPath} alt={image.name} className="w-full h-full object-cover" />
+                          </button>
+                        ))
+                      ) : (
+                        <span className="text-white/50 text-sm">Nothing similar in the library</span>
+                      )}
+                    </div>
+                  )}
                 </div>
               </div>
             </motion.div>